@@ -65,8 +65,11 @@ use bevy::render::{
     RenderPlugin,
 };
 
+#[cfg(not(target_arch = "wasm32"))]
+use rmf_workcell_format::{Angle, Rotation, UrdfExportOptions, WorldAttachment};
 use rmf_workcell_format::{
-    AssetSource, NameInWorkcell, NameOfWorkcell, Pose, PrimitiveShape, Scale,
+    AssetSource, MaterialLibrary, MaterialRef, NameInWorkcell, NameOfWorkcell, Pose,
+    PrimitiveShape, Scale,
 };
 
 #[cfg_attr(not(target_arch = "wasm32"), derive(Parser))]
@@ -77,6 +80,65 @@ pub struct CommandLineArgs {
     /// Name of a Site (.site.ron) file to import on top of the base FILENAME.
     #[cfg_attr(not(target_arch = "wasm32"), arg(short, long))]
     pub import: Option<String>,
+    /// Override for an argument of the loaded xacro file, in the form NAME:=VALUE.
+    #[cfg_attr(
+        not(target_arch = "wasm32"),
+        arg(long = "xacro-arg", value_parser = parse_xacro_arg)
+    )]
+    pub xacro_args: Vec<(String, String)>,
+    /// Location of a package referred to by xacro files, in the form NAME=PATH.
+    #[cfg_attr(
+        not(target_arch = "wasm32"),
+        arg(long = "package", value_parser = parse_package)
+    )]
+    pub packages: Vec<(String, String)>,
+    /// Name of the root link of exported urdf packages. By default the only frame at the root
+    /// of the workcell is used, or a link is added if there are several.
+    #[cfg_attr(not(target_arch = "wasm32"), arg(long))]
+    pub root_link: Option<String>,
+    /// Attach the root link of exported urdf packages to a world link with this name.
+    #[cfg_attr(not(target_arch = "wasm32"), arg(long))]
+    pub world_link: Option<String>,
+    /// Pose of the workcell relative to the world link, in the form "X Y Z ROLL PITCH YAW" with
+    /// angles in radians.
+    #[cfg_attr(
+        not(target_arch = "wasm32"),
+        arg(long, requires = "world_link", value_parser = parse_pose)
+    )]
+    pub world_pose: Option<Pose>,
+    /// Also export frames that are merged into the link of their parent as empty links.
+    #[cfg_attr(not(target_arch = "wasm32"), arg(long))]
+    pub keep_merged_frames: bool,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn parse_xacro_arg(arg: &str) -> Result<(String, String), String> {
+    arg.split_once(":=")
+        .map(|(name, value)| (name.to_owned(), value.to_owned()))
+        .ok_or_else(|| format!("expected NAME:=VALUE, found [{arg}]"))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn parse_package(package: &str) -> Result<(String, String), String> {
+    package
+        .split_once('=')
+        .map(|(name, path)| (name.to_owned(), path.to_owned()))
+        .ok_or_else(|| format!("expected NAME=PATH, found [{package}]"))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn parse_pose(pose: &str) -> Result<Pose, String> {
+    let values = pose
+        .split_whitespace()
+        .map(|v| v.parse::<f32>().map_err(|err| format!("[{v}]: {err}")))
+        .collect::<Result<Vec<_>, _>>()?;
+    let [x, y, z, roll, pitch, yaw] = values[..] else {
+        return Err(format!("expected X Y Z ROLL PITCH YAW, found [{pose}]"));
+    };
+    Ok(Pose {
+        trans: [x, y, z],
+        rot: Rotation::EulerExtrinsicXYZ([Angle::Rad(roll), Angle::Rad(pitch), Angle::Rad(yaw)]),
+    })
 }
 
 #[derive(Clone, Default, Eq, PartialEq, Debug, Hash, States)]
@@ -100,6 +162,23 @@ pub fn run(command_line_args: Vec<String>) {
     #[cfg(not(target_arch = "wasm32"))]
     {
         let command_line_args = CommandLineArgs::parse_from(command_line_args);
+        let mut xacro = rmf_workcell_format::XacroProcessor::default();
+        xacro.args.extend(command_line_args.xacro_args);
+        xacro.packages.extend(
+            command_line_args
+                .packages
+                .into_iter()
+                .map(|(name, path)| (name, path.into())),
+        );
+        app.insert_resource(XacroSettings(xacro));
+        app.insert_resource(UrdfExportOptions {
+            keep_merged_frames: command_line_args.keep_merged_frames,
+            root_link: command_line_args.root_link,
+            world: command_line_args.world_link.map(|link| WorldAttachment {
+                link,
+                pose: command_line_args.world_pose.unwrap_or_default(),
+            }),
+        });
         if let Some(path) = command_line_args.filename {
             app.insert_resource(Autoload::file(
                 path.into(),
@@ -165,6 +244,8 @@ impl Plugin for WorkcellEditor {
                 RecallPlugin::<RecallAssetSource>::default(),
                 ChangePlugin::<PrimitiveShape>::default(),
                 RecallPlugin::<RecallPrimitiveShape>::default(),
+                ChangePlugin::<MaterialRef>::default(),
+                ChangePlugin::<MaterialLibrary>::default(),
             ))
             .add_state::<AppState>()
             .add_plugins((
@@ -0,0 +1,97 @@
+/*
+ * Copyright (C) 2024 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::{
+    bevy_egui::egui::{Button, CollapsingHeader, DragValue, Ui},
+    widgets::{prelude::*, Inspect},
+    CollisionMatrixTask, ComputeCollisionMatrix,
+};
+use bevy::{ecs::system::SystemParam, prelude::*};
+use rmf_workcell_format::{CollisionMatrixOptions, NameOfWorkcell, PlanningSemantics};
+
+#[derive(SystemParam)]
+pub struct InspectCollisionMatrix<'w, 's> {
+    workcells: Query<'w, 's, Option<&'static mut PlanningSemantics>, With<NameOfWorkcell>>,
+    computing: Query<'w, 's, (), With<CollisionMatrixTask>>,
+    compute_collision_matrix: EventWriter<'w, ComputeCollisionMatrix>,
+    options: Local<'s, CollisionMatrixOptions>,
+}
+
+impl<'w, 's> WidgetSystem<Inspect> for InspectCollisionMatrix<'w, 's> {
+    fn show(
+        Inspect { selection, .. }: Inspect,
+        ui: &mut Ui,
+        state: &mut SystemState<Self>,
+        world: &mut World,
+    ) {
+        let mut params = state.get_mut(world);
+        params.show_widget(selection, ui);
+    }
+}
+
+impl<'w, 's> InspectCollisionMatrix<'w, 's> {
+    pub fn show_widget(&mut self, id: Entity, ui: &mut Ui) {
+        let Ok(planning) = self.workcells.get_mut(id) else {
+            return;
+        };
+        ui.label("Disabled collisions");
+        ui.horizontal(|ui| {
+            ui.label("Samples");
+            ui.add(DragValue::new(&mut self.options.samples).clamp_range(1..=100000))
+                .on_hover_text("Number of joint configurations checked for collisions");
+        });
+        let computing = self.computing.get(id).is_ok();
+        let label = if computing { "Computing..." } else { "Compute" };
+        if ui.add_enabled(!computing, Button::new(label)).clicked() {
+            self.compute_collision_matrix.send(ComputeCollisionMatrix {
+                workcell: id,
+                options: *self.options,
+            });
+        }
+        let Some(mut planning) = planning else {
+            return;
+        };
+        if planning.disabled_collisions.is_empty() {
+            return;
+        }
+        let mut removed = None;
+        CollapsingHeader::new(format!("{} pairs", planning.disabled_collisions.len()))
+            .id_source("inspect_disabled_collisions")
+            .show(ui, |ui| {
+                for (idx, disabled) in planning.disabled_collisions.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        if ui
+                            .button("❌")
+                            .on_hover_text("Check the collisions of this pair")
+                            .clicked()
+                        {
+                            removed = Some(idx);
+                        }
+                        ui.label(format!(
+                            "{} - {} ({})",
+                            disabled.frames[0],
+                            disabled.frames[1],
+                            disabled.reason.label()
+                        ));
+                    });
+                }
+            });
+        if let Some(idx) = removed {
+            planning.disabled_collisions.remove(idx);
+        }
+    }
+}
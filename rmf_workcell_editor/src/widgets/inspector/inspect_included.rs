@@ -0,0 +1,49 @@
+/*
+ * Copyright (C) 2024 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::{
+    bevy_egui::egui::Ui,
+    widgets::{prelude::*, Inspect},
+    workcell::IncludedElement,
+};
+use bevy::prelude::*;
+
+/// Shows an inspector widget disabled when the selection is an element of an included workcell,
+/// since it can only be edited in its source file.
+#[derive(SystemParam)]
+pub struct ReadOnlyIfIncluded<'w, 's, W: WidgetSystem<Inspect> + 'static> {
+    included: Query<'w, 's, (), With<IncludedElement>>,
+    inner: Local<'s, Option<SystemState<W>>>,
+}
+
+impl<'w, 's, W: WidgetSystem<Inspect> + 'static> WidgetSystem<Inspect>
+    for ReadOnlyIfIncluded<'w, 's, W>
+{
+    fn show(input: Inspect, ui: &mut Ui, state: &mut SystemState<Self>, world: &mut World) {
+        let (included, inner) = {
+            let mut params = state.get_mut(world);
+            (
+                params.included.contains(input.selection),
+                params.inner.take(),
+            )
+        };
+        let mut inner = inner.unwrap_or_else(|| SystemState::new(world));
+        ui.add_enabled_ui(!included, |ui| W::show(input, ui, &mut inner, world));
+        inner.apply(world);
+        *state.get_mut(world).inner = Some(inner);
+    }
+}
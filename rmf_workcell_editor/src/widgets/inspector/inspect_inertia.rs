@@ -0,0 +1,128 @@
+/*
+ * Copyright (C) 2022 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::{
+    bevy_egui::egui::{DragValue, Ui},
+    widgets::{prelude::*, Inspect},
+    ComputeInertia,
+};
+use bevy::{ecs::system::SystemParam, prelude::*};
+use rmf_workcell_format::{FrameMarker, Mass, MassDistribution, Moment, Pose};
+
+/// How the mass of a frame is specified when computing its inertia from its collisions.
+pub struct InertiaSettings {
+    pub use_density: bool,
+    /// Density in kg/m^3
+    pub density: f32,
+    /// Total mass in kg
+    pub mass: f32,
+}
+
+impl Default for InertiaSettings {
+    fn default() -> Self {
+        Self {
+            use_density: true,
+            // Roughly the density of water
+            density: 1000.0,
+            mass: 1.0,
+        }
+    }
+}
+
+#[derive(SystemParam)]
+pub struct InspectInertia<'w, 's> {
+    frames: Query<'w, 's, Option<&'static Children>, With<FrameMarker>>,
+    inertias: Query<'w, 's, (&'static Pose, &'static Mass, &'static Moment)>,
+    compute_inertia: EventWriter<'w, ComputeInertia>,
+    settings: Local<'s, InertiaSettings>,
+}
+
+impl<'w, 's> WidgetSystem<Inspect> for InspectInertia<'w, 's> {
+    fn show(
+        Inspect { selection, .. }: Inspect,
+        ui: &mut Ui,
+        state: &mut SystemState<Self>,
+        world: &mut World,
+    ) {
+        let mut params = state.get_mut(world);
+        params.show_widget(selection, ui);
+    }
+}
+
+impl<'w, 's> InspectInertia<'w, 's> {
+    pub fn show_widget(&mut self, id: Entity, ui: &mut Ui) {
+        let Ok(children) = self.frames.get(id) else {
+            return;
+        };
+        ui.label("Inertia");
+        let inertia = children
+            .into_iter()
+            .flatten()
+            .find_map(|c| self.inertias.get(*c).ok());
+        if let Some((center, mass, moment)) = inertia {
+            ui.label(format!("Mass: {:.4} kg", mass.0));
+            ui.label(format!(
+                "Center: [{:.4}, {:.4}, {:.4}]",
+                center.trans[0], center.trans[1], center.trans[2]
+            ));
+            ui.label(format!(
+                "Moment: ixx {:.4e}, iyy {:.4e}, izz {:.4e}",
+                moment.ixx, moment.iyy, moment.izz
+            ));
+            ui.label(format!(
+                "ixy {:.4e}, ixz {:.4e}, iyz {:.4e}",
+                moment.ixy, moment.ixz, moment.iyz
+            ));
+        } else {
+            ui.label("No inertia");
+        }
+
+        let settings = &mut *self.settings;
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut settings.use_density, true, "Density");
+            ui.radio_value(&mut settings.use_density, false, "Total mass");
+        });
+        ui.horizontal(|ui| {
+            if settings.use_density {
+                ui.add(
+                    DragValue::new(&mut settings.density)
+                        .clamp_range(0.0..=f32::INFINITY)
+                        .suffix(" kg/m³"),
+                );
+            } else {
+                ui.add(
+                    DragValue::new(&mut settings.mass)
+                        .clamp_range(0.0..=f32::INFINITY)
+                        .speed(0.01)
+                        .suffix(" kg"),
+                );
+            }
+        });
+        if ui
+            .button("Compute from collisions")
+            .on_hover_text("Replaces the inertia of this frame with one computed from the volume of its collisions")
+            .clicked()
+        {
+            let mass = if settings.use_density {
+                MassDistribution::Density(settings.density)
+            } else {
+                MassDistribution::TotalMass(settings.mass)
+            };
+            self.compute_inertia.send(ComputeInertia { frame: id, mass });
+        }
+    }
+}
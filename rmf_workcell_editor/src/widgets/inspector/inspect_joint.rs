@@ -69,6 +69,12 @@ impl<'w, 's> InspectJoint<'w, 's> {
             // TODO(luca) Make this a ComboBox to edit joint value data
             ui.label(joint_properties.label());
         });
+        if let Some(mimic) = joint_properties.single_dof().and_then(|j| j.mimic.as_ref()) {
+            ui.label(format!(
+                "Mimics {} (x{} {:+})",
+                mimic.joint, mimic.multiplier, mimic.offset
+            ));
+        }
         // TODO(luca) add joint limit and joint axis inspectors
     }
 }
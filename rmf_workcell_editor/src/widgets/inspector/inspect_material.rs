@@ -0,0 +1,174 @@
+/*
+ * Copyright (C) 2024 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::{
+    bevy_egui::egui::{Button, ComboBox, Ui},
+    widgets::{prelude::*, Inspect, InspectAssetSourceComponent},
+    Change, VisualMeshMarker,
+};
+use bevy::{ecs::system::SystemParam, prelude::*};
+use rmf_workcell_format::{AssetSource, MaterialLibrary, MaterialRef, VisualMaterial};
+
+#[derive(SystemParam)]
+pub struct InspectMaterial<'w, 's> {
+    visuals: Query<'w, 's, Option<&'static MaterialRef>, With<VisualMeshMarker>>,
+    libraries: Query<'w, 's, &'static MaterialLibrary>,
+    parents: Query<'w, 's, &'static Parent>,
+    change_material: EventWriter<'w, Change<MaterialRef>>,
+    commands: Commands<'w, 's>,
+}
+
+impl<'w, 's> WidgetSystem<Inspect> for InspectMaterial<'w, 's> {
+    fn show(
+        Inspect { selection, .. }: Inspect,
+        ui: &mut Ui,
+        state: &mut SystemState<Self>,
+        world: &mut World,
+    ) {
+        let mut params = state.get_mut(world);
+        params.show_widget(selection, ui);
+        state.apply(world);
+    }
+}
+
+impl<'w, 's> InspectMaterial<'w, 's> {
+    pub fn show_widget(&mut self, id: Entity, ui: &mut Ui) {
+        let Ok(material) = self.visuals.get(id) else {
+            return;
+        };
+        let Some(material) = material else {
+            if ui.button("Add material").clicked() {
+                self.commands.entity(id).insert(MaterialRef::default());
+            }
+            return;
+        };
+        let library = AncestorIter::new(&self.parents, id).find_map(|p| self.libraries.get(p).ok());
+
+        ui.label("Material");
+        let mut new_material = material.clone();
+        let selected_text = match material {
+            MaterialRef::Library(name) => name.clone(),
+            MaterialRef::Inline(_) => "Custom".to_string(),
+        };
+        ComboBox::from_id_source("inspect_material")
+            .selected_text(selected_text)
+            .show_ui(ui, |ui| {
+                // Switching to a custom material starts from the currently displayed one
+                let current = library
+                    .and_then(|l| l.resolve(material))
+                    .cloned()
+                    .unwrap_or_default();
+                ui.selectable_value(&mut new_material, MaterialRef::Inline(current), "Custom");
+                for name in library.iter().flat_map(|l| l.0.keys()) {
+                    ui.selectable_value(
+                        &mut new_material,
+                        MaterialRef::Library(name.clone()),
+                        name,
+                    );
+                }
+            });
+
+        if let MaterialRef::Inline(inline) = &mut new_material {
+            show_visual_material(inline, ui);
+        }
+
+        if new_material != *material {
+            self.change_material.send(Change::new(new_material, id));
+        }
+
+        if ui.button("Remove material").clicked() {
+            self.commands.entity(id).remove::<MaterialRef>();
+        }
+    }
+}
+
+#[derive(SystemParam)]
+pub struct InspectMaterialLibrary<'w, 's> {
+    libraries: Query<'w, 's, &'static MaterialLibrary>,
+    change_library: EventWriter<'w, Change<MaterialLibrary>>,
+    new_name: Local<'s, String>,
+}
+
+impl<'w, 's> WidgetSystem<Inspect> for InspectMaterialLibrary<'w, 's> {
+    fn show(
+        Inspect { selection, .. }: Inspect,
+        ui: &mut Ui,
+        state: &mut SystemState<Self>,
+        world: &mut World,
+    ) {
+        let mut params = state.get_mut(world);
+        params.show_widget(selection, ui);
+    }
+}
+
+impl<'w, 's> InspectMaterialLibrary<'w, 's> {
+    pub fn show_widget(&mut self, id: Entity, ui: &mut Ui) {
+        let Ok(library) = self.libraries.get(id) else {
+            return;
+        };
+        let mut new_library = library.clone();
+        ui.collapsing("Materials", |ui| {
+            let mut removed = None;
+            for (name, material) in new_library.0.iter_mut() {
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.label(name);
+                    if ui.button("Remove").clicked() {
+                        removed = Some(name.clone());
+                    }
+                });
+                show_visual_material(material, ui);
+            }
+            if let Some(removed) = removed {
+                new_library.0.remove(&removed);
+            }
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut *self.new_name);
+                let valid =
+                    !self.new_name.is_empty() && !new_library.0.contains_key(&*self.new_name);
+                if ui.add_enabled(valid, Button::new("Add")).clicked() {
+                    new_library.0.insert(
+                        std::mem::take(&mut *self.new_name),
+                        VisualMaterial::default(),
+                    );
+                }
+            });
+        });
+        if new_library != *library {
+            self.change_library.send(Change::new(new_library, id));
+        }
+    }
+}
+
+fn show_visual_material(material: &mut VisualMaterial, ui: &mut Ui) {
+    ui.horizontal(|ui| {
+        ui.label("Color");
+        ui.color_edit_button_rgba_unmultiplied(&mut material.color);
+    });
+    let mut has_texture = material.texture.is_some();
+    ui.checkbox(&mut has_texture, "Texture");
+    match (has_texture, &material.texture) {
+        (true, Some(texture)) => {
+            if let Some(new_texture) = InspectAssetSourceComponent::new(texture).show(ui) {
+                material.texture = Some(new_texture);
+            }
+        }
+        (true, None) => material.texture = Some(AssetSource::Local(String::new())),
+        (false, _) => material.texture = None,
+    }
+}
@@ -0,0 +1,228 @@
+/*
+ * Copyright (C) 2024 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::{
+    bevy_egui::egui::{ComboBox, DragValue, Ui},
+    widgets::{prelude::*, Inspect},
+    CreateSensor,
+};
+use bevy::{ecs::system::SystemParam, prelude::*};
+use rmf_workcell_format::{
+    CameraProperties, ForceTorqueFrame, ForceTorqueProperties, FrameMarker, LidarProperties,
+    MeasureDirection, SensorKind, SensorProperties,
+};
+
+/// Sensors that can be added to a frame from the inspector, with typical update rates.
+fn default_sensors() -> [SensorProperties; 4] {
+    [
+        SensorProperties {
+            update_rate: 30.0,
+            kind: SensorKind::Camera(Default::default()),
+        },
+        SensorProperties {
+            update_rate: 30.0,
+            kind: SensorKind::DepthCamera(Default::default()),
+        },
+        SensorProperties {
+            update_rate: 10.0,
+            kind: SensorKind::Lidar(Default::default()),
+        },
+        SensorProperties {
+            update_rate: 100.0,
+            kind: SensorKind::ForceTorque(Default::default()),
+        },
+    ]
+}
+
+#[derive(SystemParam)]
+pub struct InspectSensor<'w, 's> {
+    frames: Query<'w, 's, (), With<FrameMarker>>,
+    sensors: Query<'w, 's, &'static mut SensorProperties>,
+    create_sensor: EventWriter<'w, CreateSensor>,
+}
+
+impl<'w, 's> WidgetSystem<Inspect> for InspectSensor<'w, 's> {
+    fn show(
+        Inspect { selection, .. }: Inspect,
+        ui: &mut Ui,
+        state: &mut SystemState<Self>,
+        world: &mut World,
+    ) {
+        let mut params = state.get_mut(world);
+        params.show_widget(selection, ui);
+    }
+}
+
+impl<'w, 's> InspectSensor<'w, 's> {
+    pub fn show_widget(&mut self, id: Entity, ui: &mut Ui) {
+        if self.frames.get(id).is_ok() {
+            ui.label("Add sensor");
+            ui.horizontal_wrapped(|ui| {
+                for properties in default_sensors() {
+                    if ui.button(properties.kind.label()).clicked() {
+                        self.create_sensor.send(CreateSensor {
+                            frame: id,
+                            properties,
+                        });
+                    }
+                }
+            });
+            return;
+        }
+        let Ok(mut properties) = self.sensors.get_mut(id) else {
+            return;
+        };
+        ui.label(format!("{} sensor", properties.kind.label()));
+        let mut new_properties = properties.clone();
+        ui.horizontal(|ui| {
+            ui.label("Update rate");
+            ui.add(
+                DragValue::new(&mut new_properties.update_rate)
+                    .clamp_range(0.0..=f32::INFINITY)
+                    .suffix(" Hz"),
+            )
+            .on_hover_text("Zero means as fast as possible");
+        });
+        match &mut new_properties.kind {
+            SensorKind::Camera(camera) | SensorKind::DepthCamera(camera) => show_camera(camera, ui),
+            SensorKind::Lidar(lidar) => show_lidar(lidar, ui),
+            SensorKind::ForceTorque(force_torque) => show_force_torque(force_torque, ui),
+        }
+        if new_properties != *properties {
+            *properties = new_properties;
+        }
+    }
+}
+
+fn show_camera(camera: &mut CameraProperties, ui: &mut Ui) {
+    let mut resolution = camera.resolution;
+    let mut fov = camera.horizontal_fov().to_degrees();
+    ui.horizontal(|ui| {
+        ui.label("Resolution");
+        ui.add(DragValue::new(&mut resolution[0]).clamp_range(1..=u32::MAX));
+        ui.label("x");
+        ui.add(DragValue::new(&mut resolution[1]).clamp_range(1..=u32::MAX));
+    });
+    ui.horizontal(|ui| {
+        ui.label("Horizontal FOV");
+        ui.add(
+            DragValue::new(&mut fov)
+                .clamp_range(1.0..=179.0)
+                .suffix("°"),
+        )
+        .on_hover_text("Changing the resolution or field of view resets the camera intrinsics");
+    });
+    if resolution != camera.resolution || fov != camera.horizontal_fov().to_degrees() {
+        *camera = CameraProperties::from_horizontal_fov(resolution, fov.to_radians(), camera.clip);
+    }
+    let intrinsics = &camera.intrinsics;
+    ui.label(format!(
+        "fx {:.1}, fy {:.1}, cx {:.1}, cy {:.1}",
+        intrinsics.fx, intrinsics.fy, intrinsics.cx, intrinsics.cy
+    ));
+    ui.horizontal(|ui| {
+        ui.label("Clip");
+        ui.add(
+            DragValue::new(&mut camera.clip[0])
+                .clamp_range(0.0..=camera.clip[1])
+                .speed(0.01)
+                .suffix(" m"),
+        );
+        ui.add(
+            DragValue::new(&mut camera.clip[1])
+                .clamp_range(camera.clip[0]..=f32::INFINITY)
+                .speed(0.01)
+                .suffix(" m"),
+        );
+    });
+}
+
+fn show_lidar(lidar: &mut LidarProperties, ui: &mut Ui) {
+    ui.horizontal(|ui| {
+        ui.label("Samples");
+        ui.add(DragValue::new(&mut lidar.horizontal.samples).clamp_range(1..=u32::MAX));
+    });
+    let mut angles = lidar.horizontal.angles.map(f32::to_degrees);
+    ui.horizontal(|ui| {
+        ui.label("Angles");
+        ui.add(
+            DragValue::new(&mut angles[0])
+                .clamp_range(-180.0..=angles[1])
+                .suffix("°"),
+        );
+        ui.add(
+            DragValue::new(&mut angles[1])
+                .clamp_range(angles[0]..=180.0)
+                .suffix("°"),
+        );
+    });
+    if angles != lidar.horizontal.angles.map(f32::to_degrees) {
+        lidar.horizontal.angles = angles.map(f32::to_radians);
+    }
+    if let Some(vertical) = &lidar.vertical {
+        ui.label(format!("{} vertical samples", vertical.samples));
+    }
+    ui.horizontal(|ui| {
+        ui.label("Range");
+        ui.add(
+            DragValue::new(&mut lidar.range[0])
+                .clamp_range(0.0..=lidar.range[1])
+                .speed(0.01)
+                .suffix(" m"),
+        );
+        ui.add(
+            DragValue::new(&mut lidar.range[1])
+                .clamp_range(lidar.range[0]..=f32::INFINITY)
+                .speed(0.01)
+                .suffix(" m"),
+        );
+    });
+}
+
+fn show_force_torque(force_torque: &mut ForceTorqueProperties, ui: &mut Ui) {
+    ui.horizontal(|ui| {
+        ui.label("Frame");
+        ComboBox::from_id_source("inspect_force_torque_frame")
+            .selected_text(force_torque.frame.label())
+            .show_ui(ui, |ui| {
+                for frame in [
+                    ForceTorqueFrame::Child,
+                    ForceTorqueFrame::Parent,
+                    ForceTorqueFrame::Sensor,
+                ] {
+                    ui.selectable_value(&mut force_torque.frame, frame, frame.label());
+                }
+            });
+    });
+    ui.horizontal(|ui| {
+        ui.label("Measure direction");
+        ComboBox::from_id_source("inspect_force_torque_direction")
+            .selected_text(force_torque.measure_direction.label())
+            .show_ui(ui, |ui| {
+                for direction in [
+                    MeasureDirection::ChildToParent,
+                    MeasureDirection::ParentToChild,
+                ] {
+                    ui.selectable_value(
+                        &mut force_torque.measure_direction,
+                        direction,
+                        direction.label(),
+                    );
+                }
+            });
+    });
+}
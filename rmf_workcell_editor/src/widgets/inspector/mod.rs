@@ -15,12 +15,27 @@
  *
 */
 
+pub mod inspect_collision_matrix;
+pub use inspect_collision_matrix::*;
+
+pub mod inspect_included;
+pub use inspect_included::*;
+
+pub mod inspect_inertia;
+pub use inspect_inertia::*;
+
 pub mod inspect_joint;
 pub use inspect_joint::*;
 
+pub mod inspect_material;
+pub use inspect_material::*;
+
 pub mod inspect_name;
 pub use inspect_name::*;
 
+pub mod inspect_sensor;
+pub use inspect_sensor::*;
+
 pub mod inspect_workcell_parent;
 pub use inspect_workcell_parent::*;
 
@@ -41,16 +56,21 @@ impl Plugin for StandardInspectorPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(MinimalInspectorPlugin::default())
             .add_plugins((
-                InspectionPlugin::<InspectName>::new(),
-                InspectionPlugin::<InspectAnchor>::new(),
-                InspectionPlugin::<InspectJointCreator>::new(),
+                InspectionPlugin::<ReadOnlyIfIncluded<InspectName>>::new(),
+                InspectionPlugin::<ReadOnlyIfIncluded<InspectAnchor>>::new(),
+                InspectionPlugin::<ReadOnlyIfIncluded<InspectJointCreator>>::new(),
                 InspectionPlugin::<InspectAnchorDependents>::new(),
-                InspectionPlugin::<InspectPose>::new(),
-                InspectionPlugin::<InspectScale>::new(),
-                InspectionPlugin::<InspectAssetSource>::new(),
-                InspectionPlugin::<InspectPrimitiveShape>::new(),
-                InspectionPlugin::<InspectWorkcellParent>::new(),
-                InspectionPlugin::<InspectJoint>::new(),
+                InspectionPlugin::<ReadOnlyIfIncluded<InspectPose>>::new(),
+                InspectionPlugin::<ReadOnlyIfIncluded<InspectScale>>::new(),
+                InspectionPlugin::<ReadOnlyIfIncluded<InspectAssetSource>>::new(),
+                InspectionPlugin::<ReadOnlyIfIncluded<InspectPrimitiveShape>>::new(),
+                InspectionPlugin::<ReadOnlyIfIncluded<InspectMaterial>>::new(),
+                InspectionPlugin::<InspectMaterialLibrary>::new(),
+                InspectionPlugin::<ReadOnlyIfIncluded<InspectWorkcellParent>>::new(),
+                InspectionPlugin::<ReadOnlyIfIncluded<InspectJoint>>::new(),
+                InspectionPlugin::<ReadOnlyIfIncluded<InspectInertia>>::new(),
+                InspectionPlugin::<ReadOnlyIfIncluded<InspectSensor>>::new(),
+                InspectionPlugin::<InspectCollisionMatrix>::new(),
             ));
     }
 }
@@ -0,0 +1,296 @@
+/*
+ * Copyright (C) 2024 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::workcell::{inertia::mesh_triangles, parent_in_workcell, IncludedElement};
+use crate::CollisionMeshMarker;
+use bevy::ecs::system::{SystemParam, SystemState};
+use bevy::math::Affine3A;
+use bevy::prelude::*;
+use rmf_workcell_format::{
+    affine_from_pose, CollisionMeshes, CollisionShape, FrameMarker, Geometry,
+    IncludedWorkcellMarker, JointProperties, NameInWorkcell, PlacedShape, PlanningSemantics, Pose,
+    PrimitiveShape, Scale, SiteID, Workcell,
+};
+use std::collections::{HashMap, HashSet};
+
+const INTERPENETRATION_COLOR: Color = Color::RED;
+
+/// Shape of a collision relative to the frame it is attached to, building the convex hull of a
+/// mesh is expensive so it is only done when the collision changes.
+#[derive(Component)]
+pub struct CollisionShapeCache {
+    shape: CollisionShape,
+    /// Transform of the shape relative to the frame, meshes are already in frame coordinates
+    local: Affine3A,
+}
+
+/// Queries needed to access the loaded meshes of collisions.
+#[derive(SystemParam)]
+pub struct LoadedCollisionMeshes<'w, 's> {
+    children: Query<'w, 's, &'static Children>,
+    parents: Query<'w, 's, &'static Parent>,
+    transforms: Query<'w, 's, &'static Transform>,
+    meshes: Query<'w, 's, &'static Handle<Mesh>>,
+    mesh_assets: Res<'w, Assets<Mesh>>,
+}
+
+impl<'w, 's> LoadedCollisionMeshes<'w, 's> {
+    /// Vertices of the meshes of a collision relative to the frame it is attached to, None if
+    /// it has no meshes or some of them are not loaded yet.
+    pub fn vertices(&self, collision: Entity) -> Option<Vec<Vec3>> {
+        let mut vertices = Vec::new();
+        let mut found = false;
+        for e in std::iter::once(collision).chain(self.children.iter_descendants(collision)) {
+            let Ok(handle) = self.meshes.get(e) else {
+                continue;
+            };
+            let mesh = self.mesh_assets.get(handle)?;
+            // Local transforms are already up to date for meshes that were just loaded, unlike
+            // global transforms that are only propagated at the end of the frame
+            let mut tf = Affine3A::IDENTITY;
+            let mut current = e;
+            loop {
+                if let Ok(local) = self.transforms.get(current) {
+                    tf = local.compute_affine() * tf;
+                }
+                if current == collision {
+                    break;
+                }
+                current = self.parents.get(current).ok()?.get();
+            }
+            if let Some((mesh_vertices, _)) = mesh_triangles(mesh, &tf) {
+                vertices.extend(mesh_vertices);
+            }
+            found = true;
+        }
+        found.then_some(vertices)
+    }
+}
+
+/// Vertices of the loaded collision meshes of a workcell, in the coordinates of the mesh files
+/// as expected by [`Workcell::compute_collision_matrix`]. Meshes of included workcells are not
+/// part of `workcell` and are skipped.
+pub fn workcell_collision_meshes(
+    world: &mut World,
+    root: Entity,
+    workcell: &Workcell,
+) -> CollisionMeshes {
+    let mut state: SystemState<(
+        Query<(Entity, &SiteID), With<CollisionMeshMarker>>,
+        Query<&Parent>,
+        Query<(), With<IncludedWorkcellMarker>>,
+        LoadedCollisionMeshes,
+    )> = SystemState::new(world);
+    let (collisions, parents, includes, loaded_meshes) = state.get(world);
+    let mut meshes = CollisionMeshes::new();
+    for (e, id) in &collisions {
+        if !parent_in_workcell(&parents, &includes, e, root) {
+            continue;
+        }
+        let Some(collision) = workcell.collisions.get(&id.0) else {
+            continue;
+        };
+        let Geometry::Mesh { scale, .. } = &collision.bundle.geometry else {
+            continue;
+        };
+        let Some(vertices) = loaded_meshes.vertices(e) else {
+            continue;
+        };
+        // The pose and scale of the collision are applied again from the workcell
+        let to_mesh = (affine_from_pose(&collision.bundle.pose)
+            * Affine3A::from_scale(scale.unwrap_or(Vec3::ONE)))
+        .inverse();
+        meshes.insert(
+            id.0,
+            vertices
+                .into_iter()
+                .map(|v| to_mesh.transform_point3(v))
+                .collect(),
+        );
+    }
+    meshes
+}
+
+/// Builds the shapes of new or modified collisions, meshes are retried until they are loaded.
+pub fn update_collision_shapes(
+    mut commands: Commands,
+    collisions: Query<
+        (
+            Entity,
+            Option<&PrimitiveShape>,
+            &Pose,
+            Option<&CollisionShapeCache>,
+        ),
+        With<CollisionMeshMarker>,
+    >,
+    changed_collisions: Query<
+        Entity,
+        (
+            With<CollisionMeshMarker>,
+            Or<(
+                Changed<PrimitiveShape>,
+                Changed<Pose>,
+                Changed<Scale>,
+                Changed<Transform>,
+            )>,
+        ),
+    >,
+    changed_meshes: Query<Entity, Changed<Handle<Mesh>>>,
+    loaded_meshes: LoadedCollisionMeshes,
+) {
+    let mut to_update: Vec<Entity> = collisions
+        .iter()
+        .filter(|(_, _, _, cache)| cache.is_none())
+        .map(|(e, _, _, _)| e)
+        .chain(&changed_collisions)
+        .collect();
+    for e in &changed_meshes {
+        if let Some(collision) = std::iter::once(e)
+            .chain(loaded_meshes.parents.iter_ancestors(e))
+            .find(|a| collisions.get(*a).is_ok())
+        {
+            to_update.push(collision);
+        }
+    }
+    to_update.sort();
+    to_update.dedup();
+
+    for e in to_update {
+        let Ok((_, primitive, pose, _)) = collisions.get(e) else {
+            continue;
+        };
+        let cache = match primitive {
+            Some(primitive) => Some(CollisionShapeCache {
+                shape: CollisionShape::from_primitive(primitive),
+                local: affine_from_pose(pose),
+            }),
+            None => loaded_meshes
+                .vertices(e)
+                .and_then(|vertices| CollisionShape::from_mesh(&vertices, None))
+                .map(|shape| CollisionShapeCache {
+                    shape,
+                    local: Affine3A::IDENTITY,
+                }),
+        };
+        match cache {
+            Some(cache) => {
+                commands.entity(e).insert(cache);
+            }
+            None => {
+                commands.entity(e).remove::<CollisionShapeCache>();
+            }
+        }
+    }
+}
+
+/// Draws the bounding boxes of collisions that overlap collisions of other links. Pairs of
+/// links whose collisions are disabled for motion planning are not highlighted.
+pub fn draw_interpenetrating_collisions(
+    mut gizmos: Gizmos,
+    collisions: Query<
+        (Entity, &Parent, &CollisionShapeCache, &InheritedVisibility),
+        With<CollisionMeshMarker>,
+    >,
+    frames: Query<(Entity, &GlobalTransform, &NameInWorkcell), With<FrameMarker>>,
+    included: Query<(), With<IncludedElement>>,
+    joints: Query<(), With<JointProperties>>,
+    parents: Query<&Parent>,
+    workcells: Query<(Entity, &PlanningSemantics)>,
+) {
+    // As in urdf, frames that are not children of a joint are merged into the link of their
+    // parent, frames attached to the workcell are merged into a single link
+    let link_of = |mut frame: Entity| {
+        while let Ok(parent) = parents.get(frame) {
+            if joints.contains(parent.get()) {
+                break;
+            }
+            frame = parent.get();
+            if !frames.contains(frame) {
+                break;
+            }
+        }
+        frame
+    };
+    // Disabled collisions refer to any frame of the links, by name
+    let mut disabled = HashSet::new();
+    for (workcell, planning) in &workcells {
+        if planning.disabled_collisions.is_empty() {
+            continue;
+        }
+        let named: HashMap<_, _> = frames
+            .iter()
+            .filter(|(e, _, _)| {
+                !included.contains(*e) && parents.iter_ancestors(*e).any(|p| p == workcell)
+            })
+            .map(|(e, _, name)| (name.0.as_str(), e))
+            .collect();
+        for pair in &planning.disabled_collisions {
+            let [Some(a), Some(b)] = [0, 1].map(|i| named.get(pair.frames[i].as_str())) else {
+                continue;
+            };
+            let (a, b) = (link_of(*a), link_of(*b));
+            disabled.insert((a.min(b), a.max(b)));
+        }
+    }
+
+    let mut placed = Vec::new();
+    for (e, frame, cache, visibility) in &collisions {
+        if !visibility.get() {
+            continue;
+        }
+        let Ok((_, frame_tf, _)) = frames.get(frame.get()) else {
+            continue;
+        };
+        placed.push((
+            e,
+            link_of(frame.get()),
+            PlacedShape::new(&cache.shape, frame_tf.affine() * cache.local),
+        ));
+    }
+    let mut interpenetrating = Vec::new();
+    for (i, (e_a, link_a, shape_a)) in placed.iter().enumerate() {
+        for (e_b, link_b, shape_b) in &placed[i + 1..] {
+            if link_a == link_b || disabled.contains(&(*link_a.min(link_b), *link_a.max(link_b))) {
+                continue;
+            }
+            if shape_a.intersects(shape_b) {
+                interpenetrating.extend([*e_a, *e_b]);
+            }
+        }
+    }
+
+    for (_, _, shape) in placed
+        .iter()
+        .filter(|(e, _, _)| interpenetrating.contains(e))
+    {
+        // The axis aligned bounding box, from the extreme points of the shape along each axis
+        let max = Vec3::new(
+            shape.support(Vec3::X).x,
+            shape.support(Vec3::Y).y,
+            shape.support(Vec3::Z).z,
+        );
+        let min = Vec3::new(
+            shape.support(-Vec3::X).x,
+            shape.support(-Vec3::Y).y,
+            shape.support(-Vec3::Z).z,
+        );
+        gizmos.cuboid(
+            Transform::from_translation((min + max) / 2.0).with_scale(max - min),
+            INTERPENETRATION_COLOR,
+        );
+    }
+}
@@ -0,0 +1,154 @@
+/*
+ * Copyright (C) 2024 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::workcell::{generate_workcell, include_resolver, workcell_collision_meshes};
+use crate::workspace::XacroSettings;
+use crate::DefaultFile;
+use bevy::prelude::*;
+use bevy::tasks::{futures_lite::future, AsyncComputeTaskPool, Task};
+use rmf_workcell_format::{
+    CollisionMatrix, CollisionMatrixError, CollisionMatrixOptions, DisabledCollisionReason,
+    PlanningSemantics,
+};
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Event used to request computing the disabled collisions of a workcell by sampling joint
+/// configurations
+#[derive(Event)]
+pub struct ComputeCollisionMatrix {
+    pub workcell: Entity,
+    pub options: CollisionMatrixOptions,
+}
+
+/// Collision matrix being computed in the background for the workcell it is attached to
+#[derive(Component)]
+pub struct CollisionMatrixTask(Task<Result<CollisionMatrix, CollisionMatrixError>>);
+
+pub fn handle_compute_collision_matrix_events(world: &mut World) {
+    let events: Vec<_> = world
+        .resource_mut::<Events<ComputeCollisionMatrix>>()
+        .drain()
+        .collect();
+    for req in events {
+        if world.get::<CollisionMatrixTask>(req.workcell).is_some() {
+            warn!("The collision matrix of this workcell is already being computed");
+            continue;
+        }
+        let workcell = match generate_workcell(world, req.workcell) {
+            Ok(workcell) => workcell,
+            Err(err) => {
+                error!("Unable to compile workcell: {err}");
+                continue;
+            }
+        };
+        // Included workcells are part of the exported urdf, their links are checked as well
+        // but pairs can only refer to frames of this workcell, names of included frames are
+        // prefixed when flattening and don't exist in the saved file
+        let meshes = workcell_collision_meshes(world, req.workcell, &workcell);
+        let frames: HashSet<String> = workcell
+            .frames
+            .values()
+            .map(|f| f.bundle.name.0.clone())
+            .collect();
+        let dir = world
+            .get::<DefaultFile>(req.workcell)
+            .and_then(|file| file.0.parent().map(Path::to_owned));
+        let resolver = include_resolver(world.resource::<XacroSettings>());
+        let workcell = match workcell.flatten_includes(&resolver, dir.as_deref()) {
+            Ok(workcell) => workcell,
+            Err(err) => {
+                error!("Unable to resolve the included workcells: {err}");
+                continue;
+            }
+        };
+        // Sampling can take a while with many links, don't block the editor
+        let options = req.options;
+        let task = AsyncComputeTaskPool::get().spawn(async move {
+            let mut matrix = workcell.compute_collision_matrix(&options, &meshes)?;
+            let total = matrix.disabled.len();
+            matrix
+                .disabled
+                .retain(|d| d.frames.iter().all(|f| frames.contains(f)));
+            if matrix.disabled.len() < total {
+                info!(
+                    "Skipped {} disabled collisions involving included workcells",
+                    total - matrix.disabled.len()
+                );
+            }
+            Ok(matrix)
+        });
+        if let Some(mut entity) = world.get_entity_mut(req.workcell) {
+            entity.insert(CollisionMatrixTask(task));
+        }
+    }
+}
+
+/// Applies the collision matrices computed in the background to their workcells.
+pub fn apply_computed_collision_matrices(
+    mut commands: Commands,
+    mut tasks: Query<(Entity, &mut CollisionMatrixTask)>,
+    mut planning: Query<&mut PlanningSemantics>,
+) {
+    for (e, mut task) in &mut tasks {
+        let Some(result) = future::block_on(future::poll_once(&mut task.0)) else {
+            continue;
+        };
+        commands.entity(e).remove::<CollisionMatrixTask>();
+        let matrix = match result {
+            Ok(matrix) => matrix,
+            Err(err) => {
+                error!("Failed to compute the collision matrix: {err}");
+                continue;
+            }
+        };
+        if !matrix.unchecked.is_empty() {
+            warn!(
+                "{} mesh collisions are not loaded and could not be checked, their links \
+                    are only disabled with adjacent links",
+                matrix.unchecked.len()
+            );
+        }
+        info!(
+            "Disabled collisions between {} pairs of links",
+            matrix.disabled.len()
+        );
+        // Pairs added by the user are kept, computed ones are replaced
+        let mut disabled_collisions = match planning.get(e) {
+            Ok(planning) => planning.disabled_collisions.clone(),
+            Err(_) => Vec::new(),
+        };
+        disabled_collisions.retain(|d| d.reason == DisabledCollisionReason::User);
+        for disabled in matrix.disabled {
+            if !disabled_collisions
+                .iter()
+                .any(|d| disabled.frames.iter().all(|f| d.frames.contains(f)))
+            {
+                disabled_collisions.push(disabled);
+            }
+        }
+        match planning.get_mut(e) {
+            Ok(mut planning) => planning.disabled_collisions = disabled_collisions,
+            Err(_) => {
+                commands.entity(e).insert(PlanningSemantics {
+                    disabled_collisions,
+                    ..Default::default()
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,95 @@
+/*
+ * Copyright (C) 2023 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::{
+    site_asset_io::cache_path, workcell::spawn_workcell_elements, workspace::XacroSettings,
+    DefaultFile, Dependents, ModelLoader, PreventDeletion,
+};
+use bevy::prelude::*;
+use rmf_workcell_format::{AssetSource, IncludeResolver, IncludedWorkcellMarker};
+use std::collections::HashSet;
+
+/// Marks the elements of included workcells, they are loaded from their source file and edits
+/// to them would be lost when saving.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct IncludedElement;
+
+impl IncludedElement {
+    /// Components preventing the element from being deleted, its inspector widgets are disabled
+    /// through [`ReadOnlyIfIncluded`](crate::widgets::ReadOnlyIfIncluded).
+    pub fn bundle() -> (Self, PreventDeletion) {
+        (
+            Self,
+            PreventDeletion::because(
+                "Elements of included workcells can only be edited in their source file"
+                    .to_string(),
+            ),
+        )
+    }
+}
+
+/// Resolver for the sources of included workcells, packages are the ones used for xacro files.
+pub fn include_resolver(xacro_settings: &XacroSettings) -> IncludeResolver {
+    IncludeResolver {
+        packages: xacro_settings.0.packages.clone(),
+        remote_cache: Some(cache_path()),
+    }
+}
+
+/// Loads the content of included workcells as children of their include. They are not part of
+/// the workcell being edited and are not saved with it, so they can't be deleted or edited.
+pub fn load_included_workcells(
+    mut commands: Commands,
+    includes: Query<
+        (Entity, &AssetSource, Option<&Children>),
+        (With<IncludedWorkcellMarker>, Changed<AssetSource>),
+    >,
+    parents: Query<&Parent>,
+    default_files: Query<&DefaultFile>,
+    xacro_settings: Res<XacroSettings>,
+    mut model_loader: ModelLoader,
+) {
+    if includes.is_empty() {
+        return;
+    }
+    let resolver = include_resolver(&xacro_settings);
+    for (e, source, children) in &includes {
+        for child in children.into_iter().flatten() {
+            commands.entity(*child).despawn_recursive();
+        }
+        commands.entity(e).insert(Dependents(HashSet::new()));
+        // Relative sources are relative to the file of the workcell
+        let dir = AncestorIter::new(&parents, e)
+            .find_map(|p| default_files.get(p).ok())
+            .and_then(|file| file.0.parent());
+        let included = match resolver.load_include(source, dir) {
+            Ok(included) => included,
+            Err(err) => {
+                error!("Unable to load included workcell: {err}");
+                continue;
+            }
+        };
+        for diagnostic in included.validate() {
+            warn!("Issue found in included workcell: {diagnostic}");
+        }
+        // Visuals of the included workcell refer to its own materials
+        commands.entity(e).insert(included.materials.clone());
+        for element in spawn_workcell_elements(&mut commands, &included, e, &mut model_loader) {
+            commands.entity(element).insert(IncludedElement::bundle());
+        }
+    }
+}
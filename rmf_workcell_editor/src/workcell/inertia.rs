@@ -0,0 +1,151 @@
+/*
+ * Copyright (C) 2023 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::{CollisionMeshMarker, Dependents};
+use bevy::math::Affine3A;
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology, VertexAttributeValues};
+use rmf_workcell_format::{
+    affine_from_pose, Category, FrameMarker, Inertia, Mass, MassDistribution, Moment, Pose,
+    PrimitiveShape,
+};
+
+/// Event used to request computing the inertia of a frame from the collisions attached to it
+#[derive(Event)]
+pub struct ComputeInertia {
+    pub frame: Entity,
+    pub mass: MassDistribution,
+}
+
+/// Extracts the vertices and triangles of a mesh, with the vertices transformed by `tf`.
+pub(crate) fn mesh_triangles(mesh: &Mesh, tf: &Affine3A) -> Option<(Vec<Vec3>, Vec<[u32; 3]>)> {
+    if mesh.primitive_topology() != PrimitiveTopology::TriangleList {
+        return None;
+    }
+    let Some(VertexAttributeValues::Float32x3(positions)) =
+        mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+    else {
+        return None;
+    };
+    let vertices: Vec<_> = positions
+        .iter()
+        .map(|p| tf.transform_point3(Vec3::from(*p)))
+        .collect();
+    let indices: Vec<u32> = match mesh.indices() {
+        Some(Indices::U16(indices)) => indices.iter().map(|i| *i as u32).collect(),
+        Some(Indices::U32(indices)) => indices.clone(),
+        None => (0..vertices.len() as u32).collect(),
+    };
+    let triangles = indices
+        .chunks_exact(3)
+        .map(|t| [t[0], t[1], t[2]])
+        .collect();
+    Some((vertices, triangles))
+}
+
+pub fn handle_compute_inertia_events(
+    mut commands: Commands,
+    mut events: EventReader<ComputeInertia>,
+    frames: Query<&GlobalTransform, With<FrameMarker>>,
+    children: Query<&Children>,
+    collisions: Query<(Option<&PrimitiveShape>, &Pose), With<CollisionMeshMarker>>,
+    meshes: Query<(&Handle<Mesh>, &GlobalTransform)>,
+    mesh_assets: Res<Assets<Mesh>>,
+    mut inertias: Query<(&mut Pose, &mut Mass, &mut Moment), Without<CollisionMeshMarker>>,
+    mut dependents: Query<&mut Dependents>,
+) {
+    for req in events.read() {
+        let Ok(frame_tf) = frames.get(req.frame) else {
+            error!("Requested to compute the inertia of an entity that is not a frame");
+            continue;
+        };
+        // Compute with the requested density or a unit one, to scale to the total mass later
+        let density = match req.mass {
+            MassDistribution::Density(_) => req.mass,
+            MassDistribution::TotalMass(_) => MassDistribution::Density(1.0),
+        };
+        let frame_inverse = frame_tf.affine().inverse();
+        let mut parts = Vec::new();
+        for collision in children.get(req.frame).into_iter().flatten() {
+            let Ok((primitive, pose)) = collisions.get(*collision) else {
+                continue;
+            };
+            if let Some(primitive) = primitive {
+                match Inertia::from_primitive(primitive, density) {
+                    Some(inertia) => parts.push(inertia.transformed(&affine_from_pose(pose))),
+                    None => warn!("Skipping collision {:?} without volume", collision),
+                }
+                continue;
+            }
+            // Meshes are loaded as a hierarchy, the global transform of each mesh takes into
+            // account the pose and scale of the collision
+            let mut found = false;
+            for e in std::iter::once(*collision).chain(children.iter_descendants(*collision)) {
+                let Ok((handle, tf)) = meshes.get(e) else {
+                    continue;
+                };
+                let Some(mesh) = mesh_assets.get(handle) else {
+                    continue;
+                };
+                found = true;
+                let tf = frame_inverse * tf.affine();
+                match mesh_triangles(mesh, &tf)
+                    .and_then(|(v, t)| Inertia::from_triangle_mesh(&v, &t, density))
+                {
+                    Some(inertia) => parts.push(inertia),
+                    None => warn!("Skipping collision mesh {:?} without volume", e),
+                }
+            }
+            if !found {
+                warn!(
+                    "Collision {:?} is not loaded yet and was not included in the inertia",
+                    collision
+                );
+            }
+        }
+        let Some(mut inertia) = Inertia::combine(&parts) else {
+            warn!("No collisions with a volume found, inertia was not computed");
+            continue;
+        };
+        if let MassDistribution::TotalMass(mass) = req.mass {
+            inertia = inertia.with_mass(mass);
+        }
+
+        let existing = children
+            .get(req.frame)
+            .into_iter()
+            .flatten()
+            .find(|c| inertias.get(**c).is_ok());
+        if let Some(existing) = existing {
+            if let Ok((mut center, mut mass, mut moment)) = inertias.get_mut(*existing) {
+                *center = inertia.center;
+                *mass = inertia.mass;
+                *moment = inertia.moment;
+            }
+        } else {
+            let e = commands
+                .spawn(SpatialBundle::INHERITED_IDENTITY)
+                .insert(inertia)
+                .insert(Category::Inertia)
+                .set_parent(req.frame)
+                .id();
+            if let Ok(mut deps) = dependents.get_mut(req.frame) {
+                deps.insert(e);
+            }
+        }
+    }
+}
@@ -56,6 +56,7 @@ pub fn handle_create_joint_events(
         let joint = Joint {
             name: NameInWorkcell(joint_name),
             properties: JointProperties::Fixed,
+            actuation: Default::default(),
         };
         let mut cmd = commands.spawn(Dependents::single(req.child));
         let joint_id = cmd.id();
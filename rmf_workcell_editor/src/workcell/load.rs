@@ -46,20 +46,32 @@ fn generate_workcell_entities(
     workcell: &Workcell,
     model_loader: &mut ModelLoader,
 ) -> Entity {
-    // Create hashmap of ids to entity to correctly generate hierarchy
-    let mut id_to_entity = HashMap::new();
-    // Hashmap of parent id to list of its children entities
-    let mut parent_to_child_entities = HashMap::new();
-
     let root = commands
         .spawn(SpatialBundle::INHERITED_IDENTITY)
         .insert(workcell.properties.clone())
+        .insert(workcell.materials.clone())
         .insert(SiteID(workcell.id))
         .insert(Category::Workcell)
         .insert(PreventDeletion::because(
             "Workcell root cannot be deleted".to_string(),
         ))
         .id();
+    spawn_workcell_elements(commands, workcell, root, model_loader);
+    root
+}
+
+/// Spawns the elements of a workcell, the ones that are children of the workcell are attached
+/// to `root`. Returns all the spawned elements.
+pub(crate) fn spawn_workcell_elements(
+    commands: &mut Commands,
+    workcell: &Workcell,
+    root: Entity,
+    model_loader: &mut ModelLoader,
+) -> Vec<Entity> {
+    // Create hashmap of ids to entity to correctly generate hierarchy
+    let mut id_to_entity = HashMap::new();
+    // Hashmap of parent id to list of its children entities
+    let mut parent_to_child_entities = HashMap::new();
     id_to_entity.insert(workcell.id, root);
 
     let mut add_model =
@@ -92,6 +104,9 @@ fn generate_workcell_entities(
     for (id, visual) in &workcell.visuals {
         let e = commands.spawn((VisualMeshMarker, Category::Visual)).id();
         add_model(visual, *id, e, commands);
+        if let Some(material) = &visual.bundle.material {
+            commands.entity(e).insert(material.clone());
+        }
     }
 
     for (id, collision) in &workcell.collisions {
@@ -141,6 +156,33 @@ fn generate_workcell_entities(
         id_to_entity.insert(*id, e);
     }
 
+    for (id, parented_sensor) in &workcell.sensors {
+        let e = commands
+            .spawn(SpatialBundle::INHERITED_IDENTITY)
+            .insert(parented_sensor.bundle.clone())
+            .insert(SiteID(*id))
+            .id();
+        let child_entities: &mut Vec<Entity> = parent_to_child_entities
+            .entry(parented_sensor.parent)
+            .or_default();
+        child_entities.push(e);
+        id_to_entity.insert(*id, e);
+    }
+
+    for (id, parented_include) in &workcell.includes {
+        let e = commands
+            .spawn(SpatialBundle::INHERITED_IDENTITY)
+            .insert(parented_include.bundle.clone())
+            .insert(Category::Workcell)
+            .insert(SiteID(*id))
+            .id();
+        let child_entities: &mut Vec<Entity> = parent_to_child_entities
+            .entry(parented_include.parent)
+            .or_default();
+        child_entities.push(e);
+        id_to_entity.insert(*id, e);
+    }
+
     for (parent, children) in parent_to_child_entities {
         if let Some(parent) = id_to_entity.get(&parent) {
             commands
@@ -152,7 +194,11 @@ fn generate_workcell_entities(
             continue;
         }
     }
-    root
+    id_to_entity
+        .into_iter()
+        .filter(|(id, _)| *id != workcell.id)
+        .map(|(_, e)| e)
+        .collect()
 }
 
 pub fn load_workcell(
@@ -163,6 +209,9 @@ pub fn load_workcell(
 ) {
     for cmd in load_workcells.read() {
         info!("Loading workcell");
+        for diagnostic in cmd.workcell.validate() {
+            warn!("Issue found in workcell: {diagnostic}");
+        }
         let root = generate_workcell_entities(&mut commands, &cmd.workcell, &mut model_loader);
         if let Some(path) = &cmd.default_file {
             commands.entity(root).insert(DefaultFile(path.clone()));
@@ -15,9 +15,21 @@
  *
 */
 
+pub mod collision;
+pub use collision::*;
+
+pub mod collision_matrix;
+pub use collision_matrix::*;
+
 pub mod frame;
 pub use frame::*;
 
+pub mod include;
+pub use include::*;
+
+pub mod inertia;
+pub use inertia::*;
+
 pub mod joint;
 pub use joint::*;
 
@@ -33,6 +45,9 @@ pub use model::*;
 pub mod save;
 pub use save::*;
 
+pub mod sensor;
+pub use sensor::*;
+
 pub mod urdf_package_exporter;
 
 pub mod workcell;
@@ -46,6 +61,7 @@ pub use librmf_site_editor::site::{
     handle_new_primitive_shapes, update_anchor_transforms, update_model_scales,
     update_transforms_for_changed_poses,
 };
+use rmf_workcell_format::UrdfExportOptions;
 
 #[derive(Default)]
 pub struct WorkcellEditorPlugin;
@@ -64,7 +80,12 @@ impl Plugin for WorkcellEditorPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(InfiniteGridPlugin)
             .add_event::<CreateJoint>()
+            .add_event::<CreateSensor>()
+            .add_event::<ComputeInertia>()
+            .add_event::<ComputeCollisionMatrix>()
             .add_event::<ChangeCurrentWorkcell>()
+            // Set from the command line, if any
+            .init_resource::<UrdfExportOptions>()
             .add_systems(OnEnter(AppState::WorkcellEditor), spawn_grid)
             .add_systems(OnExit(AppState::WorkcellEditor), delete_grid)
             .add_systems(
@@ -73,15 +94,27 @@ impl Plugin for WorkcellEditorPlugin {
                     update_model_scales,
                     handle_new_primitive_shapes,
                     handle_create_joint_events,
+                    handle_create_sensor_events,
+                    handle_compute_inertia_events,
+                    handle_compute_collision_matrix_events,
+                    apply_computed_collision_matrices,
                     cleanup_orphaned_joints,
                     change_workcell.before(load_workcell),
                     handle_export_urdf_menu_events,
+                    draw_sensors,
+                    update_collision_shapes,
+                    draw_interpenetrating_collisions,
                 )
                     .run_if(in_state(AppState::WorkcellEditor)),
             )
             .add_systems(
                 Update,
-                (load_workcell, save_workcell, add_workcell_visualization),
+                (
+                    load_workcell,
+                    load_included_workcells,
+                    save_workcell,
+                    add_workcell_visualization,
+                ),
             )
             // TODO(luca) restore doing this before transform propagation
             .add_systems(
@@ -92,6 +125,11 @@ impl Plugin for WorkcellEditorPlugin {
                     update_transforms_for_changed_poses,
                 )
                     .run_if(in_state(AppState::WorkcellEditor)),
+            )
+            // Runs after the primitive meshes were generated, to override their default material
+            .add_systems(
+                PostUpdate,
+                update_visual_materials.run_if(in_state(AppState::WorkcellEditor)),
             );
     }
 
@@ -15,12 +15,18 @@
  *
 */
 
+use std::collections::HashSet;
+
 use crate::{
-    interaction::{DragPlaneBundle, Preview, VisualCue},
+    interaction::{DragPlaneBundle, Preview, Selectable, VisualCue},
+    workcell::IncludedElement,
     Dependents, ModelLoadingResult,
 };
 use bevy::prelude::*;
-use rmf_workcell_format::{ModelMarker, NameInSite, NameInWorkcell, Pose, PrimitiveShape};
+use rmf_workcell_format::{
+    MaterialLibrary, MaterialRef, ModelMarker, NameInSite, NameInWorkcell, Pose, PrimitiveShape,
+    VisualMaterial,
+};
 
 /// SDFs loaded through site editor wrap all the collisions and visuals into a single Model entity.
 /// This doesn't quite work for URDF / workcells since we need to export and edit single visuals
@@ -30,6 +36,7 @@ pub fn flatten_loaded_model_hierarchy(
     mut commands: Commands,
     cues: Query<&VisualCue>,
     previews: Query<&Preview>,
+    included: Query<(), With<IncludedElement>>,
     mut poses: Query<&mut Pose>,
     mut dependents: Query<&mut Dependents>,
     parents: Query<&Parent>,
@@ -55,19 +62,20 @@ pub fn flatten_loaded_model_hierarchy(
     let Ok(parent_pose) = poses.get(old_parent).cloned() else {
         return;
     };
+    // Included models can be selected but not dragged
+    let is_included = included.contains(old_parent);
     for c in DescendantIter::new(&children, old_parent) {
         if meshes.get(c).is_ok() {
             // Set its selectable to the first parent model, or to itself if none is found
-            let mut parent_found = false;
-            for p in AncestorIter::new(&parents, c) {
-                if models.get(p).is_ok() {
-                    commands.entity(c).insert(DragPlaneBundle::new(p, Vec3::Z));
-                    parent_found = true;
-                    break;
-                }
-            }
-            if !parent_found {
-                commands.entity(c).insert(DragPlaneBundle::new(c, Vec3::Z));
+            let selected = AncestorIter::new(&parents, c)
+                .find(|p| models.get(*p).is_ok())
+                .unwrap_or(c);
+            if is_included {
+                commands.entity(c).insert(Selectable::new(selected));
+            } else {
+                commands
+                    .entity(c)
+                    .insert(DragPlaneBundle::new(selected, Vec3::Z));
             }
         }
         // Change site names to workcell names
@@ -99,6 +107,9 @@ pub fn flatten_loaded_model_hierarchy(
         if let Ok(preview) = previews.get(old_parent) {
             c_mut.insert(*preview);
         }
+        if is_included {
+            c_mut.insert(IncludedElement::bundle());
+        }
     }
     if let Ok(mut parent_dependents) = dependents.get_mut(**new_parent) {
         parent_dependents.remove(&old_parent);
@@ -107,3 +118,77 @@ pub fn flatten_loaded_model_hierarchy(
     // Now despawn the unnecessary model
     commands.entity(old_parent).despawn_recursive();
 }
+
+fn make_standard_material(
+    material: &VisualMaterial,
+    asset_server: &AssetServer,
+) -> StandardMaterial {
+    let [r, g, b, a] = material.color;
+    StandardMaterial {
+        base_color: Color::rgba(r, g, b, a),
+        // SAFETY: We don't need to validate the syntax of the asset path because that will be
+        // done when the asset server attempts to load it.
+        base_color_texture: material
+            .texture
+            .as_ref()
+            .map(|texture| asset_server.load(unsafe { texture.as_unvalidated_asset_path() })),
+        alpha_mode: if a < 1.0 {
+            AlphaMode::Blend
+        } else {
+            AlphaMode::Opaque
+        },
+        ..default()
+    }
+}
+
+/// Applies the material of visuals to their primitive meshes. Mesh models keep the materials
+/// they were loaded with.
+pub fn update_visual_materials(
+    mut commands: Commands,
+    changed_visuals: Query<
+        Entity,
+        (
+            With<MaterialRef>,
+            Or<(Changed<MaterialRef>, Changed<PrimitiveShape>)>,
+        ),
+    >,
+    changed_libraries: Query<Entity, Changed<MaterialLibrary>>,
+    visuals: Query<&MaterialRef, With<PrimitiveShape>>,
+    libraries: Query<&MaterialLibrary>,
+    children: Query<&Children>,
+    parents: Query<&Parent>,
+    primitives: Query<&PrimitiveShape>,
+    mut removed_materials: RemovedComponents<MaterialRef>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    asset_server: Res<AssetServer>,
+) {
+    // Changing a library entry affects all the visuals of that workcell
+    let mut to_update: HashSet<Entity> = changed_visuals.iter().collect();
+    for root in &changed_libraries {
+        to_update.extend(DescendantIter::new(&children, root).filter(|e| visuals.get(*e).is_ok()));
+    }
+
+    for e in to_update {
+        let Ok(material) = visuals.get(e) else {
+            continue;
+        };
+        let Some(library) = AncestorIter::new(&parents, e).find_map(|p| libraries.get(p).ok())
+        else {
+            continue;
+        };
+        let Some(material) = library.resolve(material) else {
+            warn!("Material {:?} not found in the workcell library", material);
+            continue;
+        };
+        commands
+            .entity(e)
+            .insert(materials.add(make_standard_material(material, &asset_server)));
+    }
+
+    // Reinserting the primitive regenerates it with the default material
+    for e in removed_materials.read() {
+        if let Ok(primitive) = primitives.get(e) {
+            commands.entity(e).insert(primitive.clone());
+        }
+    }
+}
@@ -17,12 +17,14 @@
 
 use bevy::ecs::system::SystemState;
 use bevy::prelude::*;
-use std::collections::BTreeMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
 
+use crate::workcell::include_resolver;
 use crate::workcell::urdf_package_exporter::{generate_package, PackageContext, Person};
-use crate::ExportFormat;
+use crate::workspace::XacroSettings;
 use crate::{CollisionMeshMarker, VisualMeshMarker};
+use crate::{DefaultFile, ExportFormat};
 
 use thiserror::Error as ThisError;
 
@@ -42,15 +44,30 @@ pub enum WorkcellGenerationError {
     InvalidWorkcellEntity(Entity),
 }
 
-fn parent_in_workcell(q_parents: &Query<&Parent>, entity: Entity, root: Entity) -> bool {
-    AncestorIter::new(q_parents, entity).any(|p| p == root)
+/// Elements of included workcells are saved in their own file and are not considered part of
+/// the workcell.
+pub(crate) fn parent_in_workcell(
+    q_parents: &Query<&Parent>,
+    q_includes: &Query<(), With<IncludedWorkcellMarker>>,
+    entity: Entity,
+    root: Entity,
+) -> bool {
+    for p in AncestorIter::new(q_parents, entity) {
+        if p == root {
+            return true;
+        }
+        if q_includes.get(p).is_ok() {
+            return false;
+        }
+    }
+    false
 }
 
 // This is mostly duplicated with the function in site/save.rs, however this case
-// is a lot simpler, also site/save.rs checks for children of levels but there are no levels here
+// is a lot simpler, also site/save.rs checks for children of levels but there are no levels here.
+// Entities keep the ids they were loaded with so saved files only change where the workcell
+// was edited, new entities get ids above the ones currently in use.
 fn assign_site_ids(world: &mut World, workcell: Entity) {
-    // TODO(luca) actually keep site IDs instead of always generating them from scratch
-    // (as it is done in site editor)
     let mut state: SystemState<(
         Query<
             Entity,
@@ -61,25 +78,39 @@ fn assign_site_ids(world: &mut World, workcell: Entity) {
                     With<Moment>,
                     With<VisualMeshMarker>,
                     With<CollisionMeshMarker>,
+                    With<IncludedWorkcellMarker>,
+                    With<SensorMarker>,
                 )>,
                 Without<Pending>,
             ),
         >,
         Query<&Children>,
+        Query<&SiteID>,
+        Query<&Parent>,
+        Query<(), With<IncludedWorkcellMarker>>,
     )> = SystemState::new(world);
-    let (q_used_entities, q_children) = state.get(world);
+    let (q_used_entities, q_children, q_site_ids, q_parents, q_includes) = state.get(world);
 
-    let mut new_entities = vec![workcell];
-    for e in q_children.iter_descendants(workcell) {
-        if q_used_entities.get(e).is_ok() {
-            new_entities.push(e);
+    let mut used_ids = HashSet::new();
+    let mut new_entities = Vec::new();
+    let entities = std::iter::once(workcell).chain(
+        q_children
+            .iter_descendants(workcell)
+            .filter(|e| q_used_entities.get(*e).is_ok())
+            .filter(|e| parent_in_workcell(&q_parents, &q_includes, *e, workcell)),
+    );
+    for e in entities {
+        match q_site_ids.get(e) {
+            // Duplicated entities might have copied the id of the original one
+            Ok(id) if used_ids.insert(id.0) => {}
+            _ => new_entities.push(e),
         }
     }
 
-    for (idx, entity) in new_entities.iter().enumerate() {
-        world
-            .entity_mut(*entity)
-            .insert(SiteID(idx.try_into().unwrap()));
+    let mut next_id = used_ids.iter().max().map(|id| id + 1).unwrap_or_default();
+    for entity in new_entities {
+        world.entity_mut(entity).insert(SiteID(next_id));
+        next_id += 1;
     }
 }
 
@@ -101,42 +132,92 @@ pub fn generate_workcell(
                 &SiteID,
                 &Parent,
                 Option<&Scale>,
+                Option<&MaterialRef>,
             ),
             (
                 Or<(With<VisualMeshMarker>, With<CollisionMeshMarker>)>,
                 Without<Pending>,
             ),
         >,
-        Query<(Entity, &JointProperties, &NameInWorkcell, &SiteID, &Parent), Without<Pending>>,
+        Query<
+            (
+                Entity,
+                &JointProperties,
+                Option<&JointActuation>,
+                &NameInWorkcell,
+                &SiteID,
+                &Parent,
+            ),
+            Without<Pending>,
+        >,
+        Query<
+            (
+                Entity,
+                &NameInWorkcell,
+                &Pose,
+                &AssetSource,
+                &SiteID,
+                &Parent,
+            ),
+            (With<IncludedWorkcellMarker>, Without<Pending>),
+        >,
+        Query<
+            (
+                Entity,
+                &NameInWorkcell,
+                &Pose,
+                &SensorProperties,
+                &SiteID,
+                &Parent,
+            ),
+            (With<SensorMarker>, Without<Pending>),
+        >,
         Query<&VisualMeshMarker>,
         Query<&CollisionMeshMarker>,
         Query<&SiteID>,
-        Query<&NameOfWorkcell>,
+        Query<(
+            &NameOfWorkcell,
+            Option<&ControlHardware>,
+            Option<&PlanningSemantics>,
+        )>,
+        Query<&MaterialLibrary>,
         Query<&Parent>,
+        Query<(), With<IncludedWorkcellMarker>>,
     )> = SystemState::new(world);
     let (
         q_anchors,
         q_inertials,
         q_models,
         q_joints,
+        q_includes,
+        q_sensors,
         q_visuals,
         q_collisions,
         q_site_id,
         q_properties,
+        q_materials,
         q_parents,
+        q_include_markers,
     ) = state.get(world);
 
     let mut workcell = Workcell::default();
     match q_properties.get(root) {
-        Ok(name) => workcell.properties.name = name.clone(),
+        Ok((name, control_hardware, planning)) => {
+            workcell.properties.name = name.clone();
+            workcell.properties.control_hardware = control_hardware.cloned().unwrap_or_default();
+            workcell.properties.planning = planning.cloned().unwrap_or_default();
+        }
         Err(_) => {
             return Err(WorkcellGenerationError::InvalidWorkcellEntity(root));
         }
     }
+    if let Ok(materials) = q_materials.get(root) {
+        workcell.materials = materials.clone();
+    }
 
     // Visuals
-    for (e, name, source, primitive, pose, id, parent, scale) in &q_models {
-        if !parent_in_workcell(&q_parents, e, root) {
+    for (e, name, source, primitive, pose, id, parent, scale, material) in &q_models {
+        if !parent_in_workcell(&q_parents, &q_include_markers, e, root) {
             continue;
         }
         // Get the parent SiteID
@@ -168,6 +249,7 @@ pub fn generate_workcell(
                         name: name.0.clone(),
                         geometry: geom,
                         pose: *pose,
+                        material: material.cloned(),
                     },
                 },
             );
@@ -181,7 +263,7 @@ pub fn generate_workcell(
 
     // Anchors
     for (e, anchor, name, id, parent) in &q_anchors {
-        if !parent_in_workcell(&q_parents, e, root) {
+        if !parent_in_workcell(&q_parents, &q_include_markers, e, root) {
             continue;
         }
         let parent = match q_site_id.get(parent.get()) {
@@ -205,7 +287,7 @@ pub fn generate_workcell(
     }
 
     for (e, pose, mass, moment, id, parent) in &q_inertials {
-        if !parent_in_workcell(&q_parents, e, root) {
+        if !parent_in_workcell(&q_parents, &q_include_markers, e, root) {
             continue;
         }
         let parent = match q_site_id.get(parent.get()) {
@@ -229,8 +311,8 @@ pub fn generate_workcell(
         );
     }
 
-    for (e, properties, name, id, parent) in &q_joints {
-        if !parent_in_workcell(&q_parents, e, root) {
+    for (e, properties, actuation, name, id, parent) in &q_joints {
+        if !parent_in_workcell(&q_parents, &q_include_markers, e, root) {
             continue;
         }
         let parent = match q_site_id.get(parent.get()) {
@@ -248,6 +330,59 @@ pub fn generate_workcell(
                 bundle: Joint {
                     name: name.clone(),
                     properties: properties.clone(),
+                    actuation: actuation.cloned().unwrap_or_default(),
+                },
+            },
+        );
+    }
+
+    for (e, name, pose, source, id, parent) in &q_includes {
+        if !parent_in_workcell(&q_parents, &q_include_markers, e, root) {
+            continue;
+        }
+        let parent = match q_site_id.get(parent.get()) {
+            Ok(parent) => parent.0,
+            Err(_) => {
+                error!("Parent not found for included workcell {:?}", parent.get());
+                continue;
+            }
+        };
+
+        workcell.includes.insert(
+            id.0,
+            Parented {
+                parent,
+                bundle: IncludedWorkcell {
+                    name: name.clone(),
+                    pose: *pose,
+                    source: source.clone(),
+                    marker: IncludedWorkcellMarker,
+                },
+            },
+        );
+    }
+
+    for (e, name, pose, properties, id, parent) in &q_sensors {
+        if !parent_in_workcell(&q_parents, &q_include_markers, e, root) {
+            continue;
+        }
+        let parent = match q_site_id.get(parent.get()) {
+            Ok(parent) => parent.0,
+            Err(_) => {
+                error!("Parent not found for sensor {:?}", parent.get());
+                continue;
+            }
+        };
+
+        workcell.sensors.insert(
+            id.0,
+            Parented {
+                parent,
+                bundle: Sensor {
+                    name: name.clone(),
+                    pose: *pose,
+                    properties: properties.clone(),
+                    marker: SensorMarker,
                 },
             },
         );
@@ -294,7 +429,20 @@ pub fn save_workcell(world: &mut World) {
                 }
             }
             ExportFormat::Urdf => {
-                match export_package(&path, workcell) {
+                // Urdf has no concept of includes, their content is copied in the package
+                let dir = world
+                    .get::<DefaultFile>(save_event.root)
+                    .and_then(|file| file.0.parent().map(Path::to_owned));
+                let resolver = include_resolver(world.resource::<XacroSettings>());
+                let workcell = match workcell.flatten_includes(&resolver, dir.as_deref()) {
+                    Ok(workcell) => workcell,
+                    Err(err) => {
+                        error!("Failed to export package: {err}");
+                        continue;
+                    }
+                };
+                let options = world.resource::<UrdfExportOptions>().clone();
+                match export_package(&path, workcell, &options) {
                     Ok(()) => {
                         info!("Successfully exported package");
                     }
@@ -310,6 +458,7 @@ pub fn save_workcell(world: &mut World) {
 fn export_package(
     output_directory: &PathBuf,
     workcell: Workcell,
+    options: &UrdfExportOptions,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let package_context = PackageContext {
         license: "TODO".to_string(),
@@ -318,13 +467,16 @@ fn export_package(
             email: "todo@todo.com".to_string(),
         }],
         project_name: workcell.properties.name.0.clone() + "_description",
-        fixed_frame: "world".to_string(),
+        fixed_frame: workcell.urdf_root_link(options),
         dependencies: vec![],
         project_description: "TODO".to_string(),
         project_version: "0.0.1".to_string(),
         urdf_file_name: "robot.urdf".to_string(),
+        has_controllers: workcell.to_ros2_control().is_some(),
+        has_sensors: !workcell.sensors.is_empty(),
+        has_srdf: !workcell.properties.planning.is_empty(),
     };
 
-    generate_package(workcell, package_context, output_directory)?;
+    generate_package(workcell, package_context, options, output_directory)?;
     Ok(())
 }
@@ -0,0 +1,133 @@
+/*
+ * Copyright (C) 2024 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::Dependents;
+use bevy::prelude::*;
+use rmf_workcell_format::{
+    FrameMarker, LidarScan, NameInWorkcell, Pose, Sensor, SensorKind, SensorMarker,
+    SensorProperties,
+};
+
+/// Distance at which camera frustums and lidar scans are drawn, in meters
+const SENSOR_DISPLAY_LENGTH: f32 = 0.2;
+const SENSOR_COLOR: Color = Color::YELLOW;
+
+/// Event used to request adding a sensor to a frame
+#[derive(Event)]
+pub struct CreateSensor {
+    pub frame: Entity,
+    pub properties: SensorProperties,
+}
+
+pub fn handle_create_sensor_events(
+    mut commands: Commands,
+    mut events: EventReader<CreateSensor>,
+    mut dependents: Query<&mut Dependents>,
+    frames: Query<(), With<FrameMarker>>,
+    sensors: Query<&NameInWorkcell, With<SensorMarker>>,
+) {
+    let mut created = Vec::new();
+    for req in events.read() {
+        if frames.get(req.frame).is_err() {
+            error!(
+                "Requested to add a sensor to an entity that is not a frame, \
+                   this is not valid and will be ignored"
+            );
+            continue;
+        }
+        // Sensor names must be unique in the workcell
+        let base = req.properties.kind.label().to_lowercase().replace(' ', "_");
+        let mut name = base.clone();
+        let mut idx = 1;
+        while created.contains(&name) || sensors.iter().any(|n| n.0 == name) {
+            name = format!("{}_{}", base, idx);
+            idx += 1;
+        }
+        let e = commands
+            .spawn(SpatialBundle::INHERITED_IDENTITY)
+            .insert(Sensor::new(&name, req.properties.clone(), Pose::default()))
+            .set_parent(req.frame)
+            .id();
+        if let Ok(mut deps) = dependents.get_mut(req.frame) {
+            deps.insert(e);
+        }
+        created.push(name);
+    }
+}
+
+/// Points of a lidar scan drawn at `radius`, `direction` maps an angle to a unit vector.
+fn scan_arc(scan: &LidarScan, radius: f32, direction: impl Fn(f32) -> Vec3) -> Vec<Vec3> {
+    const SEGMENTS: u32 = 32;
+    let [start, end] = scan.angles;
+    (0..=SEGMENTS)
+        .map(|i| direction(start + (end - start) * i as f32 / SEGMENTS as f32) * radius)
+        .collect()
+}
+
+/// Draws camera frustums, lidar scans and force torque sensors.
+pub fn draw_sensors(
+    mut gizmos: Gizmos,
+    sensors: Query<(&SensorProperties, &GlobalTransform, &InheritedVisibility), With<SensorMarker>>,
+) {
+    for (properties, tf, visibility) in &sensors {
+        if !visibility.get() {
+            continue;
+        }
+        let tf = tf.affine();
+        let origin = tf.transform_point3(Vec3::ZERO);
+        match &properties.kind {
+            SensorKind::Camera(camera) | SensorKind::DepthCamera(camera) => {
+                let corners = camera
+                    .frustum_corners(SENSOR_DISPLAY_LENGTH.min(camera.clip[1]))
+                    .map(|corner| tf.transform_point3(corner));
+                for corner in corners {
+                    gizmos.line(origin, corner, SENSOR_COLOR);
+                }
+                gizmos.linestrip(corners.into_iter().chain([corners[0]]), SENSOR_COLOR);
+            }
+            SensorKind::Lidar(lidar) => {
+                let radius = SENSOR_DISPLAY_LENGTH.min(lidar.range[1]);
+                let mut arcs = vec![scan_arc(&lidar.horizontal, radius, |a| {
+                    Vec3::new(a.cos(), a.sin(), 0.0)
+                })];
+                if let Some(vertical) = &lidar.vertical {
+                    arcs.push(scan_arc(vertical, radius, |a| {
+                        Vec3::new(a.cos(), 0.0, a.sin())
+                    }));
+                }
+                for arc in arcs {
+                    let points = arc.into_iter().map(|p| tf.transform_point3(p));
+                    gizmos.linestrip(
+                        std::iter::once(origin)
+                            .chain(points)
+                            .chain(std::iter::once(origin)),
+                        SENSOR_COLOR,
+                    );
+                }
+            }
+            SensorKind::ForceTorque(_) => {
+                let axis = tf.transform_vector3(Vec3::Z).normalize_or_zero();
+                gizmos.circle(origin, axis, SENSOR_DISPLAY_LENGTH / 4.0, SENSOR_COLOR);
+                gizmos.line(
+                    origin,
+                    origin + axis * SENSOR_DISPLAY_LENGTH / 2.0,
+                    SENSOR_COLOR,
+                );
+            }
+        }
+    }
+}
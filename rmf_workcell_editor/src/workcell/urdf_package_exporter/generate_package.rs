@@ -1,52 +1,111 @@
 use crate::site_asset_io::cache_path;
 use crate::workcell::urdf_package_exporter::template::PackageContext;
-use rmf_workcell_format::{AssetSource, Geometry, Workcell};
+use bevy::log::warn;
+use rmf_workcell_format::{AssetSource, Geometry, UrdfExportOptions, Workcell};
 use std::error::Error;
 use std::io::{Error as IoError, ErrorKind as IoErrorKind};
 use std::path::{Path, PathBuf};
 use tera::Tera;
 
+/// Update rate, in Hz, of the controller manager in the generated controllers configuration.
+const CONTROLLER_UPDATE_RATE: u32 = 100;
+
 pub fn generate_package(
     workcell: Workcell,
     package_context: PackageContext,
+    options: &UrdfExportOptions,
     output_directory_path: &Path,
 ) -> Result<(), Box<dyn Error>> {
     let new_package_name = &package_context.project_name;
-
     let output_package_path = output_directory_path.join(new_package_name);
-    std::fs::create_dir_all(&output_package_path)?;
 
-    // Create the package
-    write_urdf_and_copy_mesh_files(workcell, new_package_name, &output_package_path)?;
-    generate_templates(package_context, &output_package_path)?;
+    // Everything is generated before writing, a failure doesn't leave a partial package behind
+    let mut files = generate_urdf_and_config_files(workcell, new_package_name, options)?;
+    files.extend(generate_templates(package_context)?);
+
+    // The meshes directory is installed by the package even if the workcell has no meshes
+    std::fs::create_dir_all(output_package_path.join("meshes"))?;
+    for file in files {
+        let path = output_package_path.join(&file.path);
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory)?;
+        }
+        match file.content {
+            FileContent::Text(text) => std::fs::write(path, text)?,
+            FileContent::Copy(source) => {
+                std::fs::copy(source, path)?;
+            }
+        }
+    }
 
     Ok(())
 }
 
-fn write_urdf_and_copy_mesh_files(
+/// A file of the package, with its path relative to the package directory.
+struct PackageFile {
+    path: PathBuf,
+    content: FileContent,
+}
+
+enum FileContent {
+    Text(String),
+    /// Copied from the given path
+    Copy(PathBuf),
+}
+
+impl PackageFile {
+    fn text(path: impl Into<PathBuf>, text: String) -> Self {
+        Self {
+            path: path.into(),
+            content: FileContent::Text(text),
+        }
+    }
+}
+
+fn generate_urdf_and_config_files(
     mut workcell: Workcell,
     new_package_name: &str,
-    output_package_path: &Path,
-) -> Result<(), Box<dyn Error>> {
-    convert_and_copy_meshes(&mut workcell, new_package_name, output_package_path)?;
+    options: &UrdfExportOptions,
+) -> Result<Vec<PackageFile>, Box<dyn Error>> {
+    let mut files = convert_meshes(&mut workcell, new_package_name)?;
 
-    let urdf_robot = workcell.to_urdf()?;
-    let urdf_directory_path = output_package_path.join("urdf");
-    std::fs::create_dir_all(&urdf_directory_path)?;
-    let urdf_file_path = urdf_directory_path.join("robot.urdf");
-    let urdf_string = urdf_rs::write_to_string(&urdf_robot)?;
-    std::fs::write(urdf_file_path, urdf_string)?;
+    let (urdf_string, warnings) = workcell.to_urdf_string_with_warnings(options)?;
+    for warning in warnings {
+        warn!("Exporting urdf: {warning}");
+    }
+    files.push(PackageFile::text("urdf/robot.urdf", urdf_string));
+
+    let config_directory_path = Path::new("config");
+    if let Some(controllers) = workcell.ros2_controllers_yaml(CONTROLLER_UPDATE_RATE)? {
+        files.push(PackageFile::text(
+            config_directory_path.join("controllers.yaml"),
+            controllers,
+        ));
+    }
+    if let Some(sensors) = workcell.sensors_yaml()? {
+        files.push(PackageFile::text(
+            config_directory_path.join("sensors.yaml"),
+            sensors,
+        ));
+    }
+    if !workcell.properties.planning.is_empty() {
+        let srdf = workcell.to_srdf_with_options(options)?;
+        let srdf_file_name = format!("{}.srdf", workcell.properties.name.0);
+        files.push(PackageFile::text(
+            config_directory_path.join(srdf_file_name),
+            srdf,
+        ));
+    }
 
-    Ok(())
+    Ok(files)
 }
 
-fn convert_and_copy_meshes(
+/// Points the meshes of the workcell to their copy in the package and returns the copies.
+fn convert_meshes(
     workcell: &mut Workcell,
     package_name: &str,
-    output_package_path: &Path,
-) -> Result<(), Box<dyn Error>> {
-    let meshes_directory_path = output_package_path.join("meshes");
-    std::fs::create_dir_all(&meshes_directory_path)?;
+) -> Result<Vec<PackageFile>, Box<dyn Error>> {
+    let mut files = Vec::new();
     for (_, model) in &mut workcell
         .visuals
         .iter_mut()
@@ -71,12 +130,21 @@ fn convert_and_copy_meshes(
                     "Unable to convert file name to str",
                 ))?;
 
-            std::fs::copy(&path, meshes_directory_path.join(file_name))?;
+            if !path.is_file() {
+                return Err(IoError::new(
+                    IoErrorKind::NotFound,
+                    format!("Mesh file {} not found", path.display()),
+                ))?;
+            }
+            files.push(PackageFile {
+                path: Path::new("meshes").join(file_name),
+                content: FileContent::Copy(path.clone()),
+            });
             let package_path = format!("{}/meshes/{}", package_name, file_name);
             *asset_source = AssetSource::Package(package_path);
         }
     }
-    Ok(())
+    Ok(files)
 }
 
 fn get_path_to_asset_file(asset_source: &AssetSource) -> Result<PathBuf, Box<dyn Error>> {
@@ -100,10 +168,7 @@ fn get_path_to_asset_file(asset_source: &AssetSource) -> Result<PathBuf, Box<dyn
     }
 }
 
-fn generate_templates(
-    package_context: PackageContext,
-    package_directory: &Path,
-) -> Result<(), Box<dyn Error>> {
+fn generate_templates(package_context: PackageContext) -> Result<Vec<PackageFile>, Box<dyn Error>> {
     let context = tera::Context::from_serialize(package_context)?;
     let mut tera = Tera::default();
     tera.add_raw_template("package.xml", include_str!("templates/package.xml.j2"))?;
@@ -116,21 +181,15 @@ fn generate_templates(
         "display.launch.py",
         include_str!("templates/display.launch.py.j2"),
     )?;
-    let f = std::fs::File::create(package_directory.join("package.xml"))?;
-    tera.render_to("package.xml", &context, f)?;
-
-    let f = std::fs::File::create(package_directory.join("CMakeLists.txt"))?;
-    tera.render_to("CMakeLists.txt", &context, f)?;
-
-    let rviz_directory = package_directory.join("rviz");
-    std::fs::create_dir_all(&rviz_directory)?;
-    let f = std::fs::File::create(rviz_directory.join("urdf.rviz"))?;
-    tera.render_to("urdf.rviz", &context, f)?;
-
-    let launch_directory = package_directory.join("launch");
-    std::fs::create_dir_all(&launch_directory)?;
-    let f = std::fs::File::create(launch_directory.join("display.launch.py"))?;
-    tera.render_to("display.launch.py", &context, f)?;
-
-    Ok(())
+    [
+        ("package.xml", "package.xml"),
+        ("CMakeLists.txt", "CMakeLists.txt"),
+        ("urdf.rviz", "rviz/urdf.rviz"),
+        ("display.launch.py", "launch/display.launch.py"),
+    ]
+    .into_iter()
+    .map(|(template, path)| -> Result<_, Box<dyn Error>> {
+        Ok(PackageFile::text(path, tera.render(template, &context)?))
+    })
+    .collect()
 }
@@ -11,6 +11,12 @@ pub struct PackageContext {
     pub dependencies: Vec<String>,
     pub fixed_frame: String,
     pub urdf_file_name: String,
+    /// Whether the package contains a ros2_control configuration for its joints.
+    pub has_controllers: bool,
+    /// Whether the package contains a list of the sensors of the workcell.
+    pub has_sensors: bool,
+    /// Whether the package contains an srdf with the planning semantics of the workcell.
+    pub has_srdf: bool,
 }
 
 #[derive(Debug, Serialize)]
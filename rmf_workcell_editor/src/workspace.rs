@@ -21,7 +21,7 @@ use std::path::PathBuf;
 
 use crate::workcell::{LoadWorkcell, SaveWorkcell};
 use crate::AppState;
-use rmf_workcell_format::Workcell;
+use rmf_workcell_format::{Workcell, XacroProcessor};
 
 use crate::{
     interaction::InteractionState, ChangeCurrentWorkspace, CreateNewWorkspace, CurrentWorkspace,
@@ -32,6 +32,8 @@ use crate::{
 pub enum WorkspaceData {
     Workcell(Vec<u8>),
     WorkcellUrdf(Vec<u8>),
+    WorkcellSdf(Vec<u8>),
+    WorkcellXacro(Vec<u8>),
 }
 
 impl WorkspaceData {
@@ -41,6 +43,10 @@ impl WorkspaceData {
             Some(WorkspaceData::Workcell(data))
         } else if filename.ends_with("urdf") {
             Some(WorkspaceData::WorkcellUrdf(data))
+        } else if filename.ends_with("xacro") {
+            Some(WorkspaceData::WorkcellXacro(data))
+        } else if filename.ends_with("sdf") {
+            Some(WorkspaceData::WorkcellSdf(data))
         } else {
             error!("Unrecognized file type {:?}", filename);
             None
@@ -50,6 +56,10 @@ impl WorkspaceData {
 
 pub struct LoadWorkspaceFile(pub Option<PathBuf>, pub WorkspaceData);
 
+/// Argument overrides and package locations used when expanding xacro files.
+#[derive(Resource, Clone, Default)]
+pub struct XacroSettings(pub XacroProcessor);
+
 #[derive(Clone, Default, Debug)]
 pub enum ExportFormat {
     #[default]
@@ -67,6 +77,7 @@ impl Plugin for WorkspacePlugin {
             .add_event::<LoadWorkcell>()
             .init_resource::<CurrentWorkspace>()
             .init_resource::<RecallWorkspace>()
+            .init_resource::<XacroSettings>()
             .init_resource::<FileDialogServices>()
             .init_resource::<WorkspaceLoadingServices>()
             .init_resource::<WorkspaceSavingServices>()
@@ -105,6 +116,7 @@ pub fn process_load_workspace_files(
     mut app_state: ResMut<NextState<AppState>>,
     mut interaction_state: ResMut<NextState<InteractionState>>,
     mut load_workcell: EventWriter<LoadWorkcell>,
+    xacro_settings: Res<XacroSettings>,
 ) {
     let LoadWorkspaceFile(default_file, data) = request;
     match data {
@@ -122,7 +134,7 @@ pub fn process_load_workspace_files(
                     interaction_state.set(InteractionState::Enable);
                 }
                 Err(err) => {
-                    error!("Failed loading workcell {:?}", err);
+                    error!("Failed loading workcell: {err}");
                 }
             }
         }
@@ -155,6 +167,59 @@ pub fn process_load_workspace_files(
                 }
             }
         }
+        WorkspaceData::WorkcellXacro(data) => {
+            info!("Importing xacro workcell");
+            // Packages next to the file are found automatically, explicit settings take priority
+            let mut xacro = match &default_file {
+                Some(path) => XacroProcessor::default().with_packages_near(path),
+                None => XacroProcessor::default(),
+            };
+            xacro.args.extend(xacro_settings.0.args.clone());
+            xacro.packages.extend(xacro_settings.0.packages.clone());
+            let dir = default_file.as_ref().and_then(|path| path.parent());
+            match xacro.read_urdf(&data, dir) {
+                Ok(urdf) => match Workcell::from_urdf(&urdf) {
+                    Ok(workcell) => {
+                        // Switch state
+                        app_state.set(AppState::WorkcellEditor);
+                        load_workcell.send(LoadWorkcell {
+                            workcell,
+                            focus: true,
+                            // Saving must not overwrite the xacro file with a workcell
+                            default_file: None,
+                        });
+                        interaction_state.set(InteractionState::Enable);
+                    }
+                    Err(err) => {
+                        error!("Failed converting xacro to workcell {:?}", err);
+                    }
+                },
+                Err(err) => {
+                    error!("Failed expanding xacro workcell: {err}");
+                }
+            }
+        }
+        WorkspaceData::WorkcellSdf(data) => {
+            info!("Importing sdf workcell");
+            // Relative paths of included models and meshes are resolved from the file directory
+            let dir = default_file.as_ref().and_then(|path| path.parent());
+            match Workcell::from_sdf_bytes(&data, dir) {
+                Ok(workcell) => {
+                    // Switch state
+                    app_state.set(AppState::WorkcellEditor);
+                    load_workcell.send(LoadWorkcell {
+                        workcell,
+                        focus: true,
+                        // Saving must not overwrite the sdf file with a workcell
+                        default_file: None,
+                    });
+                    interaction_state.set(InteractionState::Enable);
+                }
+                Err(err) => {
+                    error!("Failed loading sdf workcell: {err}");
+                }
+            }
+        }
     }
 }
 
@@ -183,6 +248,14 @@ impl FromWorld for WorkspaceLoadingServices {
                 name: "Urdf".into(),
                 extensions: vec!["urdf".into()],
             },
+            FileDialogFilter {
+                name: "Xacro".into(),
+                extensions: vec!["xacro".into()],
+            },
+            FileDialogFilter {
+                name: "Sdf".into(),
+                extensions: vec!["sdf".into()],
+            },
         ];
         let load_workspace_from_dialog = world.spawn_workflow(|scope, builder| {
             scope
@@ -0,0 +1,132 @@
+/*
+ * Copyright (C) 2024 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+//! Compares or merges workcell files.
+//!
+//! `workcell_diff <old> <new>` prints the semantic differences between two workcells and exits
+//! with 1 if there are any.
+//!
+//! `workcell_diff merge <base> <ours> <theirs> [-o <output>]` merges two versions of a workcell
+//! and writes the result to `output`, or to `ours` if not specified, so that it can be used as a
+//! git merge driver:
+//!
+//! ```text
+//! # .gitattributes
+//! *.workcell.json merge=workcell
+//! # .git/config
+//! [merge "workcell"]
+//!     driver = workcell_diff merge %O %A %B
+//! ```
+//!
+//! Conflicts are resolved in favor of `ours`, reported on stderr and make the command exit
+//! with 1.
+
+use rmf_workcell_format::{DiffOptions, IncludeResolver, Workcell};
+use std::path::Path;
+use std::process::ExitCode;
+
+const USAGE: &str = "Usage:
+  workcell_diff <old> <new>
+  workcell_diff merge <base> <ours> <theirs> [-o <output>]";
+
+fn load(path: &str) -> Result<Workcell, String> {
+    IncludeResolver::default()
+        .read_workcell(Path::new(path))
+        .map_err(|e| format!("Failed loading [{path}]: {e}"))
+}
+
+/// Loads a workcell json file regardless of its extension, git passes temporary files without
+/// one to merge drivers.
+fn load_json(path: &str) -> Result<Workcell, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed reading [{path}]: {e}"))?;
+    Workcell::from_bytes(&bytes).map_err(|e| format!("Failed loading [{path}]: {e}"))
+}
+
+fn diff(old: &str, new: &str) -> Result<ExitCode, String> {
+    let changes = load(old)?.diff(&load(new)?, &DiffOptions::default());
+    for change in &changes {
+        println!("{change}");
+    }
+    Ok(if changes.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+fn merge(base: &str, ours: &str, theirs: &str, output: &str) -> Result<ExitCode, String> {
+    let merge = Workcell::merge(
+        &load_json(base)?,
+        &load_json(ours)?,
+        &load_json(theirs)?,
+        &DiffOptions::default(),
+    );
+    let json = merge
+        .workcell
+        .to_string()
+        .map_err(|e| format!("Failed serializing the merged workcell: {e}"))?;
+    std::fs::write(output, json).map_err(|e| format!("Failed writing [{output}]: {e}"))?;
+    for conflict in &merge.conflicts {
+        eprintln!("Conflict: {conflict}");
+    }
+    Ok(if merge.conflicts.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+fn run(args: &[String]) -> Result<ExitCode, String> {
+    let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+    match args.as_slice() {
+        ["merge", base, ours, theirs] => merge(base, ours, theirs, ours),
+        ["merge", base, ours, theirs, "-o", output] => merge(base, ours, theirs, output),
+        [old, new] if *old != "merge" => diff(old, new),
+        _ => Err(USAGE.to_owned()),
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_inputs_without_extension_are_loaded() {
+        let dir = std::env::temp_dir().join(format!("workcell_diff_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("merge_base");
+        let workcell = Workcell::default();
+        std::fs::write(&path, workcell.to_string().unwrap()).unwrap();
+        let path = path.to_str().unwrap();
+        let loaded = load_json(path);
+        let by_extension = load(path);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(loaded.unwrap().properties.name, workcell.properties.name);
+        assert!(by_extension.is_err());
+    }
+}
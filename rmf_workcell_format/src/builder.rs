@@ -0,0 +1,384 @@
+/*
+ * Copyright (C) 2024 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+//! Programmatic construction of workcells with typed handles instead of raw ids.
+//!
+//! ```
+//! # use rmf_workcell_format::*;
+//! let mut builder = WorkcellBuilder::new("cell");
+//! let table = builder.add_frame(builder.root(), "table", Pose::default());
+//! let plate = builder.add_frame(table, "plate", Pose::default());
+//! builder.add_joint(table, plate, "plate_joint", JointProperties::Fixed);
+//! let workcell = builder.build().unwrap();
+//! assert_eq!(workcell.frames.len(), 2);
+//! ```
+
+use crate::*;
+use rmf_site_format::{Anchor, AssetSource, Pose};
+use thiserror::Error as ThisError;
+
+macro_rules! element_id {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub struct $name(u32);
+
+        impl $name {
+            /// The id of the element in the built workcell.
+            pub fn id(&self) -> u32 {
+                self.0
+            }
+        }
+    };
+}
+
+element_id!(
+    /// The workcell itself, the root of the hierarchy.
+    WorkcellRootId
+);
+element_id!(FrameId);
+element_id!(JointId);
+element_id!(VisualId);
+element_id!(CollisionId);
+element_id!(InertiaId);
+element_id!(IncludeId);
+element_id!(SensorId);
+
+/// Elements that frames can be attached to.
+pub trait FrameParent: Copy {
+    fn parent_id(&self) -> u32;
+}
+
+impl FrameParent for WorkcellRootId {
+    fn parent_id(&self) -> u32 {
+        self.0
+    }
+}
+
+impl FrameParent for FrameId {
+    fn parent_id(&self) -> u32 {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, ThisError)]
+#[error("the built workcell is not valid: {0:?}")]
+pub struct WorkcellBuildError(pub Vec<WorkcellDiagnostic>);
+
+/// Builds a [`Workcell`], allocating ids for its elements. The handles returned when adding
+/// elements only allow the parent kinds supported by the format, other issues such as duplicated
+/// names are reported by [`WorkcellBuilder::build`].
+#[derive(Debug, Clone)]
+pub struct WorkcellBuilder {
+    workcell: Workcell,
+    next_id: u32,
+}
+
+fn parented<T>(parent: u32, bundle: T) -> Parented<u32, T> {
+    Parented { parent, bundle }
+}
+
+impl WorkcellBuilder {
+    pub fn new(name: &str) -> Self {
+        let mut workcell = Workcell::default();
+        workcell.properties.name = NameOfWorkcell(name.to_owned());
+        Self {
+            next_id: workcell.id + 1,
+            workcell,
+        }
+    }
+
+    fn allocate(&mut self) -> u32 {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+
+    pub fn root(&self) -> WorkcellRootId {
+        WorkcellRootId(self.workcell.id)
+    }
+
+    /// Adds a frame, its pose is relative to its parent.
+    pub fn add_frame(&mut self, parent: impl FrameParent, name: &str, pose: Pose) -> FrameId {
+        let id = self.allocate();
+        self.workcell.frames.insert(
+            id,
+            parented(
+                parent.parent_id(),
+                Frame {
+                    anchor: Anchor::Pose3D(pose),
+                    name: NameInWorkcell(name.to_owned()),
+                    marker: FrameMarker,
+                },
+            ),
+        );
+        FrameId(id)
+    }
+
+    /// Adds a joint that moves `child` relative to `parent`. The pose of `child` is used as the
+    /// origin of the joint and is interpreted relative to `parent`, as if `child` was a child of
+    /// `parent`.
+    pub fn add_joint(
+        &mut self,
+        parent: FrameId,
+        child: FrameId,
+        name: &str,
+        properties: JointProperties,
+    ) -> JointId {
+        let id = self.allocate();
+        self.workcell.joints.insert(
+            id,
+            parented(
+                parent.0,
+                Joint {
+                    name: NameInWorkcell(name.to_owned()),
+                    properties,
+                    actuation: Default::default(),
+                },
+            ),
+        );
+        if let Some(child) = self.workcell.frames.get_mut(&child.0) {
+            child.parent = id;
+        }
+        JointId(id)
+    }
+
+    /// Adds a visual to a frame, its pose is relative to the frame.
+    pub fn add_visual(
+        &mut self,
+        frame: FrameId,
+        name: &str,
+        geometry: Geometry,
+        pose: Pose,
+    ) -> VisualId {
+        let id = self.allocate();
+        self.workcell
+            .visuals
+            .insert(id, parented(frame.0, model(name, geometry, pose)));
+        VisualId(id)
+    }
+
+    /// Sets the material of a visual, see [`WorkcellBuilder::add_material`] to refer to a named
+    /// material.
+    pub fn set_visual_material(&mut self, visual: VisualId, material: MaterialRef) {
+        if let Some(visual) = self.workcell.visuals.get_mut(&visual.0) {
+            visual.bundle.material = Some(material);
+        }
+    }
+
+    /// Adds a collision to a frame, its pose is relative to the frame.
+    pub fn add_collision(
+        &mut self,
+        frame: FrameId,
+        name: &str,
+        geometry: Geometry,
+        pose: Pose,
+    ) -> CollisionId {
+        let id = self.allocate();
+        self.workcell
+            .collisions
+            .insert(id, parented(frame.0, model(name, geometry, pose)));
+        CollisionId(id)
+    }
+
+    pub fn add_inertia(&mut self, frame: FrameId, inertia: Inertia) -> InertiaId {
+        let id = self.allocate();
+        self.workcell
+            .inertias
+            .insert(id, parented(frame.0, inertia));
+        InertiaId(id)
+    }
+
+    /// Adds a sensor to a frame, its pose is relative to the frame.
+    pub fn add_sensor(
+        &mut self,
+        frame: FrameId,
+        name: &str,
+        properties: SensorProperties,
+        pose: Pose,
+    ) -> SensorId {
+        let id = self.allocate();
+        self.workcell
+            .sensors
+            .insert(id, parented(frame.0, Sensor::new(name, properties, pose)));
+        SensorId(id)
+    }
+
+    /// Includes a workcell from another file, see [`Workcell::flatten_includes`].
+    pub fn add_include(
+        &mut self,
+        frame: FrameId,
+        name: &str,
+        source: AssetSource,
+        pose: Pose,
+    ) -> IncludeId {
+        let id = self.allocate();
+        self.workcell.includes.insert(
+            id,
+            parented(
+                frame.0,
+                IncludedWorkcell {
+                    name: NameInWorkcell(name.to_owned()),
+                    pose,
+                    source,
+                    marker: IncludedWorkcellMarker,
+                },
+            ),
+        );
+        IncludeId(id)
+    }
+
+    /// Adds a material to the library of the workcell and returns a reference to it.
+    pub fn add_material(&mut self, name: &str, material: VisualMaterial) -> MaterialRef {
+        self.workcell.materials.0.insert(name.to_owned(), material);
+        MaterialRef::Library(name.to_owned())
+    }
+
+    pub fn frame_mut(&mut self, frame: FrameId) -> Option<&mut Frame> {
+        self.workcell
+            .frames
+            .get_mut(&frame.0)
+            .map(|f| &mut f.bundle)
+    }
+
+    pub fn joint_mut(&mut self, joint: JointId) -> Option<&mut Joint> {
+        self.workcell
+            .joints
+            .get_mut(&joint.0)
+            .map(|j| &mut j.bundle)
+    }
+
+    /// Planning semantics of the workcell, they refer to frames and joints by name.
+    pub fn planning_mut(&mut self) -> &mut PlanningSemantics {
+        &mut self.workcell.properties.planning
+    }
+
+    /// Handle to an existing frame, if there is a frame with this name.
+    pub fn frame_by_name(&self, name: &str) -> Option<FrameId> {
+        self.workcell.graph().frame_by_name(name).map(FrameId)
+    }
+
+    /// The workcell built so far, it might not be valid yet.
+    pub fn workcell(&self) -> &Workcell {
+        &self.workcell
+    }
+
+    /// Validates and returns the workcell.
+    pub fn build(self) -> Result<Workcell, WorkcellBuildError> {
+        let diagnostics = self.workcell.validate();
+        if diagnostics.is_empty() {
+            Ok(self.workcell)
+        } else {
+            Err(WorkcellBuildError(diagnostics))
+        }
+    }
+}
+
+fn model(name: &str, geometry: Geometry, pose: Pose) -> WorkcellModel {
+    WorkcellModel {
+        name: name.to_owned(),
+        geometry,
+        pose,
+        material: None,
+    }
+}
+
+impl From<Workcell> for WorkcellBuilder {
+    /// Continues building an existing workcell, new elements get ids after the existing ones.
+    fn from(workcell: Workcell) -> Self {
+        let next_id = workcell
+            .element_parents()
+            .map(|(id, _, _)| id)
+            .chain([workcell.id])
+            .max()
+            .unwrap_or_default()
+            + 1;
+        Self { workcell, next_id }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmf_site_format::PrimitiveShape;
+
+    #[test]
+    fn builder_creates_valid_workcells() {
+        // A rack with a shelf every 0.5 meters
+        let mut builder = WorkcellBuilder::new("rack");
+        let base = builder.add_frame(builder.root(), "base", Pose::default());
+        let shelf = Geometry::Primitive(PrimitiveShape::Box {
+            size: [1.0, 0.5, 0.05],
+        });
+        let wood = builder.add_material(
+            "wood",
+            VisualMaterial {
+                color: [0.6, 0.4, 0.2, 1.0],
+                texture: None,
+            },
+        );
+        for i in 0..4 {
+            let pose = Pose {
+                trans: [0.0, 0.0, 0.5 * i as f32],
+                ..Default::default()
+            };
+            let frame = builder.add_frame(base, &format!("shelf_{i}"), pose);
+            let visual = builder.add_visual(frame, "shelf", shelf.clone(), Pose::default());
+            builder.set_visual_material(visual, wood.clone());
+            builder.add_collision(frame, "shelf", shelf.clone(), Pose::default());
+        }
+        let drawer = builder.add_frame(base, "drawer", Pose::default());
+        let joint = builder.add_joint(
+            base,
+            drawer,
+            "drawer_joint",
+            JointProperties::Prismatic(SingleDofJoint::new(
+                JointAxis::new([1.0, 0.0, 0.0]),
+                JointLimits::new(
+                    RangeLimits::Asymmetric {
+                        lower: Some(0.0),
+                        upper: Some(0.4),
+                    },
+                    RangeLimits::Symmetric(100.0),
+                    RangeLimits::Symmetric(0.5),
+                )
+                .unwrap(),
+            )),
+        );
+        builder.add_inertia(drawer, Inertia::default());
+        let workcell = builder.build().unwrap();
+        assert_eq!(workcell.frames.len(), 6);
+        assert_eq!(workcell.visuals.len(), 4);
+        assert_eq!(workcell.frames[&drawer.id()].parent, joint.id());
+        assert_eq!(workcell.joints[&joint.id()].parent, base.id());
+        let urdf = workcell.to_urdf().unwrap();
+        assert_eq!(urdf.links.len(), 2);
+
+        // Extending it keeps the ids unique, name clashes are reported when building
+        let mut builder = WorkcellBuilder::from(workcell);
+        let base = builder.frame_by_name("base").unwrap();
+        let extra = builder.add_frame(base, "drawer", Pose::default());
+        assert!(builder.workcell().frames.len() == 7);
+        assert!(extra.id() > joint.id());
+        assert!(matches!(
+            builder.build(),
+            Err(WorkcellBuildError(diagnostics)) if matches!(
+                diagnostics.as_slice(),
+                [WorkcellDiagnostic::DuplicateName { .. }]
+            )
+        ));
+    }
+}
@@ -0,0 +1,666 @@
+/*
+ * Copyright (C) 2024 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+//! Convex collision shapes, intersection and distance queries between them and placement of
+//! the collisions of a workcell for a joint configuration.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::*;
+use glam::{Affine3A, Vec3};
+
+/// Maximum number of iterations of the intersection and distance queries, they normally
+/// converge in a few.
+const MAX_GJK_ITERATIONS: usize = 64;
+
+/// Relative accuracy of the computed distances.
+const DISTANCE_TOLERANCE: f32 = 1e-5;
+
+/// Vertices of loaded collision meshes keyed by collision id, in the coordinates of the mesh
+/// file, the scale of the collision geometry is applied when placing them.
+pub type CollisionMeshes = HashMap<u32, Vec<Vec3>>;
+
+/// A convex shape centered in its origin. Cylinders and capsules are aligned with the z axis,
+/// as in urdf.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CollisionShape {
+    Sphere {
+        radius: f32,
+    },
+    Box {
+        half_extents: Vec3,
+    },
+    Cylinder {
+        radius: f32,
+        half_length: f32,
+    },
+    /// A segment of length `2 * half_length` inflated by `radius`
+    Capsule {
+        radius: f32,
+        half_length: f32,
+    },
+    /// The convex hull of a set of points, used to approximate meshes
+    ConvexHull {
+        points: Vec<Vec3>,
+    },
+}
+
+impl CollisionShape {
+    pub fn from_primitive(shape: &PrimitiveShape) -> Self {
+        match shape {
+            PrimitiveShape::Box { size } => CollisionShape::Box {
+                half_extents: Vec3::from(*size).abs() / 2.0,
+            },
+            PrimitiveShape::Sphere { radius } => CollisionShape::Sphere {
+                radius: radius.abs(),
+            },
+            PrimitiveShape::Cylinder { radius, length } => CollisionShape::Cylinder {
+                radius: radius.abs(),
+                half_length: length.abs() / 2.0,
+            },
+            PrimitiveShape::Capsule { radius, length } => CollisionShape::Capsule {
+                radius: radius.abs(),
+                half_length: length.abs() / 2.0,
+            },
+        }
+    }
+
+    /// Builds the shape of a geometry, meshes need to be loaded and passed to
+    /// [`CollisionShape::from_mesh`] so None is returned for them.
+    pub fn from_geometry(geometry: &Geometry) -> Option<Self> {
+        match geometry {
+            Geometry::Primitive(shape) => Some(Self::from_primitive(shape)),
+            Geometry::Mesh { .. } => None,
+        }
+    }
+
+    /// Approximates a mesh with the convex hull of its vertices, concave parts of the mesh are
+    /// filled. Returns None if the mesh has no vertices.
+    pub fn from_mesh(vertices: &[Vec3], scale: Option<Vec3>) -> Option<Self> {
+        if vertices.is_empty() {
+            return None;
+        }
+        let scale = scale.unwrap_or(Vec3::ONE);
+        Some(CollisionShape::ConvexHull {
+            points: vertices.iter().map(|v| *v * scale).collect(),
+        })
+    }
+
+    /// The point of the shape that is furthest along `direction`.
+    pub fn support(&self, direction: Vec3) -> Vec3 {
+        // Zero components pick the positive side, any point on the boundary is valid
+        let sign = |v: f32| if v < 0.0 { -1.0 } else { 1.0 };
+        match self {
+            CollisionShape::Sphere { radius } => {
+                direction.try_normalize().unwrap_or(Vec3::X) * *radius
+            }
+            CollisionShape::Box { half_extents } => Vec3::new(
+                sign(direction.x) * half_extents.x,
+                sign(direction.y) * half_extents.y,
+                sign(direction.z) * half_extents.z,
+            ),
+            CollisionShape::Cylinder {
+                radius,
+                half_length,
+            } => {
+                let radial = Vec3::new(direction.x, direction.y, 0.0).normalize_or_zero();
+                radial * *radius + Vec3::Z * sign(direction.z) * *half_length
+            }
+            CollisionShape::Capsule {
+                radius,
+                half_length,
+            } => {
+                Vec3::Z * sign(direction.z) * *half_length
+                    + direction.try_normalize().unwrap_or(Vec3::X) * *radius
+            }
+            CollisionShape::ConvexHull { points } => points
+                .iter()
+                .copied()
+                .max_by(|a, b| a.dot(direction).total_cmp(&b.dot(direction)))
+                .unwrap_or(Vec3::ZERO),
+        }
+    }
+}
+
+/// A collision shape placed in space, the transform must be a rigid transform.
+#[derive(Debug, Clone, Copy)]
+pub struct PlacedShape<'a> {
+    pub shape: &'a CollisionShape,
+    pub transform: Affine3A,
+}
+
+impl<'a> PlacedShape<'a> {
+    pub fn new(shape: &'a CollisionShape, transform: Affine3A) -> Self {
+        Self { shape, transform }
+    }
+
+    /// The point of the placed shape that is furthest along `direction`.
+    pub fn support(&self, direction: Vec3) -> Vec3 {
+        // The inverse of a rotation is its transpose
+        let local = self.transform.matrix3.transpose().mul_vec3(direction);
+        self.transform.transform_point3(self.shape.support(local))
+    }
+
+    /// Whether the two shapes overlap, shapes that are only touching may be reported either
+    /// way. Uses the GJK algorithm on the Minkowski difference of the shapes.
+    pub fn intersects(&self, other: &PlacedShape) -> bool {
+        let support = |d: Vec3| self.support(d) - other.support(-d);
+        let first = support(Vec3::X);
+        let mut simplex = vec![first];
+        let mut direction = -first;
+        for _ in 0..MAX_GJK_ITERATIONS {
+            if direction.length_squared() < f32::EPSILON * f32::EPSILON {
+                // The origin is on the boundary of the simplex
+                return true;
+            }
+            let point = support(direction);
+            if point.dot(direction) <= 0.0 {
+                return false;
+            }
+            simplex.insert(0, point);
+            if next_simplex(&mut simplex, &mut direction) {
+                return true;
+            }
+        }
+        // Not converging happens with degenerate configurations, where the shapes are
+        // touching, report them as intersecting to stay on the safe side
+        true
+    }
+
+    /// Minimum distance between the two shapes, zero if they overlap. Uses the GJK algorithm
+    /// to find the point of the Minkowski difference of the shapes closest to the origin.
+    pub fn distance(&self, other: &PlacedShape) -> f32 {
+        let support = |d: Vec3| self.support(d) - other.support(-d);
+        let mut closest = support(Vec3::X);
+        let mut simplex = vec![closest];
+        for _ in 0..MAX_GJK_ITERATIONS {
+            let distance_squared = closest.length_squared();
+            if distance_squared < f32::EPSILON * f32::EPSILON {
+                return 0.0;
+            }
+            // The projection of the new point on the search direction is a lower bound of the
+            // distance, stop when it is close enough to the current distance
+            let point = support(-closest);
+            if distance_squared - closest.dot(point) <= DISTANCE_TOLERANCE * distance_squared {
+                break;
+            }
+            simplex.push(point);
+            let Some((point, reduced)) = closest_on_simplex(&simplex) else {
+                // The origin is inside the simplex
+                return 0.0;
+            };
+            closest = point;
+            simplex = reduced;
+        }
+        closest.length()
+    }
+}
+
+fn same_direction(a: Vec3, b: Vec3) -> bool {
+    a.dot(b) > 0.0
+}
+
+/// Reduces the simplex to the feature closest to the origin and updates the search direction
+/// towards the origin. The newest point is the first, returns true if the simplex contains the
+/// origin.
+fn next_simplex(simplex: &mut Vec<Vec3>, direction: &mut Vec3) -> bool {
+    match simplex.len() {
+        2 => line_simplex(simplex, direction),
+        3 => triangle_simplex(simplex, direction),
+        _ => tetrahedron_simplex(simplex, direction),
+    }
+}
+
+fn line_simplex(simplex: &mut Vec<Vec3>, direction: &mut Vec3) -> bool {
+    let (a, b) = (simplex[0], simplex[1]);
+    let ab = b - a;
+    let ao = -a;
+    if same_direction(ab, ao) {
+        *direction = ab.cross(ao).cross(ab);
+    } else {
+        *simplex = vec![a];
+        *direction = ao;
+    }
+    false
+}
+
+fn triangle_simplex(simplex: &mut Vec<Vec3>, direction: &mut Vec3) -> bool {
+    let (a, b, c) = (simplex[0], simplex[1], simplex[2]);
+    let ab = b - a;
+    let ac = c - a;
+    let ao = -a;
+    let abc = ab.cross(ac);
+    if same_direction(abc.cross(ac), ao) {
+        if same_direction(ac, ao) {
+            *simplex = vec![a, c];
+            *direction = ac.cross(ao).cross(ac);
+        } else {
+            *simplex = vec![a, b];
+            return line_simplex(simplex, direction);
+        }
+    } else if same_direction(ab.cross(abc), ao) {
+        *simplex = vec![a, b];
+        return line_simplex(simplex, direction);
+    } else if same_direction(abc, ao) {
+        *direction = abc;
+    } else {
+        *simplex = vec![a, c, b];
+        *direction = -abc;
+    }
+    false
+}
+
+fn tetrahedron_simplex(simplex: &mut Vec<Vec3>, direction: &mut Vec3) -> bool {
+    let (a, b, c, d) = (simplex[0], simplex[1], simplex[2], simplex[3]);
+    let ab = b - a;
+    let ac = c - a;
+    let ad = d - a;
+    let ao = -a;
+    if same_direction(ab.cross(ac), ao) {
+        *simplex = vec![a, b, c];
+        return triangle_simplex(simplex, direction);
+    }
+    if same_direction(ac.cross(ad), ao) {
+        *simplex = vec![a, c, d];
+        return triangle_simplex(simplex, direction);
+    }
+    if same_direction(ad.cross(ab), ao) {
+        *simplex = vec![a, d, b];
+        return triangle_simplex(simplex, direction);
+    }
+    true
+}
+
+/// Point of the simplex closest to the origin and the smallest part of the simplex containing
+/// it. Returns None if the origin is inside the tetrahedron.
+fn closest_on_simplex(simplex: &[Vec3]) -> Option<(Vec3, Vec<Vec3>)> {
+    match *simplex {
+        [a] => Some((a, vec![a])),
+        [a, b] => Some(closest_on_segment(a, b)),
+        [a, b, c] => Some(closest_on_triangle(a, b, c)),
+        [a, b, c, d] => closest_on_tetrahedron(a, b, c, d),
+        _ => unreachable!("simplices have between one and four points"),
+    }
+}
+
+fn closest_on_segment(a: Vec3, b: Vec3) -> (Vec3, Vec<Vec3>) {
+    let ab = b - a;
+    let t = -a.dot(ab) / ab.length_squared();
+    // Degenerate segments have no direction, t is not a number
+    if t.is_nan() || t <= 0.0 {
+        (a, vec![a])
+    } else if t >= 1.0 {
+        (b, vec![b])
+    } else {
+        (a + ab * t, vec![a, b])
+    }
+}
+
+/// Finds the closest point by checking the voronoi regions of the vertices, edges and face of
+/// the triangle in turn.
+fn closest_on_triangle(a: Vec3, b: Vec3, c: Vec3) -> (Vec3, Vec<Vec3>) {
+    let ab = b - a;
+    let ac = c - a;
+    let (d1, d2) = (-ab.dot(a), -ac.dot(a));
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return (a, vec![a]);
+    }
+    let (d3, d4) = (-ab.dot(b), -ac.dot(b));
+    if d3 >= 0.0 && d4 <= d3 {
+        return (b, vec![b]);
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return (a + ab * (d1 / (d1 - d3)), vec![a, b]);
+    }
+    let (d5, d6) = (-ab.dot(c), -ac.dot(c));
+    if d6 >= 0.0 && d5 <= d6 {
+        return (c, vec![c]);
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return (a + ac * (d2 / (d2 - d6)), vec![a, c]);
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && d4 >= d3 && d5 >= d6 {
+        let w = (d4 - d3) / ((d4 - d3) + (d5 - d6));
+        return (b + (c - b) * w, vec![b, c]);
+    }
+    let denominator = va + vb + vc;
+    if denominator <= 0.0 {
+        // Degenerate triangle, its closest point is on one of its edges
+        return [(a, b), (b, c), (a, c)]
+            .into_iter()
+            .map(|(p, q)| closest_on_segment(p, q))
+            .min_by(|p, q| p.0.length_squared().total_cmp(&q.0.length_squared()))
+            .unwrap();
+    }
+    let (v, w) = (vb / denominator, vc / denominator);
+    (a + ab * v + ac * w, vec![a, b, c])
+}
+
+fn closest_on_tetrahedron(a: Vec3, b: Vec3, c: Vec3, d: Vec3) -> Option<(Vec3, Vec<Vec3>)> {
+    let faces = [(a, b, c, d), (a, c, d, b), (a, d, b, c), (b, d, c, a)];
+    // The origin is inside if it is on the same side of each face as the opposite vertex
+    let inside = faces.iter().all(|(p, q, r, opposite)| {
+        let normal = (*q - *p).cross(*r - *p);
+        (-*p).dot(normal) * (*opposite - *p).dot(normal) > 0.0
+    });
+    if inside {
+        return None;
+    }
+    // Otherwise the closest point is on the boundary
+    faces
+        .into_iter()
+        .map(|(p, q, r, _)| closest_on_triangle(p, q, r))
+        .min_by(|p, q| p.0.length_squared().total_cmp(&q.0.length_squared()))
+}
+
+/// A collision of a workcell placed relative to the workcell.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlacedCollision {
+    /// Frame the collision is attached to
+    pub frame: u32,
+    pub shape: CollisionShape,
+    pub transform: Affine3A,
+}
+
+impl PlacedCollision {
+    pub fn placed_shape(&self) -> PlacedShape<'_> {
+        PlacedShape::new(&self.shape, self.transform)
+    }
+}
+
+/// The collisions of a workcell placed for a joint configuration.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CollisionScene {
+    /// Placed collisions keyed by collision id
+    pub collisions: BTreeMap<u32, PlacedCollision>,
+    /// Ids of the mesh collisions whose vertices were not provided
+    pub unloaded: Vec<u32>,
+}
+
+impl CollisionScene {
+    /// Whether two collisions overlap, None if either of them is not in the scene.
+    pub fn intersects(&self, a: u32, b: u32) -> Option<bool> {
+        let (a, b) = (self.collisions.get(&a)?, self.collisions.get(&b)?);
+        Some(a.placed_shape().intersects(&b.placed_shape()))
+    }
+
+    /// Minimum distance between two collisions, None if either of them is not in the scene.
+    pub fn distance(&self, a: u32, b: u32) -> Option<f32> {
+        let (a, b) = (self.collisions.get(&a)?, self.collisions.get(&b)?);
+        Some(a.placed_shape().distance(&b.placed_shape()))
+    }
+
+    /// Minimum distance between the collisions attached to two frames, None if either frame has
+    /// no collisions in the scene.
+    pub fn frame_distance(&self, a: u32, b: u32) -> Option<f32> {
+        let of_frame = |frame: u32| {
+            self.collisions
+                .values()
+                .filter(move |c| c.frame == frame)
+                .map(PlacedCollision::placed_shape)
+        };
+        of_frame(a)
+            .flat_map(|s| of_frame(b).map(move |other| s.distance(&other)))
+            .min_by(f32::total_cmp)
+    }
+
+    /// Pairs of overlapping collisions attached to different frames, sorted by collision id.
+    pub fn intersecting_pairs(&self) -> Vec<(u32, u32)> {
+        let collisions: Vec<_> = self.collisions.iter().collect();
+        let mut pairs = Vec::new();
+        for (i, (a, placed_a)) in collisions.iter().enumerate() {
+            for (b, placed_b) in &collisions[i + 1..] {
+                if placed_a.frame != placed_b.frame
+                    && placed_a.placed_shape().intersects(&placed_b.placed_shape())
+                {
+                    pairs.push((**a, **b));
+                }
+            }
+        }
+        pairs
+    }
+}
+
+impl Workcell {
+    /// Builds the shape of a collision, meshes are approximated by the convex hull of their
+    /// vertices in `meshes`. Returns None if the collision doesn't exist or is a mesh whose
+    /// vertices were not provided.
+    pub fn collision_shape(&self, id: u32, meshes: &CollisionMeshes) -> Option<CollisionShape> {
+        match &self.collisions.get(&id)?.bundle.geometry {
+            Geometry::Primitive(primitive) => Some(CollisionShape::from_primitive(primitive)),
+            Geometry::Mesh { scale, .. } => meshes
+                .get(&id)
+                .and_then(|vertices| CollisionShape::from_mesh(vertices, *scale)),
+        }
+    }
+
+    /// Places the collisions of the workcell for a joint configuration, joints that are not in
+    /// `positions` are at zero. Meshes are approximated by the convex hull of the vertices in
+    /// `meshes`, the ones that are missing are reported as unloaded.
+    pub fn collision_scene(
+        &self,
+        positions: &JointPositions,
+        meshes: &CollisionMeshes,
+    ) -> Result<CollisionScene, KinematicsError> {
+        let transforms = self.frame_transforms(positions)?;
+        let mut scene = CollisionScene::default();
+        for (id, collision) in &self.collisions {
+            let shape = self.collision_shape(*id, meshes);
+            let Some(shape) = shape else {
+                scene.unloaded.push(*id);
+                continue;
+            };
+            // Collisions can be attached to the workcell itself
+            let frame_tf = match transforms.get(&collision.parent) {
+                Some(tf) => *tf,
+                None if collision.parent == self.id => Affine3A::IDENTITY,
+                None => return Err(KinematicsError::MissingFrame(collision.parent)),
+            };
+            scene.collisions.insert(
+                *id,
+                PlacedCollision {
+                    frame: collision.parent,
+                    shape,
+                    transform: frame_tf * affine_from_pose(&collision.bundle.pose),
+                },
+            );
+        }
+        Ok(scene)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use float_eq::assert_float_eq;
+    use glam::Quat;
+    use std::f32::consts::FRAC_PI_4;
+
+    /// Vertices of a cube of unit size centered in the origin
+    fn cube_vertices() -> Vec<Vec3> {
+        (0..8)
+            .map(|i| Vec3::new((i & 1) as f32, ((i >> 1) & 1) as f32, (i >> 2) as f32) - 0.5)
+            .collect()
+    }
+
+    #[test]
+    fn shapes_intersect() {
+        let cube = CollisionShape::from_primitive(&PrimitiveShape::Box { size: [1.0; 3] });
+        let sphere = CollisionShape::Sphere { radius: 0.5 };
+        let capsule = CollisionShape::Capsule {
+            radius: 0.1,
+            half_length: 1.0,
+        };
+        let at = |shape, x: f32, y: f32, z: f32| {
+            PlacedShape::new(shape, Affine3A::from_translation(Vec3::new(x, y, z)))
+        };
+        let origin = at(&cube, 0.0, 0.0, 0.0);
+        assert!(origin.intersects(&at(&cube, 0.9, 0.5, 0.0)));
+        assert!(!origin.intersects(&at(&cube, 1.1, 0.0, 0.0)));
+        // The corner of the cube is further than the radius of the sphere
+        assert!(!origin.intersects(&at(&sphere, 0.9, 0.9, 0.9)));
+        assert!(origin.intersects(&at(&sphere, 0.9, 0.0, 0.0)));
+        // The tip of the capsule reaches the cube from above
+        assert!(origin.intersects(&at(&capsule, 0.0, 0.0, 1.55)));
+        assert!(!origin.intersects(&at(&capsule, 0.0, 0.0, 1.65)));
+        // Rotating the second cube makes its edge reach the first one
+        let rotated = |x: f32| {
+            PlacedShape::new(
+                &cube,
+                Affine3A::from_rotation_translation(
+                    Quat::from_rotation_z(FRAC_PI_4),
+                    Vec3::new(x, 0.0, 0.0),
+                ),
+            )
+        };
+        assert!(!origin.intersects(&at(&cube, 1.15, 0.0, 0.0)));
+        assert!(origin.intersects(&rotated(1.15)));
+        assert!(!origin.intersects(&rotated(1.25)));
+        let cylinder = CollisionShape::Cylinder {
+            radius: 0.5,
+            half_length: 0.5,
+        };
+        // Unlike the cube, the cylinder has no corner reaching the sphere
+        assert!(origin.intersects(&at(&sphere, 0.8, 0.8, 0.0)));
+        assert!(!at(&cylinder, 0.0, 0.0, 0.0).intersects(&at(&sphere, 0.8, 0.8, 0.0)));
+        assert!(at(&cylinder, 0.0, 0.0, 0.0).intersects(&at(&sphere, 0.0, 0.0, 0.9)));
+    }
+
+    #[test]
+    fn distances_are_computed() {
+        let cube = CollisionShape::from_primitive(&PrimitiveShape::Box { size: [1.0; 3] });
+        let sphere = CollisionShape::Sphere { radius: 0.5 };
+        let capsule = CollisionShape::Capsule {
+            radius: 0.1,
+            half_length: 1.0,
+        };
+        let at = |shape, x: f32, y: f32, z: f32| {
+            PlacedShape::new(shape, Affine3A::from_translation(Vec3::new(x, y, z)))
+        };
+        let origin = at(&cube, 0.0, 0.0, 0.0);
+        assert_float_eq!(origin.distance(&at(&cube, 1.5, 0.2, 0.0)), 0.5, abs <= 1e-4);
+        assert_float_eq!(
+            origin.distance(&at(&sphere, 2.0, 0.0, 0.0)),
+            1.0,
+            abs <= 1e-4
+        );
+        // The closest point of the cube is on its edge
+        let expected = 2.0_f32.sqrt() - 0.5;
+        assert_float_eq!(
+            origin.distance(&at(&sphere, 1.5, 1.5, 0.0)),
+            expected,
+            abs <= 1e-3
+        );
+        assert_float_eq!(
+            origin.distance(&at(&capsule, 0.0, 0.0, 1.65)),
+            0.05,
+            abs <= 1e-4
+        );
+        assert_eq!(origin.distance(&at(&sphere, 0.9, 0.0, 0.0)), 0.0);
+        let rotated = PlacedShape::new(
+            &cube,
+            Affine3A::from_rotation_translation(
+                Quat::from_rotation_z(FRAC_PI_4),
+                Vec3::new(1.5, 0.0, 0.0),
+            ),
+        );
+        let expected = 1.0 - 0.5_f32.sqrt();
+        assert_float_eq!(origin.distance(&rotated), expected, abs <= 1e-4);
+        // Meshes are scaled before being placed
+        let mesh = CollisionShape::from_mesh(&cube_vertices(), Some(Vec3::splat(2.0))).unwrap();
+        assert_float_eq!(
+            at(&mesh, 0.0, 0.0, 0.0).distance(&at(&sphere, 3.0, 0.0, 0.0)),
+            1.5,
+            abs <= 1e-4
+        );
+        assert!(CollisionShape::from_mesh(&[], None).is_none());
+    }
+
+    #[test]
+    fn gripper_clears_conveyor_at_home_pose() {
+        let mut builder = WorkcellBuilder::new("cell");
+        let conveyor_pose = Pose {
+            trans: [1.0, 0.0, 0.5],
+            ..Default::default()
+        };
+        let conveyor = builder.add_frame(builder.root(), "conveyor", conveyor_pose);
+        let belt = builder.add_collision(
+            conveyor,
+            "belt",
+            Geometry::Primitive(PrimitiveShape::Box { size: [1.0; 3] }),
+            Pose::default(),
+        );
+        let shoulder_pose = Pose {
+            trans: [0.0, 0.0, 1.5],
+            ..Default::default()
+        };
+        let base = builder.add_frame(builder.root(), "base", Pose::default());
+        let arm = builder.add_frame(base, "arm", shoulder_pose);
+        builder.add_joint(
+            base,
+            arm,
+            "shoulder",
+            JointProperties::Revolute(SingleDofJoint::new(
+                JointAxis::new([0.0, 1.0, 0.0]),
+                Default::default(),
+            )),
+        );
+        let gripper_pose = Pose {
+            trans: [1.0, 0.0, 0.0],
+            ..Default::default()
+        };
+        let gripper = builder.add_frame(arm, "gripper", gripper_pose);
+        let fingers = builder.add_collision(
+            gripper,
+            "fingers",
+            Geometry::Mesh {
+                source: AssetSource::Local("fingers.stl".to_owned()),
+                scale: Some(Vec3::splat(0.2)),
+            },
+            Pose::default(),
+        );
+        let workcell = builder.build().unwrap();
+        let meshes = CollisionMeshes::from([(fingers.id(), cube_vertices())]);
+
+        // The fingers are 0.4m above the belt at the home pose
+        let home = workcell
+            .collision_scene(&Default::default(), &meshes)
+            .unwrap();
+        assert!(home.unloaded.is_empty());
+        assert!(home.intersecting_pairs().is_empty());
+        assert_eq!(home.intersects(belt.id(), fingers.id()), Some(false));
+        let clearance = home.frame_distance(gripper.id(), conveyor.id()).unwrap();
+        assert_float_eq!(clearance, 0.4, abs <= 1e-4);
+        assert_eq!(home.distance(belt.id(), fingers.id()), Some(clearance));
+
+        // Rotating the shoulder lowers the gripper into the belt
+        let positions = JointPositions::from([("shoulder".to_owned(), 0.6)]);
+        let lowered = workcell.collision_scene(&positions, &meshes).unwrap();
+        assert_eq!(lowered.intersecting_pairs(), [(belt.id(), fingers.id())]);
+        assert_eq!(lowered.distance(belt.id(), fingers.id()), Some(0.0));
+
+        // Meshes that were not loaded are reported
+        let unloaded = workcell
+            .collision_scene(&Default::default(), &Default::default())
+            .unwrap();
+        assert_eq!(unloaded.unloaded, [fingers.id()]);
+        assert_eq!(unloaded.frame_distance(gripper.id(), conveyor.id()), None);
+    }
+}
@@ -0,0 +1,483 @@
+/*
+ * Copyright (C) 2024 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+//! Computation of the pairs of links that motion planners don't need to check for collisions,
+//! by sampling random joint configurations.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::f32::consts::PI;
+
+use crate::*;
+use glam::Affine3A;
+use thiserror::Error as ThisError;
+
+#[derive(Debug, Clone, Copy)]
+pub struct CollisionMatrixOptions {
+    /// Number of joint configurations that are checked, the first one has all the joints at
+    /// zero and the others are random
+    pub samples: usize,
+    /// Seed of the random configurations, the result only depends on the workcell and the seed
+    pub seed: u64,
+}
+
+impl Default for CollisionMatrixOptions {
+    fn default() -> Self {
+        Self {
+            samples: 1000,
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, ThisError)]
+pub enum CollisionMatrixError {
+    #[error("Invalid workcell structure: {0:?}")]
+    InvalidStructure(Vec<WorkcellDiagnostic>),
+    #[error(transparent)]
+    Kinematics(#[from] KinematicsError),
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CollisionMatrix {
+    /// Pairs of links whose collisions don't need to be checked, sorted by frame names
+    pub disabled: Vec<DisabledCollision>,
+    /// Ids of the mesh collisions whose vertices were not provided, so they couldn't be
+    /// checked. The links they are attached to are only disabled together with their adjacent
+    /// links
+    pub unchecked: Vec<u32>,
+}
+
+/// Small and fast pseudo random generator (splitmix64), statistical quality is not a concern
+/// to pick joint configurations.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    /// Returns a number uniformly distributed in [0, 1).
+    fn next_f32(&mut self) -> f32 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^= z >> 31;
+        (z >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+/// A checked collision shape and the frame it is attached to.
+struct LinkShape {
+    link: u32,
+    frame: u32,
+    pose: Affine3A,
+    shape: CollisionShape,
+}
+
+impl Workcell {
+    /// The rigid body a frame belongs to, identified by the child frame of the closest joint
+    /// above it, or by the workcell if there is no joint above it.
+    fn rigid_body_of(&self, mut frame_id: u32) -> u32 {
+        for _ in 0..=self.frames.len() {
+            match self.frames.get(&frame_id) {
+                Some(frame) if !self.joints.contains_key(&frame.parent) => frame_id = frame.parent,
+                _ => break,
+            }
+        }
+        frame_id
+    }
+
+    /// Ranges of the positions sampled for each joint, joints that mimic another one follow it
+    /// and joints with more than one degree of freedom stay at their origin. Translations that
+    /// are not bounded on both sides stay at the origin, or at their only bound if the origin is
+    /// out of it.
+    fn sampled_joint_ranges(&self) -> Vec<(String, f32, f32)> {
+        self.joints
+            .values()
+            .filter_map(|joint| {
+                let single_dof = joint.bundle.properties.single_dof()?;
+                if single_dof.mimic.is_some() {
+                    return None;
+                }
+                let bounds = match joint.bundle.properties {
+                    JointProperties::Continuous(_) => (None, None),
+                    _ => single_dof.limits.position().bounds(),
+                };
+                let rotation = matches!(
+                    joint.bundle.properties,
+                    JointProperties::Revolute(_) | JointProperties::Continuous(_)
+                );
+                let (lower, upper) = match bounds {
+                    (Some(lower), Some(upper)) => (lower, upper),
+                    // A full turn covers all the positions of rotations, starting from their
+                    // bound if they have one
+                    (Some(lower), None) if rotation => (lower, lower + 2.0 * PI),
+                    (None, Some(upper)) if rotation => (upper - 2.0 * PI, upper),
+                    (None, None) if rotation => (-PI, PI),
+                    (lower, upper) => {
+                        let position = clamp_to(0.0, lower, upper);
+                        (position, position)
+                    }
+                };
+                Some((joint.bundle.name.0.clone(), lower, upper))
+            })
+            .collect()
+    }
+
+    /// Classifies the pairs of links with collisions as adjacent, always colliding, never
+    /// colliding or sometimes colliding by sampling joint configurations within the joint
+    /// limits. Links are the rigid bodies exported as urdf links. Links that are connected
+    /// through links without collisions are also considered adjacent. Meshes are approximated
+    /// by the convex hull of their vertices in `meshes`.
+    pub fn compute_collision_matrix(
+        &self,
+        options: &CollisionMatrixOptions,
+        meshes: &CollisionMeshes,
+    ) -> Result<CollisionMatrix, CollisionMatrixError> {
+        let diagnostics = self.validate();
+        if !diagnostics.is_empty() {
+            return Err(CollisionMatrixError::InvalidStructure(diagnostics));
+        }
+
+        let mut matrix = CollisionMatrix::default();
+        let mut shapes = Vec::new();
+        // Frame used to refer to each link, the link frame itself or the first frame with
+        // collisions for the links that are rigidly attached to the workcell
+        let mut link_frames = BTreeMap::new();
+        let mut unchecked_links = HashSet::new();
+        for (id, collision) in &self.collisions {
+            let link = self.rigid_body_of(collision.parent);
+            let frame = if self.frames.contains_key(&link) {
+                link
+            } else {
+                collision.parent
+            };
+            link_frames.entry(link).or_insert(frame);
+            match self.collision_shape(*id, meshes) {
+                Some(shape) => shapes.push(LinkShape {
+                    link,
+                    frame: collision.parent,
+                    pose: affine_from_pose(&collision.bundle.pose),
+                    shape,
+                }),
+                None => {
+                    matrix.unchecked.push(*id);
+                    unchecked_links.insert(link);
+                }
+            }
+        }
+        let links: Vec<u32> = link_frames.keys().copied().collect();
+
+        // Links connected by a joint, directly or through links without collisions
+        let mut neighbors: HashMap<u32, Vec<u32>> = HashMap::new();
+        for (joint_id, joint) in &self.joints {
+            let parent = self.rigid_body_of(joint.parent);
+            if let Some((child, _)) = self.frames.iter().find(|(_, f)| f.parent == *joint_id) {
+                neighbors.entry(parent).or_default().push(*child);
+                neighbors.entry(*child).or_default().push(parent);
+            }
+        }
+        let mut adjacent = BTreeSet::new();
+        for link in &links {
+            let mut visited = HashSet::from([*link]);
+            let mut queue = vec![*link];
+            while let Some(current) = queue.pop() {
+                for next in neighbors.get(&current).into_iter().flatten() {
+                    if !visited.insert(*next) {
+                        continue;
+                    }
+                    if link_frames.contains_key(next) {
+                        adjacent.insert((*link.min(next), *link.max(next)));
+                    } else {
+                        queue.push(*next);
+                    }
+                }
+            }
+        }
+
+        // Number of sampled configurations where each pair of links collides, pairs are
+        // dropped as soon as they were found both colliding and not colliding
+        let mut undecided: BTreeMap<(u32, u32), usize> = BTreeMap::new();
+        for (i, a) in links.iter().enumerate() {
+            for b in &links[i + 1..] {
+                let checked = !unchecked_links.contains(a) && !unchecked_links.contains(b);
+                if checked && !adjacent.contains(&(*a, *b)) {
+                    undecided.insert((*a, *b), 0);
+                }
+            }
+        }
+        let ranges = self.sampled_joint_ranges();
+        let mut rng = SplitMix64(options.seed);
+        for sample in 0..options.samples {
+            if undecided.is_empty() {
+                break;
+            }
+            // Start from the origin of the joints, within their limits
+            let positions: JointPositions = if sample == 0 {
+                ranges
+                    .iter()
+                    .map(|(name, lower, upper)| {
+                        (name.clone(), clamp_to(0.0, Some(*lower), Some(*upper)))
+                    })
+                    .collect()
+            } else {
+                ranges
+                    .iter()
+                    .map(|(name, lower, upper)| {
+                        (name.clone(), lower + (upper - lower) * rng.next_f32())
+                    })
+                    .collect()
+            };
+            let transforms = self.frame_transforms(&positions)?;
+            let placed: Vec<_> = shapes
+                .iter()
+                .map(|s| {
+                    let tf = transforms.get(&s.frame).copied().unwrap_or_default() * s.pose;
+                    (s.link, PlacedShape::new(&s.shape, tf))
+                })
+                .collect();
+            undecided.retain(|(a, b), count| {
+                let colliding = placed.iter().filter(|(link, _)| link == a).any(|(_, s)| {
+                    placed
+                        .iter()
+                        .filter(|(link, _)| link == b)
+                        .any(|(_, other)| s.intersects(other))
+                });
+                if colliding {
+                    *count += 1;
+                }
+                // Pairs that sometimes collide need to be checked
+                *count == 0 || *count == sample + 1
+            });
+        }
+
+        let name = |link: &u32| self.frames[&link_frames[link]].bundle.name.0.clone();
+        let classified = adjacent
+            .iter()
+            .map(|pair| (pair, DisabledCollisionReason::Adjacent))
+            .chain(undecided.iter().map(|(pair, count)| {
+                let reason = if *count == 0 {
+                    DisabledCollisionReason::Never
+                } else {
+                    DisabledCollisionReason::Always
+                };
+                (pair, reason)
+            }));
+        for ((a, b), reason) in classified {
+            let mut frames = [name(a), name(b)];
+            frames.sort();
+            matrix.disabled.push(DisabledCollision { frames, reason });
+        }
+        matrix.disabled.sort_by(|a, b| a.frames.cmp(&b.frames));
+        Ok(matrix)
+    }
+}
+
+/// Clamps a value within optional bounds.
+fn clamp_to(value: f32, lower: Option<f32>, upper: Option<f32>) -> f32 {
+    let value = lower.map_or(value, |lower| value.max(lower));
+    upper.map_or(value, |upper| value.min(upper))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec3;
+
+    #[test]
+    fn collision_matrix_is_computed() {
+        // A turntable on a table, its arm sweeps through a post standing on the table
+        let mut builder = WorkcellBuilder::new("cell");
+        let cube = |size: f32| Geometry::Primitive(PrimitiveShape::Box { size: [size; 3] });
+        let at = |x: f32, y: f32, z: f32| Pose {
+            trans: [x, y, z],
+            ..Default::default()
+        };
+        let rotation = || {
+            JointProperties::Continuous(SingleDofJoint::new(
+                JointAxis::new([0.0, 0.0, 1.0]),
+                Default::default(),
+            ))
+        };
+        let table = builder.add_frame(builder.root(), "table", Pose::default());
+        builder.add_collision(table, "table", cube(1.0), Pose::default());
+        // Merged into the link of the table
+        let post = builder.add_frame(table, "post", at(1.0, 0.0, 1.0));
+        builder.add_collision(post, "post", cube(0.2), Pose::default());
+        let turntable = builder.add_frame(table, "turntable", at(0.0, 0.0, 1.0));
+        builder.add_joint(table, turntable, "turntable_joint", rotation());
+        builder.add_collision(turntable, "turntable", cube(0.3), Pose::default());
+        let arm = builder.add_frame(turntable, "arm", Pose::default());
+        builder.add_joint(turntable, arm, "arm_joint", JointProperties::Fixed);
+        builder.add_collision(arm, "arm", cube(0.2), at(1.0, 0.0, 0.0));
+        // Overlaps the arm
+        let cover = builder.add_frame(turntable, "cover", Pose::default());
+        builder.add_joint(turntable, cover, "cover_joint", JointProperties::Fixed);
+        builder.add_collision(cover, "cover", cube(0.3), at(1.0, 0.0, 0.0));
+        // Far above, connected to the turntable through a mast without collisions
+        let mast = builder.add_frame(turntable, "mast", at(0.0, 0.0, 2.0));
+        builder.add_joint(turntable, mast, "mast_joint", JointProperties::Fixed);
+        let beacon = builder.add_frame(mast, "beacon", Pose::default());
+        builder.add_joint(mast, beacon, "beacon_joint", rotation());
+        builder.add_collision(beacon, "beacon", cube(0.1), Pose::default());
+        let workcell = builder.build().unwrap();
+
+        let matrix = workcell
+            .compute_collision_matrix(
+                &CollisionMatrixOptions {
+                    samples: 100,
+                    ..Default::default()
+                },
+                &Default::default(),
+            )
+            .unwrap();
+        assert!(matrix.unchecked.is_empty());
+        let disabled: Vec<_> = matrix
+            .disabled
+            .iter()
+            .map(|d| (d.frames[0].as_str(), d.frames[1].as_str(), d.reason))
+            .collect();
+        use DisabledCollisionReason::*;
+        // The arm and the cover sometimes collide with the post, so with the table link
+        assert_eq!(
+            disabled,
+            [
+                ("arm", "beacon", Never),
+                ("arm", "cover", Always),
+                ("arm", "turntable", Adjacent),
+                ("beacon", "cover", Never),
+                ("beacon", "table", Never),
+                ("beacon", "turntable", Adjacent),
+                ("cover", "turntable", Adjacent),
+                ("table", "turntable", Adjacent),
+            ]
+        );
+
+        // Meshes that were not loaded can't be checked, only the adjacent links of their link
+        // are disabled
+        let mut workcell = workcell;
+        let beacon_collision = workcell
+            .collisions
+            .iter_mut()
+            .find(|(_, c)| c.parent == beacon.id())
+            .unwrap();
+        beacon_collision.1.bundle.geometry = Geometry::Mesh {
+            source: AssetSource::Local("beacon.stl".to_owned()),
+            scale: None,
+        };
+        let beacon_collision = *beacon_collision.0;
+        let matrix = workcell
+            .compute_collision_matrix(&Default::default(), &Default::default())
+            .unwrap();
+        assert_eq!(matrix.unchecked, [beacon_collision]);
+        assert!(matrix
+            .disabled
+            .iter()
+            .filter(|d| d.frames.contains(&"beacon".to_owned()))
+            .all(|d| d.reason == Adjacent));
+        assert_eq!(matrix.disabled.len(), 5);
+
+        // Loaded meshes are checked like primitives
+        let cube_vertices = (0..8)
+            .map(|i| Vec3::new((i & 1) as f32, ((i >> 1) & 1) as f32, (i >> 2) as f32) - 0.5)
+            .collect();
+        let meshes = CollisionMeshes::from([(beacon_collision, cube_vertices)]);
+        let mesh_matrix = workcell
+            .compute_collision_matrix(
+                &CollisionMatrixOptions {
+                    samples: 100,
+                    ..Default::default()
+                },
+                &meshes,
+            )
+            .unwrap();
+        assert!(mesh_matrix.unchecked.is_empty());
+        assert_eq!(mesh_matrix.disabled.len(), 8);
+    }
+
+    #[test]
+    fn samples_are_within_joint_limits() {
+        // The slider would overlap the base at its origin, which is out of its limits
+        let mut builder = WorkcellBuilder::new("cell");
+        let cube = Geometry::Primitive(PrimitiveShape::Box { size: [1.0; 3] });
+        let base = builder.add_frame(builder.root(), "base", Pose::default());
+        builder.add_collision(base, "base", cube.clone(), Pose::default());
+        let slider = builder.add_frame(base, "slider", Pose::default());
+        let limits = JointLimits::new(
+            RangeLimits::Asymmetric {
+                lower: Some(2.0),
+                upper: Some(3.0),
+            },
+            RangeLimits::None,
+            RangeLimits::None,
+        )
+        .unwrap();
+        builder.add_joint(
+            base,
+            slider,
+            "slider_joint",
+            JointProperties::Prismatic(SingleDofJoint::new(
+                JointAxis::new([1.0, 0.0, 0.0]),
+                limits,
+            )),
+        );
+        builder.add_collision(slider, "slider", cube.clone(), Pose::default());
+        // Only bounded on one side, kept at its bound
+        let stop = builder.add_frame(base, "stop", Pose::default());
+        let limits = JointLimits::new(
+            RangeLimits::Asymmetric {
+                lower: None,
+                upper: Some(-2.0),
+            },
+            RangeLimits::None,
+            RangeLimits::None,
+        )
+        .unwrap();
+        builder.add_joint(
+            base,
+            stop,
+            "stop_joint",
+            JointProperties::Prismatic(SingleDofJoint::new(
+                JointAxis::new([0.0, 1.0, 0.0]),
+                limits,
+            )),
+        );
+        builder.add_collision(stop, "stop", cube, Pose::default());
+        let workcell = builder.build().unwrap();
+
+        let matrix = workcell
+            .compute_collision_matrix(
+                &CollisionMatrixOptions {
+                    samples: 1,
+                    ..Default::default()
+                },
+                &Default::default(),
+            )
+            .unwrap();
+        let disabled: Vec<_> = matrix
+            .disabled
+            .iter()
+            .map(|d| (d.frames[0].as_str(), d.frames[1].as_str(), d.reason))
+            .collect();
+        use DisabledCollisionReason::*;
+        assert_eq!(
+            disabled,
+            [
+                ("base", "slider", Adjacent),
+                ("base", "stop", Adjacent),
+                ("slider", "stop", Never),
+            ]
+        );
+    }
+}
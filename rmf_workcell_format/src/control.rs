@@ -0,0 +1,444 @@
+/*
+ * Copyright (C) 2024 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+//! ros2_control metadata of workcells, exported in the urdf and as a controllers configuration.
+
+use std::collections::BTreeMap;
+
+#[cfg(feature = "bevy")]
+use bevy::prelude::Component;
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use xmltree::Element;
+
+use crate::xml::*;
+use crate::*;
+
+pub const DEFAULT_HARDWARE_PLUGIN: &str = "mock_components/GenericSystem";
+pub const DEFAULT_TRANSMISSION_PLUGIN: &str = "transmission_interface/SimpleTransmission";
+
+/// Hardware plugin of the ros2_control system that drives the actuated joints of the workcell.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "bevy", derive(Component))]
+pub struct ControlHardware {
+    pub plugin: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub parameters: BTreeMap<String, String>,
+}
+
+impl Default for ControlHardware {
+    fn default() -> Self {
+        Self {
+            plugin: DEFAULT_HARDWARE_PLUGIN.to_owned(),
+            parameters: Default::default(),
+        }
+    }
+}
+
+/// A command or state interface of a joint, such as "position", "velocity" or "effort".
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ControlInterface {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub parameters: BTreeMap<String, String>,
+}
+
+impl ControlInterface {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            parameters: Default::default(),
+        }
+    }
+}
+
+/// Transmission between a joint and the actuator driving it, the position of the actuator is
+/// `mechanical_reduction * (joint_position - offset)`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JointTransmission {
+    #[serde(default = "default_transmission_plugin")]
+    pub plugin: String,
+    /// Name of the actuator, `<joint_name>_actuator` if not set
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub actuator: Option<String>,
+    pub mechanical_reduction: f32,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub offset: f32,
+}
+
+fn default_transmission_plugin() -> String {
+    DEFAULT_TRANSMISSION_PLUGIN.to_owned()
+}
+
+impl Default for JointTransmission {
+    fn default() -> Self {
+        Self {
+            plugin: default_transmission_plugin(),
+            actuator: None,
+            mechanical_reduction: 1.0,
+            offset: 0.0,
+        }
+    }
+}
+
+/// How a joint is driven through ros2_control, joints without command interfaces are not
+/// actuated.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "bevy", derive(Component))]
+pub struct JointActuation {
+    pub command_interfaces: Vec<ControlInterface>,
+    pub state_interfaces: Vec<ControlInterface>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub transmission: Option<JointTransmission>,
+}
+
+impl JointActuation {
+    pub fn is_actuated(&self) -> bool {
+        !self.command_interfaces.is_empty()
+    }
+}
+
+impl Workcell {
+    /// Actuated joints, sorted by name.
+    fn actuated_joints(&self) -> Vec<&Joint> {
+        let mut joints: Vec<_> = self
+            .joints
+            .values()
+            .map(|j| &j.bundle)
+            .filter(|j| j.actuation.is_actuated())
+            .collect();
+        joints.sort_by(|a, b| a.name.0.cmp(&b.name.0));
+        joints
+    }
+
+    /// Returns the `<ros2_control>` element describing the actuated joints of the workcell, if
+    /// there are any. The `min` and `max` parameters of position, velocity and effort command
+    /// interfaces default to the limits of the joint.
+    pub fn to_ros2_control(&self) -> Option<Element> {
+        let joints = self.actuated_joints();
+        if joints.is_empty() {
+            return None;
+        }
+        let param = |name: &str, value: &str| text_element("param", value).with_attr("name", name);
+        let mut hardware = Element::new("hardware").with_child(text_element(
+            "plugin",
+            &self.properties.control_hardware.plugin,
+        ));
+        for (name, value) in &self.properties.control_hardware.parameters {
+            hardware.push(param(name, value));
+        }
+        let mut control = Element::new("ros2_control")
+            .with_attr("name", format!("{}_system", self.properties.name.0))
+            .with_attr("type", "system")
+            .with_child(hardware);
+
+        for joint in &joints {
+            let interface = |tag: &str, interface: &ControlInterface, command: bool| {
+                let mut parameters = interface.parameters.clone();
+                let limits = match (command, joint.properties.limits()) {
+                    (true, Some(limits)) => match interface.name.as_str() {
+                        "position"
+                            if !matches!(joint.properties, JointProperties::Continuous(_)) =>
+                        {
+                            Some(limits.position())
+                        }
+                        "velocity" => Some(limits.velocity()),
+                        "effort" => Some(limits.effort()),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                if let Some((lower, upper)) = limits.map(|l| l.bounds()) {
+                    for (name, bound) in [("min", lower), ("max", upper)] {
+                        if let Some(bound) = bound {
+                            parameters
+                                .entry(name.to_owned())
+                                .or_insert_with(|| bound.to_string());
+                        }
+                    }
+                }
+                let mut element = Element::new(tag).with_attr("name", &interface.name);
+                for (name, value) in &parameters {
+                    element.push(param(name, value));
+                }
+                element
+            };
+            let mut element = Element::new("joint").with_attr("name", &joint.name.0);
+            for command in &joint.actuation.command_interfaces {
+                element.push(interface("command_interface", command, true));
+            }
+            for state in &joint.actuation.state_interfaces {
+                element.push(interface("state_interface", state, false));
+            }
+            control.push(element);
+        }
+
+        for joint in &joints {
+            let Some(transmission) = &joint.actuation.transmission else {
+                continue;
+            };
+            let actuator = transmission
+                .actuator
+                .clone()
+                .unwrap_or_else(|| format!("{}_actuator", joint.name.0));
+            control.push(
+                Element::new("transmission")
+                    .with_attr("name", format!("{}_transmission", joint.name.0))
+                    .with_child(text_element("plugin", &transmission.plugin))
+                    .with_child(
+                        Element::new("actuator")
+                            .with_attr("name", actuator)
+                            .with_attr("role", "actuator1"),
+                    )
+                    .with_child(
+                        Element::new("joint")
+                            .with_attr("name", &joint.name.0)
+                            .with_attr("role", "joint1")
+                            .with_child(text_element(
+                                "mechanical_reduction",
+                                transmission.mechanical_reduction,
+                            ))
+                            .with_child(text_element("offset", transmission.offset)),
+                    ),
+            );
+        }
+        Some(control)
+    }
+
+    /// Returns the parameters of a ros2 controller manager with a joint state broadcaster and
+    /// joint trajectory controllers for the actuated joints, if there are any. Joints are
+    /// grouped in a controller for each combination of command interfaces. Trajectory
+    /// controllers need PID gains to command joints through their effort, they can't be
+    /// derived from the workcell so these joints are only published by the broadcaster.
+    pub fn ros2_controllers_yaml(
+        &self,
+        update_rate: u32,
+    ) -> Result<Option<String>, serde_yaml::Error> {
+        let joints = self.actuated_joints();
+        if joints.is_empty() {
+            return Ok(None);
+        }
+        let interface_names = |interfaces: &[ControlInterface]| -> Vec<String> {
+            interfaces.iter().map(|i| i.name.clone()).collect()
+        };
+        let mut groups: BTreeMap<Vec<String>, Vec<&Joint>> = BTreeMap::new();
+        for joint in joints {
+            let commands = interface_names(&joint.actuation.command_interfaces);
+            if commands.iter().any(|c| c == "effort") {
+                continue;
+            }
+            groups.entry(commands).or_default().push(joint);
+        }
+        let controller_name = |commands: &[String]| {
+            if groups.len() == 1 {
+                "joint_trajectory_controller".to_owned()
+            } else {
+                format!("{}_trajectory_controller", commands.join("_"))
+            }
+        };
+
+        let parameters = |value: Value| {
+            Value::Mapping(Mapping::from_iter([key_value("ros__parameters", value)]))
+        };
+        let controller_type =
+            |plugin: &str| Value::Mapping(Mapping::from_iter([key_value("type", plugin)]));
+        let mut manager = Mapping::from_iter([
+            key_value("update_rate", update_rate),
+            key_value(
+                "joint_state_broadcaster",
+                controller_type("joint_state_broadcaster/JointStateBroadcaster"),
+            ),
+        ]);
+        let mut controllers = Mapping::new();
+        for (commands, joints) in &groups {
+            // Only the state interfaces that all the joints provide can be used
+            let state_interfaces = interface_names(&joints[0].actuation.state_interfaces)
+                .into_iter()
+                .filter(|state| {
+                    joints.iter().all(|j| {
+                        j.actuation
+                            .state_interfaces
+                            .iter()
+                            .any(|s| &s.name == state)
+                    })
+                })
+                .collect();
+            let name = controller_name(commands);
+            manager.insert(
+                name.clone().into(),
+                controller_type("joint_trajectory_controller/JointTrajectoryController"),
+            );
+            let controller = TrajectoryControllerParameters {
+                joints: joints.iter().map(|j| j.name.0.clone()).collect(),
+                command_interfaces: commands.clone(),
+                state_interfaces,
+            };
+            controllers.insert(name.into(), parameters(serde_yaml::to_value(controller)?));
+        }
+        let mut yaml = Mapping::from_iter([key_value(
+            "controller_manager",
+            parameters(Value::Mapping(manager)),
+        )]);
+        yaml.extend(controllers);
+        serde_yaml::to_string(&yaml).map(Some)
+    }
+}
+
+/// Parameters of a joint trajectory controller.
+#[derive(Serialize)]
+struct TrajectoryControllerParameters {
+    joints: Vec<String>,
+    command_interfaces: Vec<String>,
+    state_interfaces: Vec<String>,
+}
+
+fn key_value(key: &str, value: impl Into<Value>) -> (Value, Value) {
+    (key.into(), value.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn actuated_workcell() -> Workcell {
+        let mut builder = WorkcellBuilder::new("cell");
+        let base = builder.add_frame(builder.root(), "base", Pose::default());
+        let limits = JointLimits::new(
+            RangeLimits::Asymmetric {
+                lower: Some(-1.5),
+                upper: Some(1.5),
+            },
+            RangeLimits::Symmetric(50.0),
+            RangeLimits::Symmetric(2.0),
+        )
+        .unwrap();
+        let mut parent = base;
+        for name in ["shoulder", "elbow", "gripper"] {
+            let link = builder.add_frame(parent, &format!("{name}_link"), Pose::default());
+            let joint = builder.add_joint(
+                parent,
+                link,
+                name,
+                JointProperties::Revolute(SingleDofJoint::new(
+                    JointAxis::new([0.0, 0.0, 1.0]),
+                    limits.clone(),
+                )),
+            );
+            let command = if name == "gripper" {
+                "effort"
+            } else {
+                "position"
+            };
+            builder.joint_mut(joint).unwrap().actuation = JointActuation {
+                command_interfaces: vec![ControlInterface::new(command)],
+                state_interfaces: vec![
+                    ControlInterface::new("position"),
+                    ControlInterface::new("velocity"),
+                ],
+                transmission: (name == "elbow").then(|| JointTransmission {
+                    mechanical_reduction: 50.0,
+                    ..Default::default()
+                }),
+            };
+            parent = link;
+        }
+        builder.build().unwrap()
+    }
+
+    #[test]
+    fn ros2_control_is_exported() {
+        let workcell = actuated_workcell();
+        let urdf = workcell.to_urdf_string().unwrap();
+        let urdf = Element::parse(urdf.as_bytes()).unwrap();
+        let control = urdf.get_child("ros2_control").unwrap();
+        assert_eq!(control.attributes["name"], "cell_system");
+        assert_eq!(
+            child_text(control.get_child("hardware").unwrap(), "plugin").unwrap(),
+            DEFAULT_HARDWARE_PLUGIN
+        );
+        let joints: Vec<_> = children_named(control, "joint").collect();
+        assert_eq!(joints.len(), 3);
+        // Sorted by name, the limits of the joint are used for the command interfaces
+        assert_eq!(joints[0].attributes["name"], "elbow");
+        let command = joints[0].get_child("command_interface").unwrap();
+        assert_eq!(command.attributes["name"], "position");
+        let params: Vec<_> = children_named(command, "param")
+            .map(|p| (p.attributes["name"].as_str(), p.get_text().unwrap()))
+            .collect();
+        assert_eq!(params, [("max", "1.5".into()), ("min", "-1.5".into())]);
+        assert_eq!(children_named(joints[0], "state_interface").count(), 2);
+        let transmission = control.get_child("transmission").unwrap();
+        assert_eq!(transmission.attributes["name"], "elbow_transmission");
+        assert_eq!(
+            child_text(
+                transmission.get_child("joint").unwrap(),
+                "mechanical_reduction"
+            )
+            .unwrap(),
+            "50"
+        );
+
+        let yaml = workcell.ros2_controllers_yaml(100).unwrap().unwrap();
+        let yaml: Value = serde_yaml::from_str(&yaml).unwrap();
+        let manager = &yaml["controller_manager"]["ros__parameters"];
+        assert_eq!(manager["update_rate"], 100);
+        // The effort controlled gripper needs PID gains and is not part of any controller
+        let controller = &yaml["joint_trajectory_controller"]["ros__parameters"];
+        assert_eq!(
+            manager["joint_trajectory_controller"]["type"],
+            "joint_trajectory_controller/JointTrajectoryController"
+        );
+        assert_eq!(
+            controller["joints"],
+            serde_yaml::to_value(["elbow", "shoulder"]).unwrap()
+        );
+        assert_eq!(
+            controller["command_interfaces"],
+            serde_yaml::to_value(["position"]).unwrap()
+        );
+        assert_eq!(
+            controller["state_interfaces"],
+            serde_yaml::to_value(["position", "velocity"]).unwrap()
+        );
+
+        // Names that have a meaning in yaml are quoted
+        let mut renamed = workcell.clone();
+        for joint in renamed.joints.values_mut() {
+            if joint.bundle.name.0 == "elbow" {
+                joint.bundle.name.0 = "- elbow: #1".to_owned();
+            }
+        }
+        let yaml = renamed.ros2_controllers_yaml(100).unwrap().unwrap();
+        let yaml: Value = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(
+            yaml["joint_trajectory_controller"]["ros__parameters"]["joints"][0],
+            "- elbow: #1"
+        );
+
+        // Without actuated joints there is nothing to export
+        let mut workcell = workcell;
+        for joint in workcell.joints.values_mut() {
+            joint.bundle.actuation = Default::default();
+        }
+        assert!(workcell.to_ros2_control().is_none());
+        assert!(workcell.ros2_controllers_yaml(100).unwrap().is_none());
+        assert_eq!(
+            workcell.to_urdf_string().unwrap(),
+            urdf_rs::write_to_string(&workcell.to_urdf().unwrap()).unwrap()
+        );
+    }
+}
@@ -0,0 +1,1351 @@
+/*
+ * Copyright (C) 2024 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+//! Semantic comparison and three-way merge of workcells.
+//!
+//! Elements are matched by name rather than by id, frames, joints, includes and sensors by their
+//! own name and visuals, collisions and inertias by their parent and name, so that two versions
+//! can be compared even if their ids were renumbered. Elements that can't be matched by name
+//! but have the same id in both versions are considered renamed.
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+use crate::*;
+use rmf_site_format::{Anchor, AssetSource, Pose};
+use thiserror::Error as ThisError;
+
+/// Tolerances used when comparing workcells, smaller differences are ignored.
+#[derive(Debug, Clone, Copy)]
+pub struct DiffOptions {
+    /// Maximum distance between two positions, in meters
+    pub translation_tolerance: f32,
+    /// Maximum angle between two orientations, in radians
+    pub rotation_tolerance: f32,
+    /// Maximum difference between two masses or moments of inertia
+    pub inertia_tolerance: f32,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        Self {
+            translation_tolerance: 1e-6,
+            rotation_tolerance: 1e-6,
+            inertia_tolerance: 1e-6,
+        }
+    }
+}
+
+/// A difference between two versions of a workcell. Ids refer to the old version, except for
+/// added elements.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WorkcellChange {
+    WorkcellRenamed {
+        from: String,
+        to: String,
+    },
+    Added {
+        kind: WorkcellElementKind,
+        id: u32,
+        name: String,
+    },
+    Removed {
+        kind: WorkcellElementKind,
+        id: u32,
+        name: String,
+    },
+    Renamed {
+        kind: WorkcellElementKind,
+        id: u32,
+        from: String,
+        to: String,
+    },
+    Reparented {
+        kind: WorkcellElementKind,
+        id: u32,
+        name: String,
+        from: String,
+        to: String,
+    },
+    Moved {
+        kind: WorkcellElementKind,
+        id: u32,
+        name: String,
+        from: Pose,
+        to: Pose,
+    },
+    JointChanged {
+        id: u32,
+        name: String,
+        from: JointProperties,
+        to: JointProperties,
+    },
+    GeometryChanged {
+        kind: WorkcellElementKind,
+        id: u32,
+        name: String,
+        from: Geometry,
+        to: Geometry,
+    },
+    MaterialChanged {
+        kind: WorkcellElementKind,
+        id: u32,
+        name: String,
+        from: Option<MaterialRef>,
+        to: Option<MaterialRef>,
+    },
+    InertiaChanged {
+        id: u32,
+        name: String,
+        from: Inertia,
+        to: Inertia,
+    },
+    SourceChanged {
+        id: u32,
+        name: String,
+        from: AssetSource,
+        to: AssetSource,
+    },
+    ActuationChanged {
+        id: u32,
+        name: String,
+        from: JointActuation,
+        to: JointActuation,
+    },
+    SensorChanged {
+        id: u32,
+        name: String,
+        from: SensorProperties,
+        to: SensorProperties,
+    },
+    ControlHardwareChanged {
+        from: ControlHardware,
+        to: ControlHardware,
+    },
+    PlanningChanged {
+        from: PlanningSemantics,
+        to: PlanningSemantics,
+    },
+    LibraryMaterialAdded(String),
+    LibraryMaterialRemoved(String),
+    LibraryMaterialChanged(String),
+}
+
+fn fmt_pose(pose: &Pose) -> String {
+    let rpy = rpy_from_quat(quat_from_rotation(&pose.rot));
+    format!(
+        "xyz [{} {} {}] rpy [{} {} {}]",
+        pose.trans[0], pose.trans[1], pose.trans[2], rpy[0], rpy[1], rpy[2]
+    )
+}
+
+impl std::fmt::Display for WorkcellChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WorkcellChange::WorkcellRenamed { from, to } => {
+                write!(f, "~ workcell renamed from [{from}] to [{to}]")
+            }
+            WorkcellChange::Added { kind, id, name } => write!(f, "+ {kind} [{name}] ({id})"),
+            WorkcellChange::Removed { kind, id, name } => write!(f, "- {kind} [{name}] ({id})"),
+            WorkcellChange::Renamed { kind, id, from, to } => {
+                write!(f, "~ {kind} [{from}] ({id}) renamed to [{to}]")
+            }
+            WorkcellChange::Reparented {
+                kind,
+                id,
+                name,
+                from,
+                to,
+            } => write!(
+                f,
+                "~ {kind} [{name}] ({id}) moved from parent [{from}] to [{to}]"
+            ),
+            WorkcellChange::Moved {
+                kind,
+                id,
+                name,
+                from,
+                to,
+            } => write!(
+                f,
+                "~ {kind} [{name}] ({id}) pose changed from {} to {}",
+                fmt_pose(from),
+                fmt_pose(to)
+            ),
+            WorkcellChange::JointChanged { id, name, from, to } => write!(
+                f,
+                "~ joint [{name}] ({id}) properties changed from {from:?} to {to:?}"
+            ),
+            WorkcellChange::GeometryChanged {
+                kind,
+                id,
+                name,
+                from,
+                to,
+            } => write!(
+                f,
+                "~ {kind} [{name}] ({id}) geometry changed from {from:?} to {to:?}"
+            ),
+            WorkcellChange::MaterialChanged {
+                kind,
+                id,
+                name,
+                from,
+                to,
+            } => write!(
+                f,
+                "~ {kind} [{name}] ({id}) material changed from {from:?} to {to:?}"
+            ),
+            WorkcellChange::InertiaChanged { id, name, from, to } => write!(
+                f,
+                "~ inertia of [{name}] ({id}) changed from mass {} {:?} to mass {} {:?}",
+                from.mass.0, from.moment, to.mass.0, to.moment
+            ),
+            WorkcellChange::SourceChanged { id, name, from, to } => write!(
+                f,
+                "~ include [{name}] ({id}) source changed from {from:?} to {to:?}"
+            ),
+            WorkcellChange::ActuationChanged { id, name, from, to } => write!(
+                f,
+                "~ joint [{name}] ({id}) actuation changed from {from:?} to {to:?}"
+            ),
+            WorkcellChange::SensorChanged { id, name, from, to } => write!(
+                f,
+                "~ sensor [{name}] ({id}) properties changed from {from:?} to {to:?}"
+            ),
+            WorkcellChange::ControlHardwareChanged { from, to } => {
+                write!(f, "~ control hardware changed from {from:?} to {to:?}")
+            }
+            WorkcellChange::PlanningChanged { from, to } => {
+                write!(f, "~ planning semantics changed from {from:?} to {to:?}")
+            }
+            WorkcellChange::LibraryMaterialAdded(name) => write!(f, "+ material [{name}]"),
+            WorkcellChange::LibraryMaterialRemoved(name) => write!(f, "- material [{name}]"),
+            WorkcellChange::LibraryMaterialChanged(name) => write!(f, "~ material [{name}]"),
+        }
+    }
+}
+
+/// An element that was modified in incompatible ways by the two merged versions. Conflicts are
+/// resolved in favor of "ours", ids refer to the merged workcell.
+#[derive(Debug, Clone, PartialEq, ThisError)]
+pub enum MergeConflict {
+    #[error("the workcell was renamed to [{ours}] and [{theirs}]")]
+    WorkcellRenamed { ours: String, theirs: String },
+    #[error("{kind} [{name}] ({id}) was changed differently in both versions")]
+    BothChanged {
+        kind: WorkcellElementKind,
+        id: u32,
+        name: String,
+    },
+    #[error("{kind} [{name}] ({id}) was changed in one version and removed in the other")]
+    ChangedAndRemoved {
+        kind: WorkcellElementKind,
+        id: u32,
+        name: String,
+    },
+    #[error("{kind} [{name}] ({id}) was added with a different content in both versions")]
+    BothAdded {
+        kind: WorkcellElementKind,
+        id: u32,
+        name: String,
+    },
+    #[error("{kind} [{name}] ({id}) was removed but the other version attached elements to it")]
+    RemovedParent {
+        kind: WorkcellElementKind,
+        id: u32,
+        name: String,
+    },
+    #[error("material [{0}] was changed differently in both versions")]
+    Material(String),
+    #[error("the control hardware was changed differently in both versions")]
+    ControlHardware,
+    #[error("the planning semantics were changed differently in both versions")]
+    Planning,
+}
+
+/// The result of a three-way merge, the workcell contains all the non conflicting changes and
+/// the version of "ours" for the conflicting ones.
+#[derive(Debug, Clone)]
+pub struct WorkcellMerge {
+    pub workcell: Workcell,
+    pub conflicts: Vec<MergeConflict>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ElementData<'a> {
+    Frame(&'a Frame),
+    Joint(&'a Joint),
+    Include(&'a IncludedWorkcell),
+    Sensor(&'a Sensor),
+    Visual(&'a WorkcellModel),
+    Collision(&'a WorkcellModel),
+    Inertia(&'a Inertia),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Element<'a> {
+    id: u32,
+    parent: u32,
+    data: ElementData<'a>,
+}
+
+impl<'a> Element<'a> {
+    fn kind(&self) -> WorkcellElementKind {
+        match self.data {
+            ElementData::Frame(_) => WorkcellElementKind::Frame,
+            ElementData::Joint(_) => WorkcellElementKind::Joint,
+            ElementData::Include(_) => WorkcellElementKind::Include,
+            ElementData::Sensor(_) => WorkcellElementKind::Sensor,
+            ElementData::Visual(_) => WorkcellElementKind::Visual,
+            ElementData::Collision(_) => WorkcellElementKind::Collision,
+            ElementData::Inertia(_) => WorkcellElementKind::Inertia,
+        }
+    }
+
+    /// Frames, joints, includes and sensors have unique names, other elements are identified by
+    /// their parent and name.
+    fn has_unique_name(&self) -> bool {
+        matches!(
+            self.data,
+            ElementData::Frame(_)
+                | ElementData::Joint(_)
+                | ElementData::Include(_)
+                | ElementData::Sensor(_)
+        )
+    }
+
+    fn name(&self) -> &'a str {
+        match self.data {
+            ElementData::Frame(frame) => &frame.name.0,
+            ElementData::Joint(joint) => &joint.name.0,
+            ElementData::Include(include) => &include.name.0,
+            ElementData::Sensor(sensor) => &sensor.name.0,
+            ElementData::Visual(model) | ElementData::Collision(model) => &model.name,
+            ElementData::Inertia(_) => "",
+        }
+    }
+
+    fn pose(&self) -> Option<Pose> {
+        match self.data {
+            ElementData::Frame(frame) => match &frame.anchor {
+                Anchor::Pose3D(pose) => Some(*pose),
+                _ => None,
+            },
+            ElementData::Joint(_) => None,
+            ElementData::Include(include) => Some(include.pose),
+            ElementData::Sensor(sensor) => Some(sensor.pose),
+            ElementData::Visual(model) | ElementData::Collision(model) => Some(model.pose),
+            ElementData::Inertia(inertia) => Some(inertia.center),
+        }
+    }
+
+    fn insert_into(&self, workcell: &mut Workcell, id: u32, parent: u32) {
+        match self.data {
+            ElementData::Frame(frame) => {
+                workcell.frames.insert(
+                    id,
+                    Parented {
+                        parent,
+                        bundle: frame.clone(),
+                    },
+                );
+            }
+            ElementData::Joint(joint) => {
+                workcell.joints.insert(
+                    id,
+                    Parented {
+                        parent,
+                        bundle: joint.clone(),
+                    },
+                );
+            }
+            ElementData::Include(include) => {
+                workcell.includes.insert(
+                    id,
+                    Parented {
+                        parent,
+                        bundle: include.clone(),
+                    },
+                );
+            }
+            ElementData::Sensor(sensor) => {
+                workcell.sensors.insert(
+                    id,
+                    Parented {
+                        parent,
+                        bundle: sensor.clone(),
+                    },
+                );
+            }
+            ElementData::Visual(model) => {
+                workcell.visuals.insert(
+                    id,
+                    Parented {
+                        parent,
+                        bundle: model.clone(),
+                    },
+                );
+            }
+            ElementData::Collision(model) => {
+                workcell.collisions.insert(
+                    id,
+                    Parented {
+                        parent,
+                        bundle: model.clone(),
+                    },
+                );
+            }
+            ElementData::Inertia(inertia) => {
+                workcell.inertias.insert(
+                    id,
+                    Parented {
+                        parent,
+                        bundle: inertia.clone(),
+                    },
+                );
+            }
+        }
+    }
+}
+
+impl Workcell {
+    /// All the elements of the workcell, elements with unique names come first.
+    fn elements(&self) -> Vec<Element<'_>> {
+        fn elements<'a, T>(
+            map: &'a BTreeMap<u32, Parented<u32, T>>,
+            data: impl Fn(&'a T) -> ElementData<'a>,
+        ) -> impl Iterator<Item = Element<'a>> {
+            map.iter().map(move |(id, p)| Element {
+                id: *id,
+                parent: p.parent,
+                data: data(&p.bundle),
+            })
+        }
+        elements(&self.frames, ElementData::Frame)
+            .chain(elements(&self.joints, ElementData::Joint))
+            .chain(elements(&self.includes, ElementData::Include))
+            .chain(elements(&self.sensors, ElementData::Sensor))
+            .chain(elements(&self.visuals, ElementData::Visual))
+            .chain(elements(&self.collisions, ElementData::Collision))
+            .chain(elements(&self.inertias, ElementData::Inertia))
+            .collect()
+    }
+}
+
+/// Names used to refer to the elements of a workcell in the diff, inertias are named after
+/// their frame and the workcell after itself.
+struct ElementNames<'a>(HashMap<u32, &'a str>);
+
+impl<'a> ElementNames<'a> {
+    fn new(workcell: &'a Workcell) -> Self {
+        let mut names: HashMap<_, _> = workcell
+            .elements()
+            .iter()
+            .map(|e| (e.id, e.name()))
+            .collect();
+        names.insert(workcell.id, &workcell.properties.name.0);
+        for (id, inertia) in &workcell.inertias {
+            let frame = names.get(&inertia.parent).copied().unwrap_or_default();
+            names.insert(*id, frame);
+        }
+        Self(names)
+    }
+
+    fn get(&self, id: u32) -> String {
+        self.0.get(&id).copied().unwrap_or_default().to_owned()
+    }
+}
+
+/// Returns a map from the ids of the elements of `other` to the ids of the matching elements in
+/// `base`.
+fn match_elements(base: &Workcell, other: &Workcell) -> HashMap<u32, u32> {
+    let mut matches = HashMap::from([(other.id, base.id)]);
+    let base_elements = base.elements();
+    let other_elements = other.elements();
+    let mut matched_base = HashSet::from([base.id]);
+
+    let match_by_id =
+        |matches: &mut HashMap<u32, u32>, matched_base: &mut HashSet<u32>, unique_names: bool| {
+            let base_kinds: HashMap<_, _> = base_elements
+                .iter()
+                .filter(|e| e.has_unique_name() == unique_names && !matched_base.contains(&e.id))
+                .map(|e| (e.id, e.kind()))
+                .collect();
+            let candidates: Vec<_> = other_elements
+                .iter()
+                .filter(|e| e.has_unique_name() == unique_names && !matches.contains_key(&e.id))
+                .filter(|e| base_kinds.get(&e.id) == Some(&e.kind()))
+                .map(|e| e.id)
+                .collect();
+            for id in candidates {
+                if matched_base.insert(id) {
+                    matches.insert(id, id);
+                }
+            }
+        };
+
+    // Elements with unique names, by name first then by id to detect renames
+    let mut by_name: HashMap<_, _> = base_elements
+        .iter()
+        .filter(|e| e.has_unique_name())
+        .map(|e| ((e.kind(), e.name()), e.id))
+        .collect();
+    for e in other_elements.iter().filter(|e| e.has_unique_name()) {
+        if let Some(id) = by_name.remove(&(e.kind(), e.name())) {
+            matches.insert(e.id, id);
+            matched_base.insert(id);
+        }
+    }
+    match_by_id(&mut matches, &mut matched_base, true);
+
+    // Other elements by parent and name, in order if there are more with the same name
+    let mut by_parent: HashMap<_, VecDeque<u32>> = HashMap::new();
+    for e in base_elements.iter().filter(|e| !e.has_unique_name()) {
+        by_parent
+            .entry((e.kind(), e.parent, e.name()))
+            .or_default()
+            .push_back(e.id);
+    }
+    for e in other_elements.iter().filter(|e| !e.has_unique_name()) {
+        let Some(parent) = matches.get(&e.parent) else {
+            continue;
+        };
+        if let Some(id) = by_parent
+            .get_mut(&(e.kind(), *parent, e.name()))
+            .and_then(|ids| ids.pop_front())
+        {
+            matches.insert(e.id, id);
+            matched_base.insert(id);
+        }
+    }
+    match_by_id(&mut matches, &mut matched_base, false);
+    matches
+}
+
+fn poses_differ(a: &Pose, b: &Pose, options: &DiffOptions) -> bool {
+    let distance = glam::Vec3::from(a.trans).distance(glam::Vec3::from(b.trans));
+    let angle = quat_from_rotation(&a.rot).angle_between(quat_from_rotation(&b.rot));
+    distance > options.translation_tolerance || angle > options.rotation_tolerance
+}
+
+fn inertias_differ(a: &Inertia, b: &Inertia, options: &DiffOptions) -> bool {
+    let (ma, mb) = (&a.moment, &b.moment);
+    [
+        (a.mass.0, b.mass.0),
+        (ma.ixx, mb.ixx),
+        (ma.ixy, mb.ixy),
+        (ma.ixz, mb.ixz),
+        (ma.iyy, mb.iyy),
+        (ma.iyz, mb.iyz),
+        (ma.izz, mb.izz),
+    ]
+    .iter()
+    .any(|(a, b)| (a - b).abs() > options.inertia_tolerance)
+}
+
+/// One version of an element, together with the names of its workcell and the id of its parent
+/// converted to the ids used for the comparison.
+struct Version<'a> {
+    names: &'a ElementNames<'a>,
+    element: Element<'a>,
+    parent: u32,
+}
+
+/// Lists the changes from `old` to `new`, ids are the ones of `old`.
+fn compare(old: &Version, new: &Version, options: &DiffOptions) -> Vec<WorkcellChange> {
+    let mut changes = Vec::new();
+    let kind = old.element.kind();
+    let id = old.element.id;
+    let name = old.names.get(id);
+    if old.element.name() != new.element.name() {
+        changes.push(WorkcellChange::Renamed {
+            kind,
+            id,
+            from: old.element.name().to_owned(),
+            to: new.element.name().to_owned(),
+        });
+    }
+    if old.parent != new.parent {
+        changes.push(WorkcellChange::Reparented {
+            kind,
+            id,
+            name: name.clone(),
+            from: old.names.get(old.element.parent),
+            to: new.names.get(new.element.parent),
+        });
+    }
+    if let (Some(from), Some(to)) = (old.element.pose(), new.element.pose()) {
+        if poses_differ(&from, &to, options) {
+            changes.push(WorkcellChange::Moved {
+                kind,
+                id,
+                name: name.clone(),
+                from,
+                to,
+            });
+        }
+    }
+    match (old.element.data, new.element.data) {
+        (ElementData::Joint(from), ElementData::Joint(to)) => {
+            if from.properties != to.properties {
+                changes.push(WorkcellChange::JointChanged {
+                    id,
+                    name: name.clone(),
+                    from: from.properties.clone(),
+                    to: to.properties.clone(),
+                });
+            }
+            if from.actuation != to.actuation {
+                changes.push(WorkcellChange::ActuationChanged {
+                    id,
+                    name,
+                    from: from.actuation.clone(),
+                    to: to.actuation.clone(),
+                });
+            }
+        }
+        (ElementData::Include(from), ElementData::Include(to)) if from.source != to.source => {
+            changes.push(WorkcellChange::SourceChanged {
+                id,
+                name,
+                from: from.source.clone(),
+                to: to.source.clone(),
+            });
+        }
+        (ElementData::Sensor(from), ElementData::Sensor(to))
+            if from.properties != to.properties =>
+        {
+            changes.push(WorkcellChange::SensorChanged {
+                id,
+                name,
+                from: from.properties.clone(),
+                to: to.properties.clone(),
+            });
+        }
+        (ElementData::Visual(from), ElementData::Visual(to))
+        | (ElementData::Collision(from), ElementData::Collision(to)) => {
+            if from.geometry != to.geometry {
+                changes.push(WorkcellChange::GeometryChanged {
+                    kind,
+                    id,
+                    name: name.clone(),
+                    from: from.geometry.clone(),
+                    to: to.geometry.clone(),
+                });
+            }
+            if from.material != to.material {
+                changes.push(WorkcellChange::MaterialChanged {
+                    kind,
+                    id,
+                    name,
+                    from: from.material.clone(),
+                    to: to.material.clone(),
+                });
+            }
+        }
+        (ElementData::Inertia(from), ElementData::Inertia(to))
+            if inertias_differ(from, to, options) =>
+        {
+            changes.push(WorkcellChange::InertiaChanged {
+                id,
+                name,
+                from: from.clone(),
+                to: to.clone(),
+            });
+        }
+        _ => {}
+    }
+    changes
+}
+
+impl Workcell {
+    /// Lists the differences between this workcell and a newer version of it.
+    pub fn diff(&self, new: &Workcell, options: &DiffOptions) -> Vec<WorkcellChange> {
+        let mut changes = Vec::new();
+        if self.properties.name != new.properties.name {
+            changes.push(WorkcellChange::WorkcellRenamed {
+                from: self.properties.name.0.clone(),
+                to: new.properties.name.0.clone(),
+            });
+        }
+        if self.properties.control_hardware != new.properties.control_hardware {
+            changes.push(WorkcellChange::ControlHardwareChanged {
+                from: self.properties.control_hardware.clone(),
+                to: new.properties.control_hardware.clone(),
+            });
+        }
+        if self.properties.planning != new.properties.planning {
+            changes.push(WorkcellChange::PlanningChanged {
+                from: self.properties.planning.clone(),
+                to: new.properties.planning.clone(),
+            });
+        }
+        let matches = match_elements(self, new);
+        let (old_names, new_names) = (ElementNames::new(self), ElementNames::new(new));
+        let new_elements: HashMap<_, _> = new
+            .elements()
+            .into_iter()
+            .filter_map(|e| matches.get(&e.id).map(|id| (*id, e)))
+            .collect();
+        for element in self.elements() {
+            match new_elements.get(&element.id) {
+                Some(new_element) => {
+                    let old = Version {
+                        names: &old_names,
+                        element,
+                        parent: element.parent,
+                    };
+                    let new = Version {
+                        names: &new_names,
+                        element: *new_element,
+                        // Parents that were added can't match the old parent
+                        parent: matches
+                            .get(&new_element.parent)
+                            .copied()
+                            .unwrap_or(u32::MAX),
+                    };
+                    changes.extend(compare(&old, &new, options));
+                }
+                None => changes.push(WorkcellChange::Removed {
+                    kind: element.kind(),
+                    id: element.id,
+                    name: old_names.get(element.id),
+                }),
+            }
+        }
+        for element in new.elements() {
+            if !matches.contains_key(&element.id) {
+                changes.push(WorkcellChange::Added {
+                    kind: element.kind(),
+                    id: element.id,
+                    name: new_names.get(element.id),
+                });
+            }
+        }
+
+        let (old_materials, new_materials) = (&self.materials.0, &new.materials.0);
+        for (name, material) in old_materials {
+            match new_materials.get(name) {
+                Some(new_material) if new_material != material => {
+                    changes.push(WorkcellChange::LibraryMaterialChanged(name.clone()));
+                }
+                Some(_) => {}
+                None => changes.push(WorkcellChange::LibraryMaterialRemoved(name.clone())),
+            }
+        }
+        for name in new_materials.keys() {
+            if !old_materials.contains_key(name) {
+                changes.push(WorkcellChange::LibraryMaterialAdded(name.clone()));
+            }
+        }
+        changes
+    }
+}
+
+/// Merges two values that may have been modified from a common base, returns None if they were
+/// both modified differently.
+fn merge_values<T: PartialEq + Clone>(base: &T, ours: &T, theirs: &T) -> Option<T> {
+    if ours == theirs || theirs == base {
+        Some(ours.clone())
+    } else if ours == base {
+        Some(theirs.clone())
+    } else {
+        None
+    }
+}
+
+/// Parts of an element that are merged independently, i.e. renaming an element in one version
+/// and moving it in the other is not a conflict.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Aspect {
+    Name,
+    Parent,
+    Pose,
+    Content,
+}
+
+impl Aspect {
+    fn of(change: &WorkcellChange) -> Self {
+        match change {
+            WorkcellChange::Renamed { .. } => Aspect::Name,
+            WorkcellChange::Reparented { .. } => Aspect::Parent,
+            WorkcellChange::Moved { .. } => Aspect::Pose,
+            _ => Aspect::Content,
+        }
+    }
+}
+
+/// Copies the requested aspects of `from` to the element `id` of the workcell, the content is
+/// never copied.
+fn patch_element(
+    workcell: &mut Workcell,
+    id: u32,
+    from: &Element,
+    aspects: &[Aspect],
+    parent: u32,
+) {
+    fn patch<T>(
+        map: &mut BTreeMap<u32, Parented<u32, T>>,
+        id: u32,
+        parent: Option<u32>,
+        f: impl FnOnce(&mut T),
+    ) {
+        if let Some(element) = map.get_mut(&id) {
+            if let Some(parent) = parent {
+                element.parent = parent;
+            }
+            f(&mut element.bundle);
+        }
+    }
+    let parent = aspects.contains(&Aspect::Parent).then_some(parent);
+    let name = aspects
+        .contains(&Aspect::Name)
+        .then(|| from.name().to_owned());
+    let pose = aspects
+        .contains(&Aspect::Pose)
+        .then(|| from.pose())
+        .flatten();
+    let patch_model = |model: &mut WorkcellModel| {
+        if let Some(name) = name.clone() {
+            model.name = name;
+        }
+        if let Some(pose) = pose {
+            model.pose = pose;
+        }
+    };
+    match from.data {
+        ElementData::Frame(_) => patch(&mut workcell.frames, id, parent, |frame| {
+            if let Some(name) = name.clone() {
+                frame.name.0 = name;
+            }
+            if let Some(pose) = pose {
+                frame.anchor = Anchor::Pose3D(pose);
+            }
+        }),
+        ElementData::Joint(_) => patch(&mut workcell.joints, id, parent, |joint| {
+            if let Some(name) = name.clone() {
+                joint.name.0 = name;
+            }
+        }),
+        ElementData::Include(_) => patch(&mut workcell.includes, id, parent, |include| {
+            if let Some(name) = name.clone() {
+                include.name.0 = name;
+            }
+            if let Some(pose) = pose {
+                include.pose = pose;
+            }
+        }),
+        ElementData::Sensor(_) => patch(&mut workcell.sensors, id, parent, |sensor| {
+            if let Some(name) = name.clone() {
+                sensor.name.0 = name;
+            }
+            if let Some(pose) = pose {
+                sensor.pose = pose;
+            }
+        }),
+        ElementData::Visual(_) => patch(&mut workcell.visuals, id, parent, patch_model),
+        ElementData::Collision(_) => patch(&mut workcell.collisions, id, parent, patch_model),
+        ElementData::Inertia(_) => patch(&mut workcell.inertias, id, parent, |inertia| {
+            if let Some(pose) = pose {
+                inertia.center = pose;
+            }
+        }),
+    }
+}
+
+/// One of the two versions being merged, with its elements and the mapping of its ids to the
+/// ids of the merged workcell.
+struct MergeSide<'a> {
+    workcell: &'a Workcell,
+    names: ElementNames<'a>,
+    elements: HashMap<u32, Element<'a>>,
+    /// Ids of this side to ids of base
+    to_base: HashMap<u32, u32>,
+    /// Base ids to the matching elements of this side
+    from_base: HashMap<u32, Element<'a>>,
+    /// Ids of this side to ids of the merged workcell
+    to_result: HashMap<u32, u32>,
+}
+
+impl<'a> MergeSide<'a> {
+    fn new(base: &Workcell, workcell: &'a Workcell) -> Self {
+        let elements: HashMap<_, _> = workcell.elements().into_iter().map(|e| (e.id, e)).collect();
+        let to_base = match_elements(base, workcell);
+        let from_base = elements
+            .values()
+            .filter_map(|e| to_base.get(&e.id).map(|id| (*id, *e)))
+            .collect();
+        let to_result = to_base.clone();
+        Self {
+            workcell,
+            names: ElementNames::new(workcell),
+            elements,
+            to_base,
+            from_base,
+            to_result,
+        }
+    }
+
+    /// Elements that don't exist in base, in the order of [`Workcell::elements`].
+    fn added(&self) -> Vec<Element<'a>> {
+        self.workcell
+            .elements()
+            .into_iter()
+            .filter(|e| !self.to_base.contains_key(&e.id))
+            .collect()
+    }
+
+    fn base_version(&self, element: Element<'a>) -> Version<'_> {
+        Version {
+            names: &self.names,
+            element,
+            parent: self
+                .to_base
+                .get(&element.parent)
+                .copied()
+                .unwrap_or(u32::MAX),
+        }
+    }
+
+    fn result_version(&self, element: Element<'a>) -> Version<'_> {
+        Version {
+            names: &self.names,
+            element,
+            parent: self.result_parent(&element),
+        }
+    }
+
+    fn result_parent(&self, element: &Element) -> u32 {
+        self.to_result
+            .get(&element.parent)
+            .copied()
+            .unwrap_or(u32::MAX)
+    }
+
+    /// The aspects of the element that were modified compared to its base version.
+    fn changed_aspects(
+        &self,
+        base: &Version,
+        element: Element<'a>,
+        options: &DiffOptions,
+    ) -> HashSet<Aspect> {
+        compare(base, &self.base_version(element), options)
+            .iter()
+            .map(Aspect::of)
+            .collect()
+    }
+}
+
+impl Workcell {
+    /// Merges the changes made to `base` by two different versions of it. Conflicting changes
+    /// are resolved with the version of `ours` and reported in the result.
+    pub fn merge(
+        base: &Workcell,
+        ours: &Workcell,
+        theirs: &Workcell,
+        options: &DiffOptions,
+    ) -> WorkcellMerge {
+        let mut conflicts = Vec::new();
+        let mut ours = MergeSide::new(base, ours);
+        let mut theirs = MergeSide::new(base, theirs);
+        let base_elements: BTreeMap<_, _> =
+            base.elements().into_iter().map(|e| (e.id, e)).collect();
+        let base_names = ElementNames::new(base);
+
+        // Assign ids to the added elements, keeping their own ids when possible
+        let mut used_ids: HashSet<u32> = base_elements.keys().copied().collect();
+        used_ids.insert(base.id);
+        let mut next_id = base_elements
+            .keys()
+            .chain(ours.elements.keys())
+            .chain(theirs.elements.keys())
+            .chain([base.id, ours.workcell.id, theirs.workcell.id].iter())
+            .max()
+            .copied()
+            .unwrap_or(0)
+            + 1;
+        let mut allocate = |id: u32| {
+            if used_ids.insert(id) {
+                id
+            } else {
+                used_ids.insert(next_id);
+                next_id += 1;
+                next_id - 1
+            }
+        };
+        let ours_added = ours.added();
+        for element in &ours_added {
+            let id = allocate(element.id);
+            ours.to_result.insert(element.id, id);
+        }
+        // Elements added by both versions, as (ours, theirs)
+        let mut added_twice = Vec::new();
+        let mut theirs_added = Vec::new();
+        for element in theirs.added() {
+            let parent = theirs.result_parent(&element);
+            let same = ours_added.iter().find(|e| {
+                e.kind() == element.kind()
+                    && e.name() == element.name()
+                    && (element.has_unique_name() || ours.result_parent(e) == parent)
+            });
+            match same {
+                Some(same) => {
+                    theirs
+                        .to_result
+                        .insert(element.id, ours.to_result[&same.id]);
+                    added_twice.push((*same, element));
+                }
+                None => {
+                    let id = allocate(element.id);
+                    theirs.to_result.insert(element.id, id);
+                    theirs_added.push(element);
+                }
+            }
+        }
+
+        let mut workcell = Workcell {
+            properties: base.properties.clone(),
+            format_version: base.format_version,
+            id: base.id,
+            ..Default::default()
+        };
+        match merge_values(
+            &base.properties.name,
+            &ours.workcell.properties.name,
+            &theirs.workcell.properties.name,
+        ) {
+            Some(name) => workcell.properties.name = name,
+            None => {
+                workcell.properties.name = ours.workcell.properties.name.clone();
+                conflicts.push(MergeConflict::WorkcellRenamed {
+                    ours: ours.workcell.properties.name.0.clone(),
+                    theirs: theirs.workcell.properties.name.0.clone(),
+                });
+            }
+        }
+
+        match merge_values(
+            &base.properties.control_hardware,
+            &ours.workcell.properties.control_hardware,
+            &theirs.workcell.properties.control_hardware,
+        ) {
+            Some(hardware) => workcell.properties.control_hardware = hardware,
+            None => {
+                workcell.properties.control_hardware =
+                    ours.workcell.properties.control_hardware.clone();
+                conflicts.push(MergeConflict::ControlHardware);
+            }
+        }
+
+        match merge_values(
+            &base.properties.planning,
+            &ours.workcell.properties.planning,
+            &theirs.workcell.properties.planning,
+        ) {
+            Some(planning) => workcell.properties.planning = planning,
+            None => {
+                workcell.properties.planning = ours.workcell.properties.planning.clone();
+                conflicts.push(MergeConflict::Planning);
+            }
+        }
+
+        for (id, element) in &base_elements {
+            let base_version = Version {
+                names: &base_names,
+                element: *element,
+                parent: element.parent,
+            };
+            let ours_element = ours.from_base.get(id).copied();
+            let theirs_element = theirs.from_base.get(id).copied();
+            let ours_aspects = ours_element
+                .map(|e| ours.changed_aspects(&base_version, e, options))
+                .unwrap_or_default();
+            let theirs_aspects = theirs_element
+                .map(|e| theirs.changed_aspects(&base_version, e, options))
+                .unwrap_or_default();
+            let conflict = |e: &Element, both_present: bool| {
+                let kind = e.kind();
+                let name = e.name().to_owned();
+                if both_present {
+                    MergeConflict::BothChanged {
+                        kind,
+                        id: *id,
+                        name,
+                    }
+                } else {
+                    MergeConflict::ChangedAndRemoved {
+                        kind,
+                        id: *id,
+                        name,
+                    }
+                }
+            };
+            match (ours_element, theirs_element) {
+                (Some(o), Some(t)) => {
+                    let different: HashSet<_> =
+                        compare(&ours.result_version(o), &theirs.result_version(t), options)
+                            .iter()
+                            .map(Aspect::of)
+                            .collect();
+                    if ours_aspects
+                        .intersection(&theirs_aspects)
+                        .any(|aspect| different.contains(aspect))
+                    {
+                        conflicts.push(conflict(&o, true));
+                    }
+                    // Start from the version whose content changed and apply the name, parent
+                    // and pose changes of the other one
+                    if theirs_aspects.contains(&Aspect::Content)
+                        && !ours_aspects.contains(&Aspect::Content)
+                    {
+                        t.insert_into(&mut workcell, *id, theirs.result_parent(&t));
+                        let patch: Vec<_> = ours_aspects.iter().copied().collect();
+                        patch_element(&mut workcell, *id, &o, &patch, ours.result_parent(&o));
+                    } else {
+                        o.insert_into(&mut workcell, *id, ours.result_parent(&o));
+                        let patch: Vec<_> =
+                            theirs_aspects.difference(&ours_aspects).copied().collect();
+                        patch_element(&mut workcell, *id, &t, &patch, theirs.result_parent(&t));
+                    }
+                }
+                (Some(o), None) => {
+                    if !ours_aspects.is_empty() {
+                        conflicts.push(conflict(&o, false));
+                        o.insert_into(&mut workcell, *id, ours.result_parent(&o));
+                    }
+                }
+                (None, Some(t)) => {
+                    if !theirs_aspects.is_empty() {
+                        conflicts.push(conflict(&t, false));
+                        t.insert_into(&mut workcell, *id, theirs.result_parent(&t));
+                    }
+                }
+                (None, None) => {}
+            }
+        }
+
+        for element in &ours_added {
+            element.insert_into(
+                &mut workcell,
+                ours.to_result[&element.id],
+                ours.result_parent(element),
+            );
+        }
+        for element in &theirs_added {
+            element.insert_into(
+                &mut workcell,
+                theirs.to_result[&element.id],
+                theirs.result_parent(element),
+            );
+        }
+        for (o, t) in added_twice {
+            if !compare(&ours.result_version(o), &theirs.result_version(t), options).is_empty() {
+                conflicts.push(MergeConflict::BothAdded {
+                    kind: o.kind(),
+                    id: ours.to_result[&o.id],
+                    name: o.name().to_owned(),
+                });
+            }
+        }
+
+        // Restore the removed elements that the other version still refers to
+        loop {
+            let present: HashSet<u32> = workcell
+                .elements()
+                .iter()
+                .map(|e| e.id)
+                .chain([workcell.id])
+                .collect();
+            let missing: HashSet<u32> = workcell
+                .elements()
+                .iter()
+                .map(|e| e.parent)
+                .filter(|parent| !present.contains(parent))
+                .collect();
+            let restored: Vec<_> = missing
+                .iter()
+                .filter_map(|id| base_elements.get(id))
+                .collect();
+            if restored.is_empty() {
+                break;
+            }
+            for element in restored {
+                element.insert_into(&mut workcell, element.id, element.parent);
+                conflicts.push(MergeConflict::RemovedParent {
+                    kind: element.kind(),
+                    id: element.id,
+                    name: element.name().to_owned(),
+                });
+            }
+        }
+
+        let (base_materials, ours_materials, theirs_materials) = (
+            &base.materials.0,
+            &ours.workcell.materials.0,
+            &theirs.workcell.materials.0,
+        );
+        let names: std::collections::BTreeSet<_> = base_materials
+            .keys()
+            .chain(ours_materials.keys())
+            .chain(theirs_materials.keys())
+            .collect();
+        for name in names {
+            let (b, o, t) = (
+                base_materials.get(name),
+                ours_materials.get(name),
+                theirs_materials.get(name),
+            );
+            let material = merge_values(&b, &o, &t).unwrap_or_else(|| {
+                conflicts.push(MergeConflict::Material(name.clone()));
+                o
+            });
+            if let Some(material) = material {
+                workcell.materials.0.insert(name.clone(), material.clone());
+            }
+        }
+
+        WorkcellMerge {
+            workcell,
+            conflicts,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{frame, frame_id};
+    use std::path::Path;
+
+    fn load_workcell() -> Workcell {
+        IncludeResolver::default()
+            .read_workcell(Path::new("test/07-physics.urdf"))
+            .unwrap()
+    }
+
+    fn translate_frame(workcell: &mut Workcell, id: u32, z: f32) {
+        if let Anchor::Pose3D(pose) = &mut workcell.frames.get_mut(&id).unwrap().bundle.anchor {
+            pose.trans[2] += z;
+        }
+    }
+
+    #[test]
+    fn diff_detects_semantic_changes() {
+        let old = load_workcell();
+        assert!(old.diff(&old, &DiffOptions::default()).is_empty());
+
+        // Renumbering ids is not a change
+        let mut new = old.clone();
+        new.frames = old
+            .frames
+            .iter()
+            .map(|(id, f)| (id + 1000, f.clone()))
+            .collect();
+        for p in new.joints.values_mut() {
+            p.parent += 1000;
+        }
+        for p in new.visuals.values_mut().chain(new.collisions.values_mut()) {
+            p.parent += 1000;
+        }
+        for p in new.inertias.values_mut() {
+            p.parent += 1000;
+        }
+        for p in new.frames.values_mut() {
+            if old.frames.contains_key(&p.parent) {
+                p.parent += 1000;
+            }
+        }
+        assert!(old.diff(&new, &DiffOptions::default()).is_empty());
+
+        let mut new = old.clone();
+        let leg = frame_id(&old, "right_leg");
+        new.frames.get_mut(&leg).unwrap().bundle.name.0 = "leg".to_owned();
+        translate_frame(&mut new, leg, 1e-8);
+        let base = frame_id(&old, "base_link");
+        translate_frame(&mut new, base, 0.5);
+        let (visual, _) = new.visuals.iter_mut().next().unwrap();
+        let visual = *visual;
+        new.visuals.get_mut(&visual).unwrap().bundle.geometry =
+            Geometry::Primitive(rmf_site_format::PrimitiveShape::Sphere { radius: 2.0 });
+        let collision = *new.collisions.keys().next().unwrap();
+        new.collisions.get_mut(&collision).unwrap().bundle.material =
+            Some(MaterialRef::Library("steel".to_owned()));
+        let changes = old.diff(&new, &DiffOptions::default());
+        assert_eq!(changes.len(), 4);
+        assert!(changes.iter().any(|c| matches!(
+            c,
+            WorkcellChange::Renamed { id, to, .. } if *id == leg && to == "leg"
+        )));
+        assert!(changes
+            .iter()
+            .any(|c| matches!(c, WorkcellChange::Moved { id, .. } if *id == base)));
+        assert!(changes.iter().any(|c| matches!(
+            c,
+            WorkcellChange::GeometryChanged { id, .. } if *id == visual
+        )));
+        let material = changes
+            .iter()
+            .find(|c| matches!(c, WorkcellChange::MaterialChanged { id, .. } if *id == collision))
+            .unwrap();
+        assert!(material.to_string().starts_with("~ collision ["));
+    }
+
+    #[test]
+    fn merge_combines_changes_and_reports_conflicts() {
+        let base = load_workcell();
+        let leg = frame_id(&base, "right_leg");
+        let head = frame_id(&base, "head");
+
+        let mut ours = base.clone();
+        translate_frame(&mut ours, leg, 1.0);
+        translate_frame(&mut ours, head, 1.0);
+        let mut theirs = base.clone();
+        theirs.frames.get_mut(&leg).unwrap().bundle.name.0 = "leg".to_owned();
+        translate_frame(&mut theirs, head, 2.0);
+        let new_frame = frame(head, "camera");
+        let new_id = base.elements().iter().map(|e| e.id).max().unwrap() + 1;
+        ours.frames.insert(new_id, new_frame.clone());
+        theirs.frames.insert(new_id, new_frame.clone());
+        let mut other_frame = new_frame.clone();
+        other_frame.bundle.name.0 = "lidar".to_owned();
+        theirs.frames.insert(new_id + 1, other_frame);
+
+        let merge = Workcell::merge(&base, &ours, &theirs, &DiffOptions::default());
+        assert_eq!(
+            merge.conflicts,
+            vec![MergeConflict::BothChanged {
+                kind: WorkcellElementKind::Frame,
+                id: head,
+                name: "head".to_owned(),
+            }]
+        );
+        let merged = &merge.workcell;
+        assert!(merged.validate().is_empty());
+        assert_eq!(merged.frames.len(), base.frames.len() + 2);
+        let leg_frame = &merged.frames[&leg].bundle;
+        assert_eq!(leg_frame.name.0, "leg");
+        let Anchor::Pose3D(pose) = &leg_frame.anchor else {
+            panic!("Unexpected anchor");
+        };
+        let Anchor::Pose3D(base_pose) = &base.frames[&leg].bundle.anchor else {
+            panic!("Unexpected anchor");
+        };
+        assert_eq!(pose.trans[2], base_pose.trans[2] + 1.0);
+        assert_eq!(merged.frames[&new_id].bundle.name.0, "camera");
+        assert_eq!(merged.frames[&(new_id + 1)].bundle.name.0, "lidar");
+    }
+}
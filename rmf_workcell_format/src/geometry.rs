@@ -15,14 +15,14 @@
  *
 */
 
-use crate::is_default;
+use crate::{is_default, MaterialRef, VisualMaterial};
 
 use rmf_site_format::{AssetSource, Pose, PrimitiveShape};
 
 use glam::Vec3;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum Geometry {
     //#[serde(flatten)]
     Primitive(PrimitiveShape),
@@ -96,24 +96,32 @@ impl From<&urdf_rs::Geometry> for Geometry {
             }),
             urdf_rs::Geometry::Mesh { filename, scale } => {
                 let scale = (*scale).map(|s| Vec3::from_array(s.map(|v| v as f32)));
-                // Most (all?) Urdf files use package references, we fallback to local if that is
-                // not the case
-                let source = if let Some(path) = filename.strip_prefix("package://") {
-                    AssetSource::Package(path.to_owned())
-                } else {
-                    AssetSource::Local(filename.clone())
-                };
+                let source = asset_source_from_urdf(filename);
                 Geometry::Mesh { source, scale }
             }
         }
     }
 }
 
+/// Converts a filename found in a urdf to an asset source.
+pub(crate) fn asset_source_from_urdf(filename: &str) -> AssetSource {
+    // Most (all?) Urdf files use package references, we fallback to local if that is
+    // not the case
+    if let Some(path) = filename.strip_prefix("package://") {
+        AssetSource::Package(path.to_owned())
+    } else {
+        AssetSource::Local(filename.to_owned())
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct WorkcellModel {
     pub name: String,
     pub geometry: Geometry,
     pub pose: Pose,
+    /// Only used by visuals, collisions have no appearance
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub material: Option<MaterialRef>,
 }
 
 impl WorkcellModel {
@@ -126,13 +134,21 @@ impl WorkcellModel {
             name: name.clone().unwrap_or_default(),
             geometry: geometry.into(),
             pose: pose.into(),
+            material: None,
         }
     }
 }
 
 impl From<&urdf_rs::Visual> for WorkcellModel {
     fn from(visual: &urdf_rs::Visual) -> Self {
-        WorkcellModel::from_urdf_data(&visual.origin, &visual.name, &visual.geometry)
+        let mut model =
+            WorkcellModel::from_urdf_data(&visual.origin, &visual.name, &visual.geometry);
+        model.material = visual
+            .material
+            .as_ref()
+            .and_then(VisualMaterial::from_urdf)
+            .map(MaterialRef::Inline);
+        model
     }
 }
 
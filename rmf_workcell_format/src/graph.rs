@@ -0,0 +1,322 @@
+/*
+ * Copyright (C) 2024 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+//! Indexed view of the hierarchy of a workcell, to query it without scanning all its elements.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::*;
+use thiserror::Error as ThisError;
+
+#[derive(Debug, Clone, PartialEq, ThisError)]
+pub enum WorkcellGraphError {
+    #[error("element [{0}] not found")]
+    MissingElement(u32),
+    #[error("cycle found in the hierarchy of element [{0}]")]
+    ParentCycle(u32),
+}
+
+/// A joint crossed by a kinematic chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainJoint {
+    pub joint: u32,
+    /// True if the chain goes from the parent frame of the joint to its child, false if it goes
+    /// from the child to the parent
+    pub forward: bool,
+}
+
+/// Graph of the elements of a workcell, built with [`Workcell::graph`]. The graph borrows the
+/// workcell so it can't go out of sync with it.
+#[derive(Debug, Clone)]
+pub struct WorkcellGraph<'a> {
+    workcell: &'a Workcell,
+    kinds: HashMap<u32, WorkcellElementKind>,
+    parents: HashMap<u32, u32>,
+    children: HashMap<u32, Vec<u32>>,
+    names: HashMap<(WorkcellElementKind, &'a str), u32>,
+}
+
+impl Workcell {
+    /// Indexes the hierarchy of the workcell for fast queries.
+    pub fn graph(&self) -> WorkcellGraph<'_> {
+        let mut kinds = HashMap::from([(self.id, WorkcellElementKind::Workcell)]);
+        let mut parents = HashMap::new();
+        let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+        for (id, parent, kind) in self.element_parents() {
+            kinds.insert(id, kind);
+            parents.insert(id, parent);
+            children.entry(parent).or_default().push(id);
+        }
+        for ids in children.values_mut() {
+            ids.sort();
+        }
+        let names = self
+            .frames
+            .iter()
+            .map(|(id, f)| ((WorkcellElementKind::Frame, f.bundle.name.0.as_str()), *id))
+            .chain(
+                self.joints
+                    .iter()
+                    .map(|(id, j)| ((WorkcellElementKind::Joint, j.bundle.name.0.as_str()), *id)),
+            )
+            .chain(self.includes.iter().map(|(id, i)| {
+                (
+                    (WorkcellElementKind::Include, i.bundle.name.0.as_str()),
+                    *id,
+                )
+            }))
+            .chain(
+                self.sensors
+                    .iter()
+                    .map(|(id, s)| ((WorkcellElementKind::Sensor, s.bundle.name.0.as_str()), *id)),
+            )
+            .collect();
+        WorkcellGraph {
+            workcell: self,
+            kinds,
+            parents,
+            children,
+            names,
+        }
+    }
+}
+
+impl<'a> WorkcellGraph<'a> {
+    pub fn workcell(&self) -> &'a Workcell {
+        self.workcell
+    }
+
+    /// Kind of an element, the id of the workcell itself has kind
+    /// [`WorkcellElementKind::Workcell`].
+    pub fn kind(&self, id: u32) -> Option<WorkcellElementKind> {
+        self.kinds.get(&id).copied()
+    }
+
+    /// Parent of an element, None for the workcell itself or missing elements.
+    pub fn parent(&self, id: u32) -> Option<u32> {
+        self.parents.get(&id).copied()
+    }
+
+    /// Direct children of an element, sorted by id.
+    pub fn children(&self, id: u32) -> &[u32] {
+        self.children
+            .get(&id)
+            .map(|c| c.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Direct children of an element with the requested kind, sorted by id.
+    pub fn children_of_kind(&self, id: u32, kind: WorkcellElementKind) -> Vec<u32> {
+        self.children(id)
+            .iter()
+            .copied()
+            .filter(|c| self.kind(*c) == Some(kind))
+            .collect()
+    }
+
+    /// Visuals, collisions, inertias, includes and sensors attached to a frame. Frames and joints are not
+    /// included since they can move relative to it.
+    pub fn attached_elements(&self, frame: u32) -> Vec<(u32, WorkcellElementKind)> {
+        self.children(frame)
+            .iter()
+            .filter_map(|c| self.kind(*c).map(|kind| (*c, kind)))
+            .filter(|(_, kind)| {
+                !matches!(
+                    kind,
+                    WorkcellElementKind::Frame | WorkcellElementKind::Joint
+                )
+            })
+            .collect()
+    }
+
+    /// All the elements below an element in the hierarchy, parents come before their children.
+    pub fn descendants(&self, id: u32) -> Vec<u32> {
+        let mut descendants = Vec::new();
+        let mut visited = HashSet::from([id]);
+        let mut queue = vec![id];
+        while let Some(current) = queue.pop() {
+            for child in self.children(current) {
+                if visited.insert(*child) {
+                    descendants.push(*child);
+                    queue.push(*child);
+                }
+            }
+        }
+        descendants
+    }
+
+    /// The parent of an element, its parent and so on up to the workcell, which is the last
+    /// element of the result.
+    pub fn ancestors(&self, id: u32) -> Result<Vec<u32>, WorkcellGraphError> {
+        if !self.kinds.contains_key(&id) {
+            return Err(WorkcellGraphError::MissingElement(id));
+        }
+        let mut ancestors = Vec::new();
+        let mut current = id;
+        while current != self.workcell.id {
+            let parent = self
+                .parent(current)
+                .ok_or(WorkcellGraphError::MissingElement(current))?;
+            if ancestors.len() > self.parents.len() {
+                return Err(WorkcellGraphError::ParentCycle(id));
+            }
+            ancestors.push(parent);
+            current = parent;
+        }
+        Ok(ancestors)
+    }
+
+    /// The elements connecting `from` to `to` through the hierarchy, including both of them.
+    /// The path goes up from `from` to the closest common ancestor, then down to `to`.
+    pub fn path(&self, from: u32, to: u32) -> Result<Vec<u32>, WorkcellGraphError> {
+        Ok(self.split_path(from, to)?.concat())
+    }
+
+    /// The path between two elements, split in the part going up to the common ancestor and
+    /// the part going down from it.
+    fn split_path(&self, from: u32, to: u32) -> Result<[Vec<u32>; 2], WorkcellGraphError> {
+        let mut up = vec![from];
+        up.extend(self.ancestors(from)?);
+        let mut down = vec![to];
+        down.extend(self.ancestors(to)?);
+        // Both lists end with the workcell so there is always a common ancestor
+        let down_ancestors: HashSet<_> = down.iter().copied().collect();
+        let common = up
+            .iter()
+            .position(|id| down_ancestors.contains(id))
+            .unwrap_or(up.len() - 1);
+        up.truncate(common + 1);
+        let common = down.iter().position(|id| *id == up[common]).unwrap_or(0);
+        down.truncate(common);
+        down.reverse();
+        Ok([up, down])
+    }
+
+    /// The joints crossed going from frame `from` to frame `to`, in order.
+    pub fn joint_chain(&self, from: u32, to: u32) -> Result<Vec<ChainJoint>, WorkcellGraphError> {
+        let [up, down] = self.split_path(from, to)?;
+        let is_joint = |id: &&u32| self.kind(**id) == Some(WorkcellElementKind::Joint);
+        // A joint that is the common ancestor is not crossed, i.e. when `from` is the joint
+        let up = up[..up.len() - 1]
+            .iter()
+            .filter(is_joint)
+            .map(|joint| ChainJoint {
+                joint: *joint,
+                forward: false,
+            });
+        let down = down.iter().filter(is_joint).map(|joint| ChainJoint {
+            joint: *joint,
+            forward: true,
+        });
+        Ok(up.chain(down).collect())
+    }
+
+    pub fn frame_by_name(&self, name: &str) -> Option<u32> {
+        self.names.get(&(WorkcellElementKind::Frame, name)).copied()
+    }
+
+    pub fn joint_by_name(&self, name: &str) -> Option<u32> {
+        self.names.get(&(WorkcellElementKind::Joint, name)).copied()
+    }
+
+    pub fn include_by_name(&self, name: &str) -> Option<u32> {
+        self.names
+            .get(&(WorkcellElementKind::Include, name))
+            .copied()
+    }
+
+    pub fn sensor_by_name(&self, name: &str) -> Option<u32> {
+        self.names
+            .get(&(WorkcellElementKind::Sensor, name))
+            .copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn graph_queries() {
+        let urdf = urdf_rs::read_file("test/07-physics.urdf").unwrap();
+        let workcell = Workcell::from_urdf(&urdf).unwrap();
+        let graph = workcell.graph();
+        let frame = |name| graph.frame_by_name(name).unwrap();
+        let joint = |name| graph.joint_by_name(name).unwrap();
+        assert!(graph.frame_by_name("right_base_joint").is_none());
+
+        let base_link = frame("base_link");
+        let right_leg = frame("right_leg");
+        let gripper = frame("right_gripper");
+        assert_eq!(graph.kind(workcell.id), Some(WorkcellElementKind::Workcell));
+        assert_eq!(graph.parent(base_link), Some(workcell.id));
+        assert_eq!(graph.children(workcell.id), &[base_link]);
+        assert_eq!(
+            graph.children_of_kind(right_leg, WorkcellElementKind::Joint),
+            vec![joint("right_base_joint")]
+        );
+        let attached: Vec<_> = graph
+            .attached_elements(right_leg)
+            .into_iter()
+            .map(|(_, kind)| kind)
+            .collect();
+        assert_eq!(attached.len(), 3);
+        assert!(attached.contains(&WorkcellElementKind::Visual));
+        assert!(attached.contains(&WorkcellElementKind::Collision));
+        assert!(attached.contains(&WorkcellElementKind::Inertia));
+        assert_eq!(
+            graph.ancestors(right_leg).unwrap(),
+            vec![joint("base_to_right_leg"), base_link, workcell.id]
+        );
+        assert!(graph.descendants(base_link).contains(&gripper));
+        assert_eq!(graph.descendants(workcell.id).len(), graph.kinds.len() - 1);
+
+        // From the right tip of the gripper to the left base through the base link
+        let left_base = frame("left_base");
+        let path = graph.path(frame("right_tip"), left_base).unwrap();
+        assert_eq!(path.first(), Some(&frame("right_tip")));
+        assert_eq!(path.last(), Some(&left_base));
+        assert!(path.contains(&gripper));
+        assert!(path.contains(&base_link));
+        assert!(!path.contains(&workcell.id));
+        let chain = graph.joint_chain(frame("right_tip"), left_base).unwrap();
+        let names: Vec<_> = chain
+            .iter()
+            .map(|j| workcell.joints[&j.joint].bundle.name.0.as_str())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "right_tip_joint",
+                "right_gripper_joint",
+                "gripper_extension",
+                "base_to_left_leg",
+                "left_base_joint",
+            ]
+        );
+        assert_eq!(
+            chain.iter().map(|j| j.forward).collect::<Vec<_>>(),
+            [false, false, false, true, true]
+        );
+        assert_eq!(graph.path(right_leg, right_leg).unwrap(), vec![right_leg]);
+        assert!(graph.joint_chain(right_leg, right_leg).unwrap().is_empty());
+        assert_eq!(
+            graph.ancestors(1000),
+            Err(WorkcellGraphError::MissingElement(1000))
+        );
+    }
+}
@@ -0,0 +1,462 @@
+/*
+ * Copyright (C) 2024 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::*;
+#[cfg(feature = "bevy")]
+use bevy::prelude::{Bundle, Component};
+use rmf_site_format::{Anchor, AssetSource, Pose};
+use serde::{Deserialize, Serialize};
+use thiserror::Error as ThisError;
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[cfg_attr(feature = "bevy", derive(Component))]
+pub struct IncludedWorkcellMarker;
+
+/// Another workcell, or robot description, attached to a frame of this workcell. Its content
+/// is not copied in the workcell, it is loaded from its source when needed.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "bevy", derive(Bundle))]
+pub struct IncludedWorkcell {
+    /// Name of the frame the included workcell is attached to, also used to prefix the names of
+    /// its frames, joints and sensors.
+    pub name: NameInWorkcell,
+    /// Pose of the included workcell relative to its parent frame
+    pub pose: Pose,
+    /// Either a `.workcell.json` file or a urdf, xacro or sdf robot description
+    pub source: AssetSource,
+    #[serde(skip)]
+    pub marker: IncludedWorkcellMarker,
+}
+
+#[derive(Debug, ThisError)]
+pub enum IncludeError {
+    #[error("unable to find the workcell included from [{0}]")]
+    UnresolvedSource(String),
+    #[error("workcell [{0}] includes itself")]
+    IncludeCycle(PathBuf),
+    #[error("file [{0}] is not a supported workcell or robot description")]
+    UnsupportedFile(PathBuf),
+    #[error("Io error reading [{path}]: {error}")]
+    IoError {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    #[error("included workcell [{path}] is not valid: {diagnostics:?}")]
+    InvalidStructure {
+        path: PathBuf,
+        diagnostics: Vec<WorkcellDiagnostic>,
+    },
+    #[error(transparent)]
+    Workcell(#[from] WorkcellLoadError),
+    #[error("Urdf error: {0}")]
+    Urdf(#[from] urdf_rs::UrdfError),
+    #[error(transparent)]
+    UrdfImport(#[from] UrdfImportError),
+    #[error(transparent)]
+    Xacro(#[from] XacroError),
+    #[error(transparent)]
+    Sdf(#[from] SdfImportError),
+    #[error("include [{0}] uses a different ros2_control hardware than the including workcell")]
+    ConflictingControlHardware(String),
+}
+
+/// Finds and reads the sources of included workcells.
+#[derive(Debug, Clone, Default)]
+pub struct IncludeResolver {
+    /// Directories of the packages that `package://` sources can refer to, also used to expand
+    /// xacro files.
+    pub packages: HashMap<String, PathBuf>,
+    /// Directory where remote assets are cached, remote sources can't be resolved without it.
+    pub remote_cache: Option<PathBuf>,
+}
+
+impl IncludeResolver {
+    /// Returns the local path of a source, relative paths are resolved from `dir`, the directory
+    /// of the workcell that includes the source.
+    pub fn resolve_path(&self, source: &AssetSource, dir: Option<&Path>) -> Option<PathBuf> {
+        match source {
+            AssetSource::Local(path) => {
+                let path = Path::new(path);
+                if path.is_absolute() {
+                    Some(path.to_owned())
+                } else {
+                    dir.map(|dir| dir.join(path))
+                }
+            }
+            AssetSource::Package(path) => {
+                let (package, path) = path.split_once('/')?;
+                self.packages.get(package).map(|dir| dir.join(path))
+            }
+            AssetSource::Remote(path) => self.remote_cache.as_ref().map(|dir| dir.join(path)),
+            AssetSource::Search(_) => None,
+        }
+    }
+
+    /// Reads a workcell from a file, robot descriptions are converted to workcells.
+    pub fn read_workcell(&self, path: &Path) -> Result<Workcell, IncludeError> {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let read = || {
+            std::fs::read(path).map_err(|error| IncludeError::IoError {
+                path: path.to_owned(),
+                error,
+            })
+        };
+        if name.ends_with(".json") {
+            Ok(Workcell::from_bytes(&read()?)?)
+        } else if name.ends_with(".urdf") {
+            let robot = urdf_rs::read_from_string(&String::from_utf8_lossy(&read()?))?;
+            Ok(Workcell::from_urdf(&robot)?)
+        } else if name.ends_with(".xacro") {
+            let processor = XacroProcessor {
+                packages: self.packages.clone(),
+                ..Default::default()
+            };
+            let robot = processor.read_urdf(&read()?, path.parent())?;
+            Ok(Workcell::from_urdf(&robot)?)
+        } else if name.ends_with(".sdf") {
+            Ok(Workcell::from_sdf_file(path)?)
+        } else {
+            Err(IncludeError::UnsupportedFile(path.to_owned()))
+        }
+    }
+
+    /// Loads the workcell a source refers to, with its own includes flattened. Relative sources
+    /// are resolved from `dir`, the directory of the workcell that includes the source.
+    pub fn load_include(
+        &self,
+        source: &AssetSource,
+        dir: Option<&Path>,
+    ) -> Result<Workcell, IncludeError> {
+        self.load_include_impl(source, dir, &mut Vec::new())
+    }
+
+    fn load_include_impl(
+        &self,
+        source: &AssetSource,
+        dir: Option<&Path>,
+        stack: &mut Vec<PathBuf>,
+    ) -> Result<Workcell, IncludeError> {
+        let path = self
+            .resolve_path(source, dir)
+            .filter(|path| path.is_file())
+            .ok_or_else(|| IncludeError::UnresolvedSource(format!("{:?}", source)))?;
+        if stack.contains(&path) {
+            return Err(IncludeError::IncludeCycle(path));
+        }
+        let included = self.read_workcell(&path)?;
+        let diagnostics = included.validate();
+        if !diagnostics.is_empty() {
+            return Err(IncludeError::InvalidStructure { path, diagnostics });
+        }
+        let included_dir = path.parent().map(Path::to_owned);
+        stack.push(path);
+        let mut included = included.flatten_includes_impl(self, included_dir.as_deref(), stack)?;
+        stack.pop();
+        if let Some(dir) = &included_dir {
+            included.resolve_local_sources(dir);
+        }
+        Ok(included)
+    }
+}
+
+impl Workcell {
+    /// Returns a copy of the workcell where included workcells are loaded and merged in place.
+    /// Each include becomes a frame with its name and pose, the content of the included
+    /// workcell is attached to it and the names of its frames, joints, sensors, materials and
+    /// planning groups are prefixed with `<include_name>_` to keep them unique. Included
+    /// workcells must use the same ros2_control hardware, if any.
+    /// Relative sources are resolved from `dir`, the directory of this workcell.
+    pub fn flatten_includes(
+        &self,
+        resolver: &IncludeResolver,
+        dir: Option<&Path>,
+    ) -> Result<Workcell, IncludeError> {
+        self.flatten_includes_impl(resolver, dir, &mut Vec::new())
+    }
+
+    fn flatten_includes_impl(
+        &self,
+        resolver: &IncludeResolver,
+        dir: Option<&Path>,
+        stack: &mut Vec<PathBuf>,
+    ) -> Result<Workcell, IncludeError> {
+        let mut flat = self.clone();
+        flat.includes.clear();
+        let mut next_id = self
+            .element_parents()
+            .map(|(id, _, _)| id)
+            .chain(std::iter::once(self.id))
+            .max()
+            .unwrap_or_default()
+            + 1;
+        for (id, include) in &self.includes {
+            let included = resolver.load_include_impl(&include.bundle.source, dir, stack)?;
+            flat.merge_included(*id, include, included, &mut next_id)?;
+        }
+        Ok(flat)
+    }
+
+    /// Makes the relative paths of local meshes and textures absolute, so they can be found from
+    /// workcells in other directories.
+    fn resolve_local_sources(&mut self, dir: &Path) {
+        let resolve = |source: &mut AssetSource| {
+            if let AssetSource::Local(path) = source {
+                if Path::new(path).is_relative() {
+                    *path = dir.join(&*path).to_string_lossy().into_owned();
+                }
+            }
+        };
+        for model in self
+            .visuals
+            .values_mut()
+            .chain(self.collisions.values_mut())
+        {
+            if let Geometry::Mesh { source, .. } = &mut model.bundle.geometry {
+                resolve(source);
+            }
+            if let Some(MaterialRef::Inline(material)) = &mut model.bundle.material {
+                if let Some(texture) = &mut material.texture {
+                    resolve(texture);
+                }
+            }
+        }
+        for material in self.materials.0.values_mut() {
+            if let Some(texture) = &mut material.texture {
+                resolve(texture);
+            }
+        }
+    }
+
+    /// Adds the content of a flattened workcell in place of one of the includes. Workcells only
+    /// have one ros2_control hardware, so the included one is used if this workcell has the
+    /// default one and they must match otherwise.
+    fn merge_included(
+        &mut self,
+        include_id: u32,
+        include: &Parented<u32, IncludedWorkcell>,
+        included: Workcell,
+        next_id: &mut u32,
+    ) -> Result<(), IncludeError> {
+        let hardware = &included.properties.control_hardware;
+        if self.properties.control_hardware == ControlHardware::default() {
+            self.properties.control_hardware = hardware.clone();
+        } else if *hardware != ControlHardware::default()
+            && *hardware != self.properties.control_hardware
+        {
+            return Err(IncludeError::ConflictingControlHardware(
+                include.bundle.name.0.clone(),
+            ));
+        }
+        let prefix = |name: &str| format!("{}_{}", include.bundle.name.0, name);
+        // The root of the included workcell is the frame that replaces the include
+        let mut ids = HashMap::from([(included.id, include_id)]);
+        for (id, _, _) in included.element_parents() {
+            ids.insert(id, *next_id);
+            *next_id += 1;
+        }
+        self.frames.insert(
+            include_id,
+            Parented {
+                parent: include.parent,
+                bundle: Frame {
+                    anchor: Anchor::Pose3D(include.bundle.pose),
+                    name: include.bundle.name.clone(),
+                    marker: FrameMarker,
+                },
+            },
+        );
+        for (id, mut frame) in included.frames {
+            frame.parent = ids[&frame.parent];
+            frame.bundle.name.0 = prefix(&frame.bundle.name.0);
+            self.frames.insert(ids[&id], frame);
+        }
+        for (id, mut joint) in included.joints {
+            joint.parent = ids[&joint.parent];
+            joint.bundle.name.0 = prefix(&joint.bundle.name.0);
+            if let Some(mimic) = joint
+                .bundle
+                .properties
+                .single_dof_mut()
+                .and_then(|j| j.mimic.as_mut())
+            {
+                mimic.joint = prefix(&mimic.joint);
+            }
+            self.joints.insert(ids[&id], joint);
+        }
+        for (id, mut visual) in included.visuals {
+            visual.parent = ids[&visual.parent];
+            if let Some(MaterialRef::Library(name)) = &mut visual.bundle.material {
+                *name = prefix(name);
+            }
+            self.visuals.insert(ids[&id], visual);
+        }
+        for (id, mut collision) in included.collisions {
+            collision.parent = ids[&collision.parent];
+            self.collisions.insert(ids[&id], collision);
+        }
+        for (id, mut inertia) in included.inertias {
+            inertia.parent = ids[&inertia.parent];
+            self.inertias.insert(ids[&id], inertia);
+        }
+        for (id, mut sensor) in included.sensors {
+            sensor.parent = ids[&sensor.parent];
+            sensor.bundle.name.0 = prefix(&sensor.bundle.name.0);
+            self.sensors.insert(ids[&id], sensor);
+        }
+        for (name, material) in included.materials.0 {
+            self.materials.0.insert(prefix(&name), material);
+        }
+        self.properties
+            .planning
+            .extend_prefixed(included.properties.planning, prefix);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workcell_including(source: &str) -> Workcell {
+        let mut builder = WorkcellBuilder::new("cell");
+        let table = builder.add_frame(builder.root(), "table", Pose::default());
+        builder.add_include(
+            table,
+            "robot",
+            AssetSource::Local(source.to_owned()),
+            Pose {
+                trans: [0.0, 0.0, 1.0],
+                ..Default::default()
+            },
+        );
+        builder.build().unwrap()
+    }
+
+    #[test]
+    fn includes_are_flattened() {
+        let workcell = workcell_including("07-physics.urdf");
+        assert!(workcell.validate().is_empty());
+        assert!(matches!(
+            workcell.to_urdf(),
+            Err(WorkcellToUrdfError::UnflattenedIncludes)
+        ));
+
+        let resolver = IncludeResolver::default();
+        let flat = workcell
+            .flatten_includes(&resolver, Some(Path::new("test")))
+            .unwrap();
+        assert!(flat.includes.is_empty());
+        assert!(flat.validate().is_empty());
+        // The table, the frame replacing the include and the links of the robot
+        assert_eq!(flat.frames.len(), 18);
+        assert_eq!(flat.joints.len(), 15);
+        let include_frame = &flat.frames[&2];
+        assert_eq!(include_frame.parent, 1);
+        assert_eq!(include_frame.bundle.name.0, "robot");
+        let base = flat
+            .frames
+            .values()
+            .find(|f| f.bundle.name.0 == "robot_base_link")
+            .unwrap();
+        assert_eq!(base.parent, 2);
+        assert!(flat
+            .joints
+            .values()
+            .all(|j| j.bundle.name.0.starts_with("robot_")));
+
+        let robot = flat.to_urdf().unwrap();
+        assert!(robot.links.iter().any(|l| l.name == "robot_right_leg"));
+    }
+
+    #[test]
+    fn included_textures_and_hardware_are_merged() {
+        let dir =
+            std::env::temp_dir().join(format!("rmf_workcell_include_merge_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut builder = WorkcellBuilder::new("gripper");
+        let palm = builder.add_frame(builder.root(), "palm", Pose::default());
+        let textured = |path: &str| VisualMaterial {
+            color: [1.0; 4],
+            texture: Some(AssetSource::Local(path.to_owned())),
+        };
+        let shape = Geometry::Primitive(PrimitiveShape::Box { size: [0.1; 3] });
+        let inline = builder.add_visual(palm, "inline", shape.clone(), Pose::default());
+        builder.set_visual_material(inline, MaterialRef::Inline(textured("inline.png")));
+        let shared = builder.add_visual(palm, "shared", shape, Pose::default());
+        let material = builder.add_material("shared", textured("shared.png"));
+        builder.set_visual_material(shared, material);
+        let mut gripper = builder.build().unwrap();
+        gripper.properties.control_hardware.plugin = "gripper_hardware/Gripper".to_owned();
+        std::fs::write(
+            dir.join("gripper.workcell.json"),
+            gripper.to_string().unwrap(),
+        )
+        .unwrap();
+
+        let mut workcell = workcell_including("gripper.workcell.json");
+        let flat = workcell.flatten_includes(&IncludeResolver::default(), Some(&dir));
+        workcell.properties.control_hardware.plugin = "arm_hardware/Arm".to_owned();
+        let conflict = workcell.flatten_includes(&IncludeResolver::default(), Some(&dir));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let flat = flat.unwrap();
+        assert_eq!(
+            flat.properties.control_hardware,
+            gripper.properties.control_hardware
+        );
+        let textures: Vec<_> = flat
+            .visuals
+            .values()
+            .filter_map(|v| flat.materials.resolve(v.bundle.material.as_ref()?))
+            .filter_map(|m| m.texture.clone())
+            .collect();
+        assert_eq!(
+            textures,
+            ["inline.png", "shared.png"]
+                .map(|t| AssetSource::Local(dir.join(t).to_string_lossy().into_owned()))
+        );
+        assert!(matches!(
+            conflict,
+            Err(IncludeError::ConflictingControlHardware(name)) if name == "robot"
+        ));
+    }
+
+    #[test]
+    fn include_cycles_are_detected() {
+        let dir =
+            std::env::temp_dir().join(format!("rmf_workcell_include_cycle_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let workcell = workcell_including("cycle.workcell.json");
+        std::fs::write(
+            dir.join("cycle.workcell.json"),
+            workcell.to_string().unwrap(),
+        )
+        .unwrap();
+        let cycle = workcell.flatten_includes(&IncludeResolver::default(), Some(&dir));
+        let workcell = workcell_including("missing.workcell.json");
+        let missing = workcell.flatten_includes(&IncludeResolver::default(), Some(&dir));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(cycle, Err(IncludeError::IncludeCycle(_))));
+        assert!(matches!(missing, Err(IncludeError::UnresolvedSource(_))));
+    }
+}
@@ -15,17 +15,22 @@
  *
 */
 
-use rmf_site_format::Pose;
+use std::f64::consts::PI;
+
+use crate::{affine_from_pose, pose_from_affine, quat_from_rotation, Geometry};
+use rmf_site_format::{Pose, PrimitiveShape, Rotation};
+
+use glam::{Affine3A, DMat3, DVec3, Mat3, Vec3};
 
 #[cfg(feature = "bevy")]
 use bevy::prelude::{Bundle, Component, Deref, DerefMut};
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
 #[cfg_attr(feature = "bevy", derive(Component, Deref, DerefMut))]
 pub struct Mass(pub f32);
 
-#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
 #[cfg_attr(feature = "bevy", derive(Component))]
 pub struct Moment {
     pub ixx: f32,
@@ -36,6 +41,28 @@ pub struct Moment {
     pub izz: f32,
 }
 
+impl Moment {
+    /// Returns the symmetric inertia tensor.
+    pub fn to_mat3(&self) -> Mat3 {
+        Mat3::from_cols_array(&[
+            self.ixx, self.ixy, self.ixz, self.ixy, self.iyy, self.iyz, self.ixz, self.iyz,
+            self.izz,
+        ])
+    }
+
+    /// Creates a moment from an inertia tensor, assumed to be symmetric.
+    pub fn from_mat3(tensor: &Mat3) -> Self {
+        Self {
+            ixx: tensor.x_axis.x,
+            ixy: tensor.y_axis.x,
+            ixz: tensor.z_axis.x,
+            iyy: tensor.y_axis.y,
+            iyz: tensor.z_axis.y,
+            izz: tensor.z_axis.z,
+        }
+    }
+}
+
 impl From<&urdf_rs::Inertia> for Moment {
     fn from(inertia: &urdf_rs::Inertia) -> Self {
         Self {
@@ -49,7 +76,7 @@ impl From<&urdf_rs::Inertia> for Moment {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
 #[cfg_attr(feature = "bevy", derive(Bundle))]
 pub struct Inertia {
     pub center: Pose,
@@ -85,3 +112,356 @@ impl From<&Inertia> for urdf_rs::Inertial {
         }
     }
 }
+
+/// How the mass of a body is defined when computing its inertia from its geometry, the body is
+/// always assumed to be of uniform density.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MassDistribution {
+    /// Density in kg/m^3, the mass depends on the volume
+    Density(f32),
+    /// Total mass in kg, regardless of the volume
+    TotalMass(f32),
+}
+
+/// Mass properties of a solid of unit density, in double precision to limit the loss of
+/// accuracy when integrating over meshes.
+struct UnitMassProperties {
+    volume: f64,
+    center: DVec3,
+    /// Inertia tensor around the center of mass, for a unit density
+    tensor: DMat3,
+}
+
+impl UnitMassProperties {
+    /// Inertia tensor of a solid with the given principal moments per unit volume.
+    fn diagonal(volume: f64, moments: [f64; 3]) -> Self {
+        Self {
+            volume,
+            center: DVec3::ZERO,
+            tensor: DMat3::from_diagonal(DVec3::from(moments) * volume),
+        }
+    }
+
+    fn from_primitive(shape: &PrimitiveShape) -> Self {
+        match shape {
+            PrimitiveShape::Box { size } => {
+                let [x, y, z] = size.map(|v| (v as f64).powi(2));
+                let volume = size.iter().map(|v| *v as f64).product::<f64>().abs();
+                Self::diagonal(volume, [(y + z) / 12.0, (x + z) / 12.0, (x + y) / 12.0])
+            }
+            // Cylinders and capsules are aligned with the z axis, as in urdf
+            PrimitiveShape::Cylinder { radius, length } => {
+                let (r, h) = (*radius as f64, *length as f64);
+                let volume = PI * r * r * h;
+                let i = (3.0 * r * r + h * h) / 12.0;
+                Self::diagonal(volume, [i, i, r * r / 2.0])
+            }
+            PrimitiveShape::Capsule { radius, length } => {
+                let (r, h) = (*radius as f64, *length as f64);
+                let cylinder = PI * r * r * h;
+                // Each hemisphere, offset from the center by half the length
+                let hemisphere = 2.0 / 3.0 * PI * r.powi(3);
+                let ixx = cylinder * (h * h / 12.0 + r * r / 4.0)
+                    + 2.0 * hemisphere * (2.0 * r * r / 5.0 + h * h / 4.0 + 3.0 * h * r / 8.0);
+                let izz = cylinder * r * r / 2.0 + 2.0 * hemisphere * 2.0 * r * r / 5.0;
+                Self {
+                    volume: cylinder + 2.0 * hemisphere,
+                    center: DVec3::ZERO,
+                    tensor: DMat3::from_diagonal(DVec3::new(ixx, ixx, izz)),
+                }
+            }
+            PrimitiveShape::Sphere { radius } => {
+                let r = *radius as f64;
+                Self::diagonal(4.0 / 3.0 * PI * r.powi(3), [2.0 * r * r / 5.0; 3])
+            }
+        }
+    }
+
+    /// Integrates over a closed triangle mesh by summing the signed tetrahedra formed by each
+    /// triangle and the origin. Triangles must be wound counter clockwise seen from outside.
+    fn from_triangle_mesh(vertices: &[Vec3], triangles: &[[u32; 3]]) -> Option<Self> {
+        // Covariance of the canonical tetrahedron (0, x, y, z)
+        let canonical =
+            DMat3::from_cols_array(&[2.0, 1.0, 1.0, 1.0, 2.0, 1.0, 1.0, 1.0, 2.0]) * (1.0 / 120.0);
+        let mut volume = 0.0;
+        let mut first_moment = DVec3::ZERO;
+        let mut covariance = DMat3::ZERO;
+        for triangle in triangles {
+            let [a, b, c] = triangle.map(|i| vertices.get(i as usize).map(|v| v.as_dvec3()));
+            let tetrahedron = DMat3::from_cols(a?, b?, c?);
+            let det = tetrahedron.determinant();
+            volume += det / 6.0;
+            first_moment +=
+                det / 6.0 * (tetrahedron.x_axis + tetrahedron.y_axis + tetrahedron.z_axis) / 4.0;
+            covariance += det * tetrahedron * canonical * tetrahedron.transpose();
+        }
+        if volume.abs() < f64::EPSILON {
+            return None;
+        }
+        // Inverted winding results in a negative volume
+        if volume < 0.0 {
+            volume = -volume;
+            first_moment = -first_moment;
+            covariance = -covariance;
+        }
+        let center = first_moment / volume;
+        // Move the covariance to the center of mass and convert it to an inertia tensor
+        let covariance = covariance - volume * outer(center, center);
+        let tensor = DMat3::from_diagonal(DVec3::splat(trace(&covariance))) - covariance;
+        Some(Self {
+            volume,
+            center,
+            tensor,
+        })
+    }
+
+    /// Returns None if a total mass is requested for a body without volume, since its density
+    /// would be infinite.
+    fn into_inertia(self, mass: MassDistribution) -> Option<Inertia> {
+        let density = match mass {
+            MassDistribution::Density(density) => density as f64,
+            MassDistribution::TotalMass(_) if self.volume.abs() < f64::EPSILON => return None,
+            MassDistribution::TotalMass(mass) => mass as f64 / self.volume,
+        };
+        Some(Inertia {
+            center: Pose {
+                trans: self.center.as_vec3().to_array(),
+                rot: Rotation::Quat([0.0, 0.0, 0.0, 1.0]),
+            },
+            mass: Mass((self.volume * density) as f32),
+            moment: Moment::from_mat3(&(self.tensor * density).as_mat3()),
+        })
+    }
+}
+
+fn outer(a: DVec3, b: DVec3) -> DMat3 {
+    DMat3::from_cols(a * b.x, a * b.y, a * b.z)
+}
+
+fn trace(m: &DMat3) -> f64 {
+    m.x_axis.x + m.y_axis.y + m.z_axis.z
+}
+
+impl Inertia {
+    /// Computes the inertia of a primitive shape centered in the origin. Returns None if a total
+    /// mass is requested for a shape without volume.
+    pub fn from_primitive(shape: &PrimitiveShape, mass: MassDistribution) -> Option<Self> {
+        UnitMassProperties::from_primitive(shape).into_inertia(mass)
+    }
+
+    /// Computes the inertia of a closed triangle mesh, triangles are triplets of indices in
+    /// `vertices`. Returns None if the mesh has no volume or refers to non existing vertices.
+    pub fn from_triangle_mesh(
+        vertices: &[Vec3],
+        triangles: &[[u32; 3]],
+        mass: MassDistribution,
+    ) -> Option<Self> {
+        UnitMassProperties::from_triangle_mesh(vertices, triangles)
+            .and_then(|p| p.into_inertia(mass))
+    }
+
+    /// Computes the inertia of a geometry, meshes need to be loaded and passed to
+    /// [`Inertia::from_triangle_mesh`] so None is returned for them, as well as for primitives
+    /// without volume when a total mass is requested.
+    pub fn from_geometry(geometry: &Geometry, mass: MassDistribution) -> Option<Self> {
+        match geometry {
+            Geometry::Primitive(shape) => Self::from_primitive(shape, mass),
+            Geometry::Mesh { .. } => None,
+        }
+    }
+
+    /// Returns the same inertia with its center moved by `tf`.
+    pub fn transformed(&self, tf: &Affine3A) -> Self {
+        Self {
+            center: pose_from_affine(&(*tf * affine_from_pose(&self.center))),
+            ..self.clone()
+        }
+    }
+
+    /// Returns the same body with a different mass, keeping its density distribution.
+    pub fn with_mass(&self, mass: f32) -> Self {
+        let scale = if self.mass.0 != 0.0 {
+            mass / self.mass.0
+        } else {
+            0.0
+        };
+        Self {
+            center: self.center,
+            mass: Mass(mass),
+            moment: Moment::from_mat3(&(self.moment.to_mat3() * scale)),
+        }
+    }
+
+    /// Same as [`Inertia::combine`] but a single inertia is returned unchanged, to preserve
+    /// the orientation of its principal axes.
+    pub(crate) fn merge(mut inertias: Vec<Inertia>) -> Option<Self> {
+        if inertias.len() == 1 {
+            return inertias.pop();
+        }
+        Self::combine(&inertias)
+    }
+
+    /// Combines the inertia of rigidly attached bodies, all expressed in the same frame, using
+    /// the parallel axis theorem. The result is centered in the overall center of mass.
+    /// Returns None if the total mass is not positive.
+    pub fn combine<'a>(inertias: impl IntoIterator<Item = &'a Inertia>) -> Option<Self> {
+        let inertias: Vec<_> = inertias.into_iter().collect();
+        let mass: f64 = inertias.iter().map(|i| i.mass.0 as f64).sum();
+        if mass <= 0.0 {
+            return None;
+        }
+        let center = inertias
+            .iter()
+            .map(|i| DVec3::from(i.center.trans.map(|v| v as f64)) * i.mass.0 as f64)
+            .sum::<DVec3>()
+            / mass;
+        let mut tensor = DMat3::ZERO;
+        for inertia in &inertias {
+            // Rotate the moment to the common frame, then move it to the common center
+            let rot = Mat3::from_quat(quat_from_rotation(&inertia.center.rot)).as_dmat3();
+            let moment = inertia.moment.to_mat3().as_dmat3();
+            let d = DVec3::from(inertia.center.trans.map(|v| v as f64)) - center;
+            tensor += rot * moment * rot.transpose()
+                + inertia.mass.0 as f64
+                    * (DMat3::from_diagonal(DVec3::splat(d.length_squared())) - outer(d, d));
+        }
+        Some(Self {
+            center: Pose {
+                trans: center.as_vec3().to_array(),
+                rot: Rotation::Quat([0.0, 0.0, 0.0, 1.0]),
+            },
+            mass: Mass(mass as f32),
+            moment: Moment::from_mat3(&tensor.as_mat3()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use float_eq::assert_float_eq;
+
+    /// Closed box mesh with the given size, centered in `offset`
+    fn box_mesh(size: Vec3, offset: Vec3) -> (Vec<Vec3>, Vec<[u32; 3]>) {
+        let vertices = (0..8)
+            .map(|i| {
+                let corner = Vec3::new(
+                    (i & 1) as f32 - 0.5,
+                    ((i >> 1) & 1) as f32 - 0.5,
+                    ((i >> 2) & 1) as f32 - 0.5,
+                );
+                corner * size + offset
+            })
+            .collect();
+        let triangles = vec![
+            [0, 2, 1],
+            [1, 2, 3],
+            [4, 5, 6],
+            [5, 7, 6],
+            [0, 1, 4],
+            [1, 5, 4],
+            [2, 6, 3],
+            [3, 6, 7],
+            [0, 4, 2],
+            [2, 4, 6],
+            [1, 3, 5],
+            [3, 7, 5],
+        ];
+        (vertices, triangles)
+    }
+
+    #[test]
+    fn primitive_inertia() {
+        let size = [1.0, 2.0, 3.0];
+        let inertia = Inertia::from_primitive(
+            &PrimitiveShape::Box { size },
+            MassDistribution::Density(10.0),
+        )
+        .unwrap();
+        assert_float_eq!(inertia.mass.0, 60.0, abs <= 1e-4);
+        assert_float_eq!(inertia.moment.ixx, 60.0 * 13.0 / 12.0, abs <= 1e-4);
+        assert_float_eq!(inertia.moment.izz, 60.0 * 5.0 / 12.0, abs <= 1e-4);
+
+        let sphere = Inertia::from_primitive(
+            &PrimitiveShape::Sphere { radius: 0.5 },
+            MassDistribution::TotalMass(2.0),
+        )
+        .unwrap();
+        assert_float_eq!(sphere.mass.0, 2.0, abs <= 1e-6);
+        assert_float_eq!(sphere.moment.iyy, 0.2, abs <= 1e-6);
+
+        // A capsule without a cylinder is a sphere
+        let capsule = Inertia::from_primitive(
+            &PrimitiveShape::Capsule {
+                radius: 0.5,
+                length: 0.0,
+            },
+            MassDistribution::TotalMass(2.0),
+        )
+        .unwrap();
+        assert_float_eq!(capsule.moment.ixx, 0.2, abs <= 1e-6);
+        assert_float_eq!(capsule.moment.izz, 0.2, abs <= 1e-6);
+    }
+
+    #[test]
+    fn mesh_inertia_matches_primitives() {
+        let size = Vec3::new(1.0, 2.0, 3.0);
+        let offset = Vec3::new(0.5, -1.0, 2.0);
+        let (vertices, triangles) = box_mesh(size, offset);
+        let mesh =
+            Inertia::from_triangle_mesh(&vertices, &triangles, MassDistribution::Density(10.0))
+                .unwrap();
+        let primitive = Inertia::from_primitive(
+            &PrimitiveShape::Box {
+                size: size.to_array(),
+            },
+            MassDistribution::Density(10.0),
+        )
+        .unwrap();
+        assert_float_eq!(mesh.mass.0, primitive.mass.0, abs <= 1e-3);
+        assert_float_eq!(mesh.center.trans, offset.to_array(), abs_all <= 1e-5);
+        assert_float_eq!(mesh.moment.ixx, primitive.moment.ixx, abs <= 1e-3);
+        assert_float_eq!(mesh.moment.iyy, primitive.moment.iyy, abs <= 1e-3);
+        assert_float_eq!(mesh.moment.izz, primitive.moment.izz, abs <= 1e-3);
+        assert_float_eq!(mesh.moment.ixy, 0.0, abs <= 1e-3);
+
+        // Two halves of a box combine into the whole box
+        let half = Inertia::from_primitive(
+            &PrimitiveShape::Box {
+                size: [1.0, 2.0, 1.5],
+            },
+            MassDistribution::Density(10.0),
+        )
+        .unwrap();
+        let top = half.transformed(&Affine3A::from_translation(Vec3::new(0.0, 0.0, 0.75)));
+        let bottom = half.transformed(&Affine3A::from_translation(Vec3::new(0.0, 0.0, -0.75)));
+        let combined = Inertia::combine([&top, &bottom]).unwrap();
+        assert_float_eq!(combined.mass.0, primitive.mass.0, abs <= 1e-4);
+        assert_float_eq!(combined.center.trans, [0.0; 3], abs_all <= 1e-6);
+        assert_float_eq!(combined.moment.ixx, primitive.moment.ixx, abs <= 1e-3);
+        assert_float_eq!(combined.moment.izz, primitive.moment.izz, abs <= 1e-3);
+    }
+
+    #[test]
+    fn zero_volume_inertia() {
+        let point = PrimitiveShape::Sphere { radius: 0.0 };
+        assert!(Inertia::from_primitive(&point, MassDistribution::TotalMass(1.0)).is_none());
+        let inertia = Inertia::from_primitive(&point, MassDistribution::Density(1.0)).unwrap();
+        assert_eq!(inertia.mass.0, 0.0);
+        assert!(inertia
+            .moment
+            .to_mat3()
+            .to_cols_array()
+            .iter()
+            .all(|v| *v == 0.0));
+
+        let flat = PrimitiveShape::Box {
+            size: [1.0, 1.0, 0.0],
+        };
+        assert!(Inertia::from_geometry(
+            &Geometry::Primitive(flat),
+            MassDistribution::TotalMass(1.0)
+        )
+        .is_none());
+    }
+}
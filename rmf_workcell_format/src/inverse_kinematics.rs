@@ -0,0 +1,423 @@
+/*
+ * Copyright (C) 2024 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+//! Iterative inverse kinematics for chains of joints of a workcell.
+
+use crate::*;
+use glam::{Affine3A, Quat, Vec3A};
+use thiserror::Error as ThisError;
+
+#[derive(Debug, Clone, PartialEq, ThisError)]
+pub enum IkError {
+    #[error("kinematics error: {0}")]
+    Kinematics(#[from] KinematicsError),
+    #[error("there are no movable joints between frames [{base}] and [{tip}]")]
+    NoMovableJoints { base: u32, tip: u32 },
+    #[error("invalid limits for joint [{joint}]: {error}")]
+    InvalidLimits {
+        joint: String,
+        error: JointLimitsError,
+    },
+}
+
+/// Damped least squares inverse kinematics solver. The chain is made of the joints on the path
+/// between a base and a tip frame, joints that mimic another one move together with it.
+#[derive(Debug, Clone)]
+pub struct IkSolver {
+    pub max_iterations: usize,
+    /// Maximum distance between the tip and the target, in meters
+    pub position_tolerance: f32,
+    /// Maximum angle between the tip and the target orientation, in radians
+    pub orientation_tolerance: f32,
+    /// Only reach the target position, the orientation of the tip is free
+    pub position_only: bool,
+    /// Damping factor, higher values are more stable close to singularities but converge slower
+    pub damping: f32,
+}
+
+impl Default for IkSolver {
+    fn default() -> Self {
+        Self {
+            max_iterations: 200,
+            position_tolerance: 1e-4,
+            orientation_tolerance: 1e-3,
+            position_only: false,
+            damping: 0.05,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IkSolution {
+    /// Position of the joints of the chain, joints that mimic another one are not included
+    pub positions: JointPositions,
+    /// Whether the target was reached within the tolerances
+    pub converged: bool,
+    pub iterations: usize,
+    pub position_error: f32,
+    pub orientation_error: f32,
+}
+
+/// A joint that can be moved by the solver, with its position bounds.
+struct IkVariable {
+    name: String,
+    lower: f32,
+    upper: f32,
+}
+
+impl Workcell {
+    /// Returns the ids of the joints on the path between two frames, either of them can be the
+    /// id of the workcell itself.
+    fn joints_between(&self, base: u32, tip: u32) -> Result<Vec<u32>, KinematicsError> {
+        // Lists the frames and joints from a frame up to the workcell
+        let ancestors = |frame_id: u32| -> Result<Vec<u32>, KinematicsError> {
+            let mut path = vec![frame_id];
+            let mut current = frame_id;
+            while current != self.id {
+                if path.len() > self.frames.len() + self.joints.len() + 1 {
+                    return Err(KinematicsError::ParentCycle(frame_id));
+                }
+                current = match (self.frames.get(&current), self.joints.get(&current)) {
+                    (Some(frame), _) => frame.parent,
+                    (_, Some(joint)) => joint.parent,
+                    _ => return Err(KinematicsError::MissingFrame(current)),
+                };
+                path.push(current);
+            }
+            Ok(path)
+        };
+        let base_path = ancestors(base)?;
+        let tip_path = ancestors(tip)?;
+        // Joints on both paths are above the common ancestor and don't affect the chain
+        Ok(base_path
+            .iter()
+            .filter(|id| !tip_path.contains(id))
+            .chain(tip_path.iter().filter(|id| !base_path.contains(id)))
+            .filter(|id| self.joints.contains_key(id))
+            .copied()
+            .collect())
+    }
+
+    /// Returns the joints that the solver can move to change the pose of the tip, the joints
+    /// that are mimicked by joints of the chain are used instead of the mimic joints. The limits
+    /// of all these joints must be valid, since they are used to clamp the positions.
+    fn ik_variables(&self, base: u32, tip: u32) -> Result<Vec<IkVariable>, IkError> {
+        let validate = |joint: &Joint| {
+            let Some(single_dof) = joint.properties.single_dof() else {
+                return Ok(());
+            };
+            single_dof
+                .limits
+                .validate()
+                .map_err(|error| IkError::InvalidLimits {
+                    joint: joint.name.0.clone(),
+                    error,
+                })
+        };
+        let mut variables: Vec<IkVariable> = Vec::new();
+        for joint_id in self.joints_between(base, tip)? {
+            let mut joint = &self.joints[&joint_id].bundle;
+            validate(joint)?;
+            let mut visited = Vec::new();
+            while let Some(mimic) = joint.properties.single_dof().and_then(|j| j.mimic.as_ref()) {
+                if visited.contains(&mimic.joint) {
+                    return Err(KinematicsError::MimicCycle(joint.name.0.clone()).into());
+                }
+                visited.push(mimic.joint.clone());
+                joint = self
+                    .joints
+                    .values()
+                    .map(|j| &j.bundle)
+                    .find(|j| j.name.0 == mimic.joint)
+                    .ok_or_else(|| KinematicsError::MissingMimicJoint(mimic.joint.clone()))?;
+                validate(joint)?;
+            }
+            let Some(single_dof) = joint.properties.single_dof() else {
+                continue;
+            };
+            if variables.iter().any(|v| v.name == joint.name.0) {
+                continue;
+            }
+            let (lower, upper) = match joint.properties {
+                JointProperties::Continuous(_) => (None, None),
+                _ => single_dof.limits.position.bounds(),
+            };
+            variables.push(IkVariable {
+                name: joint.name.0.clone(),
+                lower: lower.unwrap_or(f32::NEG_INFINITY),
+                upper: upper.unwrap_or(f32::INFINITY),
+            });
+        }
+        Ok(variables)
+    }
+}
+
+/// Difference between two transforms, as the translation and the scaled rotation axis that
+/// bring `from` to `to`.
+fn pose_error(from: &Affine3A, to: &Affine3A) -> (Vec3A, Vec3A) {
+    let translation = to.translation - from.translation;
+    let from_rot = Quat::from_mat3a(&from.matrix3).normalize();
+    let to_rot = Quat::from_mat3a(&to.matrix3).normalize();
+    let mut delta = to_rot * from_rot.inverse();
+    // Take the shortest rotation
+    if delta.w < 0.0 {
+        delta = -delta;
+    }
+    let (axis, angle) = delta.to_axis_angle();
+    (translation, Vec3A::from(axis * angle))
+}
+
+/// Solves `a * x = b` for a square system through gaussian elimination with partial pivoting.
+/// Returns None if the system is singular.
+fn solve_linear_system(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    let n = b.len();
+    for col in 0..n {
+        let pivot = (col..n).max_by(|i, j| a[*i][col].abs().total_cmp(&a[*j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        for row in col + 1..n {
+            let factor = a[row][col] / a[col][col];
+            let (pivot_rows, rows) = a.split_at_mut(row);
+            for (v, p) in rows[0][col..].iter_mut().zip(&pivot_rows[col][col..]) {
+                *v -= factor * p;
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let sum: f64 = (row + 1..n).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Some(x)
+}
+
+impl IkSolver {
+    /// Looks for the joint positions that bring `tip` to `target`, expressed relative to `base`.
+    /// `seed` is used as the initial guess and to set the position of the joints that are not
+    /// part of the chain. The positions of the solution always respect the joint limits, check
+    /// [`IkSolution::converged`] to know whether the target was reached.
+    pub fn solve(
+        &self,
+        workcell: &Workcell,
+        base: u32,
+        tip: u32,
+        target: &Pose,
+        seed: &JointPositions,
+    ) -> Result<IkSolution, IkError> {
+        let variables = workcell.ik_variables(base, tip)?;
+        if variables.is_empty() {
+            return Err(IkError::NoMovableJoints { base, tip });
+        }
+        let target = affine_from_pose(target);
+        let mut positions = seed.clone();
+        for v in &variables {
+            let p = positions.entry(v.name.clone()).or_default();
+            *p = p.clamp(v.lower, v.upper);
+        }
+        let rows = if self.position_only { 3 } else { 6 };
+        let error_vector = |from: &Affine3A, to: &Affine3A| {
+            let (translation, rotation) = pose_error(from, to);
+            let mut e = translation.to_array().to_vec();
+            if !self.position_only {
+                e.extend(rotation.to_array());
+            }
+            (e, translation.length(), rotation.length())
+        };
+
+        let mut iterations = 0;
+        loop {
+            let current = workcell.relative_transform(tip, base, &positions)?;
+            let (error, position_error, orientation_error) = error_vector(&current, &target);
+            let converged = position_error <= self.position_tolerance
+                && (self.position_only || orientation_error <= self.orientation_tolerance);
+            if converged || iterations >= self.max_iterations {
+                let positions = variables
+                    .iter()
+                    .map(|v| (v.name.clone(), positions[&v.name]))
+                    .collect();
+                return Ok(IkSolution {
+                    positions,
+                    converged,
+                    iterations,
+                    position_error,
+                    orientation_error,
+                });
+            }
+            iterations += 1;
+
+            // Numerical jacobian, one column per variable
+            const STEP: f32 = 1e-3;
+            let mut jacobian = vec![vec![0.0_f64; variables.len()]; rows];
+            for (col, v) in variables.iter().enumerate() {
+                let mut perturbed = positions.clone();
+                // Perturb towards the inside of the limits
+                let step = if positions[&v.name] + STEP > v.upper {
+                    -STEP
+                } else {
+                    STEP
+                };
+                *perturbed.get_mut(&v.name).unwrap() += step;
+                let moved = workcell.relative_transform(tip, base, &perturbed)?;
+                let (delta, _, _) = error_vector(&current, &moved);
+                for (row, d) in delta.iter().enumerate() {
+                    jacobian[row][col] = (*d / step) as f64;
+                }
+            }
+
+            // dq = J^T (J J^T + lambda^2 I)^-1 e
+            let lambda_sq = (self.damping as f64).powi(2);
+            let jjt = (0..rows)
+                .map(|i| {
+                    (0..rows)
+                        .map(|j| {
+                            let dot: f64 = (0..variables.len())
+                                .map(|k| jacobian[i][k] * jacobian[j][k])
+                                .sum();
+                            if i == j {
+                                dot + lambda_sq
+                            } else {
+                                dot
+                            }
+                        })
+                        .collect()
+                })
+                .collect();
+            let Some(y) = solve_linear_system(jjt, error.iter().map(|e| *e as f64).collect())
+            else {
+                // Can only happen with no damping at a singularity, nothing else to try
+                iterations = self.max_iterations;
+                continue;
+            };
+            for (col, v) in variables.iter().enumerate() {
+                let dq: f64 = (0..rows).map(|row| jacobian[row][col] * y[row]).sum();
+                let p = positions.get_mut(&v.name).unwrap();
+                *p = (*p + dq as f32).clamp(v.lower, v.upper);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::frame_id;
+
+    const PLANAR_ARM: &str = r#"
+        <robot name="planar_arm">
+            <link name="base"/>
+            <link name="link1"/>
+            <link name="link2"/>
+            <link name="tool"/>
+            <joint name="joint1" type="revolute">
+                <parent link="base"/>
+                <child link="link1"/>
+                <axis xyz="0 0 1"/>
+                <limit lower="-3.1" upper="3.1" effort="10" velocity="1"/>
+            </joint>
+            <joint name="joint2" type="revolute">
+                <parent link="link1"/>
+                <child link="link2"/>
+                <origin xyz="1 0 0"/>
+                <axis xyz="0 0 1"/>
+                <limit lower="0" upper="2.5" effort="10" velocity="1"/>
+            </joint>
+            <joint name="tool_joint" type="fixed">
+                <parent link="link2"/>
+                <child link="tool"/>
+                <origin xyz="1 0 0"/>
+            </joint>
+        </robot>"#;
+
+    #[test]
+    fn reachable_targets_converge() {
+        let workcell =
+            Workcell::from_urdf(&urdf_rs::read_from_string(PLANAR_ARM).unwrap()).unwrap();
+        let base = frame_id(&workcell, "base");
+        let tool = frame_id(&workcell, "tool");
+        let expected =
+            JointPositions::from([("joint1".to_string(), 0.3), ("joint2".to_string(), 1.2)]);
+        let target = workcell.relative_pose(tool, base, &expected).unwrap();
+        let solver = IkSolver::default();
+        let solution = solver
+            .solve(&workcell, base, tool, &target, &JointPositions::new())
+            .unwrap();
+        assert!(solution.converged);
+        // The lower limit of joint2 excludes the mirrored elbow configuration
+        for (name, position) in &expected {
+            assert!((solution.positions[name] - position).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn unreachable_targets_respect_limits() {
+        let workcell =
+            Workcell::from_urdf(&urdf_rs::read_from_string(PLANAR_ARM).unwrap()).unwrap();
+        let base = frame_id(&workcell, "base");
+        let tool = frame_id(&workcell, "tool");
+        let solver = IkSolver {
+            position_only: true,
+            max_iterations: 50,
+            ..Default::default()
+        };
+        // Out of reach
+        let target = Pose {
+            trans: [3.0, 0.0, 0.0],
+            ..Default::default()
+        };
+        let solution = solver
+            .solve(&workcell, base, tool, &target, &JointPositions::new())
+            .unwrap();
+        assert!(!solution.converged);
+        assert!(solution.position_error > 0.9);
+        // Reaching it would require a negative joint2
+        let target = Pose {
+            trans: [1.0, -1.0, 0.0],
+            ..Default::default()
+        };
+        let seed = JointPositions::from([("joint1".to_string(), 0.0)]);
+        let solution = solver.solve(&workcell, base, tool, &target, &seed).unwrap();
+        assert!((0.0..=2.5).contains(&solution.positions["joint2"]));
+
+        assert!(matches!(
+            solver.solve(&workcell, tool, tool, &target, &seed),
+            Err(IkError::NoMovableJoints { .. })
+        ));
+    }
+
+    #[test]
+    fn invalid_limits_are_rejected() {
+        let urdf = PLANAR_ARM.replace(r#"lower="0" upper="2.5""#, r#"lower="2.5" upper="0""#);
+        let workcell = Workcell::from_urdf(&urdf_rs::read_from_string(&urdf).unwrap()).unwrap();
+        let base = frame_id(&workcell, "base");
+        let tool = frame_id(&workcell, "tool");
+        let result = IkSolver::default().solve(
+            &workcell,
+            base,
+            tool,
+            &Pose::default(),
+            &JointPositions::new(),
+        );
+        assert!(matches!(
+            result,
+            Err(IkError::InvalidLimits { joint, .. }) if joint == "joint2"
+        ));
+    }
+}
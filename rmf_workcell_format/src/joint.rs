@@ -20,12 +20,19 @@ use bevy::ecs::system::EntityCommands;
 #[cfg(feature = "bevy")]
 use bevy::prelude::{Bundle, Component, SpatialBundle};
 
-use crate::{Category, NameInWorkcell};
+use crate::{is_default, Category, JointActuation, NameInWorkcell};
 
 use serde::{Deserialize, Serialize};
+use thiserror::Error as ThisError;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct JointAxis([f32; 3]);
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JointAxis(pub(crate) [f32; 3]);
+
+impl JointAxis {
+    pub fn new(xyz: [f32; 3]) -> Self {
+        Self(xyz)
+    }
+}
 
 impl From<&urdf_rs::Axis> for JointAxis {
     fn from(axis: &urdf_rs::Axis) -> Self {
@@ -41,9 +48,71 @@ impl From<&JointAxis> for urdf_rs::Axis {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-enum RangeLimits {
+/// The quantity that a joint limit applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JointLimitKind {
+    Position,
+    Effort,
+    Velocity,
+    Acceleration,
+    Jerk,
+}
+
+impl JointLimitKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            JointLimitKind::Position => "position",
+            JointLimitKind::Effort => "effort",
+            JointLimitKind::Velocity => "velocity",
+            JointLimitKind::Acceleration => "acceleration",
+            JointLimitKind::Jerk => "jerk",
+        }
+    }
+}
+
+impl std::fmt::Display for JointLimitKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.label())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, ThisError)]
+pub enum JointLimitsError {
+    #[error("{0} limit is not a number")]
+    NotANumber(JointLimitKind),
+    #[error("{kind} limit [{value}] is negative")]
+    NegativeLimit { kind: JointLimitKind, value: f32 },
+    #[error("{kind} lower limit [{lower}] is greater than the upper limit [{upper}]")]
+    InvertedRange {
+        kind: JointLimitKind,
+        lower: f32,
+        upper: f32,
+    },
+    #[error("{kind} limits [{lower:?}, {upper:?}] don't allow the joint to stand still")]
+    ExcludesZero {
+        kind: JointLimitKind,
+        lower: Option<f32>,
+        upper: Option<f32>,
+    },
+}
+
+/// A limit that was missing or not representable in the target format and was replaced when
+/// exporting.
+#[derive(Debug, Clone, PartialEq, ThisError)]
+pub enum JointLimitWarning {
+    #[error("no {kind} limit, using {value}")]
+    Missing { kind: JointLimitKind, value: f64 },
+    #[error("asymmetric {kind} limit, using the smallest magnitude {value}")]
+    Asymmetric { kind: JointLimitKind, value: f64 },
+}
+
+/// Range of values allowed for a quantity. Units are meters or radians for positions and their
+/// derivatives, Newtons or Newton meters for efforts.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+pub enum RangeLimits {
+    #[default]
     None,
+    /// From `-limit` to `limit`
     Symmetric(f32),
     Asymmetric {
         lower: Option<f32>,
@@ -51,88 +120,206 @@ enum RangeLimits {
     },
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct JointLimits {
-    position: RangeLimits,
-    effort: RangeLimits,
-    velocity: RangeLimits,
-}
+impl RangeLimits {
+    /// Returns the lower and upper bounds of the range, if bounded.
+    pub fn bounds(&self) -> (Option<f32>, Option<f32>) {
+        match self {
+            RangeLimits::None => (None, None),
+            RangeLimits::Symmetric(l) => (Some(-l.abs()), Some(l.abs())),
+            RangeLimits::Asymmetric { lower, upper } => (*lower, *upper),
+        }
+    }
 
-impl From<&urdf_rs::JointLimit> for JointLimits {
-    fn from(limit: &urdf_rs::JointLimit) -> Self {
-        Self {
-            position: RangeLimits::Asymmetric {
-                lower: Some(limit.lower as f32),
-                upper: Some(limit.upper as f32),
+    /// Whether the value is within the range, bounds included.
+    pub fn contains(&self, value: f32) -> bool {
+        let (lower, upper) = self.bounds();
+        !matches!(lower, Some(l) if value < l) && !matches!(upper, Some(u) if value > u)
+    }
+
+    /// The largest magnitude allowed in both directions, used by formats that only support
+    /// symmetric limits.
+    pub(crate) fn magnitude(&self) -> Option<f32> {
+        match self {
+            RangeLimits::None => None,
+            RangeLimits::Symmetric(l) => Some(l.abs()),
+            RangeLimits::Asymmetric { lower, upper } => match (lower, upper) {
+                (Some(l), Some(u)) => Some(l.abs().min(u.abs())),
+                (Some(l), None) => Some(l.abs()),
+                (None, Some(u)) => Some(u.abs()),
+                (None, None) => None,
             },
-            effort: RangeLimits::Symmetric(limit.effort as f32),
-            velocity: RangeLimits::Symmetric(limit.velocity as f32),
         }
     }
-}
 
-impl From<&JointLimits> for urdf_rs::JointLimit {
-    fn from(limits: &JointLimits) -> Self {
-        const DEFAULT_EFFORT_LIMIT: f64 = 1e3;
-        const DEFAULT_VELOCITY_LIMIT: f64 = 10.0;
-        fn min_or_default(slice: [Option<f32>; 2], default: f64) -> f64 {
-            let mut vec = slice
-                .iter()
-                .filter_map(|v| v.map(|m| m as f64))
-                .collect::<Vec<_>>();
-            vec.sort_by(|a, b| a.total_cmp(b));
-            vec.first().cloned().unwrap_or(default)
-        }
-        // 0.0 is a valid default in urdf for lower and upper limits
-        let (lower, upper) = match limits.position {
-            RangeLimits::None => (0.0, 0.0),
-            RangeLimits::Symmetric(l) => (l as f64, l as f64),
-            RangeLimits::Asymmetric { lower, upper } => (
-                lower.map(|v| v as f64).unwrap_or_default(),
-                upper.map(|v| v as f64).unwrap_or_default(),
-            ),
-        };
-        let effort = match limits.effort {
-            RangeLimits::None => {
-                println!(
-                    "No effort limit found when exporting to urdf, setting to {}",
-                    DEFAULT_EFFORT_LIMIT
-                );
-                DEFAULT_EFFORT_LIMIT
+    /// Checks that the range is not empty. Limits on efforts and derivatives of the position are
+    /// also required to allow zero, i.e. the joint standing still.
+    fn validate(&self, kind: JointLimitKind) -> Result<(), JointLimitsError> {
+        match *self {
+            RangeLimits::None => Ok(()),
+            RangeLimits::Symmetric(l) if l.is_nan() => Err(JointLimitsError::NotANumber(kind)),
+            RangeLimits::Symmetric(l) if l < 0.0 => {
+                Err(JointLimitsError::NegativeLimit { kind, value: l })
             }
-            RangeLimits::Symmetric(l) => l as f64,
+            RangeLimits::Symmetric(_) => Ok(()),
             RangeLimits::Asymmetric { lower, upper } => {
-                let limit = min_or_default([lower, upper], DEFAULT_EFFORT_LIMIT);
-                println!(
-                    "Asymmetric effort limit found when exporting to urdf, setting to {}",
-                    limit
-                );
-                limit
+                if lower.is_some_and(f32::is_nan) || upper.is_some_and(f32::is_nan) {
+                    return Err(JointLimitsError::NotANumber(kind));
+                }
+                if let (Some(lower), Some(upper)) = (lower, upper) {
+                    if lower > upper {
+                        return Err(JointLimitsError::InvertedRange { kind, lower, upper });
+                    }
+                }
+                if kind != JointLimitKind::Position && !self.contains(0.0) {
+                    return Err(JointLimitsError::ExcludesZero { kind, lower, upper });
+                }
+                Ok(())
             }
+        }
+    }
+}
+
+/// Limits of a joint, see [`JointLimits::new`].
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct JointLimits {
+    pub(crate) position: RangeLimits,
+    pub(crate) effort: RangeLimits,
+    pub(crate) velocity: RangeLimits,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub(crate) acceleration: RangeLimits,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub(crate) jerk: RangeLimits,
+}
+
+impl JointLimits {
+    /// Creates limits without acceleration and jerk limits, fails if any of the ranges is
+    /// invalid, see [`JointLimits::validate`].
+    pub fn new(
+        position: RangeLimits,
+        effort: RangeLimits,
+        velocity: RangeLimits,
+    ) -> Result<Self, JointLimitsError> {
+        let limits = Self {
+            position,
+            effort,
+            velocity,
+            ..Default::default()
         };
-        let velocity = match limits.velocity {
+        limits.validate()?;
+        Ok(limits)
+    }
+
+    pub fn with_acceleration(
+        mut self,
+        acceleration: RangeLimits,
+    ) -> Result<Self, JointLimitsError> {
+        acceleration.validate(JointLimitKind::Acceleration)?;
+        self.acceleration = acceleration;
+        Ok(self)
+    }
+
+    pub fn with_jerk(mut self, jerk: RangeLimits) -> Result<Self, JointLimitsError> {
+        jerk.validate(JointLimitKind::Jerk)?;
+        self.jerk = jerk;
+        Ok(self)
+    }
+
+    pub fn position(&self) -> &RangeLimits {
+        &self.position
+    }
+
+    pub fn effort(&self) -> &RangeLimits {
+        &self.effort
+    }
+
+    pub fn velocity(&self) -> &RangeLimits {
+        &self.velocity
+    }
+
+    pub fn acceleration(&self) -> &RangeLimits {
+        &self.acceleration
+    }
+
+    pub fn jerk(&self) -> &RangeLimits {
+        &self.jerk
+    }
+
+    /// Returns the limits of a quantity.
+    pub fn get(&self, kind: JointLimitKind) -> &RangeLimits {
+        match kind {
+            JointLimitKind::Position => &self.position,
+            JointLimitKind::Effort => &self.effort,
+            JointLimitKind::Velocity => &self.velocity,
+            JointLimitKind::Acceleration => &self.acceleration,
+            JointLimitKind::Jerk => &self.jerk,
+        }
+    }
+
+    /// Checks that lower limits are not greater than upper limits and that effort, velocity,
+    /// acceleration and jerk limits allow zero, symmetric limits must be non negative.
+    pub fn validate(&self) -> Result<(), JointLimitsError> {
+        for kind in [
+            JointLimitKind::Position,
+            JointLimitKind::Effort,
+            JointLimitKind::Velocity,
+            JointLimitKind::Acceleration,
+            JointLimitKind::Jerk,
+        ] {
+            self.get(kind).validate(kind)?;
+        }
+        Ok(())
+    }
+
+    /// Converts the limits to urdf, which only supports symmetric effort and velocity limits and
+    /// has no acceleration and jerk limits. Returns the values used in place of the limits that
+    /// can't be represented.
+    pub fn to_urdf(&self) -> (urdf_rs::JointLimit, Vec<JointLimitWarning>) {
+        const DEFAULT_EFFORT_LIMIT: f64 = 1e3;
+        const DEFAULT_VELOCITY_LIMIT: f64 = 10.0;
+        let mut warnings = Vec::new();
+        // 0.0 is a valid default in urdf for lower and upper limits
+        let (lower, upper) = self.position.bounds();
+        let mut symmetric = |kind, limits: &RangeLimits, default| match limits {
             RangeLimits::None => {
-                println!(
-                    "No velocity limit found when exporting to urdf, setting to {}",
-                    DEFAULT_VELOCITY_LIMIT
-                );
-                DEFAULT_VELOCITY_LIMIT
+                warnings.push(JointLimitWarning::Missing {
+                    kind,
+                    value: default,
+                });
+                default
             }
-            RangeLimits::Symmetric(l) => l as f64,
-            RangeLimits::Asymmetric { lower, upper } => {
-                let limit = min_or_default([lower, upper], DEFAULT_VELOCITY_LIMIT);
-                println!(
-                    "Asymmetric velocity limit found when exporting to urdf, setting to {}",
-                    limit
-                );
-                limit
+            RangeLimits::Symmetric(l) => l.abs() as f64,
+            RangeLimits::Asymmetric { .. } => {
+                let value = limits.magnitude().map_or(default, |m| m as f64);
+                warnings.push(JointLimitWarning::Asymmetric { kind, value });
+                value
             }
         };
-        Self {
-            lower,
-            upper,
+        let effort = symmetric(JointLimitKind::Effort, &self.effort, DEFAULT_EFFORT_LIMIT);
+        let velocity = symmetric(
+            JointLimitKind::Velocity,
+            &self.velocity,
+            DEFAULT_VELOCITY_LIMIT,
+        );
+        let limit = urdf_rs::JointLimit {
+            lower: lower.map(|v| v as f64).unwrap_or_default(),
+            upper: upper.map(|v| v as f64).unwrap_or_default(),
             effort,
             velocity,
+        };
+        (limit, warnings)
+    }
+}
+
+impl From<&urdf_rs::JointLimit> for JointLimits {
+    fn from(limit: &urdf_rs::JointLimit) -> Self {
+        Self {
+            position: RangeLimits::Asymmetric {
+                lower: Some(limit.lower as f32),
+                upper: Some(limit.upper as f32),
+            },
+            effort: RangeLimits::Symmetric(limit.effort as f32),
+            velocity: RangeLimits::Symmetric(limit.velocity as f32),
+            ..Default::default()
         }
     }
 }
@@ -142,21 +329,145 @@ impl From<&JointLimits> for urdf_rs::JointLimit {
 pub struct Joint {
     pub name: NameInWorkcell,
     pub properties: JointProperties,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub actuation: JointActuation,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "bevy", derive(Component))]
 pub enum JointProperties {
     Fixed,
     Prismatic(SingleDofJoint),
     Revolute(SingleDofJoint),
     Continuous(SingleDofJoint),
+    /// Translates in the plane perpendicular to its axis and rotates around the axis
+    Planar(PlanarJoint),
+    /// Moves freely along all six degrees of freedom
+    Floating(FloatingJoint),
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct SingleDofJoint {
     pub limits: JointLimits,
     pub axis: JointAxis,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub dynamics: JointDynamics,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub mimic: Option<JointMimic>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub safety_controller: Option<SafetyController>,
+}
+
+impl SingleDofJoint {
+    /// Creates a joint with the given axis and limits and no dynamics, mimic or safety
+    /// controller.
+    pub fn new(axis: JointAxis, limits: JointLimits) -> Self {
+        Self {
+            limits,
+            axis,
+            dynamics: Default::default(),
+            mimic: None,
+            safety_controller: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlanarJoint {
+    /// Normal of the plane of motion
+    pub axis: JointAxis,
+    pub limits: JointLimits,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FloatingJoint {
+    pub limits: JointLimits,
+}
+
+/// Physical properties of a joint, zero means no damping or friction.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+pub struct JointDynamics {
+    pub damping: f32,
+    pub friction: f32,
+}
+
+impl From<&urdf_rs::Dynamics> for JointDynamics {
+    fn from(dynamics: &urdf_rs::Dynamics) -> Self {
+        Self {
+            damping: dynamics.damping as f32,
+            friction: dynamics.friction as f32,
+        }
+    }
+}
+
+impl From<&JointDynamics> for urdf_rs::Dynamics {
+    fn from(dynamics: &JointDynamics) -> Self {
+        Self {
+            damping: dynamics.damping as f64,
+            friction: dynamics.friction as f64,
+        }
+    }
+}
+
+/// Makes a joint follow another one, its position is `multiplier * position + offset` where
+/// position is the position of the mimicked joint.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JointMimic {
+    /// Name of the mimicked joint
+    pub joint: String,
+    pub multiplier: f32,
+    pub offset: f32,
+}
+
+impl From<&urdf_rs::Mimic> for JointMimic {
+    fn from(mimic: &urdf_rs::Mimic) -> Self {
+        Self {
+            joint: mimic.joint.clone(),
+            multiplier: mimic.multiplier.unwrap_or(1.0) as f32,
+            offset: mimic.offset.unwrap_or_default() as f32,
+        }
+    }
+}
+
+impl From<&JointMimic> for urdf_rs::Mimic {
+    fn from(mimic: &JointMimic) -> Self {
+        Self {
+            joint: mimic.joint.clone(),
+            multiplier: Some(mimic.multiplier as f64),
+            offset: Some(mimic.offset as f64),
+        }
+    }
+}
+
+/// Soft position limits and the gains used to enforce them, as in the urdf safety controller.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+pub struct SafetyController {
+    pub soft_lower_limit: f32,
+    pub soft_upper_limit: f32,
+    pub k_position: f32,
+    pub k_velocity: f32,
+}
+
+impl From<&urdf_rs::SafetyController> for SafetyController {
+    fn from(controller: &urdf_rs::SafetyController) -> Self {
+        Self {
+            soft_lower_limit: controller.soft_lower_limit as f32,
+            soft_upper_limit: controller.soft_upper_limit as f32,
+            k_position: controller.k_position as f32,
+            k_velocity: controller.k_velocity as f32,
+        }
+    }
+}
+
+impl From<&SafetyController> for urdf_rs::SafetyController {
+    fn from(controller: &SafetyController) -> Self {
+        Self {
+            soft_lower_limit: controller.soft_lower_limit as f64,
+            soft_upper_limit: controller.soft_upper_limit as f64,
+            k_position: controller.k_position as f64,
+            k_velocity: controller.k_velocity as f64,
+        }
+    }
 }
 
 impl JointProperties {
@@ -166,9 +477,72 @@ impl JointProperties {
             JointProperties::Revolute(_) => "Revolute",
             JointProperties::Prismatic(_) => "Prismatic",
             JointProperties::Continuous(_) => "Continuous",
+            JointProperties::Planar(_) => "Planar",
+            JointProperties::Floating(_) => "Floating",
         }
         .to_string()
     }
+
+    /// Returns the data of joints that have a single degree of freedom.
+    pub fn single_dof(&self) -> Option<&SingleDofJoint> {
+        match self {
+            JointProperties::Fixed | JointProperties::Planar(_) | JointProperties::Floating(_) => {
+                None
+            }
+            JointProperties::Prismatic(joint)
+            | JointProperties::Revolute(joint)
+            | JointProperties::Continuous(joint) => Some(joint),
+        }
+    }
+
+    pub fn single_dof_mut(&mut self) -> Option<&mut SingleDofJoint> {
+        match self {
+            JointProperties::Fixed | JointProperties::Planar(_) | JointProperties::Floating(_) => {
+                None
+            }
+            JointProperties::Prismatic(joint)
+            | JointProperties::Revolute(joint)
+            | JointProperties::Continuous(joint) => Some(joint),
+        }
+    }
+
+    /// Returns the limits of the joint, if it has any.
+    pub fn limits(&self) -> Option<&JointLimits> {
+        match self {
+            JointProperties::Fixed => None,
+            JointProperties::Prismatic(joint)
+            | JointProperties::Revolute(joint)
+            | JointProperties::Continuous(joint) => Some(&joint.limits),
+            JointProperties::Planar(joint) => Some(&joint.limits),
+            JointProperties::Floating(joint) => Some(&joint.limits),
+        }
+    }
+
+    /// Returns the urdf `<limit>` of the joint and the warnings of the conversion, see
+    /// [`JointLimits::to_urdf`].
+    pub fn to_urdf_limit(&self) -> (urdf_rs::JointLimit, Vec<JointLimitWarning>) {
+        let Some(limits) = self.limits() else {
+            return Default::default();
+        };
+        let (limit, mut warnings) = limits.to_urdf();
+        match self {
+            JointProperties::Revolute(_) | JointProperties::Prismatic(_) => {
+                // Unbounded positions are exported as 0.0, which makes the joint immovable
+                let (lower, upper) = limits.position.bounds();
+                if lower.is_none() || upper.is_none() {
+                    warnings.push(JointLimitWarning::Missing {
+                        kind: JointLimitKind::Position,
+                        value: 0.0,
+                    });
+                }
+            }
+            // Position limits are ignored for continuous joints and urdf parsers ignore the
+            // limits of planar and floating joints
+            JointProperties::Continuous(_) => {}
+            _ => warnings.clear(),
+        }
+        (limit, warnings)
+    }
 }
 
 // TODO(luca) should commands implementation be in rmf_workcell_editor instead of rmf_workcell_format?
@@ -181,6 +555,7 @@ impl Joint {
             Category::Joint,
             self.name.clone(),
             self.properties.clone(),
+            self.actuation.clone(),
         ));
     }
 }
@@ -0,0 +1,290 @@
+/*
+ * Copyright (C) 2024 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+//! Forward kinematics over the frame and joint hierarchy of a workcell.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::*;
+use glam::{Affine3A, Quat, Vec3};
+use thiserror::Error as ThisError;
+
+/// Position of joints, keyed by joint name. Radians for revolute and continuous joints, meters
+/// for prismatic joints.
+pub type JointPositions = HashMap<String, f32>;
+
+#[derive(Debug, Clone, PartialEq, ThisError)]
+pub enum KinematicsError {
+    #[error("frame [{0}] not found")]
+    MissingFrame(u32),
+    #[error("joint [{0}] not found")]
+    MissingJoint(u32),
+    #[error("frame [{0}] has an anchor that is not a Pose3D")]
+    InvalidAnchorType(u32),
+    #[error("cycle found in the hierarchy of frame [{0}]")]
+    ParentCycle(u32),
+    #[error("joint [{0}] mimics a joint that does not exist")]
+    MissingMimicJoint(String),
+    #[error("cycle found in the mimic relations of joint [{0}]")]
+    MimicCycle(String),
+}
+
+impl JointProperties {
+    /// Transform introduced by the joint when at the requested position. Joints with more than
+    /// one degree of freedom (planar and floating) can't be described by a single position and
+    /// are always at their origin.
+    pub fn transform(&self, position: f32) -> Affine3A {
+        match self {
+            JointProperties::Revolute(joint) | JointProperties::Continuous(joint) => {
+                let axis = Vec3::from(joint.axis.0).normalize_or_zero();
+                if axis == Vec3::ZERO {
+                    return Affine3A::IDENTITY;
+                }
+                Affine3A::from_quat(Quat::from_axis_angle(axis, position))
+            }
+            JointProperties::Prismatic(joint) => {
+                let axis = Vec3::from(joint.axis.0).normalize_or_zero();
+                Affine3A::from_translation(axis * position)
+            }
+            JointProperties::Fixed | JointProperties::Planar(_) | JointProperties::Floating(_) => {
+                Affine3A::IDENTITY
+            }
+        }
+    }
+}
+
+impl Workcell {
+    /// Returns the position of a joint. Joints that mimic another joint follow it, regardless of
+    /// the requested position, other joints that are not in `positions` are at zero.
+    pub fn joint_position(
+        &self,
+        joint: &Joint,
+        positions: &JointPositions,
+    ) -> Result<f32, KinematicsError> {
+        let mut position = 0.0;
+        let mut multiplier = 1.0;
+        let mut current = joint;
+        let mut visited = Vec::new();
+        while let Some(mimic) = current
+            .properties
+            .single_dof()
+            .and_then(|j| j.mimic.as_ref())
+        {
+            if visited.contains(&mimic.joint) {
+                return Err(KinematicsError::MimicCycle(joint.name.0.clone()));
+            }
+            visited.push(mimic.joint.clone());
+            // p = m1 * (m2 * p2 + o2) + o1, accumulate the affine map from the start
+            position += multiplier * mimic.offset;
+            multiplier *= mimic.multiplier;
+            current = self
+                .joints
+                .values()
+                .map(|j| &j.bundle)
+                .find(|j| j.name.0 == mimic.joint)
+                .ok_or_else(|| KinematicsError::MissingMimicJoint(mimic.joint.clone()))?;
+        }
+        let leader = positions.get(&current.name.0).copied().unwrap_or_default();
+        Ok(position + multiplier * leader)
+    }
+
+    /// Transform of a frame relative to its parent frame, or to the workcell for frames that
+    /// are children of the workcell. Also returns the id of the parent frame.
+    fn frame_to_parent(
+        &self,
+        frame_id: u32,
+        positions: &JointPositions,
+    ) -> Result<(Affine3A, u32), KinematicsError> {
+        let frame = self
+            .frames
+            .get(&frame_id)
+            .ok_or(KinematicsError::MissingFrame(frame_id))?;
+        let Anchor::Pose3D(pose) = &frame.bundle.anchor else {
+            return Err(KinematicsError::InvalidAnchorType(frame_id));
+        };
+        let offset = affine_from_pose(pose);
+        match self.joints.get(&frame.parent) {
+            // As in urdf, the anchor is the origin of the joint in its parent frame and the
+            // child moves with the joint
+            Some(joint) => {
+                let position = self.joint_position(&joint.bundle, positions)?;
+                Ok((
+                    offset * joint.bundle.properties.transform(position),
+                    joint.parent,
+                ))
+            }
+            None => Ok((offset, frame.parent)),
+        }
+    }
+
+    /// Computes the transform of a frame relative to the workcell.
+    pub fn frame_transform(
+        &self,
+        frame_id: u32,
+        positions: &JointPositions,
+    ) -> Result<Affine3A, KinematicsError> {
+        let mut tf = Affine3A::IDENTITY;
+        let mut current = frame_id;
+        // Each step moves up the hierarchy, more steps than frames means there is a cycle
+        for _ in 0..=self.frames.len() {
+            if current == self.id {
+                return Ok(tf);
+            }
+            let (to_parent, parent) = self.frame_to_parent(current, positions)?;
+            tf = to_parent * tf;
+            current = parent;
+        }
+        Err(KinematicsError::ParentCycle(frame_id))
+    }
+
+    /// Computes the transform of `frame_id` relative to `relative_to`, either of them can be the
+    /// id of the workcell itself.
+    pub fn relative_transform(
+        &self,
+        frame_id: u32,
+        relative_to: u32,
+        positions: &JointPositions,
+    ) -> Result<Affine3A, KinematicsError> {
+        let tf = self.frame_transform(frame_id, positions)?;
+        let reference = self.frame_transform(relative_to, positions)?;
+        Ok(reference.inverse() * tf)
+    }
+
+    /// Same as [`Workcell::relative_transform`], returning a pose.
+    pub fn relative_pose(
+        &self,
+        frame_id: u32,
+        relative_to: u32,
+        positions: &JointPositions,
+    ) -> Result<Pose, KinematicsError> {
+        self.relative_transform(frame_id, relative_to, positions)
+            .map(|tf| pose_from_affine(&tf))
+    }
+
+    /// Computes the transform of all the frames relative to the workcell, walking the hierarchy
+    /// only once.
+    pub fn frame_transforms(
+        &self,
+        positions: &JointPositions,
+    ) -> Result<BTreeMap<u32, Affine3A>, KinematicsError> {
+        let mut transforms = BTreeMap::new();
+        transforms.insert(self.id, Affine3A::IDENTITY);
+        for frame_id in self.frames.keys() {
+            // Climb until a frame with a known transform is found, then fill the path going down
+            let mut path = Vec::new();
+            let mut current = *frame_id;
+            let parent_tf = loop {
+                if let Some(tf) = transforms.get(&current) {
+                    break *tf;
+                }
+                if path.len() > self.frames.len() {
+                    return Err(KinematicsError::ParentCycle(*frame_id));
+                }
+                let (to_parent, parent) = self.frame_to_parent(current, positions)?;
+                path.push((current, to_parent));
+                current = parent;
+            };
+            let mut tf = parent_tf;
+            for (id, to_parent) in path.into_iter().rev() {
+                tf *= to_parent;
+                transforms.insert(id, tf);
+            }
+        }
+        transforms.remove(&self.id);
+        Ok(transforms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::frame_id;
+    use std::f32::consts::FRAC_PI_2;
+
+    #[test]
+    fn forward_kinematics() {
+        let urdf = r#"
+            <robot name="arm">
+                <link name="base"/>
+                <link name="upper_arm"/>
+                <link name="forearm"/>
+                <link name="left_finger"/>
+                <link name="right_finger"/>
+                <joint name="shoulder" type="revolute">
+                    <parent link="base"/>
+                    <child link="upper_arm"/>
+                    <origin xyz="0 0 0.5"/>
+                    <axis xyz="0 0 1"/>
+                    <limit lower="-3" upper="3" effort="10" velocity="1"/>
+                </joint>
+                <joint name="elbow" type="fixed">
+                    <parent link="upper_arm"/>
+                    <child link="forearm"/>
+                    <origin xyz="1 0 0"/>
+                </joint>
+                <joint name="left_finger_joint" type="prismatic">
+                    <parent link="forearm"/>
+                    <child link="left_finger"/>
+                    <axis xyz="0 1 0"/>
+                    <limit lower="0" upper="0.1" effort="10" velocity="1"/>
+                </joint>
+                <joint name="right_finger_joint" type="prismatic">
+                    <parent link="forearm"/>
+                    <child link="right_finger"/>
+                    <axis xyz="0 1 0"/>
+                    <limit lower="-0.1" upper="0" effort="10" velocity="1"/>
+                    <mimic joint="left_finger_joint" multiplier="-1" offset="0"/>
+                </joint>
+            </robot>"#;
+        let workcell = Workcell::from_urdf(&urdf_rs::read_from_string(urdf).unwrap()).unwrap();
+        let base = frame_id(&workcell, "base");
+        let forearm = frame_id(&workcell, "forearm");
+        let left = frame_id(&workcell, "left_finger");
+        let right = frame_id(&workcell, "right_finger");
+
+        let positions = JointPositions::from([
+            ("shoulder".to_string(), FRAC_PI_2),
+            ("left_finger_joint".to_string(), 0.05),
+            // Ignored, the joint follows the one it mimics
+            ("right_finger_joint".to_string(), 1.0),
+        ]);
+        let tf = workcell.frame_transform(forearm, &positions).unwrap();
+        assert!(tf
+            .translation
+            .abs_diff_eq(Vec3::new(0.0, 1.0, 0.5).into(), 1e-6));
+
+        // The fingers move along the y axis of the forearm, rotated to -x by the shoulder
+        let left_tf = workcell.relative_transform(left, base, &positions).unwrap();
+        assert!(left_tf
+            .translation
+            .abs_diff_eq(Vec3::new(-0.05, 1.0, 0.5).into(), 1e-6));
+        let right_pose = workcell.relative_pose(right, left, &positions).unwrap();
+        assert!(Vec3::from(right_pose.trans).abs_diff_eq(Vec3::new(0.0, -0.1, 0.0), 1e-6));
+
+        let transforms = workcell.frame_transforms(&positions).unwrap();
+        assert_eq!(transforms.len(), workcell.frames.len());
+        assert!(transforms[&left].abs_diff_eq(left_tf, 1e-6));
+
+        // Joints that are not set are at their zero position
+        let tf = workcell
+            .frame_transform(forearm, &JointPositions::new())
+            .unwrap();
+        assert!(tf
+            .translation
+            .abs_diff_eq(Vec3::new(1.0, 0.0, 0.5).into(), 1e-6));
+    }
+}
@@ -15,15 +15,69 @@
  *
 */
 
+pub mod builder;
+pub use builder::*;
+
+pub mod collision;
+pub use collision::*;
+
+pub mod collision_matrix;
+pub use collision_matrix::*;
+
+pub mod control;
+pub use control::*;
+
+pub mod diff;
+pub use diff::*;
+
 pub mod geometry;
 pub use geometry::*;
 
+pub mod graph;
+pub use graph::*;
+
+pub mod include;
+pub use include::*;
+
 pub mod inertial;
 pub use inertial::*;
 
 pub mod joint;
 pub use joint::*;
 
+pub mod inverse_kinematics;
+pub use inverse_kinematics::*;
+
+pub mod kinematics;
+pub use kinematics::*;
+
+pub mod material;
+pub use material::*;
+
+pub mod mjcf;
+pub use mjcf::*;
+
+pub mod planning;
+pub use planning::*;
+
+pub mod sdf;
+pub use sdf::*;
+
+pub mod sensor;
+pub use sensor::*;
+
+pub mod transform;
+pub use transform::*;
+
+pub mod validate;
+pub use validate::*;
+
+pub mod version;
+pub use version::*;
+
+pub mod xacro;
+pub use xacro::*;
+
 pub mod workcell;
 pub use workcell::*;
 
@@ -36,5 +90,10 @@ pub use rmf_site_format::{
 mod is_default;
 pub(crate) use is_default::*;
 
+mod xml;
+
+#[cfg(test)]
+mod test_utils;
+
 pub const CURRENT_MAJOR_VERSION: u32 = 0;
-pub const CURRENT_MINOR_VERSION: u32 = 1;
+pub const CURRENT_MINOR_VERSION: u32 = 7;
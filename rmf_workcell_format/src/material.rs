@@ -0,0 +1,108 @@
+/*
+ * Copyright (C) 2024 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use std::collections::BTreeMap;
+
+use crate::{asset_source_from_urdf, is_default};
+use rmf_site_format::AssetSource;
+
+#[cfg(feature = "bevy")]
+use bevy::prelude::{Component, Deref, DerefMut};
+use serde::{Deserialize, Serialize};
+
+/// Appearance of a visual, a uniform color optionally combined with a texture.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct VisualMaterial {
+    /// Red, green, blue and alpha channels, in the [0, 1] range
+    pub color: [f32; 4],
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub texture: Option<AssetSource>,
+}
+
+impl Default for VisualMaterial {
+    fn default() -> Self {
+        Self {
+            color: [1.0; 4],
+            texture: None,
+        }
+    }
+}
+
+impl VisualMaterial {
+    /// Creates a material from its urdf counterpart, returns None if the urdf material has
+    /// neither a color nor a texture, i.e. it is only a reference to a named material.
+    pub fn from_urdf(material: &urdf_rs::Material) -> Option<Self> {
+        if material.color.is_none() && material.texture.is_none() {
+            return None;
+        }
+        Some(Self {
+            color: material
+                .color
+                .as_ref()
+                .map(|c| c.rgba.0.map(|v| v as f32))
+                .unwrap_or([1.0; 4]),
+            texture: material
+                .texture
+                .as_ref()
+                .map(|t| asset_source_from_urdf(&t.filename)),
+        })
+    }
+
+    pub fn to_urdf(&self, name: String) -> urdf_rs::Material {
+        urdf_rs::Material {
+            name,
+            color: Some(urdf_rs::Color {
+                rgba: urdf_rs::Vec4(self.color.map(|v| v as f64)),
+            }),
+            texture: self.texture.as_ref().map(|source| urdf_rs::Texture {
+                // SAFETY: Same as meshes, the path is validated when loading the asset
+                filename: unsafe { source.as_unvalidated_asset_path() },
+            }),
+        }
+    }
+}
+
+/// Material of a visual, either defined in place or referring to the workcell material library.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "bevy", derive(Component))]
+pub enum MaterialRef {
+    /// Name of a material in the [`MaterialLibrary`] of the workcell
+    Library(String),
+    Inline(VisualMaterial),
+}
+
+impl Default for MaterialRef {
+    fn default() -> Self {
+        MaterialRef::Inline(VisualMaterial::default())
+    }
+}
+
+/// Named materials that can be shared by multiple visuals of a workcell.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(transparent)]
+#[cfg_attr(feature = "bevy", derive(Component, Deref, DerefMut))]
+pub struct MaterialLibrary(pub BTreeMap<String, VisualMaterial>);
+
+impl MaterialLibrary {
+    /// Returns the material that a reference points to, if it exists.
+    pub fn resolve<'a>(&'a self, material: &'a MaterialRef) -> Option<&'a VisualMaterial> {
+        match material {
+            MaterialRef::Library(name) => self.0.get(name),
+            MaterialRef::Inline(material) => Some(material),
+        }
+    }
+}
@@ -0,0 +1,450 @@
+/*
+ * Copyright (C) 2024 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use std::collections::{BTreeMap, HashSet};
+use std::io;
+use std::path::Path;
+
+use crate::xml::{space_separated, write_to_string, ElementExt};
+use crate::*;
+
+use glam::{Mat3, Quat, Vec3};
+use thiserror::Error as ThisError;
+use xmltree::Element;
+
+/// Geom group of visuals, they are excluded from collision checking.
+pub const MJCF_VISUAL_GROUP: u32 = 2;
+/// Geom group of collisions.
+pub const MJCF_COLLISION_GROUP: u32 = 3;
+
+#[derive(Debug, ThisError)]
+pub enum WorkcellToMjcfError {
+    #[error("Invalid workcell structure: {0:?}")]
+    InvalidStructure(Vec<WorkcellDiagnostic>),
+    #[error("Invalid anchor type {0:?}")]
+    InvalidAnchorType(Anchor),
+    #[error("the workcell includes other workcells, they must be flattened before exporting")]
+    UnflattenedIncludes,
+    #[error("name [{0}] is reserved in mjcf")]
+    ReservedName(String),
+    #[error("Mjcf write error: {0}")]
+    WriteError(#[from] xmltree::Error),
+}
+
+/// Sets the `pos` and `quat` attributes of an element, the identity rotation is omitted.
+fn with_mjcf_pose(element: Element, pose: &Pose) -> Element {
+    // Adding zero turns negative zeros into positive ones for a cleaner output
+    let element = element.with_attr("pos", space_separated(pose.trans.map(|v| v + 0.0)));
+    let quat = quat_from_rotation(&pose.rot);
+    if quat.abs_diff_eq(Quat::IDENTITY, 1e-6) {
+        return element;
+    }
+    // Mujoco quaternions are in w, x, y, z order
+    element.with_attr(
+        "quat",
+        space_separated([quat.w, quat.x, quat.y, quat.z].map(|v| v + 0.0)),
+    )
+}
+
+/// Mesh assets of the model, meshes with the same source and scale are only added once.
+#[derive(Default)]
+struct MjcfMeshes {
+    meshes: BTreeMap<(String, [u32; 3]), String>,
+    names: HashSet<String>,
+}
+
+impl MjcfMeshes {
+    /// Returns the name of the mesh asset for the requested file and scale.
+    fn get_or_insert(&mut self, file: String, scale: Vec3) -> String {
+        let key = (file, scale.to_array().map(f32::to_bits));
+        if let Some(name) = self.meshes.get(&key) {
+            return name.clone();
+        }
+        let stem = Path::new(&key.0)
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "mesh".to_owned());
+        let mut name = stem.clone();
+        let mut idx = 1;
+        while !self.names.insert(name.clone()) {
+            name = format!("{stem}_{idx}");
+            idx += 1;
+        }
+        self.meshes.insert(key, name.clone());
+        name
+    }
+
+    fn to_asset(&self) -> Element {
+        let mut asset = Element::new("asset");
+        for ((file, scale), name) in &self.meshes {
+            let mesh = Element::new("mesh")
+                .with_attr("name", name)
+                .with_attr("file", file);
+            let scale = scale.map(f32::from_bits);
+            asset.push(if scale == [1.0; 3] {
+                mesh
+            } else {
+                mesh.with_attr("scale", space_separated(scale))
+            });
+        }
+        asset
+    }
+}
+
+impl Geometry {
+    /// Sets the type and size of a geom, meshes are added to the mesh assets.
+    fn to_mjcf_geom(&self, geom: Element, meshes: &mut MjcfMeshes) -> Element {
+        match self {
+            // Mujoco sizes are half extents
+            Geometry::Primitive(PrimitiveShape::Box { size }) => geom
+                .with_attr("type", "box")
+                .with_attr("size", space_separated(size.map(|s| s / 2.0))),
+            Geometry::Primitive(PrimitiveShape::Cylinder { radius, length }) => geom
+                .with_attr("type", "cylinder")
+                .with_attr("size", space_separated([*radius, length / 2.0])),
+            Geometry::Primitive(PrimitiveShape::Capsule { radius, length }) => geom
+                .with_attr("type", "capsule")
+                .with_attr("size", space_separated([*radius, length / 2.0])),
+            Geometry::Primitive(PrimitiveShape::Sphere { radius }) => {
+                geom.with_attr("type", "sphere").with_attr("size", radius)
+            }
+            Geometry::Mesh { source, scale } => {
+                let file = match source {
+                    AssetSource::Local(path) => path.clone(),
+                    // SAFETY: We don't need to validate the syntax of the asset
+                    // path because that will be done later when we attempt to load
+                    // this as an asset.
+                    source => unsafe { source.as_unvalidated_asset_path() },
+                };
+                let mesh = meshes.get_or_insert(file, scale.unwrap_or(Vec3::ONE));
+                geom.with_attr("type", "mesh").with_attr("mesh", mesh)
+            }
+        }
+    }
+}
+
+impl Inertia {
+    /// Mujoco doesn't accept massless bodies, inertias without mass are skipped and the inertia
+    /// of the body is computed from its geoms instead.
+    fn to_mjcf(&self) -> Option<Element> {
+        if self.mass.0 <= 0.0 {
+            return None;
+        }
+        // Express the moment in the body frame to avoid combining a rotation and a full inertia
+        let moment = self.moment.to_mat3();
+        let rot = Mat3::from_quat(quat_from_rotation(&self.center.rot));
+        let moment = rot * moment * rot.transpose();
+        Some(
+            Element::new("inertial")
+                .with_attr("pos", space_separated(self.center.trans))
+                .with_attr("mass", self.mass.0)
+                .with_attr(
+                    "fullinertia",
+                    space_separated([
+                        moment.x_axis.x,
+                        moment.y_axis.y,
+                        moment.z_axis.z,
+                        moment.y_axis.x,
+                        moment.z_axis.x,
+                        moment.z_axis.y,
+                    ]),
+                ),
+        )
+    }
+}
+
+impl JointProperties {
+    /// Returns the `<joint>` elements of a body, fixed joints don't have any since bodies without
+    /// joints are rigidly attached to their parent. Mujoco has no planar joint and only allows
+    /// free joints in top level bodies, they are exported as a combination of simpler joints.
+    fn to_mjcf(&self, name: &str) -> Vec<Element> {
+        let new_joint = |suffix: &str, joint_type: &str| {
+            Element::new("joint")
+                .with_attr("name", name.to_owned() + suffix)
+                .with_attr("type", joint_type)
+        };
+        let (joint_type, joint) = match self {
+            JointProperties::Fixed => return vec![],
+            JointProperties::Revolute(joint) | JointProperties::Continuous(joint) => {
+                ("hinge", joint)
+            }
+            JointProperties::Prismatic(joint) => ("slide", joint),
+            JointProperties::Planar(planar) => {
+                let normal = Vec3::from(planar.axis.0).normalize_or_zero();
+                let (x, y) = normal.any_orthonormal_pair();
+                return vec![
+                    new_joint("_x", "slide").with_attr("axis", space_separated(x.to_array())),
+                    new_joint("_y", "slide").with_attr("axis", space_separated(y.to_array())),
+                    new_joint("_yaw", "hinge")
+                        .with_attr("axis", space_separated(normal.to_array())),
+                ];
+            }
+            JointProperties::Floating(_) => {
+                return vec![
+                    new_joint("_x", "slide").with_attr("axis", "1 0 0"),
+                    new_joint("_y", "slide").with_attr("axis", "0 1 0"),
+                    new_joint("_z", "slide").with_attr("axis", "0 0 1"),
+                    new_joint("_rotation", "ball"),
+                ];
+            }
+        };
+        let mut element =
+            new_joint("", joint_type).with_attr("axis", space_separated(joint.axis.0));
+        // Only finite and non empty ranges are exported, others are treated as unlimited
+        let range = |limits: &RangeLimits| {
+            let range = match limits {
+                RangeLimits::None => return None,
+                RangeLimits::Symmetric(l) => [-l.abs(), l.abs()],
+                RangeLimits::Asymmetric { lower, upper } => [(*lower)?, (*upper)?],
+            };
+            (range[0] < range[1] && range.iter().all(|v| v.is_finite()))
+                .then(|| space_separated(range))
+        };
+        if !matches!(self, JointProperties::Continuous(_)) {
+            if let Some(range) = range(&joint.limits.position) {
+                element = element.with_attr("range", range);
+            }
+        }
+        if let Some(range) = range(&joint.limits.effort) {
+            element = element.with_attr("actuatorfrcrange", range);
+        }
+        if joint.dynamics.damping != 0.0 {
+            element = element.with_attr("damping", joint.dynamics.damping);
+        }
+        if joint.dynamics.friction != 0.0 {
+            element = element.with_attr("frictionloss", joint.dynamics.friction);
+        }
+        vec![element]
+    }
+}
+
+impl Workcell {
+    fn frame_to_mjcf_body(
+        &self,
+        frame_id: u32,
+        meshes: &mut MjcfMeshes,
+    ) -> Result<Element, WorkcellToMjcfError> {
+        let frame = &self.frames[&frame_id];
+        let Anchor::Pose3D(pose) = &frame.bundle.anchor else {
+            return Err(WorkcellToMjcfError::InvalidAnchorType(
+                frame.bundle.anchor.clone(),
+            ));
+        };
+        let name = &frame.bundle.name.0;
+        if name == "world" {
+            return Err(WorkcellToMjcfError::ReservedName(name.clone()));
+        }
+        let mut body = with_mjcf_pose(Element::new("body").with_attr("name", name), pose);
+
+        let inertias = self
+            .inertias
+            .values()
+            .filter(|i| i.parent == frame_id)
+            .map(|i| i.bundle.clone())
+            .collect();
+        if let Some(inertial) = Inertia::merge(inertias).and_then(|i| i.to_mjcf()) {
+            body.push(inertial);
+        }
+        if let Some(joint) = self.joints.get(&frame.parent) {
+            for element in joint.bundle.properties.to_mjcf(&joint.bundle.name.0) {
+                body.push(element);
+            }
+        }
+        for (models, group) in [
+            (&self.collisions, MJCF_COLLISION_GROUP),
+            (&self.visuals, MJCF_VISUAL_GROUP),
+        ] {
+            for model in models.values().filter(|m| m.parent == frame_id) {
+                let mut geom = Element::new("geom");
+                if !model.bundle.name.is_empty() {
+                    geom = geom.with_attr("name", &model.bundle.name);
+                }
+                let mut geom = model.bundle.geometry.to_mjcf_geom(geom, meshes);
+                geom = with_mjcf_pose(geom, &model.bundle.pose).with_attr("group", group);
+                if group == MJCF_VISUAL_GROUP {
+                    // Visuals don't collide and don't contribute to the body inertia
+                    geom = geom
+                        .with_attr("contype", 0)
+                        .with_attr("conaffinity", 0)
+                        .with_attr("density", 0);
+                    // Textures need uv mapped meshes to be useful, only the color is exported
+                    if let Some(material) = model
+                        .bundle
+                        .material
+                        .as_ref()
+                        .and_then(|m| self.materials.resolve(m))
+                    {
+                        geom = geom.with_attr("rgba", space_separated(material.color));
+                    }
+                }
+                body.push(geom);
+            }
+        }
+        // Children are either attached directly or through a joint
+        for (child_id, child) in &self.frames {
+            let parent = self
+                .joints
+                .get(&child.parent)
+                .map_or(child.parent, |j| j.parent);
+            if parent == frame_id {
+                body.push(self.frame_to_mjcf_body(*child_id, meshes)?);
+            }
+        }
+        Ok(body)
+    }
+
+    /// Exports the workcell as a MuJoCo model. Every frame becomes a `<body>`, nested according
+    /// to the workcell hierarchy, with its joint if it is the child of one. Frames that are not
+    /// connected through a joint are rigidly attached to their parent body.
+    /// Local meshes are referenced through their path, other sources through their asset path
+    /// that needs to be converted to a local file before loading the model.
+    pub fn to_mjcf(&self) -> Result<Element, WorkcellToMjcfError> {
+        let diagnostics = self.validate();
+        if !diagnostics.is_empty() {
+            return Err(WorkcellToMjcfError::InvalidStructure(diagnostics));
+        }
+        if !self.includes.is_empty() {
+            return Err(WorkcellToMjcfError::UnflattenedIncludes);
+        }
+        let mut meshes = MjcfMeshes::default();
+        let mut worldbody = Element::new("worldbody");
+        for (frame_id, frame) in &self.frames {
+            if frame.parent == self.id {
+                worldbody.push(self.frame_to_mjcf_body(*frame_id, &mut meshes)?);
+            }
+        }
+        let mut mujoco = Element::new("mujoco")
+            .with_attr("model", &self.properties.name.0)
+            .with_child(
+                Element::new("compiler")
+                    .with_attr("angle", "radian")
+                    .with_attr("autolimits", "true"),
+            );
+        if !meshes.meshes.is_empty() {
+            mujoco.push(meshes.to_asset());
+        }
+        mujoco.push(worldbody);
+        // Mimic joints are coupled through equality constraints, the polynomial coefficients
+        // map the position of the mimicked joint to the position of the mimic one
+        let mut equality = Element::new("equality");
+        for joint in self.joints.values() {
+            let Some(mimic) = joint
+                .bundle
+                .properties
+                .single_dof()
+                .and_then(|j| j.mimic.as_ref())
+            else {
+                continue;
+            };
+            equality.push(
+                Element::new("joint")
+                    .with_attr("joint1", &joint.bundle.name.0)
+                    .with_attr("joint2", &mimic.joint)
+                    .with_attr(
+                        "polycoef",
+                        space_separated([mimic.offset, mimic.multiplier, 0.0, 0.0, 0.0]),
+                    ),
+            );
+        }
+        if !equality.children.is_empty() {
+            mujoco.push(equality);
+        }
+        Ok(mujoco)
+    }
+
+    pub fn to_mjcf_string(&self) -> Result<String, WorkcellToMjcfError> {
+        let mjcf = self.to_mjcf()?;
+        Ok(write_to_string(&mjcf)?)
+    }
+
+    pub fn to_mjcf_writer(&self, mut writer: impl io::Write) -> Result<(), std::io::Error> {
+        let mjcf = self
+            .to_mjcf_string()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        writer.write_all(mjcf.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xml::children_named;
+
+    fn count_descendants(element: &Element, name: &str) -> usize {
+        element
+            .children
+            .iter()
+            .filter_map(|c| c.as_element())
+            .map(|c| (c.name == name) as usize + count_descendants(c, name))
+            .sum()
+    }
+
+    fn find_body<'a>(element: &'a Element, name: &str) -> Option<&'a Element> {
+        children_named(element, "body").find_map(|body| {
+            if body.attributes["name"] == name {
+                Some(body)
+            } else {
+                find_body(body, name)
+            }
+        })
+    }
+
+    #[test]
+    fn urdf_to_mjcf() {
+        let urdf = urdf_rs::read_file("test/07-physics.urdf").unwrap();
+        let workcell = Workcell::from_urdf(&urdf).unwrap();
+        let mjcf = workcell.to_mjcf().unwrap();
+        let worldbody = mjcf.get_child("worldbody").unwrap();
+        assert_eq!(children_named(worldbody, "body").count(), 1);
+        assert_eq!(count_descendants(worldbody, "body"), 16);
+        // Fixed joints are not exported
+        assert_eq!(count_descendants(worldbody, "joint"), 8);
+        assert_eq!(count_descendants(worldbody, "geom"), 32);
+        // Repeated meshes are only added once
+        let asset = mjcf.get_child("asset").unwrap();
+        assert_eq!(children_named(asset, "mesh").count(), 2);
+
+        let right_leg = find_body(worldbody, "right_leg").unwrap();
+        assert_eq!(right_leg.attributes["pos"], "0 -0.22 0.25");
+        let right_leg_geom = right_leg.get_child("geom").unwrap();
+        assert_eq!(right_leg_geom.attributes["type"], "box");
+        assert_eq!(right_leg_geom.attributes["size"], "0.3 0.05 0.1");
+        let gripper_joint = find_body(worldbody, "gripper_pole")
+            .and_then(|body| body.get_child("joint"))
+            .unwrap();
+        assert_eq!(gripper_joint.attributes["type"], "slide");
+        assert_eq!(gripper_joint.attributes["range"], "-0.38 0");
+        assert!(workcell.to_mjcf_string().is_ok());
+    }
+
+    #[test]
+    fn includes_must_be_flattened() {
+        let mut builder = WorkcellBuilder::new("cell");
+        let table = builder.add_frame(builder.root(), "table", Pose::default());
+        builder.add_include(
+            table,
+            "robot",
+            AssetSource::Local("07-physics.urdf".to_owned()),
+            Pose::default(),
+        );
+        let workcell = builder.build().unwrap();
+        assert!(matches!(
+            workcell.to_mjcf(),
+            Err(WorkcellToMjcfError::UnflattenedIncludes)
+        ));
+    }
+}
@@ -0,0 +1,633 @@
+/*
+ * Copyright (C) 2024 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+//! Motion planning semantics of workcells, exported as an srdf to configure planners such as
+//! MoveIt.
+
+use std::collections::{BTreeMap, HashSet};
+
+#[cfg(feature = "bevy")]
+use bevy::prelude::Component;
+use serde::{Deserialize, Serialize};
+use thiserror::Error as ThisError;
+use xmltree::Element;
+
+use crate::xml::*;
+use crate::*;
+
+/// Joints that are planned for together, such as the joints of an arm.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanningGroupMembers {
+    /// The joints connecting the `base` frame to the `tip` frame, which must be below it
+    Chain { base: String, tip: String },
+    /// Names of the joints of the group
+    Joints(Vec<String>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlanningGroup {
+    pub name: String,
+    pub members: PlanningGroupMembers,
+}
+
+/// A group moving an end effector, such as a gripper, attached to a frame of the workcell.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EndEffector {
+    pub name: String,
+    pub group: String,
+    /// Name of the frame the end effector is attached to
+    pub parent_frame: String,
+    /// Group that moves the parent frame, if any
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub parent_group: Option<String>,
+}
+
+/// Named positions of the joints of a group, such as "home" or "ready".
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GroupState {
+    pub name: String,
+    pub group: String,
+    /// Position of each joint, by joint name
+    pub positions: BTreeMap<String, f32>,
+}
+
+/// Why collisions between two links don't need to be checked, as in srdf.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum DisabledCollisionReason {
+    /// The links are connected by a joint
+    Adjacent,
+    /// The links collide in every configuration, i.e. they are mounted against each other
+    Always,
+    /// The links can't collide in any configuration
+    Never,
+    /// Disabled by users
+    #[default]
+    User,
+}
+
+impl DisabledCollisionReason {
+    pub fn label(&self) -> &'static str {
+        match self {
+            DisabledCollisionReason::Adjacent => "Adjacent",
+            DisabledCollisionReason::Always => "Always",
+            DisabledCollisionReason::Never => "Never",
+            DisabledCollisionReason::User => "User",
+        }
+    }
+}
+
+/// A pair of links that motion planners don't need to check for collisions. Links are referred
+/// to by the name of one of their frames, any frame merged into the link can be used.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DisabledCollision {
+    pub frames: [String; 2],
+    #[serde(default)]
+    pub reason: DisabledCollisionReason,
+}
+
+/// Planning semantics of a workcell, all the elements are referred to by name.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "bevy", derive(Component))]
+pub struct PlanningSemantics {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<PlanningGroup>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub end_effectors: Vec<EndEffector>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub group_states: Vec<GroupState>,
+    /// Names of the joints that are not actuated and can't be planned for
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub passive_joints: Vec<String>,
+    /// Usually computed with [`Workcell::compute_collision_matrix`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub disabled_collisions: Vec<DisabledCollision>,
+}
+
+impl PlanningSemantics {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn group(&self, name: &str) -> Option<&PlanningGroup> {
+        self.groups.iter().find(|g| g.name == name)
+    }
+
+    /// Adds the semantics of another workcell, with all its names passed through `prefix`.
+    pub(crate) fn extend_prefixed(
+        &mut self,
+        other: PlanningSemantics,
+        prefix: impl Fn(&str) -> String,
+    ) {
+        let prefix = &prefix;
+        self.groups
+            .extend(other.groups.into_iter().map(|group| PlanningGroup {
+                name: prefix(&group.name),
+                members: match group.members {
+                    PlanningGroupMembers::Chain { base, tip } => PlanningGroupMembers::Chain {
+                        base: prefix(&base),
+                        tip: prefix(&tip),
+                    },
+                    PlanningGroupMembers::Joints(joints) => {
+                        PlanningGroupMembers::Joints(joints.iter().map(|j| prefix(j)).collect())
+                    }
+                },
+            }));
+        self.end_effectors
+            .extend(other.end_effectors.into_iter().map(|ee| EndEffector {
+                name: prefix(&ee.name),
+                group: prefix(&ee.group),
+                parent_frame: prefix(&ee.parent_frame),
+                parent_group: ee.parent_group.map(|g| prefix(&g)),
+            }));
+        self.group_states
+            .extend(other.group_states.into_iter().map(|state| {
+                GroupState {
+                    name: state.name,
+                    group: prefix(&state.group),
+                    positions: state
+                        .positions
+                        .into_iter()
+                        .map(|(joint, position)| (prefix(&joint), position))
+                        .collect(),
+                }
+            }));
+        self.passive_joints
+            .extend(other.passive_joints.iter().map(|j| prefix(j)));
+        self.disabled_collisions
+            .extend(
+                other
+                    .disabled_collisions
+                    .into_iter()
+                    .map(|disabled| DisabledCollision {
+                        frames: disabled.frames.map(|f| prefix(&f)),
+                        reason: disabled.reason,
+                    }),
+            );
+    }
+}
+
+/// An inconsistency in the planning semantics of a workcell.
+#[derive(Debug, Clone, PartialEq, ThisError)]
+pub enum PlanningError {
+    #[error("planning group name [{0}] is used more than once")]
+    DuplicateGroup(String),
+    #[error("end effector name [{0}] is used more than once")]
+    DuplicateEndEffector(String),
+    #[error("state [{state}] of group [{group}] is defined more than once")]
+    DuplicateGroupState { group: String, state: String },
+    #[error("{context} refers to a non existing group [{group}]")]
+    MissingGroup { context: String, group: String },
+    #[error("{context} refers to a non existing joint [{joint}]")]
+    MissingJoint { context: String, joint: String },
+    #[error("{context} refers to a non existing frame [{frame}]")]
+    MissingFrame { context: String, frame: String },
+    #[error("frame [{tip}] of group [{group}] is not below its base frame [{base}]")]
+    InvalidChain {
+        group: String,
+        base: String,
+        tip: String,
+    },
+    #[error(
+        "state [{state}] of group [{group}] sets joint [{joint}], which is not a single degree \
+        of freedom joint of the group"
+    )]
+    InvalidStateJoint {
+        group: String,
+        state: String,
+        joint: String,
+    },
+}
+
+#[derive(Debug, ThisError)]
+pub enum SrdfExportError {
+    #[error("Invalid planning semantics: {0:?}")]
+    InvalidPlanning(Vec<PlanningError>),
+    /// The srdf refers to the links of the urdf, it can't be exported if the urdf can't
+    #[error(transparent)]
+    Urdf(#[from] WorkcellToUrdfError),
+    #[error("Xml error: {0}")]
+    XmlError(#[from] xmltree::Error),
+}
+
+impl Workcell {
+    /// Ids of the joints of a planning group, in order from the base to the tip for chains.
+    pub fn planning_group_joints(&self, group: &PlanningGroup) -> Result<Vec<u32>, PlanningError> {
+        let graph = self.graph();
+        let context = || format!("group [{}]", group.name);
+        match &group.members {
+            PlanningGroupMembers::Chain { base, tip } => {
+                let frame = |frame: &String| {
+                    graph
+                        .frame_by_name(frame)
+                        .ok_or_else(|| PlanningError::MissingFrame {
+                            context: context(),
+                            frame: frame.clone(),
+                        })
+                };
+                let (base_id, tip_id) = (frame(base)?, frame(tip)?);
+                let invalid_chain = || PlanningError::InvalidChain {
+                    group: group.name.clone(),
+                    base: base.clone(),
+                    tip: tip.clone(),
+                };
+                let below = graph
+                    .ancestors(tip_id)
+                    .map_err(|_| invalid_chain())?
+                    .contains(&base_id);
+                if !below {
+                    return Err(invalid_chain());
+                }
+                let chain = graph
+                    .joint_chain(base_id, tip_id)
+                    .map_err(|_| invalid_chain())?;
+                Ok(chain.into_iter().map(|j| j.joint).collect())
+            }
+            PlanningGroupMembers::Joints(joints) => joints
+                .iter()
+                .map(|joint| {
+                    graph
+                        .joint_by_name(joint)
+                        .ok_or_else(|| PlanningError::MissingJoint {
+                            context: context(),
+                            joint: joint.clone(),
+                        })
+                })
+                .collect(),
+        }
+    }
+
+    /// Checks that the planning semantics only refer to existing elements, an empty result
+    /// means that they can be exported.
+    pub fn validate_planning(&self) -> Vec<PlanningError> {
+        let planning = &self.properties.planning;
+        let graph = self.graph();
+        let mut errors = Vec::new();
+
+        let mut groups = HashSet::new();
+        for group in &planning.groups {
+            if !groups.insert(group.name.as_str()) {
+                errors.push(PlanningError::DuplicateGroup(group.name.clone()));
+            }
+            if let Err(error) = self.planning_group_joints(group) {
+                errors.push(error);
+            }
+        }
+        let mut check_group = |context: String, group: &String| {
+            if !groups.contains(group.as_str()) {
+                errors.push(PlanningError::MissingGroup {
+                    context,
+                    group: group.clone(),
+                });
+            }
+        };
+        for ee in &planning.end_effectors {
+            let context = || format!("end effector [{}]", ee.name);
+            check_group(context(), &ee.group);
+            if let Some(parent_group) = &ee.parent_group {
+                check_group(context(), parent_group);
+            }
+        }
+        for state in &planning.group_states {
+            check_group(format!("state [{}]", state.name), &state.group);
+        }
+
+        let mut end_effectors = HashSet::new();
+        for ee in &planning.end_effectors {
+            if !end_effectors.insert(ee.name.as_str()) {
+                errors.push(PlanningError::DuplicateEndEffector(ee.name.clone()));
+            }
+            if graph.frame_by_name(&ee.parent_frame).is_none() {
+                errors.push(PlanningError::MissingFrame {
+                    context: format!("end effector [{}]", ee.name),
+                    frame: ee.parent_frame.clone(),
+                });
+            }
+        }
+
+        let mut states = HashSet::new();
+        for state in &planning.group_states {
+            if !states.insert((state.group.as_str(), state.name.as_str())) {
+                errors.push(PlanningError::DuplicateGroupState {
+                    group: state.group.clone(),
+                    state: state.name.clone(),
+                });
+            }
+            let Some(group_joints) = planning
+                .group(&state.group)
+                .and_then(|g| self.planning_group_joints(g).ok())
+            else {
+                continue;
+            };
+            for joint in state.positions.keys() {
+                let valid = graph.joint_by_name(joint).is_some_and(|id| {
+                    group_joints.contains(&id)
+                        && self.joints[&id].bundle.properties.single_dof().is_some()
+                });
+                if !valid {
+                    errors.push(PlanningError::InvalidStateJoint {
+                        group: state.group.clone(),
+                        state: state.name.clone(),
+                        joint: joint.clone(),
+                    });
+                }
+            }
+        }
+
+        for joint in &planning.passive_joints {
+            if graph.joint_by_name(joint).is_none() {
+                errors.push(PlanningError::MissingJoint {
+                    context: "passive joints".to_owned(),
+                    joint: joint.clone(),
+                });
+            }
+        }
+
+        for disabled in &planning.disabled_collisions {
+            for frame in &disabled.frames {
+                if graph.frame_by_name(frame).is_none() {
+                    errors.push(PlanningError::MissingFrame {
+                        context: "disabled collisions".to_owned(),
+                        frame: frame.clone(),
+                    });
+                }
+            }
+        }
+        errors
+    }
+
+    /// Returns the srdf describing the planning semantics of the workcell, for the urdf
+    /// exported with the same options. Frames are replaced by their links, see
+    /// [`Workcell::urdf_link_name`], and collisions by the links that contain them.
+    pub fn to_srdf_with_options(
+        &self,
+        options: &UrdfExportOptions,
+    ) -> Result<String, SrdfExportError> {
+        let diagnostics = self.validate();
+        if !diagnostics.is_empty() {
+            return Err(WorkcellToUrdfError::InvalidStructure(diagnostics).into());
+        }
+        if !self.includes.is_empty() {
+            return Err(WorkcellToUrdfError::UnflattenedIncludes.into());
+        }
+        let errors = self.validate_planning();
+        if !errors.is_empty() {
+            return Err(SrdfExportError::InvalidPlanning(errors));
+        }
+
+        let graph = self.graph();
+        // Frames were checked to exist when validating
+        let link_with_options = |frame: &str, options: &UrdfExportOptions| {
+            let id = graph.frame_by_name(frame).unwrap_or_default();
+            self.urdf_link_name(id, options)
+        };
+        let link = |frame: &str| link_with_options(frame, options);
+        // Collisions stay in the link their frame is merged into, even if the frame is also
+        // exported as its own empty link
+        let merged_options = UrdfExportOptions {
+            keep_merged_frames: false,
+            ..options.clone()
+        };
+        let collision_link = |frame: &str| link_with_options(frame, &merged_options);
+        let planning = &self.properties.planning;
+        let mut robot = Element::new("robot").with_attr("name", &self.properties.name.0);
+        for group in &planning.groups {
+            let mut element = Element::new("group").with_attr("name", &group.name);
+            match &group.members {
+                PlanningGroupMembers::Chain { base, tip } => {
+                    element.push(
+                        Element::new("chain")
+                            .with_attr("base_link", link(base)?)
+                            .with_attr("tip_link", link(tip)?),
+                    );
+                }
+                PlanningGroupMembers::Joints(joints) => {
+                    for joint in joints {
+                        element.push(Element::new("joint").with_attr("name", joint));
+                    }
+                }
+            }
+            robot.push(element);
+        }
+        for state in &planning.group_states {
+            let mut element = Element::new("group_state")
+                .with_attr("name", &state.name)
+                .with_attr("group", &state.group);
+            for (joint, position) in &state.positions {
+                element.push(
+                    Element::new("joint")
+                        .with_attr("name", joint)
+                        .with_attr("value", position),
+                );
+            }
+            robot.push(element);
+        }
+        for ee in &planning.end_effectors {
+            let mut element = Element::new("end_effector")
+                .with_attr("name", &ee.name)
+                .with_attr("parent_link", link(&ee.parent_frame)?)
+                .with_attr("group", &ee.group);
+            if let Some(parent_group) = &ee.parent_group {
+                element = element.with_attr("parent_group", parent_group);
+            }
+            robot.push(element);
+        }
+        for joint in &planning.passive_joints {
+            robot.push(Element::new("passive_joint").with_attr("name", joint));
+        }
+        // Several frames can refer to the same pair of links
+        let mut disabled_links = HashSet::new();
+        for disabled in &planning.disabled_collisions {
+            let [link1, link2] = [
+                collision_link(&disabled.frames[0])?,
+                collision_link(&disabled.frames[1])?,
+            ];
+            if link1 == link2 || !disabled_links.insert([link1.clone(), link2.clone()]) {
+                continue;
+            }
+            disabled_links.insert([link2.clone(), link1.clone()]);
+            robot.push(
+                Element::new("disable_collisions")
+                    .with_attr("link1", link1)
+                    .with_attr("link2", link2)
+                    .with_attr("reason", disabled.reason.label()),
+            );
+        }
+        Ok(write_to_string(&robot)?)
+    }
+
+    pub fn to_srdf(&self) -> Result<String, SrdfExportError> {
+        self.to_srdf_with_options(&UrdfExportOptions::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arm_workcell() -> Workcell {
+        let mut builder = WorkcellBuilder::new("cell");
+        let base = builder.add_frame(builder.root(), "base", Pose::default());
+        let revolute = || {
+            JointProperties::Revolute(SingleDofJoint::new(
+                JointAxis::new([0.0, 0.0, 1.0]),
+                Default::default(),
+            ))
+        };
+        let mut parent = base;
+        for name in ["shoulder", "elbow", "finger"] {
+            let link = builder.add_frame(parent, &format!("{name}_link"), Pose::default());
+            builder.add_joint(parent, link, name, revolute());
+            parent = link;
+        }
+        // Merged into the link of the elbow
+        let elbow = builder.frame_by_name("elbow_link").unwrap();
+        builder.add_frame(elbow, "flange", Pose::default());
+        let planning = builder.planning_mut();
+        planning.groups = vec![
+            PlanningGroup {
+                name: "arm".to_owned(),
+                members: PlanningGroupMembers::Chain {
+                    base: "base".to_owned(),
+                    tip: "flange".to_owned(),
+                },
+            },
+            PlanningGroup {
+                name: "hand".to_owned(),
+                members: PlanningGroupMembers::Joints(vec!["finger".to_owned()]),
+            },
+        ];
+        planning.end_effectors = vec![EndEffector {
+            name: "gripper".to_owned(),
+            group: "hand".to_owned(),
+            parent_frame: "flange".to_owned(),
+            parent_group: Some("arm".to_owned()),
+        }];
+        planning.group_states = vec![GroupState {
+            name: "home".to_owned(),
+            group: "arm".to_owned(),
+            positions: BTreeMap::from([("shoulder".to_owned(), 0.5), ("elbow".to_owned(), -0.5)]),
+        }];
+        planning.passive_joints = vec!["finger".to_owned()];
+        planning.disabled_collisions = vec![
+            DisabledCollision {
+                frames: ["shoulder_link".to_owned(), "flange".to_owned()],
+                reason: DisabledCollisionReason::Adjacent,
+            },
+            // Both frames are in the same link
+            DisabledCollision {
+                frames: ["elbow_link".to_owned(), "flange".to_owned()],
+                reason: DisabledCollisionReason::User,
+            },
+        ];
+        builder.build().unwrap()
+    }
+
+    #[test]
+    fn srdf_is_exported() {
+        let workcell = arm_workcell();
+        assert!(workcell.validate_planning().is_empty());
+        let arm = workcell.properties.planning.group("arm").unwrap();
+        let names: Vec<_> = workcell
+            .planning_group_joints(arm)
+            .unwrap()
+            .into_iter()
+            .map(|id| workcell.joints[&id].bundle.name.0.as_str())
+            .collect();
+        assert_eq!(names, ["shoulder", "elbow"]);
+
+        let srdf = workcell.to_srdf().unwrap();
+        let srdf = Element::parse(srdf.as_bytes()).unwrap();
+        assert_eq!(srdf.attributes["name"], "cell");
+        let groups: Vec<_> = children_named(&srdf, "group").collect();
+        assert_eq!(groups.len(), 2);
+        // The flange is merged into the link of the elbow
+        let chain = groups[0].get_child("chain").unwrap();
+        assert_eq!(chain.attributes["base_link"], "base");
+        assert_eq!(chain.attributes["tip_link"], "elbow_link");
+        assert_eq!(
+            groups[1].get_child("joint").unwrap().attributes["name"],
+            "finger"
+        );
+        let state = srdf.get_child("group_state").unwrap();
+        assert_eq!(children_named(state, "joint").count(), 2);
+        let ee = srdf.get_child("end_effector").unwrap();
+        assert_eq!(ee.attributes["parent_link"], "elbow_link");
+        assert_eq!(ee.attributes["parent_group"], "arm");
+        assert!(srdf.get_child("passive_joint").is_some());
+        let disabled: Vec<_> = children_named(&srdf, "disable_collisions").collect();
+        assert_eq!(disabled.len(), 1);
+        assert_eq!(disabled[0].attributes["link2"], "elbow_link");
+        assert_eq!(disabled[0].attributes["reason"], "Adjacent");
+
+        // Kept frames are links of their own, collisions are still in the merged link
+        let options = UrdfExportOptions {
+            keep_merged_frames: true,
+            ..Default::default()
+        };
+        let robot = workcell.to_urdf_with_options(&options).unwrap();
+        assert!(robot.links.iter().any(|l| l.name == "flange"));
+        let srdf = workcell.to_srdf_with_options(&options).unwrap();
+        let srdf = Element::parse(srdf.as_bytes()).unwrap();
+        let chain = srdf.get_child("group").unwrap().get_child("chain").unwrap();
+        assert_eq!(chain.attributes["tip_link"], "flange");
+        let ee = srdf.get_child("end_effector").unwrap();
+        assert_eq!(ee.attributes["parent_link"], "flange");
+        let disabled: Vec<_> = children_named(&srdf, "disable_collisions").collect();
+        assert_eq!(disabled.len(), 1);
+        assert_eq!(disabled[0].attributes["link2"], "elbow_link");
+
+        // Broken references are reported
+        let mut workcell = workcell;
+        let planning = &mut workcell.properties.planning;
+        planning.groups[0].members = PlanningGroupMembers::Chain {
+            base: "finger_link".to_owned(),
+            tip: "base".to_owned(),
+        };
+        planning.group_states.push(GroupState {
+            name: "open".to_owned(),
+            group: "hand".to_owned(),
+            positions: BTreeMap::from([("shoulder".to_owned(), 0.0)]),
+        });
+        planning.passive_joints.push("wrist".to_owned());
+        let errors = workcell.validate_planning();
+        assert_eq!(
+            errors,
+            [
+                PlanningError::InvalidChain {
+                    group: "arm".to_owned(),
+                    base: "finger_link".to_owned(),
+                    tip: "base".to_owned(),
+                },
+                PlanningError::InvalidStateJoint {
+                    group: "hand".to_owned(),
+                    state: "open".to_owned(),
+                    joint: "shoulder".to_owned(),
+                },
+                PlanningError::MissingJoint {
+                    context: "passive joints".to_owned(),
+                    joint: "wrist".to_owned(),
+                },
+            ]
+        );
+        assert!(matches!(
+            workcell.to_srdf(),
+            Err(SrdfExportError::InvalidPlanning(_))
+        ));
+    }
+}
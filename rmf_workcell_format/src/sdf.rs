@@ -0,0 +1,1752 @@
+/*
+ * Copyright (C) 2024 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::xml::{
+    child_text, children_named, space_separated, text_element, write_to_string, ElementExt,
+};
+use crate::*;
+
+use glam::{Affine3A, Vec3};
+use thiserror::Error as ThisError;
+use xmltree::Element;
+
+/// Maximum difference between the frame of a joint and the origin of its child link
+const JOINT_POSE_TOLERANCE: f32 = 1e-5;
+
+/// SDFormat version written by the exporter, 1.9 is the first version supporting capsules.
+pub const SDF_VERSION: &str = "1.9";
+
+#[derive(Debug, ThisError)]
+pub enum WorkcellToSdfError {
+    #[error("Invalid workcell structure: {0:?}")]
+    InvalidStructure(Vec<WorkcellDiagnostic>),
+    #[error("Invalid anchor type {0:?}")]
+    InvalidAnchorType(Anchor),
+    #[error("the workcell includes other workcells, they must be flattened before exporting")]
+    UnflattenedIncludes,
+    #[error("name [{0}] is used by more than one link, joint or frame")]
+    DuplicateName(String),
+    #[error("joint [{joint}] of type [{joint_type}] can't be represented in sdf")]
+    UnsupportedJointType { joint: String, joint_type: String },
+    #[error("Sdf write error: {0}")]
+    WriteError(#[from] xmltree::Error),
+}
+
+fn sdf_pose(pose: &Pose, relative_to: Option<&str>) -> Element {
+    let rpy = rpy_from_quat(quat_from_rotation(&pose.rot));
+    // Adding zero turns negative zeros into positive ones for a cleaner output
+    let values = pose.trans.iter().chain(&rpy).map(|v| v + 0.0);
+    let pose = Element::new("pose").with_text(space_separated(values));
+    match relative_to {
+        Some(frame) => pose.with_attr("relative_to", frame),
+        None => pose,
+    }
+}
+
+impl Geometry {
+    pub fn to_sdf(&self) -> Element {
+        let shape = match self {
+            Geometry::Primitive(PrimitiveShape::Box { size }) => {
+                Element::new("box").with_child(text_element("size", space_separated(size)))
+            }
+            Geometry::Primitive(PrimitiveShape::Cylinder { radius, length }) => {
+                Element::new("cylinder")
+                    .with_child(text_element("radius", radius))
+                    .with_child(text_element("length", length))
+            }
+            Geometry::Primitive(PrimitiveShape::Capsule { radius, length }) => {
+                Element::new("capsule")
+                    .with_child(text_element("radius", radius))
+                    .with_child(text_element("length", length))
+            }
+            Geometry::Primitive(PrimitiveShape::Sphere { radius }) => {
+                Element::new("sphere").with_child(text_element("radius", radius))
+            }
+            Geometry::Mesh { source, scale } => {
+                // SAFETY: We don't need to validate the syntax of the asset
+                // path because that will be done later when we attempt to load
+                // this as an asset.
+                let uri = unsafe { source.as_unvalidated_asset_path() };
+                let mesh = Element::new("mesh").with_child(text_element("uri", uri));
+                match scale {
+                    Some(scale) => {
+                        mesh.with_child(text_element("scale", space_separated(scale.to_array())))
+                    }
+                    None => mesh,
+                }
+            }
+        };
+        Element::new("geometry").with_child(shape)
+    }
+}
+
+impl VisualMaterial {
+    pub fn to_sdf(&self) -> Element {
+        let color = space_separated(self.color);
+        let material = Element::new("material")
+            .with_child(text_element("ambient", &color))
+            .with_child(text_element("diffuse", &color));
+        match &self.texture {
+            Some(texture) => {
+                // SAFETY: Same as meshes, the path is validated when loading the asset
+                let uri = unsafe { texture.as_unvalidated_asset_path() };
+                material.with_child(
+                    Element::new("pbr").with_child(
+                        Element::new("metal").with_child(text_element("albedo_map", uri)),
+                    ),
+                )
+            }
+            None => material,
+        }
+    }
+}
+
+impl Inertia {
+    pub fn to_sdf(&self, center: &Pose) -> Element {
+        let moment = &self.moment;
+        Element::new("inertial")
+            .with_child(sdf_pose(center, None))
+            .with_child(text_element("mass", self.mass.0))
+            .with_child(
+                Element::new("inertia")
+                    .with_child(text_element("ixx", moment.ixx))
+                    .with_child(text_element("ixy", moment.ixy))
+                    .with_child(text_element("ixz", moment.ixz))
+                    .with_child(text_element("iyy", moment.iyy))
+                    .with_child(text_element("iyz", moment.iyz))
+                    .with_child(text_element("izz", moment.izz)),
+            )
+    }
+}
+
+impl CameraProperties {
+    pub fn to_sdf(&self) -> Element {
+        let intrinsics = &self.intrinsics;
+        Element::new("camera")
+            .with_child(text_element("horizontal_fov", self.horizontal_fov()))
+            .with_child(
+                Element::new("image")
+                    .with_child(text_element("width", self.resolution[0]))
+                    .with_child(text_element("height", self.resolution[1])),
+            )
+            .with_child(
+                Element::new("clip")
+                    .with_child(text_element("near", self.clip[0]))
+                    .with_child(text_element("far", self.clip[1])),
+            )
+            .with_child(
+                Element::new("lens").with_child(
+                    Element::new("intrinsics")
+                        .with_child(text_element("fx", intrinsics.fx))
+                        .with_child(text_element("fy", intrinsics.fy))
+                        .with_child(text_element("cx", intrinsics.cx))
+                        .with_child(text_element("cy", intrinsics.cy))
+                        .with_child(text_element("s", 0)),
+                ),
+            )
+    }
+}
+
+impl LidarProperties {
+    pub fn to_sdf(&self) -> Element {
+        let scan_direction = |name: &str, scan: &LidarScan| {
+            Element::new(name)
+                .with_child(text_element("samples", scan.samples))
+                .with_child(text_element("min_angle", scan.angles[0]))
+                .with_child(text_element("max_angle", scan.angles[1]))
+        };
+        let mut scan =
+            Element::new("scan").with_child(scan_direction("horizontal", &self.horizontal));
+        if let Some(vertical) = &self.vertical {
+            scan.push(scan_direction("vertical", vertical));
+        }
+        Element::new("lidar").with_child(scan).with_child(
+            Element::new("range")
+                .with_child(text_element("min", self.range[0]))
+                .with_child(text_element("max", self.range[1]))
+                .with_child(text_element("resolution", self.range_resolution)),
+        )
+    }
+}
+
+impl Sensor {
+    /// Returns the `<sensor>` element, `pose` is relative to `relative_to` or, if not set, to the
+    /// link or joint the element is added to.
+    pub fn to_sdf(&self, pose: &Pose, relative_to: Option<&str>) -> Element {
+        let properties = &self.properties;
+        let mut sensor = Element::new("sensor")
+            .with_attr("name", &self.name.0)
+            .with_attr("type", properties.kind.sdf_type())
+            .with_child(sdf_pose(pose, relative_to))
+            .with_child(text_element("always_on", 1))
+            .with_child(text_element("update_rate", properties.update_rate));
+        match &properties.kind {
+            SensorKind::Camera(camera) | SensorKind::DepthCamera(camera) => {
+                sensor.push(camera.to_sdf())
+            }
+            SensorKind::Lidar(lidar) => sensor.push(lidar.to_sdf()),
+            SensorKind::ForceTorque(ft) => sensor.push(
+                Element::new("force_torque")
+                    .with_child(text_element("frame", ft.frame.label()))
+                    .with_child(text_element(
+                        "measure_direction",
+                        ft.measure_direction.label(),
+                    )),
+            ),
+        }
+        sensor
+    }
+}
+
+impl Workcell {
+    /// Frames that are children of the workcell or of a joint become links, all the other frames
+    /// are rigidly attached to their parent.
+    fn is_link(&self, frame: &Parented<u32, Frame>) -> bool {
+        frame.parent == self.id || self.joints.contains_key(&frame.parent)
+    }
+
+    /// Returns the id of the frame that will be exported as the link the requested frame is
+    /// rigidly attached to.
+    /// Assumes the workcell has been validated.
+    fn link_of(&self, mut frame_id: u32) -> u32 {
+        while let Some(frame) = self.frames.get(&frame_id) {
+            if self.is_link(frame) {
+                break;
+            }
+            frame_id = frame.parent;
+        }
+        frame_id
+    }
+
+    /// Transform of a frame relative to the link it is attached to.
+    fn transform_in_link(&self, mut frame_id: u32) -> Result<Affine3A, WorkcellToSdfError> {
+        let mut tf = Affine3A::IDENTITY;
+        while let Some(frame) = self.frames.get(&frame_id) {
+            if self.is_link(frame) {
+                break;
+            }
+            let Anchor::Pose3D(pose) = &frame.bundle.anchor else {
+                return Err(WorkcellToSdfError::InvalidAnchorType(
+                    frame.bundle.anchor.clone(),
+                ));
+            };
+            tf = affine_from_pose(pose) * tf;
+            frame_id = frame.parent;
+        }
+        Ok(tf)
+    }
+
+    fn frame_name(&self, frame_id: u32) -> &str {
+        self.frames
+            .get(&frame_id)
+            .map(|f| f.bundle.name.0.as_str())
+            .unwrap_or_default()
+    }
+
+    /// Exports the workcell as an SDFormat document containing a single `<model>`.
+    /// Frames that are not connected to their parent through a joint are exported as `<frame>`
+    /// elements, visuals and collisions are posed relative to the frame they are attached to.
+    pub fn to_sdf(&self) -> Result<Element, WorkcellToSdfError> {
+        let diagnostics = self.validate();
+        if !diagnostics.is_empty() {
+            return Err(WorkcellToSdfError::InvalidStructure(diagnostics));
+        }
+        if !self.includes.is_empty() {
+            return Err(WorkcellToSdfError::UnflattenedIncludes);
+        }
+        // Links, joints and frames share the same namespace in sdf
+        let mut names = HashSet::new();
+        for name in self
+            .frames
+            .values()
+            .map(|f| &f.bundle.name)
+            .chain(self.joints.values().map(|j| &j.bundle.name))
+        {
+            if !names.insert(name.0.as_str()) {
+                return Err(WorkcellToSdfError::DuplicateName(name.0.clone()));
+            }
+        }
+
+        let mut links = BTreeMap::new();
+        let mut frames = Vec::new();
+        for (id, frame) in &self.frames {
+            let Anchor::Pose3D(pose) = &frame.bundle.anchor else {
+                return Err(WorkcellToSdfError::InvalidAnchorType(
+                    frame.bundle.anchor.clone(),
+                ));
+            };
+            let name = frame.bundle.name.0.as_str();
+            if frame.parent == self.id {
+                let link = Element::new("link")
+                    .with_attr("name", name)
+                    .with_child(sdf_pose(pose, None));
+                links.insert(*id, link);
+            } else if let Some(joint) = self.joints.get(&frame.parent) {
+                // The child of a joint is posed relative to the parent frame of the joint
+                let link = Element::new("link")
+                    .with_attr("name", name)
+                    .with_child(sdf_pose(pose, Some(self.frame_name(joint.parent))));
+                links.insert(*id, link);
+            } else {
+                let parent = self.frame_name(frame.parent);
+                frames.push(
+                    Element::new("frame")
+                        .with_attr("name", name)
+                        .with_attr("attached_to", parent)
+                        .with_child(sdf_pose(pose, Some(parent))),
+                );
+            }
+        }
+
+        // Inertias of frames merged into the same link are combined
+        let mut link_inertias: BTreeMap<u32, Vec<Inertia>> = BTreeMap::new();
+        for inertia in self.inertias.values() {
+            let tf = self.transform_in_link(inertia.parent)?;
+            link_inertias
+                .entry(self.link_of(inertia.parent))
+                .or_default()
+                .push(inertia.bundle.transformed(&tf));
+        }
+        for (link_id, inertias) in link_inertias {
+            if let (Some(link), Some(inertia)) = (links.get_mut(&link_id), Inertia::merge(inertias))
+            {
+                link.push(inertia.to_sdf(&inertia.center));
+            }
+        }
+
+        let materials = &self.materials;
+        let mut push_models = |models: &BTreeMap<u32, Parented<u32, WorkcellModel>>, kind: &str| {
+            let mut used_names = HashSet::new();
+            for (id, model) in models {
+                let link_id = self.link_of(model.parent);
+                let Some(link) = links.get_mut(&link_id) else {
+                    continue;
+                };
+                // Names must be unique within the same link
+                let name = &model.bundle.name;
+                let name = if name.is_empty() || !used_names.insert((link_id, name.clone())) {
+                    format!("{}_{}", if name.is_empty() { kind } else { name }, id)
+                } else {
+                    name.clone()
+                };
+                let mut element = Element::new(kind)
+                    .with_attr("name", name)
+                    .with_child(sdf_pose(
+                        &model.bundle.pose,
+                        Some(self.frame_name(model.parent)),
+                    ))
+                    .with_child(model.bundle.geometry.to_sdf());
+                // Sdf has no material library, references are resolved in place
+                if let Some(material) = model
+                    .bundle
+                    .material
+                    .as_ref()
+                    .and_then(|m| materials.resolve(m))
+                {
+                    element.push(material.to_sdf());
+                }
+                link.push(element);
+            }
+        };
+        push_models(&self.collisions, "collision");
+        push_models(&self.visuals, "visual");
+
+        // Force torque sensors belong to the joint they measure, validation ensures there is one
+        let mut joint_sensors: BTreeMap<u32, Vec<Element>> = BTreeMap::new();
+        for sensor in self.sensors.values() {
+            let element = sensor
+                .bundle
+                .to_sdf(&sensor.bundle.pose, Some(self.frame_name(sensor.parent)));
+            if matches!(sensor.bundle.properties.kind, SensorKind::ForceTorque(_)) {
+                if let Some(joint) = self.sensor_joint(sensor.parent) {
+                    joint_sensors.entry(joint).or_default().push(element);
+                }
+            } else if let Some(link) = links.get_mut(&self.link_of(sensor.parent)) {
+                link.push(element);
+            }
+        }
+
+        let mut model = Element::new("model").with_attr("name", &self.properties.name.0);
+        for link in links.into_values() {
+            model.push(link);
+        }
+        for (joint_id, joint) in &self.joints {
+            let Some(child) = self.frames.values().find(|f| f.parent == *joint_id) else {
+                continue;
+            };
+            match &joint.bundle.properties {
+                // Links that are not attached through a joint are free to move in sdf
+                JointProperties::Floating(_) => continue,
+                JointProperties::Planar(_) => {
+                    return Err(WorkcellToSdfError::UnsupportedJointType {
+                        joint: joint.bundle.name.0.clone(),
+                        joint_type: joint.bundle.properties.label().to_lowercase(),
+                    });
+                }
+                _ => {}
+            }
+            let mut element = Element::new("joint")
+                .with_attr("name", &joint.bundle.name.0)
+                .with_attr("type", joint.bundle.properties.label().to_lowercase())
+                .with_child(text_element("parent", self.frame_name(joint.parent)))
+                .with_child(text_element("child", &child.bundle.name.0));
+            if let Some(axis) = joint.bundle.properties.to_sdf_axis() {
+                element.push(axis);
+            }
+            for sensor in joint_sensors.remove(joint_id).into_iter().flatten() {
+                element.push(sensor);
+            }
+            model.push(element);
+        }
+        for frame in frames {
+            model.push(frame);
+        }
+
+        Ok(Element::new("sdf")
+            .with_attr("version", SDF_VERSION)
+            .with_child(model))
+    }
+
+    pub fn to_sdf_string(&self) -> Result<String, WorkcellToSdfError> {
+        let sdf = self.to_sdf()?;
+        Ok(write_to_string(&sdf)?)
+    }
+
+    pub fn to_sdf_writer(&self, mut writer: impl io::Write) -> Result<(), std::io::Error> {
+        let sdf = self
+            .to_sdf_string()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        writer.write_all(sdf.as_bytes())
+    }
+}
+
+impl JointProperties {
+    /// Returns the `<axis>` element of the joint, if it has one.
+    pub fn to_sdf_axis(&self) -> Option<Element> {
+        let joint = self.single_dof()?;
+        let limits = &joint.limits;
+        let mut limit = Element::new("limit");
+        if !matches!(self, JointProperties::Continuous(_)) {
+            let (lower, upper) = limits.position.bounds();
+            if let Some(lower) = lower {
+                limit.push(text_element("lower", lower));
+            }
+            if let Some(upper) = upper {
+                limit.push(text_element("upper", upper));
+            }
+        }
+        // Negative values mean unlimited in sdf
+        let magnitude = |limits: &RangeLimits| limits.magnitude().unwrap_or(-1.0);
+        limit.push(text_element("effort", magnitude(&limits.effort)));
+        limit.push(text_element("velocity", magnitude(&limits.velocity)));
+        let axis = urdf_rs::Axis::from(&joint.axis);
+        let mut axis = Element::new("axis")
+            .with_child(text_element("xyz", space_separated(axis.xyz.0)))
+            .with_child(limit);
+        if !is_default(&joint.dynamics) {
+            axis.push(
+                Element::new("dynamics")
+                    .with_child(text_element("damping", joint.dynamics.damping))
+                    .with_child(text_element("friction", joint.dynamics.friction)),
+            );
+        }
+        if let Some(mimic) = &joint.mimic {
+            axis.push(
+                Element::new("mimic")
+                    .with_attr("joint", &mimic.joint)
+                    .with_child(text_element("multiplier", mimic.multiplier))
+                    .with_child(text_element("offset", mimic.offset)),
+            );
+        }
+        Some(axis)
+    }
+}
+
+#[derive(Debug, ThisError)]
+pub enum SdfImportError {
+    #[error("Sdf parse error: {0}")]
+    ParseError(#[from] xmltree::ParseError),
+    #[error("Io error: {0}")]
+    IoError(#[from] io::Error),
+    #[error("no model found in sdf")]
+    MissingModel,
+    #[error("<{element}> is missing the required [{field}]")]
+    MissingField { element: String, field: String },
+    #[error("invalid value [{value}] found in <{element}>")]
+    InvalidValue { element: String, value: String },
+    #[error("a reference to a non existing link or frame [{0}] was found")]
+    BrokenReference(String),
+    #[error("the pose of [{0}] is defined relative to itself")]
+    CyclicPoseReference(String),
+    #[error("joint [{joint}] has unsupported type [{joint_type}]")]
+    UnsupportedJointType { joint: String, joint_type: String },
+    #[error("joint [{0}] connects a link to the world, only fixed joints are supported")]
+    UnsupportedWorldJoint(String),
+    #[error("joint [{0}] is not placed at the origin of its child link, which is not supported")]
+    UnsupportedJointPose(String),
+    #[error("link [{0}] is the child of more than one joint")]
+    MultipleParentJoints(String),
+    #[error("unsupported geometry type [{0}]")]
+    UnsupportedGeometry(String),
+    #[error("unable to find the model included from [{0}]")]
+    UnresolvedInclude(String),
+    #[error("model file [{0:?}] is part of an include cycle")]
+    IncludeCycle(PathBuf),
+}
+
+/// Resolves the uris of included models and meshes found in sdf files.
+#[derive(Debug, Clone)]
+pub struct SdfResolver {
+    /// Directories searched for `model://` uris, each model is a subdirectory named after it.
+    pub model_paths: Vec<PathBuf>,
+}
+
+impl Default for SdfResolver {
+    /// Looks up models in the paths set by the environment variables used by Gazebo.
+    fn default() -> Self {
+        let model_paths = [
+            "GZ_SIM_RESOURCE_PATH",
+            "IGN_GAZEBO_RESOURCE_PATH",
+            "SDF_PATH",
+            "GAZEBO_MODEL_PATH",
+        ]
+        .iter()
+        .filter_map(std::env::var_os)
+        .flat_map(|paths| std::env::split_paths(&paths).collect::<Vec<_>>())
+        .collect();
+        Self { model_paths }
+    }
+}
+
+impl SdfResolver {
+    /// Returns the local path an uri refers to, relative paths are resolved from `dir`, the
+    /// directory of the file that contains the uri.
+    pub fn resolve_path(&self, uri: &str, dir: Option<&Path>) -> Option<PathBuf> {
+        if let Some(model) = uri.strip_prefix("model://") {
+            // Models next to the current one are also found, as is common for model databases
+            self.model_paths
+                .iter()
+                .map(PathBuf::as_path)
+                .chain(dir)
+                .chain(dir.and_then(Path::parent))
+                .map(|path| path.join(model))
+                .find(|path| path.exists())
+        } else {
+            let path = Path::new(uri.strip_prefix("file://").unwrap_or(uri));
+            if path.is_absolute() {
+                Some(path.to_owned())
+            } else {
+                dir.map(|dir| dir.join(path))
+            }
+        }
+    }
+
+    /// Reads the sdf document referred to by an `<include>` uri, returns it together with the
+    /// path of the file it was read from.
+    pub fn read_include(
+        &self,
+        uri: &str,
+        dir: Option<&Path>,
+    ) -> Result<(Element, PathBuf), SdfImportError> {
+        let path = self
+            .resolve_path(uri, dir)
+            .filter(|path| path.exists())
+            .ok_or_else(|| SdfImportError::UnresolvedInclude(uri.to_owned()))?;
+        let path = if path.is_dir() {
+            Self::model_file(&path)
+        } else {
+            path
+        };
+        let sdf = Element::parse(std::fs::File::open(&path)?)?;
+        Ok((sdf, path))
+    }
+
+    /// Returns the sdf file of a model directory, as listed in its `model.config`.
+    fn model_file(dir: &Path) -> PathBuf {
+        std::fs::File::open(dir.join("model.config"))
+            .ok()
+            .and_then(|file| Element::parse(file).ok())
+            .and_then(|config| child_text(&config, "sdf"))
+            .map(|file| dir.join(file))
+            .unwrap_or_else(|| dir.join("model.sdf"))
+    }
+
+    /// Converts a mesh uri to an asset source, relative paths and `model://` uris are resolved to
+    /// local paths when possible.
+    pub fn asset_source(&self, uri: &str, dir: Option<&Path>) -> AssetSource {
+        if let Some(path) = uri.strip_prefix("package://") {
+            return AssetSource::Package(path.to_owned());
+        }
+        match self.resolve_path(uri, dir) {
+            Some(path) => AssetSource::Local(path.to_string_lossy().into_owned()),
+            None => AssetSource::Local(uri.strip_prefix("file://").unwrap_or(uri).to_owned()),
+        }
+    }
+}
+
+fn parse_values(element: &Element) -> Result<Vec<f32>, SdfImportError> {
+    let text = element.get_text().unwrap_or_default();
+    text.split_whitespace()
+        .map(|v| v.parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| SdfImportError::InvalidValue {
+            element: element.name.clone(),
+            value: text.trim().to_owned(),
+        })
+}
+
+/// Parses the child element with the requested name as an array of floats.
+fn child_values<const N: usize>(
+    element: &Element,
+    name: &str,
+) -> Result<Option<[f32; N]>, SdfImportError> {
+    let Some(child) = element.get_child(name) else {
+        return Ok(None);
+    };
+    let values = parse_values(child)?;
+    values
+        .try_into()
+        .map(Some)
+        .map_err(|values: Vec<f32>| SdfImportError::InvalidValue {
+            element: name.to_owned(),
+            value: space_separated(values),
+        })
+}
+
+fn child_value(element: &Element, name: &str) -> Result<Option<f32>, SdfImportError> {
+    Ok(child_values::<1>(element, name)?.map(|[v]| v))
+}
+
+fn required_attr<'a>(element: &'a Element, attr: &str) -> Result<&'a str, SdfImportError> {
+    element
+        .attributes
+        .get(attr)
+        .map(String::as_str)
+        .ok_or_else(|| SdfImportError::MissingField {
+            element: element.name.clone(),
+            field: attr.to_owned(),
+        })
+}
+
+fn required_text(element: &Element, name: &str) -> Result<String, SdfImportError> {
+    child_text(element, name).ok_or_else(|| SdfImportError::MissingField {
+        element: element.name.clone(),
+        field: name.to_owned(),
+    })
+}
+
+/// Parses the `<pose>` child of an element, returns it together with the name of the frame it is
+/// relative to, if specified.
+fn parse_pose(element: &Element) -> Result<(Option<String>, Pose), SdfImportError> {
+    let Some(pose) = element.get_child("pose") else {
+        return Ok((None, Pose::default()));
+    };
+    let relative_to = pose
+        .attributes
+        .get("relative_to")
+        .filter(|frame| !frame.is_empty())
+        .cloned();
+    let values = parse_values(pose)?;
+    let degrees = pose.attributes.get("degrees").is_some_and(|d| d == "true");
+    let angle = |v: f32| {
+        if degrees {
+            Angle::Deg(v)
+        } else {
+            Angle::Rad(v)
+        }
+    };
+    let format = pose.attributes.get("rotation_format").map(String::as_str);
+    let rot = match (format, values.len()) {
+        (None | Some("euler_rpy"), 6) => {
+            Rotation::EulerExtrinsicXYZ([angle(values[3]), angle(values[4]), angle(values[5])])
+        }
+        (Some("quat_xyzw"), 7) => Rotation::Quat([values[3], values[4], values[5], values[6]]),
+        _ => {
+            return Err(SdfImportError::InvalidValue {
+                element: "pose".to_owned(),
+                value: space_separated(values),
+            })
+        }
+    };
+    let trans = [values[0], values[1], values[2]];
+    Ok((relative_to, Pose { trans, rot }))
+}
+
+impl Geometry {
+    pub fn from_sdf(
+        geometry: &Element,
+        resolver: &SdfResolver,
+        dir: Option<&Path>,
+    ) -> Result<Self, SdfImportError> {
+        let shape = geometry
+            .children
+            .iter()
+            .find_map(|c| c.as_element())
+            .ok_or_else(|| SdfImportError::MissingField {
+                element: "geometry".to_owned(),
+                field: "shape".to_owned(),
+            })?;
+        let required = |name: &str| {
+            child_value(shape, name)?.ok_or_else(|| SdfImportError::MissingField {
+                element: shape.name.clone(),
+                field: name.to_owned(),
+            })
+        };
+        let geometry = match shape.name.as_str() {
+            "box" => Geometry::Primitive(PrimitiveShape::Box {
+                size: child_values(shape, "size")?.ok_or_else(|| SdfImportError::MissingField {
+                    element: "box".to_owned(),
+                    field: "size".to_owned(),
+                })?,
+            }),
+            "cylinder" => Geometry::Primitive(PrimitiveShape::Cylinder {
+                radius: required("radius")?,
+                length: required("length")?,
+            }),
+            "capsule" => Geometry::Primitive(PrimitiveShape::Capsule {
+                radius: required("radius")?,
+                length: required("length")?,
+            }),
+            "sphere" => Geometry::Primitive(PrimitiveShape::Sphere {
+                radius: required("radius")?,
+            }),
+            "mesh" => Geometry::Mesh {
+                source: resolver.asset_source(&required_text(shape, "uri")?, dir),
+                scale: child_values(shape, "scale")?.map(Vec3::from_array),
+            },
+            other => return Err(SdfImportError::UnsupportedGeometry(other.to_owned())),
+        };
+        Ok(geometry)
+    }
+}
+
+impl VisualMaterial {
+    /// Parses the diffuse color and albedo map of a `<material>` element. Scripted materials are
+    /// not supported.
+    pub fn from_sdf(
+        material: &Element,
+        resolver: &SdfResolver,
+        dir: Option<&Path>,
+    ) -> Result<Option<Self>, SdfImportError> {
+        let color = match child_values::<4>(material, "diffuse")? {
+            Some(color) => Some(color),
+            None => child_values::<4>(material, "ambient")?,
+        };
+        let texture = material
+            .get_child("pbr")
+            .and_then(|pbr| pbr.get_child("metal").or_else(|| pbr.get_child("specular")))
+            .and_then(|workflow| child_text(workflow, "albedo_map"))
+            .map(|uri| resolver.asset_source(&uri, dir));
+        if color.is_none() && texture.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            color: color.unwrap_or([1.0; 4]),
+            texture,
+        }))
+    }
+}
+
+impl Inertia {
+    /// Parses an `<inertial>` element, using the sdf default values for missing fields.
+    pub fn from_sdf(inertial: &Element) -> Result<Self, SdfImportError> {
+        let (_, center) = parse_pose(inertial)?;
+        let mass = child_value(inertial, "mass")?.unwrap_or(1.0);
+        let moment = match inertial.get_child("inertia") {
+            Some(inertia) => Moment {
+                ixx: child_value(inertia, "ixx")?.unwrap_or(1.0),
+                ixy: child_value(inertia, "ixy")?.unwrap_or_default(),
+                ixz: child_value(inertia, "ixz")?.unwrap_or_default(),
+                iyy: child_value(inertia, "iyy")?.unwrap_or(1.0),
+                iyz: child_value(inertia, "iyz")?.unwrap_or_default(),
+                izz: child_value(inertia, "izz")?.unwrap_or(1.0),
+            },
+            None => Moment {
+                ixx: 1.0,
+                iyy: 1.0,
+                izz: 1.0,
+                ..Default::default()
+            },
+        };
+        Ok(Self {
+            center,
+            mass: Mass(mass),
+            moment,
+        })
+    }
+}
+
+impl Sensor {
+    /// Parses a `<sensor>` element using the sdf default values for missing fields, the pose is
+    /// kept relative to the frame it is expressed in. Returns None for sensor types that are not
+    /// supported.
+    pub fn from_sdf(sensor: &Element) -> Result<Option<Self>, SdfImportError> {
+        let name = required_attr(sensor, "name")?;
+        let sensor_type = required_attr(sensor, "type")?;
+        let (_, pose) = parse_pose(sensor)?;
+        let required_child = |element: &Element, name: &str| {
+            element
+                .get_child(name)
+                .cloned()
+                .ok_or_else(|| SdfImportError::MissingField {
+                    element: element.name.clone(),
+                    field: name.to_owned(),
+                })
+        };
+        let camera = || -> Result<CameraProperties, SdfImportError> {
+            let camera = required_child(sensor, "camera")?;
+            let image = camera.get_child("image");
+            let dimension = |name: &str, default: f32| -> Result<u32, SdfImportError> {
+                Ok(image
+                    .map(|image| child_value(image, name))
+                    .transpose()?
+                    .flatten()
+                    .unwrap_or(default) as u32)
+            };
+            let resolution = [dimension("width", 320.0)?, dimension("height", 240.0)?];
+            let clip = camera.get_child("clip");
+            let clip_value = |name: &str, default: f32| -> Result<f32, SdfImportError> {
+                Ok(clip
+                    .map(|clip| child_value(clip, name))
+                    .transpose()?
+                    .flatten()
+                    .unwrap_or(default))
+            };
+            let horizontal_fov = child_value(&camera, "horizontal_fov")?.unwrap_or(1.047);
+            let mut properties = CameraProperties::from_horizontal_fov(
+                resolution,
+                horizontal_fov,
+                [clip_value("near", 0.1)?, clip_value("far", 100.0)?],
+            );
+            if let Some(intrinsics) = camera
+                .get_child("lens")
+                .and_then(|lens| lens.get_child("intrinsics"))
+            {
+                let defaults = properties.intrinsics;
+                properties.intrinsics = CameraIntrinsics {
+                    fx: child_value(intrinsics, "fx")?.unwrap_or(defaults.fx),
+                    fy: child_value(intrinsics, "fy")?.unwrap_or(defaults.fy),
+                    cx: child_value(intrinsics, "cx")?.unwrap_or(defaults.cx),
+                    cy: child_value(intrinsics, "cy")?.unwrap_or(defaults.cy),
+                };
+            }
+            Ok(properties)
+        };
+        let kind = match sensor_type {
+            "camera" => SensorKind::Camera(camera()?),
+            "depth_camera" | "depth" => SensorKind::DepthCamera(camera()?),
+            "gpu_lidar" | "lidar" | "gpu_ray" | "ray" => {
+                // Gazebo classic uses <ray> for the same content
+                let lidar = match sensor.get_child("lidar") {
+                    Some(lidar) => lidar.clone(),
+                    None => required_child(sensor, "ray")?,
+                };
+                let scan = required_child(&lidar, "scan")?;
+                let scan_direction = |name: &str| -> Result<Option<LidarScan>, SdfImportError> {
+                    let Some(direction) = scan.get_child(name) else {
+                        return Ok(None);
+                    };
+                    Ok(Some(LidarScan {
+                        samples: child_value(direction, "samples")?.unwrap_or(1.0) as u32,
+                        angles: [
+                            child_value(direction, "min_angle")?.unwrap_or_default(),
+                            child_value(direction, "max_angle")?.unwrap_or_default(),
+                        ],
+                    }))
+                };
+                let range = required_child(&lidar, "range")?;
+                SensorKind::Lidar(LidarProperties {
+                    horizontal: scan_direction("horizontal")?.ok_or_else(|| {
+                        SdfImportError::MissingField {
+                            element: "scan".to_owned(),
+                            field: "horizontal".to_owned(),
+                        }
+                    })?,
+                    vertical: scan_direction("vertical")?,
+                    range: [
+                        child_value(&range, "min")?.unwrap_or_default(),
+                        child_value(&range, "max")?.unwrap_or_default(),
+                    ],
+                    range_resolution: child_value(&range, "resolution")?.unwrap_or_default(),
+                })
+            }
+            "force_torque" => {
+                let ft = sensor.get_child("force_torque");
+                let text = |name: &str| ft.and_then(|ft| child_text(ft, name));
+                let invalid = |name: &str, value: String| SdfImportError::InvalidValue {
+                    element: name.to_owned(),
+                    value,
+                };
+                SensorKind::ForceTorque(ForceTorqueProperties {
+                    frame: match text("frame") {
+                        None => Default::default(),
+                        Some(frame) => match frame.as_str() {
+                            "child" => ForceTorqueFrame::Child,
+                            "parent" => ForceTorqueFrame::Parent,
+                            "sensor" => ForceTorqueFrame::Sensor,
+                            _ => return Err(invalid("frame", frame)),
+                        },
+                    },
+                    measure_direction: match text("measure_direction") {
+                        None => Default::default(),
+                        Some(direction) => match direction.as_str() {
+                            "child_to_parent" => MeasureDirection::ChildToParent,
+                            "parent_to_child" => MeasureDirection::ParentToChild,
+                            _ => return Err(invalid("measure_direction", direction)),
+                        },
+                    },
+                })
+            }
+            _ => return Ok(None),
+        };
+        let properties = SensorProperties {
+            update_rate: child_value(sensor, "update_rate")?.unwrap_or_default(),
+            kind,
+        };
+        Ok(Some(Sensor::new(name, properties, pose)))
+    }
+}
+
+impl JointLimits {
+    /// Parses the `<limit>` element of a joint axis, negative effort and velocity mean unlimited.
+    fn from_sdf(limit: Option<&Element>) -> Result<Self, SdfImportError> {
+        let Some(limit) = limit else {
+            return Ok(Self::default());
+        };
+        let symmetric = |name: &str| -> Result<RangeLimits, SdfImportError> {
+            Ok(match child_value(limit, name)? {
+                Some(value) if value >= 0.0 => RangeLimits::Symmetric(value),
+                _ => RangeLimits::None,
+            })
+        };
+        let lower = child_value(limit, "lower")?;
+        let upper = child_value(limit, "upper")?;
+        Ok(Self {
+            position: match (lower, upper) {
+                (None, None) => RangeLimits::None,
+                (lower, upper) => RangeLimits::Asymmetric { lower, upper },
+            },
+            effort: symmetric("effort")?,
+            velocity: symmetric("velocity")?,
+            ..Default::default()
+        })
+    }
+}
+
+struct SdfLink {
+    name: String,
+    model: String,
+    /// Visuals, collisions and inertias keep the frame their pose is relative to, if set
+    visuals: Vec<(Option<String>, WorkcellModel)>,
+    collisions: Vec<(Option<String>, WorkcellModel)>,
+    inertial: Option<(Option<String>, Inertia)>,
+    sensors: Vec<(Option<String>, Sensor)>,
+}
+
+struct SdfJoint {
+    name: String,
+    joint_type: String,
+    parent: String,
+    child: String,
+    /// Axis direction and the frame it is expressed in
+    axis: Option<([f32; 3], String)>,
+    limits: Option<Element>,
+    dynamics: JointDynamics,
+    mimic: Option<JointMimic>,
+    /// Force torque sensors, posed relative to the joint frame by default
+    sensors: Vec<(Option<String>, Sensor)>,
+}
+
+struct SdfFrame {
+    name: String,
+    attached_to: String,
+}
+
+/// Collects the elements of an sdf model and its nested models, names are scoped with `::` as
+/// in sdf. The root model is the empty string.
+#[derive(Default)]
+struct SdfModelGraph {
+    /// Frame each pose is expressed in, and the pose itself
+    poses: HashMap<String, (String, Pose)>,
+    /// Nested models and the model that contains them
+    models: Vec<(String, String)>,
+    links: Vec<SdfLink>,
+    joints: Vec<SdfJoint>,
+    frames: Vec<SdfFrame>,
+    transforms: HashMap<String, Affine3A>,
+    /// Files of the includes being added, to detect cycles
+    include_stack: Vec<PathBuf>,
+}
+
+/// Name of an element in the model with the given scoped name, `__model__` refers to the model
+/// frame itself and `world` to the root of the workcell.
+fn scoped_name(model: &str, name: &str) -> String {
+    match name {
+        "" | "__model__" => model.to_owned(),
+        "world" => String::new(),
+        name if model.is_empty() => name.to_owned(),
+        name => format!("{model}::{name}"),
+    }
+}
+
+/// Parses the supported `<sensor>` children of a link or joint, keeping the scoped name of the
+/// frame their pose is relative to, if set.
+fn parse_sensors(
+    element: &Element,
+    model: &str,
+) -> Result<Vec<(Option<String>, Sensor)>, SdfImportError> {
+    let mut sensors = Vec::new();
+    for sensor in children_named(element, "sensor") {
+        let Some(mut parsed) = Sensor::from_sdf(sensor)? else {
+            continue;
+        };
+        parsed.name.0 = scoped_name(model, &parsed.name.0);
+        let (relative_to, _) = parse_pose(sensor)?;
+        sensors.push((relative_to.map(|frame| scoped_name(model, &frame)), parsed));
+    }
+    Ok(sensors)
+}
+
+impl SdfModelGraph {
+    fn add_model(
+        &mut self,
+        model: &Element,
+        name: String,
+        parent: Option<&str>,
+        resolver: &SdfResolver,
+        dir: Option<&Path>,
+    ) -> Result<(), SdfImportError> {
+        if let Some(parent) = parent {
+            let (relative_to, pose) = parse_pose(model)?;
+            let relative_to = relative_to
+                .map(|frame| scoped_name(parent, &frame))
+                .unwrap_or_else(|| parent.to_owned());
+            self.poses.insert(name.clone(), (relative_to, pose));
+            self.models.push((name.clone(), parent.to_owned()));
+        }
+        for element in model.children.iter().filter_map(|c| c.as_element()) {
+            match element.name.as_str() {
+                "link" => self.add_link(element, &name, resolver, dir)?,
+                "joint" => self.add_joint(element, &name)?,
+                "frame" => self.add_frame(element, &name)?,
+                "model" => {
+                    let nested = scoped_name(&name, required_attr(element, "name")?);
+                    self.add_model(element, nested, Some(&name), resolver, dir)?;
+                }
+                "include" => self.add_include(element, &name, resolver, dir)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn add_include(
+        &mut self,
+        include: &Element,
+        parent: &str,
+        resolver: &SdfResolver,
+        dir: Option<&Path>,
+    ) -> Result<(), SdfImportError> {
+        let uri = required_text(include, "uri")?;
+        let (sdf, path) = resolver.read_include(&uri, dir)?;
+        let include_dir = path.parent().map(Path::to_owned);
+        let path = path.canonicalize().unwrap_or(path);
+        if self.include_stack.contains(&path) {
+            return Err(SdfImportError::IncludeCycle(path));
+        }
+        let mut model = sdf
+            .get_child("model")
+            .ok_or(SdfImportError::MissingModel)?
+            .clone();
+        // The name and pose of the include tag override the ones of the model
+        if let Some(name) = child_text(include, "name") {
+            model.attributes.insert("name".to_owned(), name);
+        }
+        if let Some(pose) = include.get_child("pose") {
+            model
+                .children
+                .retain(|c| !matches!(c.as_element(), Some(e) if e.name == "pose"));
+            model.push(pose.clone());
+        }
+        let name = scoped_name(parent, required_attr(&model, "name")?);
+        self.include_stack.push(path);
+        let result = self.add_model(&model, name, Some(parent), resolver, include_dir.as_deref());
+        self.include_stack.pop();
+        result
+    }
+
+    fn add_link(
+        &mut self,
+        link: &Element,
+        model: &str,
+        resolver: &SdfResolver,
+        dir: Option<&Path>,
+    ) -> Result<(), SdfImportError> {
+        let name = scoped_name(model, required_attr(link, "name")?);
+        let (relative_to, pose) = parse_pose(link)?;
+        let relative_to = relative_to
+            .map(|frame| scoped_name(model, &frame))
+            .unwrap_or_else(|| model.to_owned());
+        self.poses.insert(name.clone(), (relative_to, pose));
+        let parse_model = |element: &Element| {
+            let (relative_to, pose) = parse_pose(element)?;
+            let geometry =
+                element
+                    .get_child("geometry")
+                    .ok_or_else(|| SdfImportError::MissingField {
+                        element: element.name.clone(),
+                        field: "geometry".to_owned(),
+                    })?;
+            Ok::<_, SdfImportError>((
+                relative_to.map(|frame| scoped_name(model, &frame)),
+                WorkcellModel {
+                    name: element.attributes.get("name").cloned().unwrap_or_default(),
+                    geometry: Geometry::from_sdf(geometry, resolver, dir)?,
+                    pose,
+                    material: element
+                        .get_child("material")
+                        .map(|material| VisualMaterial::from_sdf(material, resolver, dir))
+                        .transpose()?
+                        .flatten()
+                        .map(MaterialRef::Inline),
+                },
+            ))
+        };
+        let visuals = children_named(link, "visual")
+            .map(parse_model)
+            .collect::<Result<_, _>>()?;
+        let collisions = children_named(link, "collision")
+            .map(parse_model)
+            .collect::<Result<_, _>>()?;
+        let inertial = link
+            .get_child("inertial")
+            .map(|inertial| {
+                let (relative_to, _) = parse_pose(inertial)?;
+                Ok::<_, SdfImportError>((
+                    relative_to.map(|frame| scoped_name(model, &frame)),
+                    Inertia::from_sdf(inertial)?,
+                ))
+            })
+            .transpose()?;
+        self.links.push(SdfLink {
+            name,
+            model: model.to_owned(),
+            visuals,
+            collisions,
+            inertial,
+            sensors: parse_sensors(link, model)?,
+        });
+        Ok(())
+    }
+
+    fn add_joint(&mut self, joint: &Element, model: &str) -> Result<(), SdfImportError> {
+        let name = scoped_name(model, required_attr(joint, "name")?);
+        let child = scoped_name(model, &required_text(joint, "child")?);
+        // Joint frames are relative to the child link by default
+        let (relative_to, pose) = parse_pose(joint)?;
+        let relative_to = relative_to
+            .map(|frame| scoped_name(model, &frame))
+            .unwrap_or_else(|| child.clone());
+        self.poses.insert(name.clone(), (relative_to, pose));
+        let axis_element = joint.get_child("axis");
+        let axis = axis_element
+            .map(|axis| {
+                let xyz = child_values(axis, "xyz")?.unwrap_or([0.0, 0.0, 1.0]);
+                let expressed_in = axis
+                    .get_child("xyz")
+                    .and_then(|xyz| xyz.attributes.get("expressed_in"))
+                    .map(|frame| scoped_name(model, frame))
+                    .unwrap_or_else(|| name.clone());
+                Ok::<_, SdfImportError>((xyz, expressed_in))
+            })
+            .transpose()?;
+        self.joints.push(SdfJoint {
+            joint_type: required_attr(joint, "type")?.to_owned(),
+            parent: scoped_name(model, &required_text(joint, "parent")?),
+            child,
+            axis,
+            limits: axis_element
+                .and_then(|axis| axis.get_child("limit"))
+                .cloned(),
+            dynamics: match axis_element.and_then(|axis| axis.get_child("dynamics")) {
+                Some(dynamics) => JointDynamics {
+                    damping: child_value(dynamics, "damping")?.unwrap_or_default(),
+                    friction: child_value(dynamics, "friction")?.unwrap_or_default(),
+                },
+                None => Default::default(),
+            },
+            mimic: axis_element
+                .and_then(|axis| axis.get_child("mimic"))
+                .map(|mimic| {
+                    Ok::<_, SdfImportError>(JointMimic {
+                        joint: scoped_name(model, required_attr(mimic, "joint")?),
+                        multiplier: child_value(mimic, "multiplier")?.unwrap_or(1.0),
+                        offset: child_value(mimic, "offset")?.unwrap_or_default(),
+                    })
+                })
+                .transpose()?,
+            sensors: parse_sensors(joint, model)?,
+            name,
+        });
+        Ok(())
+    }
+
+    fn add_frame(&mut self, frame: &Element, model: &str) -> Result<(), SdfImportError> {
+        let name = scoped_name(model, required_attr(frame, "name")?);
+        let attached_to = scoped_name(
+            model,
+            frame
+                .attributes
+                .get("attached_to")
+                .map(String::as_str)
+                .unwrap_or_default(),
+        );
+        // Frame poses are relative to the frame they are attached to by default
+        let (relative_to, pose) = parse_pose(frame)?;
+        let relative_to = relative_to
+            .map(|frame| scoped_name(model, &frame))
+            .unwrap_or_else(|| attached_to.clone());
+        self.poses.insert(name.clone(), (relative_to, pose));
+        self.frames.push(SdfFrame { name, attached_to });
+        Ok(())
+    }
+
+    /// Transform of a frame relative to the root model.
+    fn transform(&mut self, name: &str) -> Result<Affine3A, SdfImportError> {
+        self.transform_impl(name, &mut HashSet::new())
+    }
+
+    fn transform_impl(
+        &mut self,
+        name: &str,
+        visited: &mut HashSet<String>,
+    ) -> Result<Affine3A, SdfImportError> {
+        if name.is_empty() {
+            return Ok(Affine3A::IDENTITY);
+        }
+        if let Some(tf) = self.transforms.get(name) {
+            return Ok(*tf);
+        }
+        if !visited.insert(name.to_owned()) {
+            return Err(SdfImportError::CyclicPoseReference(name.to_owned()));
+        }
+        let (relative_to, pose) = self
+            .poses
+            .get(name)
+            .cloned()
+            .ok_or_else(|| SdfImportError::BrokenReference(name.to_owned()))?;
+        let tf = self.transform_impl(&relative_to, visited)? * affine_from_pose(&pose);
+        self.transforms.insert(name.to_owned(), tf);
+        Ok(tf)
+    }
+
+    /// Pose of a frame relative to another one, the original pose is kept if it was already
+    /// expressed in the requested frame.
+    fn relative_pose(&mut self, name: &str, frame: &str) -> Result<Pose, SdfImportError> {
+        if let Some((relative_to, pose)) = self.poses.get(name) {
+            if relative_to == frame {
+                return Ok(*pose);
+            }
+        }
+        let tf = self.transform(frame)?.inverse() * self.transform(name)?;
+        Ok(pose_from_affine(&tf))
+    }
+
+    /// Expresses the pose of a visual, collision or inertia in the frame of its link.
+    fn pose_in_link(
+        &mut self,
+        link: &str,
+        relative_to: &Option<String>,
+        pose: &Pose,
+    ) -> Result<Pose, SdfImportError> {
+        match relative_to {
+            Some(frame) if frame != link => {
+                let tf = self.transform(link)?.inverse()
+                    * self.transform(frame)?
+                    * affine_from_pose(pose);
+                Ok(pose_from_affine(&tf))
+            }
+            _ => Ok(*pose),
+        }
+    }
+
+    fn into_workcell(mut self, name: String) -> Result<Workcell, SdfImportError> {
+        let root_id = 0_u32;
+        let mut cur_id = 1u32..;
+        let mut name_to_id = HashMap::from([(String::new(), root_id)]);
+        let mut frames = BTreeMap::new();
+        let mut visuals = BTreeMap::new();
+        let mut collisions = BTreeMap::new();
+        let mut inertias = BTreeMap::new();
+        let mut joints = BTreeMap::new();
+        let mut sensors = BTreeMap::new();
+        let new_frame = |parent: u32, name: &str, pose: Pose| Parented {
+            parent,
+            bundle: Frame {
+                anchor: Anchor::Pose3D(pose),
+                name: NameInWorkcell(name.to_owned()),
+                marker: Default::default(),
+            },
+        };
+        // Ids are assigned first since frames can refer to frames that are defined later
+        for name in self
+            .models
+            .iter()
+            .map(|(name, _)| name)
+            .chain(self.links.iter().map(|l| &l.name))
+            .chain(self.frames.iter().map(|f| &f.name))
+        {
+            name_to_id.insert(name.clone(), cur_id.next().unwrap());
+        }
+        let get_id = |name: &str| {
+            name_to_id
+                .get(name)
+                .copied()
+                .ok_or_else(|| SdfImportError::BrokenReference(name.to_owned()))
+        };
+
+        let models = std::mem::take(&mut self.models);
+        for (name, parent) in &models {
+            let pose = self.relative_pose(name, parent)?;
+            frames.insert(get_id(name)?, new_frame(get_id(parent)?, name, pose));
+        }
+
+        let links = std::mem::take(&mut self.links);
+        for link in &links {
+            // Pose and parent will be overwritten by joints, if needed
+            let pose = self.relative_pose(&link.name, &link.model)?;
+            let frame_id = get_id(&link.name)?;
+            frames.insert(frame_id, new_frame(get_id(&link.model)?, &link.name, pose));
+            if let Some((relative_to, inertia)) = &link.inertial {
+                let mut inertia = inertia.clone();
+                inertia.center = self.pose_in_link(&link.name, relative_to, &inertia.center)?;
+                inertias.insert(
+                    cur_id.next().unwrap(),
+                    Parented {
+                        parent: frame_id,
+                        bundle: inertia,
+                    },
+                );
+            }
+            for (models, elements) in [
+                (&mut visuals, &link.visuals),
+                (&mut collisions, &link.collisions),
+            ] {
+                for (relative_to, model) in elements {
+                    let mut model = model.clone();
+                    model.pose = self.pose_in_link(&link.name, relative_to, &model.pose)?;
+                    models.insert(
+                        cur_id.next().unwrap(),
+                        Parented {
+                            parent: frame_id,
+                            bundle: model,
+                        },
+                    );
+                }
+            }
+            for (relative_to, sensor) in &link.sensors {
+                let mut sensor = sensor.clone();
+                sensor.pose = self.pose_in_link(&link.name, relative_to, &sensor.pose)?;
+                sensors.insert(
+                    cur_id.next().unwrap(),
+                    Parented {
+                        parent: frame_id,
+                        bundle: sensor,
+                    },
+                );
+            }
+        }
+
+        let joint_children = self
+            .joints
+            .iter()
+            .map(|j| (j.name.clone(), j.child.clone()))
+            .collect::<HashMap<_, _>>();
+        let sdf_frames = std::mem::take(&mut self.frames);
+        for frame in &sdf_frames {
+            // Frames attached to a joint are attached to its child link
+            let attached_to = joint_children
+                .get(&frame.attached_to)
+                .unwrap_or(&frame.attached_to);
+            let pose = self.relative_pose(&frame.name, attached_to)?;
+            frames.insert(
+                get_id(&frame.name)?,
+                new_frame(get_id(attached_to)?, &frame.name, pose),
+            );
+        }
+
+        let sdf_joints = std::mem::take(&mut self.joints);
+        let mut claimed_children = HashSet::new();
+        for joint in &sdf_joints {
+            if !links.iter().any(|l| l.name == joint.child) {
+                return Err(SdfImportError::BrokenReference(joint.child.clone()));
+            }
+            if !claimed_children.insert(&joint.child) {
+                return Err(SdfImportError::MultipleParentJoints(joint.child.clone()));
+            }
+            if joint.parent.is_empty() {
+                // Links fixed to the world are already children of the workcell
+                if joint.joint_type == "fixed" {
+                    continue;
+                }
+                return Err(SdfImportError::UnsupportedWorldJoint(joint.name.clone()));
+            }
+            let single_dof = |graph: &mut Self| -> Result<SingleDofJoint, SdfImportError> {
+                // The joint frame is placed at the child origin, as in urdf, moving joints
+                // offset from it would rotate or slide around the wrong point
+                let child = graph.transform(&joint.child)?;
+                if !graph
+                    .transform(&joint.name)?
+                    .abs_diff_eq(child, JOINT_POSE_TOLERANCE)
+                {
+                    return Err(SdfImportError::UnsupportedJointPose(joint.name.clone()));
+                }
+                // Express the axis in the frame of the child
+                let axis = match &joint.axis {
+                    Some((xyz, expressed_in)) => {
+                        let frame = graph.transform(expressed_in)?;
+                        (child.inverse() * frame).transform_vector3a(Vec3::from(*xyz).into())
+                    }
+                    None => Vec3::Z.into(),
+                };
+                Ok(SingleDofJoint {
+                    limits: JointLimits::from_sdf(joint.limits.as_ref())?,
+                    axis: JointAxis(axis.normalize_or_zero().to_array()),
+                    dynamics: joint.dynamics,
+                    mimic: joint.mimic.clone(),
+                    safety_controller: None,
+                })
+            };
+            let properties = match joint.joint_type.as_str() {
+                "fixed" => JointProperties::Fixed,
+                "revolute" => JointProperties::Revolute(single_dof(&mut self)?),
+                "prismatic" => JointProperties::Prismatic(single_dof(&mut self)?),
+                "continuous" => JointProperties::Continuous(single_dof(&mut self)?),
+                other => {
+                    return Err(SdfImportError::UnsupportedJointType {
+                        joint: joint.name.clone(),
+                        joint_type: other.to_owned(),
+                    })
+                }
+            };
+            let joint_id = cur_id.next().unwrap();
+            // As in urdf, the child is posed relative to the parent frame of the joint
+            let pose = self.relative_pose(&joint.child, &joint.parent)?;
+            let child_frame = frames.get_mut(&get_id(&joint.child)?).unwrap();
+            child_frame.parent = joint_id;
+            child_frame.bundle.anchor = Anchor::Pose3D(pose);
+            for (relative_to, sensor) in &joint.sensors {
+                // Sensors of a joint measure it from its child link
+                let relative_to = relative_to.clone().or_else(|| Some(joint.name.clone()));
+                let mut sensor = sensor.clone();
+                sensor.pose = self.pose_in_link(&joint.child, &relative_to, &sensor.pose)?;
+                sensors.insert(
+                    cur_id.next().unwrap(),
+                    Parented {
+                        parent: get_id(&joint.child)?,
+                        bundle: sensor,
+                    },
+                );
+            }
+            joints.insert(
+                joint_id,
+                Parented {
+                    parent: get_id(&joint.parent)?,
+                    bundle: Joint {
+                        name: NameInWorkcell(joint.name.clone()),
+                        properties,
+                        actuation: Default::default(),
+                    },
+                },
+            );
+        }
+
+        Ok(Workcell {
+            properties: WorkcellProperties {
+                name: NameOfWorkcell(name),
+                ..Default::default()
+            },
+            format_version: Default::default(),
+            id: root_id,
+            frames,
+            visuals,
+            collisions,
+            inertias,
+            joints,
+            sensors,
+            includes: Default::default(),
+            materials: Default::default(),
+        })
+    }
+}
+
+impl Workcell {
+    /// Imports the first `<model>` of an sdf document. Nested models are added as frames, with
+    /// the names of their elements scoped as `model::element`.
+    /// Included models are looked up through [`SdfResolver::default`], relative paths are not
+    /// supported, use [`Workcell::from_sdf_file`] or [`Workcell::from_sdf_with_resolver`] for
+    /// them.
+    pub fn from_sdf(sdf: &Element) -> Result<Self, SdfImportError> {
+        Self::from_sdf_with_resolver(sdf, &SdfResolver::default(), None)
+    }
+
+    /// Imports the first `<model>` of an sdf document, relative uris are resolved from `dir`.
+    pub fn from_sdf_with_resolver(
+        sdf: &Element,
+        resolver: &SdfResolver,
+        dir: Option<&Path>,
+    ) -> Result<Self, SdfImportError> {
+        let model = if sdf.name == "model" {
+            sdf
+        } else {
+            sdf.get_child("model").ok_or(SdfImportError::MissingModel)?
+        };
+        let name = required_attr(model, "name")?.to_owned();
+        let mut graph = SdfModelGraph::default();
+        graph.add_model(model, String::new(), None, resolver, dir)?;
+        graph.into_workcell(name)
+    }
+
+    pub fn from_sdf_bytes(data: &[u8], dir: Option<&Path>) -> Result<Self, SdfImportError> {
+        let sdf = Element::parse(data)?;
+        Self::from_sdf_with_resolver(&sdf, &SdfResolver::default(), dir)
+    }
+
+    pub fn from_sdf_file(path: impl AsRef<Path>) -> Result<Self, SdfImportError> {
+        let path = path.as_ref();
+        let data = std::fs::read(path)?;
+        Self::from_sdf_bytes(&data, path.parent())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::frame_id;
+
+    fn children<'a>(element: &'a Element, name: &'a str) -> Vec<&'a Element> {
+        children_named(element, name).collect()
+    }
+
+    fn frame_pose(workcell: &Workcell, name: &str) -> Pose {
+        match workcell.frames[&frame_id(workcell, name)].bundle.anchor {
+            Anchor::Pose3D(pose) => pose,
+            _ => panic!("Frame {name} doesn't have a 3D pose"),
+        }
+    }
+
+    fn assert_close(a: [f32; 3], b: [f32; 3]) {
+        assert!(
+            Vec3::from(a).abs_diff_eq(Vec3::from(b), 1e-5),
+            "{a:?} != {b:?}"
+        );
+    }
+
+    #[test]
+    fn urdf_to_sdf() {
+        let urdf = urdf_rs::read_file("test/07-physics.urdf").unwrap();
+        let workcell = Workcell::from_urdf(&urdf).unwrap();
+        let sdf = workcell.to_sdf().unwrap();
+        let model = sdf.get_child("model").unwrap();
+        assert_eq!(children(model, "link").len(), 16);
+        assert_eq!(children(model, "joint").len(), 15);
+        assert!(children(model, "frame").is_empty());
+        assert!(workcell.to_sdf_string().is_ok());
+    }
+
+    #[test]
+    fn jointless_frames_are_exported_as_frames() {
+        let mut builder = WorkcellBuilder::new("cell");
+        let up = Pose {
+            trans: [0.0, 0.0, 1.0],
+            ..Default::default()
+        };
+        let base = builder.add_frame(builder.root(), "base", up);
+        let tool = builder.add_frame(base, "tool", up);
+        builder.add_visual(tool, "", Default::default(), Pose::default());
+        builder.add_inertia(tool, Inertia::default());
+        let workcell = builder.build().unwrap();
+        let sdf = workcell.to_sdf().unwrap();
+        let model = sdf.get_child("model").unwrap();
+        let links = children(model, "link");
+        assert_eq!(links.len(), 1);
+        let frames = children(model, "frame");
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].attributes["attached_to"], "base");
+        let visual = links[0].get_child("visual").unwrap();
+        assert_eq!(
+            visual.get_child("pose").unwrap().attributes["relative_to"],
+            "tool"
+        );
+        // The inertia is expressed in the link frame
+        let inertial_pose = links[0]
+            .get_child("inertial")
+            .and_then(|i| i.get_child("pose"))
+            .and_then(|p| p.get_text())
+            .unwrap();
+        assert_eq!(inertial_pose, "0 0 1 0 0 0");
+    }
+
+    #[test]
+    fn sdf_roundtrip() {
+        let urdf = urdf_rs::read_file("test/07-physics.urdf").unwrap();
+        let workcell = Workcell::from_urdf(&urdf).unwrap();
+        let sdf = workcell.to_sdf().unwrap();
+        let imported = Workcell::from_sdf(&sdf).unwrap();
+        assert!(imported.validate().is_empty());
+        assert_eq!(imported.properties.name.0, "physics");
+        assert_eq!(imported.frames.len(), workcell.frames.len());
+        assert_eq!(imported.joints.len(), workcell.joints.len());
+        assert_eq!(imported.visuals.len(), workcell.visuals.len());
+        assert_eq!(imported.collisions.len(), workcell.collisions.len());
+        assert_eq!(imported.inertias.len(), workcell.inertias.len());
+        assert_close(
+            frame_pose(&imported, "right_leg").trans,
+            frame_pose(&workcell, "right_leg").trans,
+        );
+    }
+
+    #[test]
+    fn sdf_with_nested_models_and_includes() {
+        let workcell = Workcell::from_sdf_file("test/arm.sdf").unwrap();
+        assert!(workcell.validate().is_empty());
+        assert_eq!(workcell.properties.name.0, "arm");
+        assert_eq!(workcell.frames.len(), 7);
+        // The fixed joint to the world is not imported
+        assert_eq!(workcell.joints.len(), 3);
+        let base = frame_id(&workcell, "base");
+        assert_eq!(workcell.frames[&base].parent, workcell.id);
+        // Poses of joint children are relative to the parent link
+        let upper_arm = frame_id(&workcell, "upper_arm");
+        let shoulder = workcell.frames[&upper_arm].parent;
+        assert_eq!(workcell.joints[&shoulder].parent, base);
+        assert_close(frame_pose(&workcell, "upper_arm").trans, [0.0, 0.0, 0.5]);
+        let JointProperties::Revolute(joint) = &workcell.joints[&shoulder].bundle.properties else {
+            panic!("Shoulder joint should be revolute");
+        };
+        // The axis was expressed in the model frame, the child is rotated by 90 degrees
+        assert_close(joint.axis.0, [0.0, -1.0, 0.0]);
+        let (_, collision) = workcell
+            .collisions
+            .iter()
+            .find(|(_, c)| c.parent == upper_arm)
+            .unwrap();
+        assert_close(collision.bundle.pose.trans, [0.0, 0.0, 0.25]);
+        // Frames attached to a joint are attached to its child link
+        let tool = frame_id(&workcell, "tool");
+        assert_eq!(workcell.frames[&tool].parent, upper_arm);
+        assert_close(frame_pose(&workcell, "tool").trans, [0.0, 0.0, 0.5]);
+        // Included and nested models become frames, their elements are scoped
+        let gripper = frame_id(&workcell, "left_gripper");
+        assert_eq!(workcell.frames[&gripper].parent, workcell.id);
+        assert_close(frame_pose(&workcell, "left_gripper").trans, [0.0, 0.0, 1.1]);
+        assert_close(
+            frame_pose(&workcell, "left_gripper::palm").trans,
+            [0.0, 0.0, 0.6],
+        );
+        let fingers = frame_id(&workcell, "left_gripper::fingers");
+        assert_eq!(workcell.frames[&fingers].parent, gripper);
+        let finger = frame_id(&workcell, "left_gripper::fingers::finger");
+        let finger_joint = workcell.frames[&finger].parent;
+        assert_eq!(workcell.joints[&finger_joint].parent, fingers);
+        // Relative mesh paths are resolved from the included model directory
+        let palm = frame_id(&workcell, "left_gripper::palm");
+        let (_, palm_visual) = workcell
+            .visuals
+            .iter()
+            .find(|(_, v)| v.parent == palm)
+            .unwrap();
+        let Geometry::Mesh {
+            source: AssetSource::Local(path),
+            ..
+        } = &palm_visual.bundle.geometry
+        else {
+            panic!("Palm visual should be a local mesh");
+        };
+        assert!(Path::new(path).ends_with("gripper/meshes/palm.stl"));
+    }
+
+    #[test]
+    fn include_cycles_are_rejected() {
+        let dir =
+            std::env::temp_dir().join(format!("rmf_workcell_sdf_cycle_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("model.sdf");
+        std::fs::write(
+            &path,
+            r#"<sdf version="1.8">
+                <model name="recursive">
+                    <link name="base"/>
+                    <include><uri>model.sdf</uri><name>nested</name></include>
+                </model>
+            </sdf>"#,
+        )
+        .unwrap();
+        let result = Workcell::from_sdf_file(&path);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(result, Err(SdfImportError::IncludeCycle(_))));
+    }
+
+    #[test]
+    fn includes_must_be_flattened() {
+        let mut builder = WorkcellBuilder::new("cell");
+        let table = builder.add_frame(builder.root(), "table", Pose::default());
+        builder.add_include(
+            table,
+            "robot",
+            AssetSource::Local("07-physics.urdf".to_owned()),
+            Pose::default(),
+        );
+        let workcell = builder.build().unwrap();
+        assert!(matches!(
+            workcell.to_sdf(),
+            Err(WorkcellToSdfError::UnflattenedIncludes)
+        ));
+    }
+
+    #[test]
+    fn offset_joints_and_multiple_parents_are_rejected() {
+        let sdf = |joint_pose: &str, second_child: &str| {
+            format!(
+                r#"<sdf version="1.9"><model name="arm">
+                    <link name="base"/><link name="upper_arm"/><link name="forearm"/>
+                    <joint name="shoulder" type="revolute">
+                        <parent>base</parent><child>upper_arm</child>
+                        <pose>{joint_pose}</pose>
+                    </joint>
+                    <joint name="elbow" type="revolute">
+                        <parent>upper_arm</parent><child>{second_child}</child>
+                    </joint>
+                </model></sdf>"#
+            )
+        };
+        let import = |sdf: String| Workcell::from_sdf_bytes(sdf.as_bytes(), None);
+        assert!(import(sdf("0 0 0 0 0 0", "forearm")).is_ok());
+        assert!(matches!(
+            import(sdf("0 0 0.1 0 0 0", "forearm")),
+            Err(SdfImportError::UnsupportedJointPose(joint)) if joint == "shoulder"
+        ));
+        assert!(matches!(
+            import(sdf("0 0 0 0 0 0", "upper_arm")),
+            Err(SdfImportError::MultipleParentJoints(link)) if link == "upper_arm"
+        ));
+    }
+}
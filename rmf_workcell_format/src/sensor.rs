@@ -0,0 +1,528 @@
+/*
+ * Copyright (C) 2024 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+//! Cameras, depth cameras, lidars and force torque sensors attached to the frames of a workcell.
+
+use crate::*;
+#[cfg(feature = "bevy")]
+use bevy::prelude::{Bundle, Component};
+use glam::{Affine3A, Vec3};
+use rmf_site_format::Pose;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use thiserror::Error as ThisError;
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "bevy", derive(Component))]
+pub struct SensorMarker;
+
+/// A sensor rigidly attached to a frame. Cameras and lidars look along the x axis of the sensor
+/// with the z axis pointing up, the same convention used by Gazebo.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "bevy", derive(Bundle))]
+pub struct Sensor {
+    pub name: NameInWorkcell,
+    /// Pose of the sensor relative to its parent frame
+    pub pose: Pose,
+    pub properties: SensorProperties,
+    #[serde(skip)]
+    pub marker: SensorMarker,
+}
+
+impl Sensor {
+    pub fn new(name: &str, properties: SensorProperties, pose: Pose) -> Self {
+        Self {
+            name: NameInWorkcell(name.to_owned()),
+            pose,
+            properties,
+            marker: SensorMarker,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "bevy", derive(Component))]
+pub struct SensorProperties {
+    /// Rate at which the sensor produces measurements in Hz, zero means as fast as possible
+    pub update_rate: f32,
+    pub kind: SensorKind,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum SensorKind {
+    Camera(CameraProperties),
+    /// A camera measuring depth, its clipping planes are the range of the measured depths
+    DepthCamera(CameraProperties),
+    Lidar(LidarProperties),
+    /// Measures the wrench transmitted through the joint the sensor frame is rigidly attached to
+    ForceTorque(ForceTorqueProperties),
+}
+
+impl SensorKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            SensorKind::Camera(_) => "Camera",
+            SensorKind::DepthCamera(_) => "Depth camera",
+            SensorKind::Lidar(_) => "Lidar",
+            SensorKind::ForceTorque(_) => "Force torque",
+        }
+    }
+
+    /// Type of the sensor in sdf and in the exported sensor list.
+    pub fn sdf_type(&self) -> &'static str {
+        match self {
+            SensorKind::Camera(_) => "camera",
+            SensorKind::DepthCamera(_) => "depth_camera",
+            SensorKind::Lidar(_) => "gpu_lidar",
+            SensorKind::ForceTorque(_) => "force_torque",
+        }
+    }
+}
+
+/// Pinhole camera parameters, in pixels.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct CameraIntrinsics {
+    pub fx: f32,
+    pub fy: f32,
+    pub cx: f32,
+    pub cy: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CameraProperties {
+    /// Width and height of the image, in pixels
+    pub resolution: [u32; 2],
+    pub intrinsics: CameraIntrinsics,
+    /// Distances of the near and far clipping planes, in meters
+    pub clip: [f32; 2],
+}
+
+impl Default for CameraProperties {
+    fn default() -> Self {
+        Self::from_horizontal_fov([640, 480], 60_f32.to_radians(), [0.05, 10.0])
+    }
+}
+
+impl CameraProperties {
+    /// A camera with square pixels and the principal point at the center of the image.
+    pub fn from_horizontal_fov(resolution: [u32; 2], horizontal_fov: f32, clip: [f32; 2]) -> Self {
+        let [width, height] = resolution.map(|v| v as f32);
+        let focal_length = width / 2.0 / (horizontal_fov / 2.0).tan();
+        Self {
+            resolution,
+            intrinsics: CameraIntrinsics {
+                fx: focal_length,
+                fy: focal_length,
+                cx: width / 2.0,
+                cy: height / 2.0,
+            },
+            clip,
+        }
+    }
+
+    /// Horizontal field of view in radians, assuming the principal point is centered.
+    pub fn horizontal_fov(&self) -> f32 {
+        2.0 * (self.resolution[0] as f32 / (2.0 * self.intrinsics.fx)).atan()
+    }
+
+    /// Vertical field of view in radians, assuming the principal point is centered.
+    pub fn vertical_fov(&self) -> f32 {
+        2.0 * (self.resolution[1] as f32 / (2.0 * self.intrinsics.fy)).atan()
+    }
+
+    /// Corners of the image projected at `distance` along the viewing direction, in the frame of
+    /// the sensor. The order is top left, top right, bottom right, bottom left.
+    pub fn frustum_corners(&self, distance: f32) -> [Vec3; 4] {
+        let CameraIntrinsics { fx, fy, cx, cy } = self.intrinsics;
+        let [width, height] = self.resolution.map(|v| v as f32);
+        // Image coordinates grow right and down, the sensor frame has y left and z up
+        [(0.0, 0.0), (width, 0.0), (width, height), (0.0, height)]
+            .map(|(u, v)| Vec3::new(1.0, -(u - cx) / fx, -(v - cy) / fy) * distance)
+    }
+}
+
+/// Samples of a lidar scan along one direction.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LidarScan {
+    pub samples: u32,
+    /// Angles of the first and last samples, in radians
+    pub angles: [f32; 2],
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LidarProperties {
+    /// Samples around the z axis of the sensor
+    pub horizontal: LidarScan,
+    /// Samples above and below the xy plane of the sensor, planar lidars don't have any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vertical: Option<LidarScan>,
+    /// Minimum and maximum measured distances, in meters
+    pub range: [f32; 2],
+    /// Resolution of the measured distances, in meters
+    pub range_resolution: f32,
+}
+
+impl Default for LidarProperties {
+    fn default() -> Self {
+        Self {
+            horizontal: LidarScan {
+                samples: 360,
+                angles: [-std::f32::consts::PI, std::f32::consts::PI],
+            },
+            vertical: None,
+            range: [0.1, 10.0],
+            range_resolution: 0.01,
+        }
+    }
+}
+
+/// Frame the measured wrench is expressed in.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ForceTorqueFrame {
+    /// The child frame of the joint
+    Child,
+    /// The parent frame of the joint
+    Parent,
+    #[default]
+    Sensor,
+}
+
+impl ForceTorqueFrame {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ForceTorqueFrame::Child => "child",
+            ForceTorqueFrame::Parent => "parent",
+            ForceTorqueFrame::Sensor => "sensor",
+        }
+    }
+}
+
+/// Whether the measured wrench is the one applied by the child on the parent or the opposite.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MeasureDirection {
+    #[default]
+    ChildToParent,
+    ParentToChild,
+}
+
+impl MeasureDirection {
+    pub fn label(&self) -> &'static str {
+        match self {
+            MeasureDirection::ChildToParent => "child_to_parent",
+            MeasureDirection::ParentToChild => "parent_to_child",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct ForceTorqueProperties {
+    #[serde(default)]
+    pub frame: ForceTorqueFrame,
+    #[serde(default)]
+    pub measure_direction: MeasureDirection,
+}
+
+#[derive(Debug, Clone, PartialEq, ThisError)]
+pub enum SensorError {
+    #[error("update rate [{0}] is negative or not a number")]
+    InvalidUpdateRate(f32),
+    #[error("resolution {0:?} has no pixels")]
+    InvalidResolution([u32; 2]),
+    #[error("focal lengths [{fx}, {fy}] must be positive")]
+    InvalidFocalLength { fx: f32, fy: f32 },
+    #[error("range {0:?} must start from a positive value and be increasing")]
+    InvalidRange([f32; 2]),
+    #[error("scan of {samples} samples between angles {angles:?} is empty or decreasing")]
+    InvalidScan { samples: u32, angles: [f32; 2] },
+}
+
+impl SensorProperties {
+    pub fn validate(&self) -> Result<(), SensorError> {
+        if self.update_rate.is_nan() || self.update_rate < 0.0 {
+            return Err(SensorError::InvalidUpdateRate(self.update_rate));
+        }
+        let check_range = |range: [f32; 2]| {
+            let valid = range[0] >= 0.0 && range[1] > range[0];
+            if valid {
+                Ok(())
+            } else {
+                Err(SensorError::InvalidRange(range))
+            }
+        };
+        match &self.kind {
+            SensorKind::Camera(camera) | SensorKind::DepthCamera(camera) => {
+                if camera.resolution.contains(&0) {
+                    return Err(SensorError::InvalidResolution(camera.resolution));
+                }
+                let CameraIntrinsics { fx, fy, .. } = camera.intrinsics;
+                let valid = fx > 0.0 && fy > 0.0;
+                if !valid {
+                    return Err(SensorError::InvalidFocalLength { fx, fy });
+                }
+                check_range(camera.clip)
+            }
+            SensorKind::Lidar(lidar) => {
+                for scan in std::iter::once(&lidar.horizontal).chain(&lidar.vertical) {
+                    let valid = scan.samples > 0 && scan.angles[1] >= scan.angles[0];
+                    if !valid {
+                        return Err(SensorError::InvalidScan {
+                            samples: scan.samples,
+                            angles: scan.angles,
+                        });
+                    }
+                }
+                check_range(lidar.range)
+            }
+            SensorKind::ForceTorque(_) => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, ThisError)]
+pub enum SensorsYamlError {
+    #[error(transparent)]
+    Kinematics(#[from] KinematicsError),
+    #[error(transparent)]
+    Yaml(#[from] serde_yaml::Error),
+}
+
+/// A sensor in the exported sensor list.
+#[derive(Serialize)]
+struct SensorEntry<'a> {
+    name: &'a str,
+    #[serde(rename = "type")]
+    sensor_type: &'static str,
+    frame: &'a str,
+    update_rate: f32,
+    pose: PoseEntry,
+    pose_in_workcell: PoseEntry,
+    #[serde(flatten)]
+    properties: SensorPropertiesEntry<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum SensorPropertiesEntry<'a> {
+    Camera(&'a CameraProperties),
+    Lidar(&'a LidarProperties),
+    ForceTorque {
+        joint: &'a str,
+        measure_frame: &'static str,
+        measure_direction: &'static str,
+    },
+}
+
+/// Position and quaternion of a sensor pose.
+#[derive(Serialize)]
+struct PoseEntry {
+    position: [f32; 3],
+    orientation: [f32; 4],
+}
+
+impl From<&Affine3A> for PoseEntry {
+    fn from(tf: &Affine3A) -> Self {
+        let pose = pose_from_affine(tf);
+        let quat = quat_from_rotation(&pose.rot);
+        Self {
+            position: pose.trans,
+            orientation: [quat.x, quat.y, quat.z, quat.w],
+        }
+    }
+}
+
+impl Workcell {
+    /// The joint that moves the frame, i.e. the joint whose child frame `frame_id` is rigidly
+    /// attached to. None if the frame is rigidly attached to the workcell.
+    pub fn sensor_joint(&self, mut frame_id: u32) -> Option<u32> {
+        while let Some(frame) = self.frames.get(&frame_id) {
+            if self.joints.contains_key(&frame.parent) {
+                return Some(frame.parent);
+            }
+            frame_id = frame.parent;
+        }
+        None
+    }
+
+    /// Lists the sensors of the workcell for perception pipelines, if there are any. Poses are
+    /// given relative to the frame of the sensor and to the workcell with all the joints at
+    /// zero, orientations are quaternions in x, y, z, w order.
+    pub fn sensors_yaml(&self) -> Result<Option<String>, SensorsYamlError> {
+        if self.sensors.is_empty() {
+            return Ok(None);
+        }
+        let transforms = self.frame_transforms(&JointPositions::default())?;
+        let frame_name = |id: u32| {
+            self.frames
+                .get(&id)
+                .map(|f| f.bundle.name.0.as_str())
+                .unwrap_or_default()
+        };
+        let mut sensors: Vec<_> = self.sensors.values().collect();
+        sensors.sort_by(|a, b| a.bundle.name.0.cmp(&b.bundle.name.0));
+
+        let mut entries = Vec::new();
+        for sensor in sensors {
+            let properties = &sensor.bundle.properties;
+            let pose = affine_from_pose(&sensor.bundle.pose);
+            let parent_tf = transforms
+                .get(&sensor.parent)
+                .ok_or(KinematicsError::MissingFrame(sensor.parent))?;
+            let kind = match &properties.kind {
+                SensorKind::Camera(camera) | SensorKind::DepthCamera(camera) => {
+                    SensorPropertiesEntry::Camera(camera)
+                }
+                SensorKind::Lidar(lidar) => SensorPropertiesEntry::Lidar(lidar),
+                SensorKind::ForceTorque(ft) => SensorPropertiesEntry::ForceTorque {
+                    joint: self
+                        .sensor_joint(sensor.parent)
+                        .and_then(|j| self.joints.get(&j))
+                        .map(|j| j.bundle.name.0.as_str())
+                        .unwrap_or_default(),
+                    measure_frame: ft.frame.label(),
+                    measure_direction: ft.measure_direction.label(),
+                },
+            };
+            entries.push(SensorEntry {
+                name: &sensor.bundle.name.0,
+                sensor_type: properties.kind.sdf_type(),
+                frame: frame_name(sensor.parent),
+                update_rate: properties.update_rate,
+                pose: (&pose).into(),
+                pose_in_workcell: (&(*parent_tf * pose)).into(),
+                properties: kind,
+            });
+        }
+        let yaml = BTreeMap::from([("sensors", entries)]);
+        Ok(Some(serde_yaml::to_string(&yaml)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use float_eq::assert_float_eq;
+
+    fn sensor_workcell() -> Workcell {
+        let mut builder = WorkcellBuilder::new("vision_cell");
+        let fixture = builder.add_frame(
+            builder.root(),
+            "fixture",
+            Pose {
+                trans: [1.0, 0.0, 0.0],
+                ..Default::default()
+            },
+        );
+        let mount = builder.add_frame(fixture, "camera_mount", Pose::default());
+        let wrist = builder.add_frame(fixture, "wrist", Pose::default());
+        builder.add_joint(fixture, wrist, "wrist_joint", JointProperties::Fixed);
+        builder.add_sensor(
+            mount,
+            "top_camera",
+            SensorProperties {
+                update_rate: 30.0,
+                kind: SensorKind::Camera(CameraProperties::default()),
+            },
+            Pose {
+                trans: [0.0, 0.0, 0.5],
+                ..Default::default()
+            },
+        );
+        builder.add_sensor(
+            wrist,
+            "wrist_ft",
+            SensorProperties {
+                update_rate: 100.0,
+                kind: SensorKind::ForceTorque(Default::default()),
+            },
+            Pose::default(),
+        );
+        builder.build().unwrap()
+    }
+
+    #[test]
+    fn sensors_are_validated_and_exported() {
+        let workcell = sensor_workcell();
+        let camera = CameraProperties::default();
+        assert_float_eq!(camera.horizontal_fov(), 60_f32.to_radians(), abs <= 1e-5);
+        let corners = camera.frustum_corners(1.0);
+        assert_float_eq!(corners[0].y, -corners[1].y, abs <= 1e-5);
+        assert!(corners[0].z > 0.0 && corners[2].z < 0.0);
+
+        // Sdf has the camera in the link of its frame and the force torque sensor in the joint
+        let sdf = workcell.to_sdf_string().unwrap();
+        let sdf = Workcell::from_sdf_bytes(sdf.as_bytes(), None).unwrap();
+        assert_eq!(sdf.sensors.len(), 2);
+        let graph = sdf.graph();
+        let sdf_camera = sdf
+            .sensors
+            .values()
+            .find(|s| s.bundle.name.0 == "top_camera")
+            .unwrap();
+        assert_eq!(Some(sdf_camera.parent), graph.frame_by_name("fixture"));
+        assert_eq!(sdf_camera.bundle.pose.trans, [0.0, 0.0, 0.5]);
+        let SensorKind::Camera(sdf_properties) = &sdf_camera.bundle.properties.kind else {
+            panic!("camera imported as {:?}", sdf_camera.bundle.properties.kind);
+        };
+        assert_float_eq!(
+            sdf_properties.intrinsics.fx,
+            camera.intrinsics.fx,
+            abs <= 1e-3
+        );
+        assert!(sdf.sensors.values().any(
+            |s| s.bundle.name.0 == "wrist_ft" && Some(s.parent) == graph.frame_by_name("wrist")
+        ));
+
+        let urdf = workcell.to_urdf_string().unwrap();
+        assert!(urdf.contains(r#"<gazebo reference="fixture">"#));
+        assert!(urdf.contains(r#"<gazebo reference="wrist_joint">"#));
+        assert!(urdf.contains(r#"<sensor name="top_camera" type="camera">"#));
+
+        let yaml = workcell.sensors_yaml().unwrap().unwrap();
+        let yaml: serde_yaml::Value = serde_yaml::from_str(&yaml).unwrap();
+        let camera = &yaml["sensors"][0];
+        assert_eq!(camera["name"], "top_camera");
+        assert_eq!(camera["type"], "camera");
+        assert_eq!(camera["frame"], "camera_mount");
+        assert_eq!(
+            camera["pose_in_workcell"]["position"],
+            serde_yaml::to_value([1.0, 0.0, 0.5]).unwrap()
+        );
+        assert_eq!(
+            camera["resolution"],
+            serde_yaml::to_value([640, 480]).unwrap()
+        );
+        assert_eq!(yaml["sensors"][1]["joint"], "wrist_joint");
+
+        // Force torque sensors need a joint to measure and parameters are checked
+        let mut invalid = workcell.clone();
+        let graph = workcell.graph();
+        let sensor_id = |name: &str| graph.sensor_by_name(name).unwrap();
+        let (ft_id, camera_id) = (sensor_id("wrist_ft"), sensor_id("top_camera"));
+        invalid.sensors.get_mut(&ft_id).unwrap().parent =
+            graph.frame_by_name("camera_mount").unwrap();
+        let camera = &mut invalid.sensors.get_mut(&camera_id).unwrap().bundle;
+        camera.properties.update_rate = -1.0;
+        let diagnostics = invalid.validate();
+        assert!(diagnostics.contains(&WorkcellDiagnostic::ForceTorqueSensorWithoutJoint(ft_id)));
+        assert!(diagnostics.contains(&WorkcellDiagnostic::InvalidSensor {
+            sensor: camera_id,
+            error: SensorError::InvalidUpdateRate(-1.0),
+        }));
+    }
+}
@@ -0,0 +1,37 @@
+/*
+ * Copyright (C) 2024 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+//! Fixtures shared by the tests of the crate, see [`WorkcellBuilder`] to build valid workcells.
+
+use crate::*;
+
+/// A frame at the origin of its parent, to build workcells that are not valid.
+pub(crate) fn frame(parent: u32, name: &str) -> Parented<u32, Frame> {
+    Parented {
+        parent,
+        bundle: Frame {
+            anchor: Anchor::Pose3D(Pose::default()),
+            name: NameInWorkcell(name.to_owned()),
+            marker: FrameMarker,
+        },
+    }
+}
+
+/// Id of the frame with the given name, panics if there is none.
+pub(crate) fn frame_id(workcell: &Workcell, name: &str) -> u32 {
+    workcell.graph().frame_by_name(name).unwrap()
+}
@@ -0,0 +1,54 @@
+/*
+ * Copyright (C) 2024 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use rmf_site_format::{Pose, Rotation};
+
+use glam::{Affine3A, EulerRot, Quat, Vec3};
+
+/// Converts a rotation to a quaternion. Euler angles are extrinsic XYZ, the same convention
+/// used by the roll, pitch, yaw of URDF and SDF.
+pub fn quat_from_rotation(rotation: &Rotation) -> Quat {
+    match rotation {
+        Rotation::Yaw(yaw) => Quat::from_rotation_z(yaw.radians()),
+        Rotation::EulerExtrinsicXYZ([roll, pitch, yaw]) => Quat::from_euler(
+            EulerRot::ZYX,
+            yaw.radians(),
+            pitch.radians(),
+            roll.radians(),
+        ),
+        Rotation::Quat(quat) => Quat::from_array(*quat),
+    }
+}
+
+/// Returns the roll, pitch and yaw of a quaternion, in radians.
+pub fn rpy_from_quat(quat: Quat) -> [f32; 3] {
+    let (yaw, pitch, roll) = quat.to_euler(EulerRot::ZYX);
+    [roll, pitch, yaw]
+}
+
+pub fn affine_from_pose(pose: &Pose) -> Affine3A {
+    Affine3A::from_rotation_translation(quat_from_rotation(&pose.rot), Vec3::from(pose.trans))
+}
+
+/// Converts a rigid transform back to a pose, any scaling is discarded.
+pub fn pose_from_affine(tf: &Affine3A) -> Pose {
+    let (_, rot, trans) = tf.to_scale_rotation_translation();
+    Pose {
+        trans: trans.to_array(),
+        rot: Rotation::Quat(rot.normalize().to_array()),
+    }
+}
@@ -0,0 +1,403 @@
+/*
+ * Copyright (C) 2024 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use std::collections::{BTreeMap, HashSet};
+
+use crate::*;
+use thiserror::Error as ThisError;
+
+/// The kind of element that a workcell id refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WorkcellElementKind {
+    Workcell,
+    Frame,
+    Joint,
+    Visual,
+    Collision,
+    Inertia,
+    Include,
+    Sensor,
+}
+
+impl WorkcellElementKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            WorkcellElementKind::Workcell => "workcell",
+            WorkcellElementKind::Frame => "frame",
+            WorkcellElementKind::Joint => "joint",
+            WorkcellElementKind::Visual => "visual",
+            WorkcellElementKind::Collision => "collision",
+            WorkcellElementKind::Inertia => "inertia",
+            WorkcellElementKind::Include => "include",
+            WorkcellElementKind::Sensor => "sensor",
+        }
+    }
+}
+
+impl std::fmt::Display for WorkcellElementKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.label())
+    }
+}
+
+/// A structural issue found when validating a [`Workcell`].
+#[derive(Debug, Clone, PartialEq, ThisError)]
+pub enum WorkcellDiagnostic {
+    #[error("id [{id}] is used by more than one element: {kinds:?}")]
+    DuplicateId {
+        id: u32,
+        kinds: Vec<WorkcellElementKind>,
+    },
+    #[error("{kind} [{id}] refers to a non existing parent [{parent}]")]
+    MissingParent {
+        id: u32,
+        kind: WorkcellElementKind,
+        parent: u32,
+    },
+    #[error("{kind} [{id}] has parent [{parent}] of kind {parent_kind}, which is not allowed")]
+    InvalidParentKind {
+        id: u32,
+        kind: WorkcellElementKind,
+        parent: u32,
+        parent_kind: WorkcellElementKind,
+    },
+    #[error("cycle found in the parent hierarchy: {0:?}")]
+    ParentCycle(Vec<u32>),
+    #[error("joint [{joint}] has {num_children} child frames, exactly one is required")]
+    InvalidJointChildren { joint: u32, num_children: usize },
+    #[error("{kind} name [{name}] is used by multiple elements: {ids:?}")]
+    DuplicateName {
+        name: String,
+        kind: WorkcellElementKind,
+        ids: Vec<u32>,
+    },
+    #[error("frame [{0}] has an anchor that is not a Pose3D")]
+    InvalidAnchorType(u32),
+    #[error("visual [{visual}] refers to material [{material}] which is not in the library")]
+    MissingMaterial { visual: u32, material: String },
+    #[error("joint [{joint}] mimics joint [{mimic}] which does not exist")]
+    MissingMimicJoint { joint: u32, mimic: String },
+    #[error("joint [{joint}] has invalid limits: {error}")]
+    InvalidJointLimits { joint: u32, error: JointLimitsError },
+    #[error("sensor [{sensor}] has invalid properties: {error}")]
+    InvalidSensor { sensor: u32, error: SensorError },
+    #[error("force torque sensor [{0}] is not attached to the child frame of a joint")]
+    ForceTorqueSensorWithoutJoint(u32),
+}
+
+impl WorkcellDiagnostic {
+    /// Returns the id of the element this diagnostic is mostly related to.
+    pub fn element(&self) -> Option<u32> {
+        match self {
+            WorkcellDiagnostic::DuplicateId { id, .. } => Some(*id),
+            WorkcellDiagnostic::MissingParent { id, .. } => Some(*id),
+            WorkcellDiagnostic::InvalidParentKind { id, .. } => Some(*id),
+            WorkcellDiagnostic::ParentCycle(ids) => ids.first().copied(),
+            WorkcellDiagnostic::InvalidJointChildren { joint, .. } => Some(*joint),
+            WorkcellDiagnostic::DuplicateName { ids, .. } => ids.first().copied(),
+            WorkcellDiagnostic::InvalidAnchorType(id) => Some(*id),
+            WorkcellDiagnostic::MissingMaterial { visual, .. } => Some(*visual),
+            WorkcellDiagnostic::MissingMimicJoint { joint, .. } => Some(*joint),
+            WorkcellDiagnostic::InvalidJointLimits { joint, .. } => Some(*joint),
+            WorkcellDiagnostic::InvalidSensor { sensor, .. } => Some(*sensor),
+            WorkcellDiagnostic::ForceTorqueSensorWithoutJoint(sensor) => Some(*sensor),
+        }
+    }
+}
+
+impl Workcell {
+    /// Returns an iterator over the id, parent and kind of all the elements in the workcell.
+    pub(crate) fn element_parents(
+        &self,
+    ) -> impl Iterator<Item = (u32, u32, WorkcellElementKind)> + '_ {
+        fn parents<T>(
+            map: &BTreeMap<u32, Parented<u32, T>>,
+            kind: WorkcellElementKind,
+        ) -> impl Iterator<Item = (u32, u32, WorkcellElementKind)> + '_ {
+            map.iter().map(move |(id, p)| (*id, p.parent, kind))
+        }
+        parents(&self.frames, WorkcellElementKind::Frame)
+            .chain(parents(&self.joints, WorkcellElementKind::Joint))
+            .chain(parents(&self.visuals, WorkcellElementKind::Visual))
+            .chain(parents(&self.collisions, WorkcellElementKind::Collision))
+            .chain(parents(&self.inertias, WorkcellElementKind::Inertia))
+            .chain(parents(&self.includes, WorkcellElementKind::Include))
+            .chain(parents(&self.sensors, WorkcellElementKind::Sensor))
+    }
+
+    /// Checks the structure of the workcell and returns all the issues that were found.
+    /// An empty result means that the workcell is structurally valid.
+    pub fn validate(&self) -> Vec<WorkcellDiagnostic> {
+        let mut diagnostics = Vec::new();
+
+        let mut kinds: BTreeMap<u32, Vec<WorkcellElementKind>> = BTreeMap::new();
+        kinds.insert(self.id, vec![WorkcellElementKind::Workcell]);
+        for (id, _, kind) in self.element_parents() {
+            kinds.entry(id).or_default().push(kind);
+        }
+        for (id, kinds) in kinds.iter() {
+            if kinds.len() > 1 {
+                diagnostics.push(WorkcellDiagnostic::DuplicateId {
+                    id: *id,
+                    kinds: kinds.clone(),
+                });
+            }
+        }
+
+        // Parent existence and kind
+        for (id, parent, kind) in self.element_parents() {
+            let Some(parent_kind) = kinds.get(&parent).and_then(|k| k.first()).copied() else {
+                diagnostics.push(WorkcellDiagnostic::MissingParent { id, kind, parent });
+                continue;
+            };
+            let allowed = match kind {
+                WorkcellElementKind::Workcell => false,
+                WorkcellElementKind::Frame => matches!(
+                    parent_kind,
+                    WorkcellElementKind::Workcell
+                        | WorkcellElementKind::Frame
+                        | WorkcellElementKind::Joint
+                ),
+                WorkcellElementKind::Joint
+                | WorkcellElementKind::Visual
+                | WorkcellElementKind::Collision
+                | WorkcellElementKind::Inertia
+                | WorkcellElementKind::Include
+                | WorkcellElementKind::Sensor => parent_kind == WorkcellElementKind::Frame,
+            };
+            if !allowed {
+                diagnostics.push(WorkcellDiagnostic::InvalidParentKind {
+                    id,
+                    kind,
+                    parent,
+                    parent_kind,
+                });
+            }
+        }
+
+        // Cycles in the hierarchy
+        let parent_of: BTreeMap<u32, u32> = self
+            .element_parents()
+            .map(|(id, parent, _)| (id, parent))
+            .collect();
+        let mut visited = HashSet::new();
+        for start in parent_of.keys() {
+            let mut path = Vec::new();
+            let mut current = *start;
+            loop {
+                if visited.contains(&current) {
+                    break;
+                }
+                if let Some(pos) = path.iter().position(|id| *id == current) {
+                    let mut cycle: Vec<u32> = path[pos..].to_vec();
+                    // Start the cycle from its smallest id to have a deterministic output
+                    if let Some((min_pos, _)) = cycle.iter().enumerate().min_by_key(|(_, id)| **id)
+                    {
+                        cycle.rotate_left(min_pos);
+                    }
+                    diagnostics.push(WorkcellDiagnostic::ParentCycle(cycle));
+                    break;
+                }
+                path.push(current);
+                match parent_of.get(&current) {
+                    Some(parent) => current = *parent,
+                    None => break,
+                }
+            }
+            visited.extend(path);
+        }
+
+        // Joints must have exactly one child frame
+        for joint in self.joints.keys() {
+            let num_children = self
+                .frames
+                .values()
+                .filter(|frame| frame.parent == *joint)
+                .count();
+            if num_children != 1 {
+                diagnostics.push(WorkcellDiagnostic::InvalidJointChildren {
+                    joint: *joint,
+                    num_children,
+                });
+            }
+        }
+
+        // Duplicated names, frames become links and joints become joints so their names only
+        // need to be unique within their own kind. Includes become frames when flattened
+        let mut check_names = |names: Vec<(u32, &NameInWorkcell)>, kind| {
+            let mut ids_by_name: BTreeMap<&str, Vec<u32>> = BTreeMap::new();
+            for (id, name) in names {
+                ids_by_name.entry(name.0.as_str()).or_default().push(id);
+            }
+            for (name, ids) in ids_by_name {
+                if ids.len() > 1 {
+                    diagnostics.push(WorkcellDiagnostic::DuplicateName {
+                        name: name.to_owned(),
+                        kind,
+                        ids,
+                    });
+                }
+            }
+        };
+        check_names(
+            self.frames
+                .iter()
+                .map(|(id, frame)| (*id, &frame.bundle.name))
+                .chain(
+                    self.includes
+                        .iter()
+                        .map(|(id, include)| (*id, &include.bundle.name)),
+                )
+                .collect(),
+            WorkcellElementKind::Frame,
+        );
+        check_names(
+            self.joints
+                .iter()
+                .map(|(id, joint)| (*id, &joint.bundle.name))
+                .collect(),
+            WorkcellElementKind::Joint,
+        );
+        // Simulators and perception pipelines refer to sensors by name
+        check_names(
+            self.sensors
+                .iter()
+                .map(|(id, sensor)| (*id, &sensor.bundle.name))
+                .collect(),
+            WorkcellElementKind::Sensor,
+        );
+
+        for (id, frame) in &self.frames {
+            if !matches!(frame.bundle.anchor, Anchor::Pose3D(_)) {
+                diagnostics.push(WorkcellDiagnostic::InvalidAnchorType(*id));
+            }
+        }
+
+        for (id, joint) in &self.joints {
+            if let Some(Err(error)) = joint.bundle.properties.limits().map(|l| l.validate()) {
+                diagnostics.push(WorkcellDiagnostic::InvalidJointLimits { joint: *id, error });
+            }
+            let Some(mimic) = joint
+                .bundle
+                .properties
+                .single_dof()
+                .and_then(|j| j.mimic.as_ref())
+            else {
+                continue;
+            };
+            if !self.joints.values().any(|j| j.bundle.name.0 == mimic.joint) {
+                diagnostics.push(WorkcellDiagnostic::MissingMimicJoint {
+                    joint: *id,
+                    mimic: mimic.joint.clone(),
+                });
+            }
+        }
+
+        for (id, visual) in &self.visuals {
+            if let Some(MaterialRef::Library(material)) = &visual.bundle.material {
+                if !self.materials.0.contains_key(material) {
+                    diagnostics.push(WorkcellDiagnostic::MissingMaterial {
+                        visual: *id,
+                        material: material.clone(),
+                    });
+                }
+            }
+        }
+
+        for (id, sensor) in &self.sensors {
+            if let Err(error) = sensor.bundle.properties.validate() {
+                diagnostics.push(WorkcellDiagnostic::InvalidSensor { sensor: *id, error });
+            }
+            if matches!(sensor.bundle.properties.kind, SensorKind::ForceTorque(_))
+                && self.sensor_joint(sensor.parent).is_none()
+            {
+                diagnostics.push(WorkcellDiagnostic::ForceTorqueSensorWithoutJoint(*id));
+            }
+        }
+
+        diagnostics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::frame;
+
+    #[test]
+    fn valid_urdf_has_no_diagnostics() {
+        let urdf = urdf_rs::read_file("test/07-physics.urdf").unwrap();
+        let workcell = Workcell::from_urdf(&urdf).unwrap();
+        assert!(workcell.validate().is_empty());
+    }
+
+    #[test]
+    fn structural_issues_are_reported() {
+        let mut workcell = Workcell::default();
+        workcell.frames.insert(1, frame(0, "base"));
+        workcell.frames.insert(2, frame(0, "base"));
+        // Cycle between frames 3 and 4
+        workcell.frames.insert(3, frame(4, "a"));
+        workcell.frames.insert(4, frame(3, "b"));
+        workcell.frames.insert(5, frame(42, "c"));
+        workcell.joints.insert(
+            6,
+            Parented {
+                parent: 1,
+                bundle: Joint {
+                    name: NameInWorkcell("joint".to_string()),
+                    properties: JointProperties::Fixed,
+                    actuation: Default::default(),
+                },
+            },
+        );
+        workcell.inertias.insert(
+            7,
+            Parented {
+                parent: 6,
+                bundle: Inertia::default(),
+            },
+        );
+        let diagnostics = workcell.validate();
+        assert!(diagnostics.contains(&WorkcellDiagnostic::DuplicateName {
+            name: "base".to_string(),
+            kind: WorkcellElementKind::Frame,
+            ids: vec![1, 2],
+        }));
+        assert!(diagnostics.contains(&WorkcellDiagnostic::ParentCycle(vec![3, 4])));
+        assert!(diagnostics.contains(&WorkcellDiagnostic::MissingParent {
+            id: 5,
+            kind: WorkcellElementKind::Frame,
+            parent: 42,
+        }));
+        assert!(
+            diagnostics.contains(&WorkcellDiagnostic::InvalidJointChildren {
+                joint: 6,
+                num_children: 0,
+            })
+        );
+        assert!(
+            diagnostics.contains(&WorkcellDiagnostic::InvalidParentKind {
+                id: 7,
+                kind: WorkcellElementKind::Inertia,
+                parent: 6,
+                parent_kind: WorkcellElementKind::Joint,
+            })
+        );
+        assert_eq!(diagnostics.len(), 5);
+    }
+}
@@ -0,0 +1,297 @@
+/*
+ * Copyright (C) 2024 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::{CURRENT_MAJOR_VERSION, CURRENT_MINOR_VERSION};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error as ThisError;
+
+/// Version of the workcell file format, serialized as a "major.minor" string.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub struct FormatVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl FormatVersion {
+    pub const CURRENT: FormatVersion = FormatVersion {
+        major: CURRENT_MAJOR_VERSION,
+        minor: CURRENT_MINOR_VERSION,
+    };
+
+    /// Version of files that were saved before the format version was recorded.
+    pub const UNVERSIONED: FormatVersion = FormatVersion { major: 0, minor: 1 };
+
+    pub const fn new(major: u32, minor: u32) -> Self {
+        Self { major, minor }
+    }
+}
+
+impl Default for FormatVersion {
+    fn default() -> Self {
+        Self::CURRENT
+    }
+}
+
+impl std::fmt::Display for FormatVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+impl std::str::FromStr for FormatVersion {
+    type Err = WorkcellLoadError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || WorkcellLoadError::InvalidVersion(s.to_owned());
+        let (major, minor) = s.split_once('.').ok_or_else(invalid)?;
+        Ok(Self {
+            major: major.trim().parse().map_err(|_| invalid())?,
+            minor: minor.trim().parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl TryFrom<String> for FormatVersion {
+    type Error = WorkcellLoadError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<FormatVersion> for String {
+    fn from(version: FormatVersion) -> Self {
+        version.to_string()
+    }
+}
+
+#[derive(Debug, ThisError)]
+pub enum WorkcellLoadError {
+    #[error("Json error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("invalid format version [{0}]")]
+    InvalidVersion(String),
+    #[error(
+        "workcell format version {found} is newer than the latest supported version \
+         {supported}, please update to a newer release to open this file"
+    )]
+    UnsupportedVersion {
+        found: FormatVersion,
+        supported: FormatVersion,
+    },
+    #[error("no migration available from format version {0}")]
+    MissingMigration(FormatVersion),
+    #[error("failed migrating from format version {from}: {reason}")]
+    MigrationFailed { from: FormatVersion, reason: String },
+}
+
+/// A single step to upgrade a serialized workcell from one format version to the next one.
+pub struct Migration {
+    pub from: FormatVersion,
+    pub to: FormatVersion,
+    /// Modifies the serialized workcell in place, the `format_version` field is updated
+    /// automatically after the migration succeeds.
+    pub apply: fn(&mut serde_json::Map<String, Value>) -> Result<(), String>,
+}
+
+/// Chain of migrations, each entry must start from the version the previous one upgrades to.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: FormatVersion::new(0, 1),
+        to: FormatVersion::new(0, 2),
+        // 0.2 only introduced the format_version field
+        apply: |_| Ok(()),
+    },
+    Migration {
+        from: FormatVersion::new(0, 2),
+        to: FormatVersion::new(0, 3),
+        // 0.3 introduced the material library and the materials of visuals, older files have
+        // none
+        apply: |_| Ok(()),
+    },
+    Migration {
+        from: FormatVersion::new(0, 3),
+        to: FormatVersion::new(0, 4),
+        // 0.4 introduced workcells included from other files, older files have none
+        apply: |_| Ok(()),
+    },
+    Migration {
+        from: FormatVersion::new(0, 4),
+        to: FormatVersion::new(0, 5),
+        // 0.5 introduced the actuation of joints and the ros2_control hardware of workcells,
+        // older files have neither
+        apply: |_| Ok(()),
+    },
+    Migration {
+        from: FormatVersion::new(0, 5),
+        to: FormatVersion::new(0, 6),
+        // 0.6 introduced sensors, older files have none
+        apply: |_| Ok(()),
+    },
+    Migration {
+        from: FormatVersion::new(0, 6),
+        to: FormatVersion::new(0, 7),
+        // 0.7 introduced the planning semantics of workcells, older files have none
+        apply: |_| Ok(()),
+    },
+];
+
+/// Reads the format version of a serialized workcell, files without it are considered
+/// [`FormatVersion::UNVERSIONED`].
+pub fn format_version(value: &Value) -> Result<FormatVersion, WorkcellLoadError> {
+    match value.get("format_version") {
+        None => Ok(FormatVersion::UNVERSIONED),
+        Some(Value::String(s)) => s.parse(),
+        Some(other) => Err(WorkcellLoadError::InvalidVersion(other.to_string())),
+    }
+}
+
+/// Upgrades a serialized workcell to the current format version, returns the version the
+/// workcell was originally in.
+pub fn migrate(value: &mut Value) -> Result<FormatVersion, WorkcellLoadError> {
+    migrate_with(value, MIGRATIONS, FormatVersion::CURRENT)
+}
+
+fn migrate_with(
+    value: &mut Value,
+    migrations: &[Migration],
+    target: FormatVersion,
+) -> Result<FormatVersion, WorkcellLoadError> {
+    let original = format_version(value)?;
+    if original > target {
+        return Err(WorkcellLoadError::UnsupportedVersion {
+            found: original,
+            supported: target,
+        });
+    }
+    let Value::Object(map) = value else {
+        return Err(WorkcellLoadError::MigrationFailed {
+            from: original,
+            reason: "workcell is not a json object".to_owned(),
+        });
+    };
+    let mut version = original;
+    while version < target {
+        let migration = migrations
+            .iter()
+            .find(|m| m.from == version)
+            .ok_or(WorkcellLoadError::MissingMigration(version))?;
+        (migration.apply)(map).map_err(|reason| WorkcellLoadError::MigrationFailed {
+            from: version,
+            reason,
+        })?;
+        version = migration.to;
+        map.insert("format_version".to_owned(), Value::String(version.into()));
+    }
+    Ok(original)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Workcell;
+
+    #[test]
+    fn unversioned_files_are_upgraded() {
+        let workcell = Workcell::from_str(
+            r#"{"name": "test", "id": 0, "frames": {}, "visuals": {}, "collisions": {}, "inertias": {}, "joints": {}}"#,
+        )
+        .unwrap();
+        assert_eq!(workcell.format_version, FormatVersion::CURRENT);
+        let mut value = serde_json::json!({"name": "test", "id": 0});
+        assert_eq!(migrate(&mut value).unwrap(), FormatVersion::UNVERSIONED);
+        assert_eq!(format_version(&value).unwrap(), FormatVersion::CURRENT);
+    }
+
+    #[test]
+    fn newer_files_are_rejected() {
+        let newer = FormatVersion::new(CURRENT_MAJOR_VERSION + 1, 0);
+        let mut value = serde_json::json!({"format_version": newer});
+        assert!(matches!(
+            migrate(&mut value),
+            Err(WorkcellLoadError::UnsupportedVersion { .. })
+        ));
+    }
+
+    #[test]
+    fn migrations_are_chained() {
+        let migrations = [
+            Migration {
+                from: FormatVersion::new(1, 0),
+                to: FormatVersion::new(1, 1),
+                apply: |map| {
+                    let name = map.remove("old_name").ok_or("missing old_name")?;
+                    map.insert("name".to_owned(), name);
+                    Ok(())
+                },
+            },
+            Migration {
+                from: FormatVersion::new(1, 1),
+                to: FormatVersion::new(2, 0),
+                apply: |map| {
+                    map.insert("id".to_owned(), 0.into());
+                    Ok(())
+                },
+            },
+        ];
+        let mut value = serde_json::json!({"format_version": "1.0", "old_name": "test"});
+        migrate_with(&mut value, &migrations, FormatVersion::new(2, 0)).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({"format_version": "2.0", "name": "test", "id": 0})
+        );
+        let mut value = serde_json::json!({"format_version": "1.0"});
+        assert!(matches!(
+            migrate_with(&mut value, &migrations, FormatVersion::new(2, 0)),
+            Err(WorkcellLoadError::MigrationFailed { .. })
+        ));
+    }
+
+    #[test]
+    fn every_version_is_upgraded() {
+        let first = MIGRATIONS.first().unwrap();
+        assert_eq!(first.from, FormatVersion::UNVERSIONED);
+        assert_eq!(MIGRATIONS.last().unwrap().to, FormatVersion::CURRENT);
+        for pair in MIGRATIONS.windows(2) {
+            assert_eq!(pair[0].to, pair[1].from);
+        }
+        // Every field added since the first version is optional, so an empty workcell of any
+        // version loads as an empty workcell of the current version
+        let empty = |version: FormatVersion| {
+            Workcell::from_value(serde_json::json!({
+                "format_version": version,
+                "name": "test",
+                "id": 0,
+                "frames": {},
+                "visuals": {},
+                "collisions": {},
+                "inertias": {},
+                "joints": {},
+            }))
+            .unwrap()
+            .to_string()
+            .unwrap()
+        };
+        let current = empty(FormatVersion::CURRENT);
+        for migration in MIGRATIONS {
+            assert_eq!(empty(migration.from), current, "from {}", migration.from);
+        }
+    }
+}
@@ -15,18 +15,21 @@
  *
 */
 
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 use std::io;
 
+use crate::xml::{write_to_string, ElementExt};
 use crate::*;
 #[cfg(feature = "bevy")]
-use bevy::prelude::{Bundle, Component, Deref, DerefMut};
+use bevy::prelude::{Bundle, Component, Deref, DerefMut, Resource};
 #[cfg(feature = "bevy")]
 use bevy::reflect::{TypePath, TypeUuid};
-use rmf_site_format::{misc::Rotation, Anchor, Pose, RefTrait};
+use glam::Affine3A;
+use rmf_site_format::{Anchor, Pose, RefTrait};
 use serde::{Deserialize, Serialize};
 use thiserror::Error as ThisError;
+use xmltree::Element;
 
 /// Helper structure to serialize / deserialize entities with parents
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -58,20 +61,27 @@ pub struct NameOfWorkcell(pub String);
 #[cfg_attr(feature = "bevy", derive(Bundle))]
 pub struct WorkcellProperties {
     pub name: NameOfWorkcell,
+    /// Hardware of the ros2_control system, used if any joint is actuated
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub control_hardware: ControlHardware,
+    /// Motion planning groups, end effectors and named states, exported as an srdf
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub planning: PlanningSemantics,
 }
 
 #[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
 #[cfg_attr(feature = "bevy", derive(Component, Deref, DerefMut))]
 pub struct NameInWorkcell(pub String);
 
-// TODO(luca) we might need a different bundle to denote a workcell included in site
-// editor mode to deal with serde of workcells there (that would only have an asset source?)
 /// Container for serialization / deserialization of workcells
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct Workcell {
     /// Workcell specific properties
     #[serde(flatten)]
     pub properties: WorkcellProperties,
+    /// Version of the file format, older files are migrated to the current version on load
+    #[serde(default)]
+    pub format_version: FormatVersion,
     /// Site ID, used for entities to set their parent to the root workcell
     pub id: u32,
     /// Frames, key is their id, used for hierarchy
@@ -85,15 +95,23 @@ pub struct Workcell {
     /// Joints, key is their id, used for hierarchy. They must have a frame as a parent and a frame
     /// as a child
     pub joints: BTreeMap<u32, Parented<u32, Joint>>,
+    /// Sensors, key is their id, used for hierarchy. They must have a frame as a parent
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub sensors: BTreeMap<u32, Parented<u32, Sensor>>,
+    /// Workcells included from other files, key is their id. They must have a frame as a parent
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub includes: BTreeMap<u32, Parented<u32, IncludedWorkcell>>,
+    /// Named materials that visuals can refer to
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub materials: MaterialLibrary,
 }
 
 #[derive(Debug, ThisError)]
 pub enum UrdfImportError {
     #[error("a joint refers to a non existing link [{0}]")]
     BrokenJointReference(String),
-    // TODO(luca) Add urdf_rs::JointType to this error, it doesn't implement Display
-    #[error("unsupported joint type found")]
-    UnsupportedJointType,
+    #[error("joint [{joint}] has unsupported type [{joint_type}]")]
+    UnsupportedJointType { joint: String, joint_type: String },
 }
 
 #[derive(Debug, ThisError)]
@@ -104,6 +122,71 @@ pub enum WorkcellToUrdfError {
     WriteToStringError(#[from] urdf_rs::UrdfError),
     #[error("Broken reference: {0}")]
     BrokenReference(u32),
+    #[error("Invalid workcell structure: {0:?}")]
+    InvalidStructure(Vec<WorkcellDiagnostic>),
+    #[error("name [{0}] is used by more than one link")]
+    DuplicateLinkName(String),
+    #[error("name [{0}] is used by more than one joint")]
+    DuplicateJointName(String),
+    #[error("the workcell includes other workcells, they must be flattened before exporting")]
+    UnflattenedIncludes,
+    #[error("Xml error: {0}")]
+    XmlError(#[from] xmltree::Error),
+    #[error("Xml parse error: {0}")]
+    XmlParseError(#[from] xmltree::ParseError),
+}
+
+/// A value that can't be represented in urdf and was replaced when exporting.
+#[derive(Debug, Clone, PartialEq, ThisError)]
+pub enum UrdfExportWarning {
+    #[error("joint [{joint}]: {warning}")]
+    JointLimit {
+        joint: String,
+        warning: JointLimitWarning,
+    },
+}
+
+/// Options for the urdf export of a workcell.
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "bevy", derive(Resource))]
+pub struct UrdfExportOptions {
+    /// Also export the frames that are merged into the link of their parent as empty links,
+    /// attached to their parent frame through a fixed joint.
+    pub keep_merged_frames: bool,
+    /// Name of the root link. If set, a link with this name is always added at the origin of
+    /// the workcell and the frames that are children of the workcell are merged into it.
+    /// Otherwise the only child frame of the workcell is used as the root link, or a
+    /// `<workcell_name>_workcell_link` is added if the workcell has more than one child frame.
+    pub root_link: Option<String>,
+    /// Attaches the root link to a world link through a fixed joint.
+    pub world: Option<WorldAttachment>,
+}
+
+/// Describes how the root link of an exported workcell is attached to the world.
+#[derive(Debug, Clone)]
+pub struct WorldAttachment {
+    /// Name of the world link
+    pub link: String,
+    /// Pose of the root link of the workcell relative to the world link
+    pub pose: Pose,
+}
+
+impl Default for WorldAttachment {
+    fn default() -> Self {
+        Self {
+            link: "world".to_owned(),
+            pose: Pose::default(),
+        }
+    }
+}
+
+/// Expresses an origin relative to a frame in the link the frame was merged into.
+fn origin_in_link(tf: &Affine3A, origin: urdf_rs::Pose) -> urdf_rs::Pose {
+    if *tf == Affine3A::IDENTITY {
+        return origin;
+    }
+    let pose = Pose::from(&origin);
+    pose_from_affine(&(*tf * affine_from_pose(&pose))).into()
 }
 
 impl Workcell {
@@ -116,6 +199,32 @@ impl Workcell {
         let mut collisions = BTreeMap::new();
         let mut inertias = BTreeMap::new();
         let mut joints = BTreeMap::new();
+        // Materials can be defined at the robot level or in any visual and referred to by name
+        // anywhere else. Robot materials and the visual ones that are referred to elsewhere go
+        // in the library, the others stay inline in their visual
+        let mut materials = MaterialLibrary::default();
+        let visual_materials: Vec<_> = urdf
+            .links
+            .iter()
+            .flat_map(|link| link.visual.iter().filter_map(|v| v.material.as_ref()))
+            .collect();
+        let referred: HashSet<_> = visual_materials
+            .iter()
+            .filter(|m| m.color.is_none() && m.texture.is_none())
+            .map(|m| &m.name)
+            .collect();
+        let shared_visual_materials = visual_materials
+            .iter()
+            .copied()
+            .filter(|m| referred.contains(&m.name));
+        for material in urdf.materials.iter().chain(shared_visual_materials) {
+            if material.name.is_empty() || materials.0.contains_key(&material.name) {
+                continue;
+            }
+            if let Some(m) = VisualMaterial::from_urdf(material) {
+                materials.0.insert(material.name.clone(), m);
+            }
+        }
         // Populate here
         for link in &urdf.links {
             let inertia = Inertia::from(&link.inertial);
@@ -143,7 +252,19 @@ impl Workcell {
                 },
             );
             for visual in &link.visual {
-                let model = WorkcellModel::from(visual);
+                let mut model = WorkcellModel::from(visual);
+                model.material = visual.material.as_ref().and_then(|material| {
+                    match (
+                        materials.0.get(&material.name),
+                        VisualMaterial::from_urdf(material),
+                    ) {
+                        (Some(named), Some(inline)) if *named != inline => {
+                            Some(MaterialRef::Inline(inline))
+                        }
+                        (Some(_), _) => Some(MaterialRef::Library(material.name.clone())),
+                        (None, inline) => inline.map(MaterialRef::Inline),
+                    }
+                });
                 let visual_id = cur_id.next().unwrap();
                 visuals.insert(
                     visual_id,
@@ -172,22 +293,36 @@ impl Workcell {
             let child = frame_name_to_id.get(&joint.child.link).ok_or(
                 UrdfImportError::BrokenJointReference(joint.child.link.clone()),
             )?;
+            let single_dof = || SingleDofJoint {
+                axis: (&joint.axis).into(),
+                limits: (&joint.limit).into(),
+                dynamics: joint
+                    .dynamics
+                    .as_ref()
+                    .map(JointDynamics::from)
+                    .unwrap_or_default(),
+                mimic: joint.mimic.as_ref().map(JointMimic::from),
+                safety_controller: joint.safety_controller.as_ref().map(SafetyController::from),
+            };
             let properties = match joint.joint_type {
-                urdf_rs::JointType::Revolute => JointProperties::Revolute(SingleDofJoint {
-                    axis: (&joint.axis).into(),
-                    limits: (&joint.limit).into(),
-                }),
-                urdf_rs::JointType::Prismatic => JointProperties::Prismatic(SingleDofJoint {
+                urdf_rs::JointType::Revolute => JointProperties::Revolute(single_dof()),
+                urdf_rs::JointType::Prismatic => JointProperties::Prismatic(single_dof()),
+                urdf_rs::JointType::Fixed => JointProperties::Fixed,
+                urdf_rs::JointType::Continuous => JointProperties::Continuous(single_dof()),
+                urdf_rs::JointType::Planar => JointProperties::Planar(PlanarJoint {
                     axis: (&joint.axis).into(),
                     limits: (&joint.limit).into(),
                 }),
-                urdf_rs::JointType::Fixed => JointProperties::Fixed,
-                urdf_rs::JointType::Continuous => JointProperties::Continuous(SingleDofJoint {
-                    axis: (&joint.axis).into(),
+                urdf_rs::JointType::Floating => JointProperties::Floating(FloatingJoint {
                     limits: (&joint.limit).into(),
                 }),
                 _ => {
-                    return Err(UrdfImportError::UnsupportedJointType);
+                    // urdf_rs::JointType doesn't implement Display, its Debug matches the
+                    // urdf type name except for the case
+                    return Err(UrdfImportError::UnsupportedJointType {
+                        joint: joint.name.clone(),
+                        joint_type: format!("{:?}", joint.joint_type).to_lowercase(),
+                    });
                 }
             };
             let joint_id = cur_id.next().unwrap();
@@ -206,6 +341,7 @@ impl Workcell {
                     bundle: Joint {
                         name: NameInWorkcell(joint.name.clone()),
                         properties,
+                        actuation: Default::default(),
                     },
                 },
             );
@@ -214,13 +350,18 @@ impl Workcell {
         Ok(Workcell {
             properties: WorkcellProperties {
                 name: NameOfWorkcell(urdf.name.clone()),
+                ..Default::default()
             },
+            format_version: Default::default(),
             id: root_id,
             frames,
             visuals,
             collisions,
             inertias,
             joints,
+            sensors: Default::default(),
+            includes: Default::default(),
+            materials,
         })
     }
     pub fn to_writer<W: io::Write>(&self, writer: W) -> serde_json::Result<()> {
@@ -232,15 +373,146 @@ impl Workcell {
     }
 
     pub fn to_urdf(&self) -> Result<urdf_rs::Robot, WorkcellToUrdfError> {
+        self.to_urdf_with_options(&UrdfExportOptions::default())
+    }
+
+    /// Returns the frame that will be exported as the root urdf link and the name of the link.
+    /// The workcell id is returned when a link is synthesized to hold the workcell children.
+    fn urdf_root(&self, options: &UrdfExportOptions) -> (u32, String) {
+        if let Some(name) = &options.root_link {
+            return (self.id, name.clone());
+        }
+        // If the workcell has a single frame child we can use the child as the base link.
+        // Otherwise, we will need to spawn a new base link to contain all the workcell children
+        let mut workcell_child_frames = self
+            .frames
+            .iter()
+            .filter(|(_, frame)| frame.parent == self.id);
+        match (workcell_child_frames.next(), workcell_child_frames.next()) {
+            // Flatten the hierarchy by making the only child the new workcell base link
+            (Some((id, frame)), None) => (*id, frame.bundle.name.0.clone()),
+            // As per Industrial Workcell Coordinate Conventions, the name of the workcell
+            // datum link shall be "<workcell_name>_workcell_link".
+            _ => (self.id, self.properties.name.0.clone() + "_workcell_link"),
+        }
+    }
+
+    /// Name of the link at the root of the urdf tree exported with the given options, i.e. the
+    /// frame the whole workcell is relative to.
+    pub fn urdf_root_link(&self, options: &UrdfExportOptions) -> String {
+        match &options.world {
+            Some(world) => world.link.clone(),
+            None => self.urdf_root(options).1,
+        }
+    }
+
+    /// Name of the urdf link of a frame when exported with the given options. Frames that are
+    /// not children of a joint are merged into the link of their parent, unless
+    /// [`UrdfExportOptions::keep_merged_frames`] is set.
+    pub fn urdf_link_name(
+        &self,
+        frame: u32,
+        options: &UrdfExportOptions,
+    ) -> Result<String, WorkcellToUrdfError> {
+        let (root, root_name) = self.urdf_root(options);
+        let link = if options.keep_merged_frames {
+            frame
+        } else {
+            self.urdf_link_of(frame, root)?.0
+        };
+        if link == root {
+            return Ok(root_name);
+        }
+        self.frames
+            .get(&link)
+            .map(|f| f.bundle.name.0.clone())
+            .ok_or(WorkcellToUrdfError::BrokenReference(frame))
+    }
+
+    /// Returns the frame that will be exported as the urdf link the requested frame is merged
+    /// into, together with the transform of the frame relative to the link.
+    fn urdf_link_of(
+        &self,
+        mut frame_id: u32,
+        root: u32,
+    ) -> Result<(u32, Affine3A), WorkcellToUrdfError> {
+        let mut tf = Affine3A::IDENTITY;
+        while frame_id != root {
+            let Some(frame) = self.frames.get(&frame_id) else {
+                break;
+            };
+            if self.joints.contains_key(&frame.parent) {
+                break;
+            }
+            let Anchor::Pose3D(pose) = &frame.bundle.anchor else {
+                return Err(WorkcellToUrdfError::InvalidAnchorType(
+                    frame.bundle.anchor.clone(),
+                ));
+            };
+            tf = affine_from_pose(pose) * tf;
+            frame_id = frame.parent;
+        }
+        Ok((frame_id, tf))
+    }
+
+    /// Exports the workcell as a urdf robot. Frames that are not connected to their parent
+    /// through a joint are merged into the same link, since urdf links can only be connected
+    /// through joints.
+    pub fn to_urdf_with_options(
+        &self,
+        options: &UrdfExportOptions,
+    ) -> Result<urdf_rs::Robot, WorkcellToUrdfError> {
+        self.to_urdf_with_warnings(options).map(|(robot, _)| robot)
+    }
+
+    /// Same as [`Workcell::to_urdf_with_options`], also returning the values that couldn't be
+    /// represented in urdf and were replaced.
+    pub fn to_urdf_with_warnings(
+        &self,
+        options: &UrdfExportOptions,
+    ) -> Result<(urdf_rs::Robot, Vec<UrdfExportWarning>), WorkcellToUrdfError> {
+        let diagnostics = self.validate();
+        if !diagnostics.is_empty() {
+            return Err(WorkcellToUrdfError::InvalidStructure(diagnostics));
+        }
+        if !self.includes.is_empty() {
+            return Err(WorkcellToUrdfError::UnflattenedIncludes);
+        }
+        let materials: Vec<_> = self
+            .materials
+            .0
+            .iter()
+            .map(|(name, material)| material.to_urdf(name.clone()))
+            .collect();
+        let mut material_names: HashSet<_> = self.materials.0.keys().cloned().collect();
         let mut parent_to_visuals = HashMap::new();
         for (_, visual) in self.visuals.iter() {
             let parent = visual.parent;
             let visual = &visual.bundle;
+            let material = match &visual.material {
+                // Only the name is needed, the definition is in the robot materials
+                Some(MaterialRef::Library(name)) => Some(urdf_rs::Material {
+                    name: name.clone(),
+                    ..Default::default()
+                }),
+                Some(MaterialRef::Inline(material)) => {
+                    // Urdf materials must be named and the name must not clash with other ones
+                    let base = visual.name.clone() + "_material";
+                    let mut name = base.clone();
+                    let mut idx = 1;
+                    while !material_names.insert(name.clone()) {
+                        name = format!("{}_{}", base, idx);
+                        idx += 1;
+                    }
+                    Some(material.to_urdf(name))
+                }
+                None => None,
+            };
             let visual = urdf_rs::Visual {
                 name: Some(visual.name.clone()),
                 origin: visual.pose.into(),
                 geometry: visual.geometry.clone().into(),
-                material: None,
+                material,
             };
             parent_to_visuals
                 .entry(parent)
@@ -263,143 +535,300 @@ impl Workcell {
                 .push(collision);
         }
 
-        // If the workcell has a single frame child we can use the child as the base link.
-        // Otherwise, we will need to spawn a new base link to contain all the workcell children
-        let workcell_child_frames = self
-            .frames
-            .iter()
-            .filter(|(_, frame)| frame.parent == self.id);
-        let num_children = workcell_child_frames.clone().count();
-        let frames = if num_children != 1 {
-            // TODO(luca) remove hardcoding of base link name, it might in some cases create
-            // duplicates
-            let mut frames = self.frames.clone();
-            let dummy_frame = Frame {
-                anchor: Anchor::Pose3D(Pose {
-                    rot: Rotation::Quat([0.0, 0.0, 0.0, 0.0]),
-                    trans: [0.0, 0.0, 0.0],
-                }),
-                // As per Industrial Workcell Coordinate Conventions, the name of the workcell
-                // datum link shall be "<workcell_name>_workcell_link".
-                name: NameInWorkcell(self.properties.name.0.clone() + "_workcell_link"),
-                marker: FrameMarker,
-            };
-            frames.insert(
-                self.id,
-                Parented {
-                    // Root has no parent, use placeholder of max u32
-                    parent: u32::MAX,
-                    bundle: dummy_frame,
-                },
-            );
-            frames
-        } else {
-            // Flatten the hierarchy by making the only child the new workcell base link
-            self.frames.clone()
+        let (root, root_name) = self.urdf_root(options);
+        let link_name = |id: u32| {
+            if id == root {
+                root_name.clone()
+            } else {
+                self.frames
+                    .get(&id)
+                    .map(|f| f.bundle.name.0.clone())
+                    .unwrap_or_default()
+            }
         };
 
-        let mut parent_to_inertials = HashMap::new();
-        for (_, inertia) in self.inertias.iter() {
-            let parent = inertia.parent;
-            let inertia = &inertia.bundle;
-            let inertial = urdf_rs::Inertial::from(inertia);
-            parent_to_inertials.insert(parent, inertial);
-        }
+        // Frames that are not connected to their parent through a joint are merged into the
+        // link of their parent, everything attached to them is moved to the link origin
+        let mut links: BTreeMap<u32, urdf_rs::Link> = std::iter::once(root)
+            .chain(
+                self.frames
+                    .iter()
+                    .filter(|(_, frame)| self.joints.contains_key(&frame.parent))
+                    .map(|(id, _)| *id),
+            )
+            .map(|id| {
+                let link = urdf_rs::Link {
+                    name: link_name(id),
+                    ..Default::default()
+                };
+                (id, link)
+            })
+            .collect();
 
-        // TODO(luca) combine multiple frames without a joint inbetween into a single link.
-        // For now as soon as a joint is missing the hierarchy will be broken
-        let links = frames
-            .iter()
-            .map(|(frame_id, parented_frame)| {
-                let name = parented_frame.bundle.name.0.clone();
-                let inertial = parent_to_inertials.remove(frame_id).unwrap_or_default();
-                let collision = parent_to_collisions.remove(frame_id).unwrap_or_default();
-                let visual = parent_to_visuals.remove(frame_id).unwrap_or_default();
+        for (parent, visuals) in parent_to_visuals {
+            let (link_id, tf) = self.urdf_link_of(parent, root)?;
+            if let Some(link) = links.get_mut(&link_id) {
+                link.visual.extend(visuals.into_iter().map(|mut visual| {
+                    visual.origin = origin_in_link(&tf, visual.origin);
+                    visual
+                }));
+            }
+        }
+        for (parent, collisions) in parent_to_collisions {
+            let (link_id, tf) = self.urdf_link_of(parent, root)?;
+            if let Some(link) = links.get_mut(&link_id) {
+                link.collision
+                    .extend(collisions.into_iter().map(|mut collision| {
+                        collision.origin = origin_in_link(&tf, collision.origin);
+                        collision
+                    }));
+            }
+        }
 
-                urdf_rs::Link {
-                    name,
-                    inertial,
-                    collision,
-                    visual,
-                }
-            })
-            .collect::<Vec<_>>();
+        let mut link_inertias: BTreeMap<u32, Vec<Inertia>> = BTreeMap::new();
+        for inertia in self.inertias.values() {
+            let (link_id, tf) = self.urdf_link_of(inertia.parent, root)?;
+            let inertia = if tf == Affine3A::IDENTITY {
+                inertia.bundle.clone()
+            } else {
+                inertia.bundle.transformed(&tf)
+            };
+            link_inertias.entry(link_id).or_default().push(inertia);
+        }
+        for (link_id, inertias) in link_inertias {
+            if let (Some(link), Some(inertia)) = (links.get_mut(&link_id), Inertia::merge(inertias))
+            {
+                link.inertial = urdf_rs::Inertial::from(&inertia);
+            }
+        }
 
-        let joints = self
+        let mut warnings = Vec::new();
+        let mut joints = self
             .joints
             .iter()
             .map(|(joint_id, parented_joint)| {
                 let joint_parent = parented_joint.parent;
                 let joint = &parented_joint.bundle;
                 // The pose of the joint is the pose of the frame that has it as its parent
-                let parent_frame = self
-                    .frames
-                    .get(&joint_parent)
-                    .ok_or(WorkcellToUrdfError::BrokenReference(joint_parent))?;
+                if !self.frames.contains_key(&joint_parent) {
+                    return Err(WorkcellToUrdfError::BrokenReference(joint_parent));
+                }
                 let child_frame = self
                     .frames
                     .values()
                     .find(|frame| frame.parent == *joint_id)
                     .ok_or(WorkcellToUrdfError::BrokenReference(*joint_id))?;
-                let parent_name = parent_frame.bundle.name.clone();
                 let child_name = child_frame.bundle.name.clone();
                 let Anchor::Pose3D(pose) = child_frame.bundle.anchor else {
                     return Err(WorkcellToUrdfError::InvalidAnchorType(
                         child_frame.bundle.anchor.clone(),
                     ));
                 };
-                let (joint_type, axis, limit) = match &joint.properties {
-                    JointProperties::Fixed => (
-                        urdf_rs::JointType::Fixed,
-                        urdf_rs::Axis::default(),
-                        urdf_rs::JointLimit::default(),
-                    ),
-                    JointProperties::Revolute(joint) => (
-                        urdf_rs::JointType::Revolute,
-                        (&joint.axis).into(),
-                        (&joint.limits).into(),
-                    ),
-                    JointProperties::Prismatic(joint) => (
-                        urdf_rs::JointType::Prismatic,
-                        (&joint.axis).into(),
-                        (&joint.limits).into(),
-                    ),
-                    JointProperties::Continuous(joint) => (
-                        urdf_rs::JointType::Continuous,
-                        (&joint.axis).into(),
-                        (&joint.limits).into(),
-                    ),
+                // The parent frame might have been merged into another link
+                let (parent_link, tf) = self.urdf_link_of(joint_parent, root)?;
+                let single_dof = joint.properties.single_dof();
+                let (limit, limit_warnings) = joint.properties.to_urdf_limit();
+                warnings.extend(limit_warnings.into_iter().map(|warning| {
+                    UrdfExportWarning::JointLimit {
+                        joint: joint.name.0.clone(),
+                        warning,
+                    }
+                }));
+                let (joint_type, axis) = match &joint.properties {
+                    JointProperties::Fixed => (urdf_rs::JointType::Fixed, urdf_rs::Axis::default()),
+                    JointProperties::Revolute(joint) => {
+                        (urdf_rs::JointType::Revolute, (&joint.axis).into())
+                    }
+                    JointProperties::Prismatic(joint) => {
+                        (urdf_rs::JointType::Prismatic, (&joint.axis).into())
+                    }
+                    JointProperties::Continuous(joint) => {
+                        (urdf_rs::JointType::Continuous, (&joint.axis).into())
+                    }
+                    JointProperties::Planar(joint) => {
+                        (urdf_rs::JointType::Planar, (&joint.axis).into())
+                    }
+                    JointProperties::Floating(_) => {
+                        (urdf_rs::JointType::Floating, urdf_rs::Axis::default())
+                    }
                 };
                 Ok(urdf_rs::Joint {
                     name: joint.name.0.clone(),
                     joint_type,
-                    origin: pose.into(),
+                    origin: origin_in_link(&tf, pose.into()),
                     parent: urdf_rs::LinkName {
-                        link: parent_name.0,
+                        link: link_name(parent_link),
                     },
                     child: urdf_rs::LinkName { link: child_name.0 },
                     axis,
                     limit,
-                    dynamics: None,
-                    mimic: None,
-                    safety_controller: None,
+                    dynamics: single_dof
+                        .map(|j| &j.dynamics)
+                        .filter(|d| !is_default(*d))
+                        .map(urdf_rs::Dynamics::from),
+                    mimic: single_dof
+                        .and_then(|j| j.mimic.as_ref())
+                        .map(urdf_rs::Mimic::from),
+                    safety_controller: single_dof
+                        .and_then(|j| j.safety_controller.as_ref())
+                        .map(urdf_rs::SafetyController::from),
                 })
             })
             .collect::<Result<Vec<_>, WorkcellToUrdfError>>()?;
 
-        // TODO(luca) implement materials
+        let mut links: Vec<_> = links.into_values().collect();
+        if options.keep_merged_frames {
+            // Merged frames are exported as empty links rigidly attached to their parent, this
+            // keeps their name and pose available to tools that look up frames by link name
+            let mut joint_names: HashSet<_> = joints.iter().map(|j| j.name.clone()).collect();
+            for (id, frame) in &self.frames {
+                if *id == root || self.joints.contains_key(&frame.parent) {
+                    continue;
+                }
+                let Anchor::Pose3D(pose) = frame.bundle.anchor else {
+                    return Err(WorkcellToUrdfError::InvalidAnchorType(
+                        frame.bundle.anchor.clone(),
+                    ));
+                };
+                let base = frame.bundle.name.0.clone() + "_joint";
+                let mut name = base.clone();
+                let mut idx = 1;
+                while !joint_names.insert(name.clone()) {
+                    name = format!("{}_{}", base, idx);
+                    idx += 1;
+                }
+                links.push(urdf_rs::Link {
+                    name: frame.bundle.name.0.clone(),
+                    ..Default::default()
+                });
+                joints.push(urdf_rs::Joint {
+                    name,
+                    joint_type: urdf_rs::JointType::Fixed,
+                    origin: pose.into(),
+                    parent: urdf_rs::LinkName {
+                        link: link_name(frame.parent),
+                    },
+                    child: urdf_rs::LinkName {
+                        link: frame.bundle.name.0.clone(),
+                    },
+                    axis: Default::default(),
+                    limit: Default::default(),
+                    dynamics: None,
+                    mimic: None,
+                    safety_controller: None,
+                });
+            }
+        }
+
+        if let Some(world) = &options.world {
+            joints.push(urdf_rs::Joint {
+                name: format!("{}_to_{}", world.link, root_name),
+                joint_type: urdf_rs::JointType::Fixed,
+                origin: world.pose.into(),
+                parent: urdf_rs::LinkName {
+                    link: world.link.clone(),
+                },
+                child: urdf_rs::LinkName {
+                    link: root_name.clone(),
+                },
+                axis: Default::default(),
+                limit: Default::default(),
+                dynamics: None,
+                mimic: None,
+                safety_controller: None,
+            });
+            links.insert(
+                0,
+                urdf_rs::Link {
+                    name: world.link.clone(),
+                    ..Default::default()
+                },
+            );
+        }
+
+        // Generated names could clash with the ones chosen by users
+        let mut link_names = HashSet::new();
+        if let Some(link) = links.iter().find(|l| !link_names.insert(l.name.as_str())) {
+            return Err(WorkcellToUrdfError::DuplicateLinkName(link.name.clone()));
+        }
+        let mut joint_names = HashSet::new();
+        if let Some(joint) = joints.iter().find(|j| !joint_names.insert(j.name.as_str())) {
+            return Err(WorkcellToUrdfError::DuplicateJointName(joint.name.clone()));
+        }
+
         let robot = urdf_rs::Robot {
             name: self.properties.name.0.clone(),
             links,
             joints,
-            materials: vec![],
+            materials,
         };
-        Ok(robot)
+        Ok((robot, warnings))
+    }
+
+    /// Returns the `<gazebo>` extensions adding the sensors of the workcell to a urdf exported
+    /// with the same options. Force torque sensors refer to the joint they measure, the other
+    /// sensors to the link their frame is merged into.
+    pub fn to_urdf_gazebo_sensors(
+        &self,
+        options: &UrdfExportOptions,
+    ) -> Result<Vec<Element>, WorkcellToUrdfError> {
+        let (root, root_name) = self.urdf_root(options);
+        self.sensors
+            .values()
+            .map(|sensor| {
+                let (link, tf) = self.urdf_link_of(sensor.parent, root)?;
+                let pose = if tf == Affine3A::IDENTITY {
+                    sensor.bundle.pose
+                } else {
+                    pose_from_affine(&(tf * affine_from_pose(&sensor.bundle.pose)))
+                };
+                let reference = match &sensor.bundle.properties.kind {
+                    SensorKind::ForceTorque(_) => self
+                        .sensor_joint(sensor.parent)
+                        .and_then(|joint| self.joints.get(&joint))
+                        .map(|joint| joint.bundle.name.0.clone())
+                        .ok_or(WorkcellToUrdfError::BrokenReference(sensor.parent))?,
+                    _ if link == root => root_name.clone(),
+                    _ => self
+                        .frames
+                        .get(&link)
+                        .map(|f| f.bundle.name.0.clone())
+                        .unwrap_or_default(),
+                };
+                Ok(Element::new("gazebo")
+                    .with_attr("reference", reference)
+                    .with_child(sensor.bundle.to_sdf(&pose, None)))
+            })
+            .collect()
+    }
+
+    /// Same as [`Workcell::to_urdf_with_warnings`], writing the robot as a string that also
+    /// contains the `<ros2_control>` element and the Gazebo extensions of the sensors, which
+    /// can't be represented in [`urdf_rs::Robot`].
+    pub fn to_urdf_string_with_warnings(
+        &self,
+        options: &UrdfExportOptions,
+    ) -> Result<(String, Vec<UrdfExportWarning>), WorkcellToUrdfError> {
+        let (robot, warnings) = self.to_urdf_with_warnings(options)?;
+        let urdf = urdf_rs::write_to_string(&robot)?;
+        let extensions: Vec<_> = self
+            .to_ros2_control()
+            .into_iter()
+            .chain(self.to_urdf_gazebo_sensors(options)?)
+            .collect();
+        if extensions.is_empty() {
+            return Ok((urdf, warnings));
+        }
+        let mut urdf = Element::parse(urdf.as_bytes())?;
+        for extension in extensions {
+            urdf.push(extension);
+        }
+        Ok((write_to_string(&urdf)?, warnings))
     }
 
     pub fn to_urdf_string(&self) -> Result<String, WorkcellToUrdfError> {
-        let urdf = self.to_urdf()?;
-        urdf_rs::write_to_string(&urdf).map_err(WorkcellToUrdfError::WriteToStringError)
+        self.to_urdf_string_with_warnings(&UrdfExportOptions::default())
+            .map(|(urdf, _)| urdf)
     }
 
     pub fn to_urdf_writer(&self, mut writer: impl io::Write) -> Result<(), std::io::Error> {
@@ -409,16 +838,22 @@ impl Workcell {
         writer.write_all(urdf.as_bytes())
     }
 
-    pub fn from_reader<R: io::Read>(reader: R) -> serde_json::Result<Self> {
-        serde_json::de::from_reader(reader)
+    /// Deserializes a workcell, migrating it to the current format version if needed.
+    pub fn from_value(mut value: serde_json::Value) -> Result<Self, WorkcellLoadError> {
+        migrate(&mut value)?;
+        Ok(serde_json::from_value(value)?)
     }
 
-    pub fn from_str(s: &str) -> serde_json::Result<Self> {
-        serde_json::de::from_str(s)
+    pub fn from_reader<R: io::Read>(reader: R) -> Result<Self, WorkcellLoadError> {
+        Self::from_value(serde_json::de::from_reader(reader)?)
     }
 
-    pub fn from_bytes(s: &[u8]) -> serde_json::Result<Self> {
-        serde_json::from_slice(s)
+    pub fn from_str(s: &str) -> Result<Self, WorkcellLoadError> {
+        Self::from_value(serde_json::de::from_str(s)?)
+    }
+
+    pub fn from_bytes(s: &[u8]) -> Result<Self, WorkcellLoadError> {
+        Self::from_value(serde_json::from_slice(s)?)
     }
 }
 
@@ -433,29 +868,7 @@ pub struct UrdfRoot(pub urdf_rs::Robot);
 mod tests {
     use super::*;
     use float_eq::{assert_float_eq, float_eq};
-    use rmf_site_format::{Angle, PrimitiveShape};
-
-    fn frame_by_name(
-        frames: &BTreeMap<u32, Parented<u32, Frame>>,
-        name: &str,
-    ) -> Option<(u32, Parented<u32, Frame>)> {
-        frames
-            .iter()
-            .find(|(_, parented_frame)| {
-                parented_frame.bundle.name == NameInWorkcell(name.to_string())
-            })
-            .map(|(id, f)| (*id, f.clone()))
-    }
-
-    fn element_by_parent<T: Clone>(
-        models: &BTreeMap<u32, Parented<u32, T>>,
-        parent: u32,
-    ) -> Option<(u32, Parented<u32, T>)> {
-        models
-            .iter()
-            .find(|(_, parented_element)| parented_element.parent == parent)
-            .map(|(id, e)| (*id, e.clone()))
-    }
+    use rmf_site_format::{Angle, PrimitiveShape, Rotation};
 
     fn is_pose_eq(p1: &Pose, p2: &Pose) -> bool {
         if !p1
@@ -501,7 +914,9 @@ mod tests {
         assert_eq!(workcell.joints.len(), 15);
         assert_eq!(workcell.properties.name.0, "physics");
         // Test that we convert poses from joints to frames
-        let (right_leg_id, right_leg) = frame_by_name(&workcell.frames, "right_leg").unwrap();
+        let graph = workcell.graph();
+        let right_leg_id = graph.frame_by_name("right_leg").unwrap();
+        let right_leg = &workcell.frames[&right_leg_id];
         let target_right_leg_pose = Pose {
             trans: [0.0, -0.22, 0.25],
             rot: Default::default(),
@@ -511,7 +926,8 @@ mod tests {
             .anchor
             .is_close(&Anchor::Pose3D(target_right_leg_pose), 1e-6));
         // Test that we can parse parenthood and properties of visuals and collisions correctly
-        let (_, right_leg_visual) = element_by_parent(&workcell.visuals, right_leg_id).unwrap();
+        let right_leg_visual = &workcell.visuals
+            [&graph.children_of_kind(right_leg_id, WorkcellElementKind::Visual)[0]];
         let target_right_leg_model_pose = Pose {
             trans: [0.0, 0.0, -0.3],
             rot: Rotation::EulerExtrinsicXYZ([
@@ -528,8 +944,8 @@ mod tests {
             right_leg_visual.bundle.geometry,
             Geometry::Primitive(PrimitiveShape::Box { .. })
         ));
-        let (_, right_leg_collision) =
-            element_by_parent(&workcell.collisions, right_leg_id).unwrap();
+        let right_leg_collision = &workcell.collisions
+            [&graph.children_of_kind(right_leg_id, WorkcellElementKind::Collision)[0]];
         assert!(is_pose_eq(
             &right_leg_collision.bundle.pose,
             &target_right_leg_model_pose
@@ -539,7 +955,8 @@ mod tests {
             Geometry::Primitive(PrimitiveShape::Box { .. })
         ));
         // Test inertia parenthood and parsing
-        let (_, right_leg_inertia) = element_by_parent(&workcell.inertias, right_leg_id).unwrap();
+        let right_leg_inertia = &workcell.inertias
+            [&graph.children_of_kind(right_leg_id, WorkcellElementKind::Inertia)[0]];
         assert_float_eq!(right_leg_inertia.bundle.mass.0, 10.0, abs <= 1e6);
         let target_right_leg_inertia = Inertia {
             center: Pose::default(),
@@ -558,7 +975,8 @@ mod tests {
             &target_right_leg_inertia
         ));
         // Test joint parenthood and parsing
-        let (_, right_leg_joint) = element_by_parent(&workcell.joints, right_leg_id).unwrap();
+        let right_leg_joint =
+            &workcell.joints[&graph.children_of_kind(right_leg_id, WorkcellElementKind::Joint)[0]];
         assert!(matches!(
             right_leg_joint.bundle.properties,
             JointProperties::Fixed
@@ -617,4 +1035,419 @@ mod tests {
             urdf_rs::JointType::Fixed
         ));
     }
+
+    #[test]
+    fn urdf_materials_roundtrip() {
+        let urdf = urdf_rs::read_file("test/07-physics.urdf").unwrap();
+        let mut workcell = Workcell::from_urdf(&urdf).unwrap();
+        assert_eq!(workcell.materials.0.len(), 3);
+        assert_eq!(workcell.materials.0["blue"].color, [0.0, 0.0, 0.8, 1.0]);
+        let graph = workcell.graph();
+        let base_link_id = graph.frame_by_name("base_link").unwrap();
+        let base_visual_id = graph.children_of_kind(base_link_id, WorkcellElementKind::Visual)[0];
+        let base_visual = workcell.visuals[&base_visual_id].clone();
+        assert_eq!(
+            base_visual.bundle.material,
+            Some(MaterialRef::Library("blue".to_string()))
+        );
+        assert_eq!(
+            workcell
+                .materials
+                .resolve(&base_visual.bundle.material.unwrap()),
+            Some(&workcell.materials.0["blue"])
+        );
+
+        // Inline materials are given a unique name when exported
+        let red = VisualMaterial {
+            color: [1.0, 0.0, 0.0, 0.5],
+            texture: Some(AssetSource::Package("physics/textures/red.png".to_string())),
+        };
+        let base_visual = workcell.visuals.get_mut(&base_visual_id).unwrap();
+        base_visual.bundle.name = "blue".to_string();
+        base_visual.bundle.material = Some(MaterialRef::Inline(red.clone()));
+        let urdf = workcell.to_urdf().unwrap();
+        assert_eq!(urdf.materials.len(), 3);
+        let base_link = urdf.links.iter().find(|l| l.name == "base_link").unwrap();
+        let material = base_link.visual[0].material.as_ref().unwrap();
+        assert_eq!(material.name, "blue_material");
+        let right_leg = urdf.links.iter().find(|l| l.name == "right_leg").unwrap();
+        let material = right_leg.visual[0].material.as_ref().unwrap();
+        assert_eq!(material.name, "white");
+        assert!(material.color.is_none());
+
+        // And parsed back as inline materials, so the file is unchanged by a load and save
+        let written = urdf_rs::write_to_string(&urdf).unwrap();
+        let urdf = urdf_rs::read_from_string(&written).unwrap();
+        let workcell = Workcell::from_urdf(&urdf).unwrap();
+        assert_eq!(workcell.materials.0.len(), 3);
+        let graph = workcell.graph();
+        let base_link_id = graph.frame_by_name("base_link").unwrap();
+        let base_visual = &workcell.visuals
+            [&graph.children_of_kind(base_link_id, WorkcellElementKind::Visual)[0]];
+        assert_eq!(base_visual.bundle.material, Some(MaterialRef::Inline(red)));
+        assert!(workcell.validate().is_empty());
+        assert_eq!(
+            urdf_rs::write_to_string(&workcell.to_urdf().unwrap()).unwrap(),
+            written
+        );
+
+        // Materials defined in a visual and referred to by name in another one are shared
+        let urdf = urdf_rs::read_from_string(
+            r#"
+            <robot name="shared">
+                <link name="a">
+                    <visual>
+                        <geometry><box size="1 1 1"/></geometry>
+                        <material name="green"><color rgba="0 1 0 1"/></material>
+                    </visual>
+                </link>
+                <link name="b">
+                    <visual>
+                        <geometry><box size="1 1 1"/></geometry>
+                        <material name="green"/>
+                    </visual>
+                </link>
+                <joint name="a_to_b" type="fixed">
+                    <parent link="a"/>
+                    <child link="b"/>
+                </joint>
+            </robot>
+            "#,
+        )
+        .unwrap();
+        let workcell = Workcell::from_urdf(&urdf).unwrap();
+        assert_eq!(workcell.materials.0["green"].color, [0.0, 1.0, 0.0, 1.0]);
+        assert!(workcell
+            .visuals
+            .values()
+            .all(|v| v.bundle.material == Some(MaterialRef::Library("green".to_string()))));
+    }
+
+    #[test]
+    fn urdf_joint_dynamics_roundtrip() {
+        let urdf = r#"
+            <robot name="gripper">
+                <link name="palm"/>
+                <link name="left_finger"/>
+                <link name="right_finger"/>
+                <joint name="left_finger_joint" type="prismatic">
+                    <parent link="palm"/>
+                    <child link="left_finger"/>
+                    <axis xyz="0 1 0"/>
+                    <limit lower="0" upper="0.04" effort="20" velocity="0.2"/>
+                    <dynamics damping="0.5" friction="0.1"/>
+                    <safety_controller soft_lower_limit="0.001" soft_upper_limit="0.039" k_position="15" k_velocity="10"/>
+                </joint>
+                <joint name="right_finger_joint" type="prismatic">
+                    <parent link="palm"/>
+                    <child link="right_finger"/>
+                    <axis xyz="0 -1 0"/>
+                    <limit lower="0" upper="0.04" effort="20" velocity="0.2"/>
+                    <mimic joint="left_finger_joint" multiplier="1" offset="0.001"/>
+                </joint>
+            </robot>"#;
+        let urdf = urdf_rs::read_from_string(urdf).unwrap();
+        let workcell = Workcell::from_urdf(&urdf).unwrap();
+        assert!(workcell.validate().is_empty());
+        let joint = |workcell: &Workcell, name: &str| {
+            workcell
+                .joints
+                .values()
+                .find(|j| j.bundle.name.0 == name)
+                .and_then(|j| j.bundle.properties.single_dof().cloned())
+                .unwrap()
+        };
+        let left = joint(&workcell, "left_finger_joint");
+        assert_eq!(
+            left.dynamics,
+            JointDynamics {
+                damping: 0.5,
+                friction: 0.1
+            }
+        );
+        assert_eq!(left.safety_controller.unwrap().k_position, 15.0);
+        assert!(left.mimic.is_none());
+        let right = joint(&workcell, "right_finger_joint");
+        let mimic = JointMimic {
+            joint: "left_finger_joint".to_string(),
+            multiplier: 1.0,
+            offset: 0.001,
+        };
+        assert_eq!(right.mimic.as_ref(), Some(&mimic));
+        assert!(right.safety_controller.is_none());
+
+        let urdf = urdf_rs::write_to_string(&workcell.to_urdf().unwrap()).unwrap();
+        let urdf = urdf_rs::read_from_string(&urdf).unwrap();
+        let workcell = Workcell::from_urdf(&urdf).unwrap();
+        let new_left = joint(&workcell, "left_finger_joint");
+        assert_eq!(new_left.dynamics, left.dynamics);
+        assert_eq!(new_left.safety_controller, left.safety_controller);
+        assert_eq!(joint(&workcell, "right_finger_joint").mimic, Some(mimic));
+        let urdf_right = urdf
+            .joints
+            .iter()
+            .find(|j| j.name == "right_finger_joint")
+            .unwrap();
+        assert!(urdf_right.dynamics.is_none());
+    }
+
+    #[test]
+    fn urdf_planar_and_floating_joints() {
+        let urdf = |base_joint_type: &str| {
+            format!(
+                r#"
+                <robot name="mobile_manipulator">
+                    <link name="odom"/>
+                    <link name="base_link"/>
+                    <link name="payload"/>
+                    <joint name="base_joint" type="{base_joint_type}">
+                        <parent link="odom"/>
+                        <child link="base_link"/>
+                        <axis xyz="0 0 1"/>
+                    </joint>
+                    <joint name="payload_joint" type="floating">
+                        <parent link="base_link"/>
+                        <child link="payload"/>
+                    </joint>
+                </robot>"#
+            )
+        };
+        let robot = urdf_rs::read_from_string(&urdf("planar")).unwrap();
+        let workcell = Workcell::from_urdf(&robot).unwrap();
+        let properties = |workcell: &Workcell, name: &str| {
+            workcell
+                .joints
+                .values()
+                .find(|j| j.bundle.name.0 == name)
+                .map(|j| j.bundle.properties.clone())
+                .unwrap()
+        };
+        let JointProperties::Planar(planar) = properties(&workcell, "base_joint") else {
+            panic!("base joint should be planar");
+        };
+        assert_eq!(planar.axis.0, [0.0, 0.0, 1.0]);
+        assert_eq!(properties(&workcell, "payload_joint").label(), "Floating");
+
+        let new_robot = workcell.to_urdf().unwrap();
+        let joint_type = |name: &str| {
+            new_robot
+                .joints
+                .iter()
+                .find(|j| j.name == name)
+                .map(|j| j.joint_type.clone())
+                .unwrap()
+        };
+        assert!(matches!(
+            joint_type("base_joint"),
+            urdf_rs::JointType::Planar
+        ));
+        assert!(matches!(
+            joint_type("payload_joint"),
+            urdf_rs::JointType::Floating
+        ));
+
+        let robot = urdf_rs::read_from_string(&urdf("spherical")).unwrap();
+        match Workcell::from_urdf(&robot) {
+            Err(UrdfImportError::UnsupportedJointType { joint, joint_type }) => {
+                assert_eq!(joint, "base_joint");
+                assert_eq!(joint_type, "spherical");
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn jointless_frames_are_merged_into_links() {
+        let mut builder = WorkcellBuilder::new("cell");
+        let up = |z| Pose {
+            trans: [0.0, 0.0, z],
+            ..Default::default()
+        };
+        let inertia = Inertia {
+            mass: Mass(1.0),
+            ..Default::default()
+        };
+        let base = builder.add_frame(builder.root(), "base", up(1.0));
+        let tool = builder.add_frame(base, "tool", up(1.0));
+        builder.add_visual(tool, "", Default::default(), Pose::default());
+        builder.add_inertia(base, inertia.clone());
+        builder.add_inertia(tool, inertia);
+        let arm = builder.add_frame(tool, "arm", up(0.5));
+        builder.add_joint(tool, arm, "arm_joint", JointProperties::Fixed);
+        let workcell = builder.build().unwrap();
+
+        let robot = workcell.to_urdf().unwrap();
+        let names: Vec<_> = robot.links.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, ["base", "arm"]);
+        let base = &robot.links[0];
+        assert_eq!(base.visual.len(), 1);
+        assert_float_eq!(base.visual[0].origin.xyz[2], 1.0, abs <= 1e-6);
+        // Inertias are combined around their common center of mass
+        assert_float_eq!(base.inertial.mass.value, 2.0, abs <= 1e-6);
+        assert_float_eq!(base.inertial.origin.xyz[2], 0.5, abs <= 1e-6);
+        assert_float_eq!(base.inertial.inertia.ixx, 0.5, abs <= 1e-6);
+        assert_float_eq!(base.inertial.inertia.izz, 0.0, abs <= 1e-6);
+        let joint = &robot.joints[0];
+        assert_eq!(joint.parent.link, "base");
+        assert_float_eq!(joint.origin.xyz[2], 1.5, abs <= 1e-6);
+
+        let robot = workcell
+            .to_urdf_with_options(&UrdfExportOptions {
+                keep_merged_frames: true,
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(robot.links.len(), 3);
+        let tool_joint = robot
+            .joints
+            .iter()
+            .find(|j| j.child.link == "tool")
+            .unwrap();
+        assert_eq!(tool_joint.parent.link, "base");
+        assert!(matches!(tool_joint.joint_type, urdf_rs::JointType::Fixed));
+        assert_float_eq!(tool_joint.origin.xyz[2], 1.0, abs <= 1e-6);
+    }
+
+    #[test]
+    fn urdf_root_link_and_world_attachment() {
+        let urdf = urdf_rs::read_file("test/07-physics.urdf").unwrap();
+        let workcell = Workcell::from_urdf(&urdf).unwrap();
+        let options = UrdfExportOptions {
+            root_link: Some("cell_link".to_owned()),
+            world: Some(WorldAttachment {
+                pose: Pose {
+                    trans: [1.0, 2.0, 0.0],
+                    ..Default::default()
+                },
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert_eq!(workcell.urdf_root_link(&options), "world");
+        let robot = workcell.to_urdf_with_options(&options).unwrap();
+        // The only child frame of the workcell is merged into the new root link
+        assert_eq!(robot.links.len(), workcell.frames.len() + 1);
+        assert!(robot.links.iter().all(|l| l.name != "base_link"));
+        let world_joint = robot
+            .joints
+            .iter()
+            .find(|j| j.parent.link == "world")
+            .unwrap();
+        assert_eq!(world_joint.child.link, "cell_link");
+        assert_eq!(*world_joint.origin.xyz, [1.0, 2.0, 0.0]);
+        assert_eq!(robot.joints.len(), workcell.joints.len() + 1);
+
+        // Clashing names are reported
+        let options = UrdfExportOptions {
+            root_link: Some("right_leg".to_owned()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            workcell.to_urdf_with_options(&options),
+            Err(WorkcellToUrdfError::DuplicateLinkName(name)) if name == "right_leg"
+        ));
+    }
+
+    #[test]
+    fn joint_limits_are_validated_and_export_warnings() {
+        let position = RangeLimits::Asymmetric {
+            lower: Some(-1.0),
+            upper: Some(1.0),
+        };
+        let limits = JointLimits::new(
+            position,
+            RangeLimits::Asymmetric {
+                lower: Some(-5.0),
+                upper: Some(10.0),
+            },
+            RangeLimits::None,
+        )
+        .unwrap()
+        .with_acceleration(RangeLimits::Symmetric(2.0))
+        .unwrap();
+        assert_eq!(limits.acceleration(), &RangeLimits::Symmetric(2.0));
+        assert!(limits.position().contains(0.5));
+        assert!(!limits.position().contains(1.5));
+        assert_eq!(
+            JointLimits::new(
+                RangeLimits::Asymmetric {
+                    lower: Some(1.0),
+                    upper: Some(-1.0),
+                },
+                RangeLimits::None,
+                RangeLimits::None,
+            ),
+            Err(JointLimitsError::InvertedRange {
+                kind: JointLimitKind::Position,
+                lower: 1.0,
+                upper: -1.0,
+            })
+        );
+        assert_eq!(
+            limits.clone().with_jerk(RangeLimits::Symmetric(-1.0)),
+            Err(JointLimitsError::NegativeLimit {
+                kind: JointLimitKind::Jerk,
+                value: -1.0,
+            })
+        );
+        assert!(matches!(
+            JointLimits::new(
+                position,
+                RangeLimits::Asymmetric {
+                    lower: Some(1.0),
+                    upper: None,
+                },
+                RangeLimits::None,
+            ),
+            Err(JointLimitsError::ExcludesZero { .. })
+        ));
+
+        let mut builder = WorkcellBuilder::new("cell");
+        let base = builder.add_frame(builder.root(), "base", Pose::default());
+        let arm = builder.add_frame(base, "arm", Pose::default());
+        let shoulder = builder.add_joint(
+            base,
+            arm,
+            "shoulder",
+            JointProperties::Revolute(SingleDofJoint::new(JointAxis::new([0.0, 0.0, 1.0]), limits)),
+        );
+        let workcell = builder.build().unwrap();
+        let (urdf, warnings) = workcell
+            .to_urdf_with_warnings(&UrdfExportOptions::default())
+            .unwrap();
+        let limit = &urdf.joints[0].limit;
+        assert_eq!((limit.lower, limit.upper), (-1.0, 1.0));
+        assert_eq!(limit.effort, 5.0);
+        assert_eq!(limit.velocity, 10.0);
+        assert_eq!(
+            warnings,
+            vec![
+                UrdfExportWarning::JointLimit {
+                    joint: "shoulder".to_owned(),
+                    warning: JointLimitWarning::Asymmetric {
+                        kind: JointLimitKind::Effort,
+                        value: 5.0,
+                    },
+                },
+                UrdfExportWarning::JointLimit {
+                    joint: "shoulder".to_owned(),
+                    warning: JointLimitWarning::Missing {
+                        kind: JointLimitKind::Velocity,
+                        value: 10.0,
+                    },
+                },
+            ]
+        );
+
+        // Limits edited in files are reported when validating
+        let mut json = serde_json::to_value(&workcell).unwrap();
+        let joint = &mut json["joints"][shoulder.id().to_string()];
+        joint["properties"]["Revolute"]["limits"]["velocity"] =
+            serde_json::json!({ "Symmetric": -1.0 });
+        let workcell: Workcell = serde_json::from_value(json).unwrap();
+        assert!(matches!(
+            workcell.validate().as_slice(),
+            [WorkcellDiagnostic::InvalidJointLimits { joint, .. }] if *joint == shoulder.id()
+        ));
+    }
 }
@@ -0,0 +1,1180 @@
+/*
+ * Copyright (C) 2024 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+//! In process expansion of xacro files, supporting properties, arguments, macros, math
+//! expressions, conditionals and includes. Packages referred to by `$(find pkg)` are looked up in
+//! a user provided map so no ROS installation is needed.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::xml::{child_text, write_to_string};
+
+use thiserror::Error as ThisError;
+use xmltree::{Element, XMLNode};
+
+pub const XACRO_NAMESPACE: &str = "http://www.ros.org/wiki/xacro";
+
+/// Maximum depth of nested macro calls and includes, to catch infinite recursion.
+const MAX_DEPTH: usize = 100;
+
+#[derive(Debug, ThisError)]
+pub enum XacroError {
+    #[error("Xml parse error: {0}")]
+    ParseError(#[from] xmltree::ParseError),
+    #[error("Xml write error: {0}")]
+    WriteError(#[from] xmltree::Error),
+    #[error("Io error while reading [{path}]: {error}")]
+    IoError { path: PathBuf, error: io::Error },
+    #[error("Urdf error: {0}")]
+    UrdfError(#[from] urdf_rs::UrdfError),
+    #[error("<xacro:{element}> is missing the required attribute [{attribute}]")]
+    MissingAttribute { element: String, attribute: String },
+    #[error("property [{0}] is not defined")]
+    UndefinedProperty(String),
+    #[error("argument [{0}] is not defined")]
+    UndefinedArg(String),
+    #[error("macro [{0}] is not defined")]
+    UndefinedMacro(String),
+    #[error("block [{0}] is not defined")]
+    UndefinedBlock(String),
+    #[error("macro [{macro_name}] is missing parameter [{param}]")]
+    MissingMacroParameter { macro_name: String, param: String },
+    #[error("package [{0}] not found in the package map")]
+    PackageNotFound(String),
+    #[error("unsupported substitution [$({0})]")]
+    UnsupportedSubstitution(String),
+    #[error("invalid expression [{expression}]: {reason}")]
+    InvalidExpression { expression: String, reason: String },
+    #[error("[{0}] is not a valid boolean condition")]
+    InvalidCondition(String),
+    #[error("maximum depth reached while expanding [{0}], is a macro or include recursive?")]
+    RecursionLimit(String),
+}
+
+/// Value of a property or an evaluated expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum XacroValue {
+    Number(f64),
+    Bool(bool),
+    String(String),
+}
+
+impl XacroValue {
+    /// Interprets literal text, numbers and booleans are converted as in python xacro.
+    fn from_literal(text: &str) -> Self {
+        let trimmed = text.trim();
+        if let Ok(number) = trimmed.parse::<f64>() {
+            return XacroValue::Number(number);
+        }
+        match trimmed {
+            "true" | "True" => XacroValue::Bool(true),
+            "false" | "False" => XacroValue::Bool(false),
+            _ => XacroValue::String(text.to_owned()),
+        }
+    }
+
+    fn as_number(&self) -> Option<f64> {
+        match self {
+            XacroValue::Number(n) => Some(*n),
+            XacroValue::Bool(b) => Some(*b as u8 as f64),
+            XacroValue::String(s) => s.trim().parse().ok(),
+        }
+    }
+
+    fn is_true(&self) -> Option<bool> {
+        match self {
+            XacroValue::Bool(b) => Some(*b),
+            XacroValue::Number(n) => Some(*n != 0.0),
+            XacroValue::String(s) => match XacroValue::from_literal(s) {
+                XacroValue::String(_) => None,
+                value => value.is_true(),
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for XacroValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            XacroValue::Number(n) => write!(f, "{n}"),
+            XacroValue::Bool(b) => write!(f, "{b}"),
+            XacroValue::String(s) => write!(f, "{s}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ParamKind {
+    Value,
+    /// `*name`, receives the next child element of the macro call
+    Block,
+    /// `**name`, receives the children of the next child element of the macro call
+    Blocks,
+}
+
+#[derive(Debug, Clone)]
+struct MacroParam {
+    name: String,
+    kind: ParamKind,
+    default: Option<String>,
+    /// `^` parameters take the value of the property with the same name in the calling scope
+    forward: bool,
+}
+
+#[derive(Debug)]
+struct XacroMacro {
+    params: Vec<MacroParam>,
+    body: Vec<XMLNode>,
+}
+
+impl XacroMacro {
+    fn parse_params(params: &str) -> Vec<MacroParam> {
+        split_params(params)
+            .into_iter()
+            .map(|param| {
+                let (name, default) = match param.split_once(":=") {
+                    Some((name, default)) => (name, Some(default)),
+                    None => (param, None),
+                };
+                let (kind, name) = if let Some(name) = name.strip_prefix("**") {
+                    (ParamKind::Blocks, name)
+                } else if let Some(name) = name.strip_prefix('*') {
+                    (ParamKind::Block, name)
+                } else {
+                    (ParamKind::Value, name)
+                };
+                let (forward, default) = match default {
+                    Some(default) if default.starts_with('^') => {
+                        (true, default[1..].strip_prefix('|').map(str::to_owned))
+                    }
+                    default => (false, default.map(str::to_owned)),
+                };
+                let default = default.map(|d| d.trim_matches(['\'', '"']).to_owned());
+                MacroParam {
+                    name: name.to_owned(),
+                    kind,
+                    default,
+                    forward,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Splits the parameters of a macro on whitespace, except within quoted default values.
+fn split_params(params: &str) -> Vec<&str> {
+    let mut result = Vec::new();
+    let mut start = None;
+    let mut quote = None;
+    for (idx, c) in params.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, c) if c.is_whitespace() => {
+                if let Some(start) = start.take() {
+                    result.push(&params[start..idx]);
+                }
+                continue;
+            }
+            _ => {}
+        }
+        start.get_or_insert(idx);
+    }
+    if let Some(start) = start {
+        result.push(&params[start..]);
+    }
+    result
+}
+
+#[derive(Default)]
+struct Scope {
+    properties: HashMap<String, XacroValue>,
+    blocks: HashMap<String, Vec<XMLNode>>,
+    macros: HashMap<String, Rc<XacroMacro>>,
+}
+
+/// Expands xacro documents into plain xml.
+#[derive(Debug, Clone, Default)]
+pub struct XacroProcessor {
+    /// Overrides for the values of `<xacro:arg>`
+    pub args: HashMap<String, String>,
+    /// Directories of the packages that can be referred to through `$(find package)`
+    pub packages: HashMap<String, PathBuf>,
+}
+
+impl XacroProcessor {
+    pub fn with_arg(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.args.insert(name.into(), value.into());
+        self
+    }
+
+    pub fn with_package(mut self, name: impl Into<String>, path: impl Into<PathBuf>) -> Self {
+        self.packages.insert(name.into(), path.into());
+        self
+    }
+
+    /// Adds all the packages found in `dir` and its subdirectories, packages are directories
+    /// containing a `package.xml` manifest.
+    pub fn with_packages_in(mut self, dir: impl AsRef<Path>) -> Self {
+        self.add_packages_in(dir.as_ref(), 0);
+        self
+    }
+
+    fn add_packages_in(&mut self, dir: &Path, depth: usize) {
+        if depth > 5 {
+            return;
+        }
+        let manifest = dir.join("package.xml");
+        if let Some(name) = std::fs::File::open(manifest)
+            .ok()
+            .and_then(|file| Element::parse(file).ok())
+            .and_then(|manifest| child_text(&manifest, "name"))
+        {
+            self.packages.insert(name, dir.to_owned());
+            // Packages can't be nested
+            return;
+        }
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if path.is_dir() && !hidden {
+                self.add_packages_in(&path, depth + 1);
+            }
+        }
+    }
+
+    /// Adds the package containing `path` and the packages next to it, as they would be found
+    /// in the source directory of a workspace.
+    pub fn with_packages_near(self, path: impl AsRef<Path>) -> Self {
+        let package = path
+            .as_ref()
+            .ancestors()
+            .find(|dir| dir.join("package.xml").is_file());
+        match package.and_then(Path::parent) {
+            Some(workspace) => self.with_packages_in(workspace),
+            None => self,
+        }
+    }
+
+    /// Expands a xacro document, relative includes are resolved from `dir`.
+    pub fn expand(&self, xacro: &Element, dir: Option<&Path>) -> Result<Element, XacroError> {
+        let mut expander = XacroExpander {
+            processor: self,
+            args: self.args.clone(),
+            scopes: vec![Scope::default()],
+            dirs: dir.map(Path::to_owned).into_iter().collect(),
+            depth: 0,
+        };
+        let mut root = Element {
+            children: Vec::new(),
+            ..xacro.clone()
+        };
+        expander.expand_attributes(&mut root)?;
+        expander.expand_nodes(&xacro.children, &mut root.children)?;
+        strip_xacro_namespace(&mut root);
+        Ok(root)
+    }
+
+    pub fn expand_to_string(&self, data: &[u8], dir: Option<&Path>) -> Result<String, XacroError> {
+        let xacro = Element::parse(data)?;
+        Ok(write_to_string(&self.expand(&xacro, dir)?)?)
+    }
+
+    /// Expands a xacro document and parses the result as a urdf.
+    pub fn read_urdf(&self, data: &[u8], dir: Option<&Path>) -> Result<urdf_rs::Robot, XacroError> {
+        let urdf = self.expand_to_string(data, dir)?;
+        Ok(urdf_rs::read_from_string(&urdf)?)
+    }
+
+    pub fn read_urdf_file(&self, path: impl AsRef<Path>) -> Result<urdf_rs::Robot, XacroError> {
+        let path = path.as_ref();
+        let data = std::fs::read(path).map_err(|error| XacroError::IoError {
+            path: path.to_owned(),
+            error,
+        })?;
+        self.read_urdf(&data, path.parent())
+    }
+}
+
+fn is_xacro(element: &Element) -> bool {
+    element.namespace.as_deref() == Some(XACRO_NAMESPACE)
+        || element.prefix.as_deref() == Some("xacro")
+}
+
+fn strip_xacro_namespace(element: &mut Element) {
+    if let Some(namespaces) = &mut element.namespaces {
+        namespaces.0.retain(|_, uri| uri != XACRO_NAMESPACE);
+    }
+    for child in element.children.iter_mut() {
+        if let XMLNode::Element(child) = child {
+            strip_xacro_namespace(child);
+        }
+    }
+}
+
+fn required_attr<'a>(element: &'a Element, attribute: &str) -> Result<&'a str, XacroError> {
+    element
+        .attributes
+        .get(attribute)
+        .map(String::as_str)
+        .ok_or_else(|| XacroError::MissingAttribute {
+            element: element.name.clone(),
+            attribute: attribute.to_owned(),
+        })
+}
+
+struct XacroExpander<'a> {
+    processor: &'a XacroProcessor,
+    args: HashMap<String, String>,
+    /// Macros are dynamically scoped, as in python xacro, the last scope is the innermost one
+    scopes: Vec<Scope>,
+    /// Directories of the files being expanded, used to resolve relative includes
+    dirs: Vec<PathBuf>,
+    depth: usize,
+}
+
+impl<'a> XacroExpander<'a> {
+    fn property(&self, name: &str) -> Option<&XacroValue> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.properties.get(name))
+    }
+
+    fn block(&self, name: &str) -> Option<&Vec<XMLNode>> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.blocks.get(name))
+    }
+
+    fn find_macro(&self, name: &str) -> Option<Rc<XacroMacro>> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.macros.get(name).cloned())
+    }
+
+    fn current_scope(&mut self) -> &mut Scope {
+        // There is always at least the global scope
+        self.scopes.last_mut().unwrap()
+    }
+
+    fn expand_attributes(&mut self, element: &mut Element) -> Result<(), XacroError> {
+        for value in element.attributes.values_mut() {
+            *value = self.substitute_text(value)?;
+        }
+        Ok(())
+    }
+
+    fn expand_nodes(
+        &mut self,
+        nodes: &[XMLNode],
+        out: &mut Vec<XMLNode>,
+    ) -> Result<(), XacroError> {
+        for node in nodes {
+            match node {
+                XMLNode::Element(element) if is_xacro(element) => {
+                    self.expand_xacro_element(element, out)?;
+                }
+                XMLNode::Element(element) => {
+                    let mut expanded = Element {
+                        children: Vec::new(),
+                        ..element.clone()
+                    };
+                    self.expand_attributes(&mut expanded)?;
+                    self.expand_nodes(&element.children, &mut expanded.children)?;
+                    out.push(XMLNode::Element(expanded));
+                }
+                XMLNode::Text(text) => {
+                    out.push(XMLNode::Text(self.substitute_text(text)?));
+                }
+                XMLNode::CData(_) => out.push(node.clone()),
+                XMLNode::Comment(_) | XMLNode::ProcessingInstruction(..) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn expand_xacro_element(
+        &mut self,
+        element: &Element,
+        out: &mut Vec<XMLNode>,
+    ) -> Result<(), XacroError> {
+        match element.name.as_str() {
+            "property" => {
+                let name = required_attr(element, "name")?.to_owned();
+                let scope = match element.attributes.get("scope").map(String::as_str) {
+                    Some("global") => 0,
+                    Some("parent") => self.scopes.len().saturating_sub(2),
+                    _ => self.scopes.len() - 1,
+                };
+                if let Some(value) = element.attributes.get("value") {
+                    let value = self.substitute(value)?;
+                    self.scopes[scope].properties.insert(name, value);
+                } else if let Some(default) = element.attributes.get("default") {
+                    if self.property(&name).is_none() {
+                        let value = self.substitute(default)?;
+                        self.scopes[scope].properties.insert(name, value);
+                    }
+                } else {
+                    // Block properties hold their children, expanded when inserted
+                    self.scopes[scope]
+                        .blocks
+                        .insert(name, element.children.clone());
+                }
+            }
+            "arg" => {
+                let name = required_attr(element, "name")?.to_owned();
+                if !self.args.contains_key(&name) {
+                    let default = element.attributes.get("default").ok_or_else(|| {
+                        XacroError::MissingAttribute {
+                            element: format!("arg {name}"),
+                            attribute: "default".to_owned(),
+                        }
+                    })?;
+                    let value = self.substitute_text(default)?;
+                    self.args.insert(name, value);
+                }
+            }
+            "macro" => {
+                let name = required_attr(element, "name")?.to_owned();
+                let params = element
+                    .attributes
+                    .get("params")
+                    .map(|p| XacroMacro::parse_params(p))
+                    .unwrap_or_default();
+                let xacro_macro = XacroMacro {
+                    params,
+                    body: element.children.clone(),
+                };
+                self.current_scope()
+                    .macros
+                    .insert(name, Rc::new(xacro_macro));
+            }
+            "include" => self.expand_include(element, out)?,
+            "if" | "unless" => {
+                let value = self.substitute(required_attr(element, "value")?)?;
+                let condition = value
+                    .is_true()
+                    .ok_or_else(|| XacroError::InvalidCondition(value.to_string()))?;
+                if condition == (element.name == "if") {
+                    self.expand_nodes(&element.children, out)?;
+                }
+            }
+            "insert_block" => {
+                let name = self.substitute_text(required_attr(element, "name")?)?;
+                let block = self
+                    .block(&name)
+                    .cloned()
+                    .ok_or(XacroError::UndefinedBlock(name))?;
+                self.expand_nodes(&block, out)?;
+            }
+            name => self.expand_macro_call(name, element, out)?,
+        }
+        Ok(())
+    }
+
+    fn expand_include(
+        &mut self,
+        element: &Element,
+        out: &mut Vec<XMLNode>,
+    ) -> Result<(), XacroError> {
+        let filename = self.substitute_text(required_attr(element, "filename")?)?;
+        let mut path = PathBuf::from(&filename);
+        if path.is_relative() {
+            if let Some(dir) = self.dirs.last() {
+                path = dir.join(path);
+            }
+        }
+        let file = std::fs::File::open(&path).map_err(|error| XacroError::IoError {
+            path: path.clone(),
+            error,
+        })?;
+        let included = Element::parse(file)?;
+        self.enter(&filename)?;
+        self.dirs
+            .push(path.parent().map(Path::to_owned).unwrap_or_default());
+        // The content of the included file is expanded in the current scope
+        let result = self.expand_nodes(&included.children, out);
+        self.dirs.pop();
+        self.depth -= 1;
+        result
+    }
+
+    fn enter(&mut self, name: &str) -> Result<(), XacroError> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(XacroError::RecursionLimit(name.to_owned()));
+        }
+        Ok(())
+    }
+
+    fn expand_macro_call(
+        &mut self,
+        name: &str,
+        call: &Element,
+        out: &mut Vec<XMLNode>,
+    ) -> Result<(), XacroError> {
+        let xacro_macro = self
+            .find_macro(name)
+            .ok_or_else(|| XacroError::UndefinedMacro(name.to_owned()))?;
+        let mut scope = Scope::default();
+        let mut block_children = call.children.iter().filter_map(|c| c.as_element());
+        for param in &xacro_macro.params {
+            let missing = || XacroError::MissingMacroParameter {
+                macro_name: name.to_owned(),
+                param: param.name.clone(),
+            };
+            match param.kind {
+                ParamKind::Value => {
+                    let value = if let Some(value) = call.attributes.get(&param.name) {
+                        self.substitute(value)?
+                    } else if let Some(value) = self.property(&param.name).filter(|_| param.forward)
+                    {
+                        value.clone()
+                    } else if let Some(default) = &param.default {
+                        self.substitute(default)?
+                    } else {
+                        return Err(missing());
+                    };
+                    scope.properties.insert(param.name.clone(), value);
+                }
+                ParamKind::Block | ParamKind::Blocks => {
+                    let block = block_children.next().ok_or_else(missing)?;
+                    let nodes = if param.kind == ParamKind::Block {
+                        vec![XMLNode::Element(block.clone())]
+                    } else {
+                        block.children.clone()
+                    };
+                    scope.blocks.insert(param.name.clone(), nodes);
+                }
+            }
+        }
+        self.enter(name)?;
+        self.scopes.push(scope);
+        let result = self.expand_nodes(&xacro_macro.body, out);
+        self.scopes.pop();
+        self.depth -= 1;
+        result
+    }
+
+    /// Substitutes `$(...)` and `${...}` expressions in a text. A text made of a single
+    /// expression keeps the type of its value, other texts are parsed as literals.
+    fn substitute(&mut self, text: &str) -> Result<XacroValue, XacroError> {
+        Ok(match self.substitute_parts(text)? {
+            (_, Some(value)) => value,
+            (result, None) => XacroValue::from_literal(&result),
+        })
+    }
+
+    /// Same as [`XacroExpander::substitute`] but for text written to the output, which is kept
+    /// as is outside of the substituted expressions.
+    fn substitute_text(&mut self, text: &str) -> Result<String, XacroError> {
+        Ok(match self.substitute_parts(text)? {
+            (_, Some(value)) => value.to_string(),
+            (result, None) => result,
+        })
+    }
+
+    /// Returns the substituted text and, if the text is made of a single `${...}` expression,
+    /// its value.
+    fn substitute_parts(&mut self, text: &str) -> Result<(String, Option<XacroValue>), XacroError> {
+        let text = self.substitute_extensions(text)?;
+        let mut result = String::new();
+        let mut rest = text.as_str();
+        let mut single_value = None;
+        while let Some(start) = rest.find("${") {
+            // $${ is an escaped ${
+            if rest[..start].ends_with('$') {
+                result.push_str(&rest[..start - 1]);
+                result.push_str("${");
+                rest = &rest[start + 2..];
+                continue;
+            }
+            result.push_str(&rest[..start]);
+            let expression = &rest[start + 2..];
+            let end =
+                find_closing_brace(expression).ok_or_else(|| XacroError::InvalidExpression {
+                    expression: expression.to_owned(),
+                    reason: "missing closing brace".to_owned(),
+                })?;
+            let value = self.evaluate(&expression[..end])?;
+            rest = &expression[end + 1..];
+            if start == 0 && rest.is_empty() && result.is_empty() {
+                single_value = Some(value.clone());
+            }
+            result.push_str(&value.to_string());
+        }
+        result.push_str(rest);
+        Ok((result, single_value))
+    }
+
+    fn substitute_extensions(&mut self, text: &str) -> Result<String, XacroError> {
+        let mut result = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find("$(") {
+            if rest[..start].ends_with('$') {
+                result.push_str(&rest[..start - 1]);
+                result.push_str("$(");
+                rest = &rest[start + 2..];
+                continue;
+            }
+            result.push_str(&rest[..start]);
+            let extension = &rest[start + 2..];
+            let end = extension
+                .find(')')
+                .ok_or_else(|| XacroError::UnsupportedSubstitution(extension.to_owned()))?;
+            let (command, args) = extension[..end]
+                .trim()
+                .split_once(char::is_whitespace)
+                .unwrap_or((&extension[..end], ""));
+            let args = args.trim();
+            let value = match command {
+                "arg" => self
+                    .args
+                    .get(args)
+                    .cloned()
+                    .ok_or_else(|| XacroError::UndefinedArg(args.to_owned()))?,
+                "find" => self
+                    .processor
+                    .packages
+                    .get(args)
+                    .map(|path| path.to_string_lossy().into_owned())
+                    .ok_or_else(|| XacroError::PackageNotFound(args.to_owned()))?,
+                "env" => std::env::var(args).unwrap_or_default(),
+                "optenv" => {
+                    let (var, default) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+                    std::env::var(var).unwrap_or_else(|_| default.trim().to_owned())
+                }
+                "eval" => self.evaluate(args)?.to_string(),
+                _ => {
+                    return Err(XacroError::UnsupportedSubstitution(
+                        extension[..end].to_owned(),
+                    ))
+                }
+            };
+            result.push_str(&value);
+            rest = &extension[end + 1..];
+        }
+        result.push_str(rest);
+        Ok(result)
+    }
+
+    fn evaluate(&self, expression: &str) -> Result<XacroValue, XacroError> {
+        let invalid = |reason: String| XacroError::InvalidExpression {
+            expression: expression.to_owned(),
+            reason,
+        };
+        let tokens = tokenize(expression).map_err(invalid)?;
+        let mut parser = ExpressionParser {
+            tokens: &tokens,
+            pos: 0,
+            expander: self,
+        };
+        let value = parser.ternary().map_err(|err| match err {
+            EvalError::Invalid(reason) => invalid(reason),
+            EvalError::Xacro(err) => err,
+        })?;
+        if parser.pos != tokens.len() {
+            return Err(invalid("unexpected trailing tokens".to_owned()));
+        }
+        Ok(value)
+    }
+}
+
+/// Finds the closing brace of an expression, skipping the ones in quoted strings.
+fn find_closing_brace(expression: &str) -> Option<usize> {
+    let mut quote = None;
+    let mut depth = 0;
+    for (idx, c) in expression.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '{') => depth += 1,
+            (None, '}') if depth == 0 => return Some(idx),
+            (None, '}') => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    String(String),
+    Ident(String),
+    Op(&'static str),
+}
+
+const OPERATORS: [&str; 18] = [
+    "**", "//", "==", "!=", "<=", ">=", "+", "-", "*", "/", "%", "<", ">", "(", ")", ",", "[", "]",
+];
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = expression.trim_start();
+    while let Some(c) = rest.chars().next() {
+        if c.is_ascii_digit() || (c == '.' && rest[1..].starts_with(|c: char| c.is_ascii_digit())) {
+            let mut end = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .unwrap_or(rest.len());
+            // Exponent, i.e. 1e-3
+            if rest[end..].starts_with(['e', 'E']) {
+                let exponent = &rest[end + 1..];
+                let sign = exponent.starts_with(['+', '-']) as usize;
+                let digits = exponent[sign..]
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(exponent.len() - sign);
+                if digits > 0 {
+                    end += 1 + sign + digits;
+                }
+            }
+            let number = rest[..end]
+                .parse()
+                .map_err(|_| format!("invalid number [{}]", &rest[..end]))?;
+            tokens.push(Token::Number(number));
+            rest = &rest[end..];
+        } else if c.is_alphabetic() || c == '_' {
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+                .unwrap_or(rest.len());
+            let ident = &rest[..end];
+            // Functions and constants of the python math module are available without prefix
+            let ident = ident.strip_prefix("math.").unwrap_or(ident);
+            tokens.push(Token::Ident(ident.to_owned()));
+            rest = &rest[end..];
+        } else if c == '\'' || c == '"' {
+            let end = rest[1..]
+                .find(c)
+                .ok_or_else(|| "unterminated string".to_owned())?;
+            tokens.push(Token::String(rest[1..end + 1].to_owned()));
+            rest = &rest[end + 2..];
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else {
+            return Err(format!("unexpected character [{c}]"));
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+enum EvalError {
+    Invalid(String),
+    Xacro(XacroError),
+}
+
+impl From<String> for EvalError {
+    fn from(reason: String) -> Self {
+        EvalError::Invalid(reason)
+    }
+}
+
+/// Recursive descent parser that evaluates the subset of python used in xacro expressions.
+struct ExpressionParser<'t, 'e, 'a> {
+    tokens: &'t [Token],
+    pos: usize,
+    expander: &'e XacroExpander<'a>,
+}
+
+fn number(value: &XacroValue) -> Result<f64, EvalError> {
+    value
+        .as_number()
+        .ok_or_else(|| EvalError::Invalid(format!("[{value}] is not a number")))
+}
+
+fn truth(value: &XacroValue) -> Result<bool, EvalError> {
+    value
+        .is_true()
+        .ok_or_else(|| EvalError::Invalid(format!("[{value}] is not a boolean")))
+}
+
+impl<'t, 'e, 'a> ExpressionParser<'t, 'e, 'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<&Token> {
+        self.pos += 1;
+        self.tokens.get(self.pos - 1)
+    }
+
+    fn eat_op(&mut self, op: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Op(o)) if *o == op) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Ident(ident)) if ident == keyword) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect_op(&mut self, op: &str) -> Result<(), EvalError> {
+        if self.eat_op(op) {
+            Ok(())
+        } else {
+            Err(format!("expected [{op}]").into())
+        }
+    }
+
+    fn ternary(&mut self) -> Result<XacroValue, EvalError> {
+        let value = self.or()?;
+        if self.eat_keyword("if") {
+            let condition = self.or()?;
+            if !self.eat_keyword("else") {
+                return Err("expected [else]".to_owned().into());
+            }
+            let alternative = self.ternary()?;
+            return Ok(if truth(&condition)? {
+                value
+            } else {
+                alternative
+            });
+        }
+        Ok(value)
+    }
+
+    fn or(&mut self) -> Result<XacroValue, EvalError> {
+        let mut value = self.and()?;
+        while self.eat_keyword("or") {
+            let rhs = self.and()?;
+            value = XacroValue::Bool(truth(&value)? || truth(&rhs)?);
+        }
+        Ok(value)
+    }
+
+    fn and(&mut self) -> Result<XacroValue, EvalError> {
+        let mut value = self.not()?;
+        while self.eat_keyword("and") {
+            let rhs = self.not()?;
+            value = XacroValue::Bool(truth(&value)? && truth(&rhs)?);
+        }
+        Ok(value)
+    }
+
+    fn not(&mut self) -> Result<XacroValue, EvalError> {
+        if self.eat_keyword("not") {
+            let value = self.not()?;
+            return Ok(XacroValue::Bool(!truth(&value)?));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<XacroValue, EvalError> {
+        let lhs = self.additive()?;
+        for op in ["==", "!=", "<=", ">=", "<", ">"] {
+            if self.eat_op(op) {
+                let rhs = self.additive()?;
+                let result = match (op, lhs.as_number(), rhs.as_number()) {
+                    ("==", Some(l), Some(r)) => l == r,
+                    ("!=", Some(l), Some(r)) => l != r,
+                    ("==", _, _) => lhs.to_string() == rhs.to_string(),
+                    ("!=", _, _) => lhs.to_string() != rhs.to_string(),
+                    (_, Some(l), Some(r)) => match op {
+                        "<=" => l <= r,
+                        ">=" => l >= r,
+                        "<" => l < r,
+                        _ => l > r,
+                    },
+                    _ => return Err(format!("cannot compare [{lhs}] and [{rhs}]").into()),
+                };
+                return Ok(XacroValue::Bool(result));
+            }
+        }
+        Ok(lhs)
+    }
+
+    fn additive(&mut self) -> Result<XacroValue, EvalError> {
+        let mut value = self.term()?;
+        loop {
+            if self.eat_op("+") {
+                let rhs = self.term()?;
+                value = match (&value, &rhs) {
+                    (XacroValue::String(l), XacroValue::String(r)) => {
+                        XacroValue::String(format!("{l}{r}"))
+                    }
+                    _ => XacroValue::Number(number(&value)? + number(&rhs)?),
+                };
+            } else if self.eat_op("-") {
+                let rhs = self.term()?;
+                value = XacroValue::Number(number(&value)? - number(&rhs)?);
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<XacroValue, EvalError> {
+        let mut value = self.unary()?;
+        loop {
+            let op = ["*", "//", "/", "%"].into_iter().find(|op| self.eat_op(op));
+            let Some(op) = op else {
+                return Ok(value);
+            };
+            let lhs = number(&value)?;
+            let rhs = number(&self.unary()?)?;
+            value = XacroValue::Number(match op {
+                "*" => lhs * rhs,
+                "/" => lhs / rhs,
+                "//" => (lhs / rhs).floor(),
+                // Python modulo has the sign of the divisor
+                _ => lhs - rhs * (lhs / rhs).floor(),
+            });
+        }
+    }
+
+    fn unary(&mut self) -> Result<XacroValue, EvalError> {
+        if self.eat_op("-") {
+            return Ok(XacroValue::Number(-number(&self.unary()?)?));
+        }
+        if self.eat_op("+") {
+            return Ok(XacroValue::Number(number(&self.unary()?)?));
+        }
+        self.power()
+    }
+
+    fn power(&mut self) -> Result<XacroValue, EvalError> {
+        let base = self.atom()?;
+        if self.eat_op("**") {
+            let exponent = number(&self.unary()?)?;
+            return Ok(XacroValue::Number(number(&base)?.powf(exponent)));
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> Result<XacroValue, EvalError> {
+        let token = self
+            .next()
+            .cloned()
+            .ok_or_else(|| "unexpected end of expression".to_owned())?;
+        match token {
+            Token::Number(n) => Ok(XacroValue::Number(n)),
+            Token::String(s) => Ok(XacroValue::String(s)),
+            Token::Op("(") => {
+                let value = self.ternary()?;
+                self.expect_op(")")?;
+                Ok(value)
+            }
+            Token::Ident(ident) if self.eat_op("(") => {
+                let mut args = Vec::new();
+                if !self.eat_op(")") {
+                    loop {
+                        args.push(self.ternary()?);
+                        if self.eat_op(")") {
+                            break;
+                        }
+                        self.expect_op(",")?;
+                    }
+                }
+                call_function(&ident, &args)
+            }
+            Token::Ident(ident) => match ident.as_str() {
+                "pi" => Ok(XacroValue::Number(std::f64::consts::PI)),
+                "e" => Ok(XacroValue::Number(std::f64::consts::E)),
+                "True" | "true" => Ok(XacroValue::Bool(true)),
+                "False" | "false" => Ok(XacroValue::Bool(false)),
+                name => self.expander.property(name).cloned().ok_or_else(|| {
+                    EvalError::Xacro(XacroError::UndefinedProperty(name.to_owned()))
+                }),
+            },
+            Token::Op(op) => Err(format!("unexpected [{op}]").into()),
+        }
+    }
+}
+
+fn call_function(name: &str, args: &[XacroValue]) -> Result<XacroValue, EvalError> {
+    if let ("str", [value]) = (name, args) {
+        return Ok(XacroValue::String(value.to_string()));
+    }
+    let numbers = args.iter().map(number).collect::<Result<Vec<_>, _>>()?;
+    let value = match (name, numbers.as_slice()) {
+        ("sin", [x]) => x.sin(),
+        ("cos", [x]) => x.cos(),
+        ("tan", [x]) => x.tan(),
+        ("asin", [x]) => x.asin(),
+        ("acos", [x]) => x.acos(),
+        ("atan", [x]) => x.atan(),
+        ("sqrt", [x]) => x.sqrt(),
+        ("exp", [x]) => x.exp(),
+        ("log", [x]) => x.ln(),
+        ("abs" | "fabs", [x]) => x.abs(),
+        ("floor", [x]) => x.floor(),
+        ("ceil", [x]) => x.ceil(),
+        ("round", [x]) => x.round(),
+        ("int", [x]) => x.trunc(),
+        ("float", [x]) => *x,
+        ("radians", [x]) => x.to_radians(),
+        ("degrees", [x]) => x.to_degrees(),
+        ("atan2", [y, x]) => y.atan2(*x),
+        ("pow", [x, y]) => x.powf(*y),
+        ("min", [first, rest @ ..]) => rest.iter().fold(*first, |a, b| a.min(*b)),
+        ("max", [first, rest @ ..]) => rest.iter().fold(*first, |a, b| a.max(*b)),
+        _ => {
+            return Err(format!(
+                "unknown function [{name}] or wrong number of arguments ({})",
+                args.len()
+            )
+            .into())
+        }
+    };
+    Ok(XacroValue::Number(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ARM: &str = r#"<?xml version="1.0"?>
+<robot name="arm" xmlns:xacro="http://www.ros.org/wiki/xacro">
+  <xacro:arg name="prefix" default="left_"/>
+  <xacro:arg name="with_tool" default="false"/>
+  <xacro:property name="prefix" value="$(arg prefix)"/>
+  <xacro:property name="length" value="0.5"/>
+  <xacro:property name="default_origin">
+    <origin xyz="0 0 0" rpy="0 0 0"/>
+  </xacro:property>
+  <xacro:include filename="$(find xacro_test)/urdf/link.xacro"/>
+  <xacro:macro name="segment" params="name parent *origin axis:='0 0 1'">
+    <xacro:link_with_box name="${prefix}${name}" size="0.1 0.1 ${length}"/>
+    <joint name="${prefix}${name}_joint" type="revolute">
+      <parent link="${prefix}${parent}"/>
+      <child link="${prefix}${name}"/>
+      <xacro:insert_block name="origin"/>
+      <axis xyz="${axis}"/>
+      <limit lower="${-pi/2}" upper="${radians(90)}" effort="${2**3 * 10}" velocity="1"/>
+    </joint>
+  </xacro:macro>
+  <xacro:link_with_box name="${prefix}base" size="0.2 0.2 0.1"/>
+  <xacro:segment name="upper" parent="base">
+    <origin xyz="0 0 ${length / 2}" rpy="0 0 0"/>
+  </xacro:segment>
+  <xacro:segment name="lower" parent="upper" axis="0 1 0">
+    <xacro:insert_block name="default_origin"/>
+  </xacro:segment>
+  <xacro:if value="$(arg with_tool)">
+    <link name="${prefix}tool"/>
+  </xacro:if>
+  <xacro:unless value="${length > 1 and prefix == 'left_'}">
+    <link name="${'short' if length &lt; 1 else 'long'}"/>
+  </xacro:unless>
+</robot>
+"#;
+
+    fn processor() -> XacroProcessor {
+        XacroProcessor::default().with_packages_in("test/xacro")
+    }
+
+    #[test]
+    fn xacro_is_expanded() {
+        let urdf = processor().read_urdf(ARM.as_bytes(), None).unwrap();
+        assert_eq!(urdf.name, "arm");
+        let link_names = urdf
+            .links
+            .iter()
+            .map(|l| l.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            link_names,
+            ["left_base", "left_upper", "left_lower", "short"]
+        );
+        assert_eq!(urdf.joints.len(), 2);
+        let upper = &urdf.joints[0];
+        assert_eq!(upper.parent.link, "left_base");
+        assert_eq!(upper.origin.xyz.0, [0.0, 0.0, 0.25]);
+        assert_eq!(upper.axis.xyz.0, [0.0, 0.0, 1.0]);
+        assert!((upper.limit.lower + std::f64::consts::FRAC_PI_2).abs() < 1e-9);
+        assert!((upper.limit.upper - std::f64::consts::FRAC_PI_2).abs() < 1e-9);
+        assert_eq!(upper.limit.effort, 80.0);
+        let lower = &urdf.joints[1];
+        assert_eq!(lower.axis.xyz.0, [0.0, 1.0, 0.0]);
+        assert_eq!(lower.origin.xyz.0, [0.0, 0.0, 0.0]);
+        let urdf_rs::Geometry::Box { size } = &urdf.links[1].visual[0].geometry else {
+            panic!("Expected a box geometry");
+        };
+        assert_eq!(size.0, [0.1, 0.1, 0.5]);
+    }
+
+    #[test]
+    fn xacro_args_are_overridden() {
+        let urdf = processor()
+            .with_arg("prefix", "right_")
+            .with_arg("with_tool", "true")
+            .read_urdf(ARM.as_bytes(), None)
+            .unwrap();
+        assert!(urdf.links.iter().any(|l| l.name == "right_tool"));
+        assert!(urdf.links.iter().all(|l| !l.name.starts_with("left_")));
+    }
+
+    #[test]
+    fn xacro_keeps_literal_text() {
+        let xacro = r#"<robot name="r" xmlns:xacro="http://www.ros.org/wiki/xacro">
+            <xacro:property name="version" value="0.10"/>
+            <link name="007" version="0.10" serial="1e3">nan</link>
+            <link name="${version}" copy="${version}_$(arg id)"/>
+        </robot>"#;
+        let expanded = XacroProcessor::default()
+            .with_arg("id", "007")
+            .expand(&Element::parse(xacro.as_bytes()).unwrap(), None)
+            .unwrap();
+        let links: Vec<_> = expanded
+            .children
+            .iter()
+            .filter_map(|n| n.as_element())
+            .collect();
+        assert_eq!(links[0].attributes["name"], "007");
+        assert_eq!(links[0].attributes["version"], "0.10");
+        assert_eq!(links[0].attributes["serial"], "1e3");
+        assert_eq!(links[0].get_text().unwrap(), "nan");
+        // Single expressions are evaluated
+        assert_eq!(links[1].attributes["name"], "0.1");
+        assert_eq!(links[1].attributes["copy"], "0.1_007");
+    }
+
+    #[test]
+    fn xacro_errors() {
+        let missing_package = XacroProcessor::default().read_urdf(ARM.as_bytes(), None);
+        assert!(matches!(
+            missing_package,
+            Err(XacroError::PackageNotFound(package)) if package == "xacro_test"
+        ));
+        let recursive = r#"<robot name="r" xmlns:xacro="http://www.ros.org/wiki/xacro">
+            <xacro:macro name="loop"><xacro:loop/></xacro:macro>
+            <xacro:loop/>
+        </robot>"#;
+        assert!(matches!(
+            processor().read_urdf(recursive.as_bytes(), None),
+            Err(XacroError::RecursionLimit(_))
+        ));
+    }
+}
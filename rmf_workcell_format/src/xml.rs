@@ -0,0 +1,94 @@
+/*
+ * Copyright (C) 2024 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+//! Small helpers to build, read and write xmltree documents.
+
+use xmltree::{Element, EmitterConfig, XMLNode};
+
+pub(crate) trait ElementExt: Sized {
+    fn with_attr(self, key: &str, value: impl ToString) -> Self;
+    fn with_child(self, child: Element) -> Self;
+    fn with_text(self, text: impl ToString) -> Self;
+    fn push(&mut self, child: Element);
+}
+
+impl ElementExt for Element {
+    fn with_attr(mut self, key: &str, value: impl ToString) -> Self {
+        self.attributes.insert(key.to_owned(), value.to_string());
+        self
+    }
+
+    fn with_child(mut self, child: Element) -> Self {
+        self.push(child);
+        self
+    }
+
+    fn with_text(mut self, text: impl ToString) -> Self {
+        self.children.push(XMLNode::Text(text.to_string()));
+        self
+    }
+
+    fn push(&mut self, child: Element) {
+        self.children.push(XMLNode::Element(child));
+    }
+}
+
+/// Creates an element only containing the given text, i.e. `<name>text</name>`.
+pub(crate) fn text_element(name: &str, text: impl ToString) -> Element {
+    Element::new(name).with_text(text)
+}
+
+/// Formats a list of values separated by spaces, as used by most robot description formats.
+pub(crate) fn space_separated<T: ToString>(values: impl IntoIterator<Item = T>) -> String {
+    values
+        .into_iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Iterates over the child elements of `element` with the requested name.
+pub(crate) fn children_named<'a>(
+    element: &'a Element,
+    name: &'a str,
+) -> impl Iterator<Item = &'a Element> + 'a {
+    element
+        .children
+        .iter()
+        .filter_map(|c| c.as_element())
+        .filter(move |c| c.name == name)
+}
+
+/// Returns the trimmed text of the first child element with the requested name.
+pub(crate) fn child_text(element: &Element, name: &str) -> Option<String> {
+    element
+        .get_child(name)
+        .and_then(|c| c.get_text())
+        .map(|t| t.trim().to_owned())
+}
+
+pub(crate) fn write_to_string(element: &Element) -> Result<String, xmltree::Error> {
+    let mut buffer = Vec::new();
+    element.write_with_config(
+        &mut buffer,
+        EmitterConfig::new()
+            .perform_indent(true)
+            .indent_string("  "),
+    )?;
+    // The emitter only writes valid utf8
+    Ok(String::from_utf8_lossy(&buffer).into_owned())
+}